@@ -6,7 +6,7 @@ use crate::{ProcessHandle, Timestamp};
 #[derive(Debug, Clone, Copy, PartialOrd, Ord, PartialEq, Eq, Hash)]
 pub struct CounterHandle(pub(crate) usize);
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Counter {
     name: String,
     category: String,
@@ -75,7 +75,7 @@ impl<'a> Serialize for SerializableCounter<'a> {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 struct CounterSamples {
     time: Vec<Timestamp>,
     number: Vec<u32>,
@@ -45,6 +45,11 @@ impl CpuDelta {
         }
     }
 
+    /// The CPU delta in integer microseconds.
+    pub fn as_micros(&self) -> u64 {
+        self.micros
+    }
+
     /// Whether the CPU delta is zero.
     pub fn is_zero(&self) -> bool {
         self.micros == 0
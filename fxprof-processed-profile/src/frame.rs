@@ -2,6 +2,7 @@ use bitflags::bitflags;
 
 use crate::category::CategoryPairHandle;
 use crate::global_lib_table::LibraryHandle;
+use crate::library_info::LibraryInfo;
 use crate::profile::StringHandle;
 
 /// A part of the information about a single stack frame.
@@ -61,6 +62,19 @@ pub struct FrameInfo {
     pub flags: FrameFlags,
 }
 
+/// A stack frame of a sample in a finished profile, as passed to the callback of
+/// [`Profile::for_each_sample_stack`](crate::Profile::for_each_sample_stack).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SampleFrame<'a> {
+    /// A label, or a function name from a symbol table which was set with
+    /// [`Profile::set_lib_symbol_table`](crate::Profile::set_lib_symbol_table).
+    Function(&'a str),
+    /// A relative address in a library which hasn't been symbolicated.
+    AddressInLib(&'a LibraryInfo, u32),
+    /// An absolute address which isn't in any known library.
+    UnknownAddress(u64),
+}
+
 bitflags! {
     /// Flags for a stack frame.
     #[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Clone, Copy)]
@@ -4,7 +4,7 @@ use crate::category::{
     Category, CategoryHandle, CategoryPairHandle, SerializableSubcategoryColumn, Subcategory,
 };
 use crate::fast_hash_map::FastHashMap;
-use crate::frame::FrameFlags;
+use crate::frame::{FrameFlags, SampleFrame};
 use crate::func_table::{FuncIndex, FuncTable};
 use crate::global_lib_table::{GlobalLibIndex, GlobalLibTable};
 use crate::native_symbols::{NativeSymbolIndex, NativeSymbols};
@@ -19,6 +19,7 @@ pub struct FrameTable {
     subcategories: Vec<Subcategory>,
     funcs: Vec<FuncIndex>,
     native_symbols: Vec<Option<NativeSymbolIndex>>,
+    lines: Vec<Option<u32>>,
     internal_frame_to_frame_index: FastHashMap<InternalFrame, usize>,
 }
 
@@ -41,11 +42,13 @@ impl FrameTable {
         let native_symbols = &mut self.native_symbols;
         let categories = &mut self.categories;
         let subcategories = &mut self.subcategories;
+        let lines = &mut self.lines;
         *self
             .internal_frame_to_frame_index
             .entry(frame.clone())
             .or_insert_with(|| {
                 let frame_index = addresses.len();
+                let mut source_line = None;
                 let (address, location_string_index, native_symbol, resource) = match frame.location
                 {
                     InternalFrameLocation::UnknownAddress(address) => {
@@ -70,6 +73,18 @@ impl FrameTable {
                             });
                         let (native_symbol, s) = match native_symbol_and_name {
                             Some((native_symbol, name_string_index)) => {
+                                source_line = lib
+                                    .symbol_table
+                                    .as_deref()
+                                    .and_then(|symbol_table| {
+                                        symbol_table.lookup_source_line(address)
+                                    })
+                                    .map(|source_line| {
+                                        (
+                                            string_table.index_for_string(&source_line.file_path),
+                                            source_line.line,
+                                        )
+                                    });
                                 (Some(native_symbol), name_string_index)
                             }
                             None => {
@@ -84,8 +99,12 @@ impl FrameTable {
                     }
                     InternalFrameLocation::Label(string_index) => (None, string_index, None, None),
                 };
-                let func_index =
-                    func_table.index_for_func(location_string_index, resource, frame.flags);
+                let func_index = func_table.index_for_func(
+                    location_string_index,
+                    resource,
+                    frame.flags,
+                    source_line.map(|(file_name, _line)| file_name),
+                );
                 let CategoryPairHandle(category, subcategory_index) = frame.category_pair;
                 let subcategory = match subcategory_index {
                     Some(index) => Subcategory::Normal(index),
@@ -96,10 +115,41 @@ impl FrameTable {
                 subcategories.push(subcategory);
                 funcs.push(func_index);
                 native_symbols.push(native_symbol);
+                lines.push(source_line.map(|(_file_name, line)| line));
                 frame_index
             })
     }
 
+    /// Returns the [`SampleFrame`] for each frame index.
+    pub fn sample_frames<'a>(
+        &self,
+        func_table: &FuncTable,
+        string_table: &'a ThreadStringTable,
+        global_libs: &'a GlobalLibTable,
+    ) -> Vec<SampleFrame<'a>> {
+        let mut frames = vec![SampleFrame::UnknownAddress(0); self.addresses.len()];
+        for (frame, &frame_index) in &self.internal_frame_to_frame_index {
+            let func_name = || {
+                let name = func_table.name(self.funcs[frame_index]);
+                SampleFrame::Function(string_table.get_string(name).unwrap())
+            };
+            frames[frame_index] = match frame.location {
+                InternalFrameLocation::UnknownAddress(address) => {
+                    SampleFrame::UnknownAddress(address)
+                }
+                InternalFrameLocation::AddressInLib(address, lib_index)
+                    if self.native_symbols[frame_index].is_none() =>
+                {
+                    SampleFrame::AddressInLib(global_libs.get_lib(lib_index).unwrap(), address)
+                }
+                InternalFrameLocation::AddressInLib(..) | InternalFrameLocation::Label(_) => {
+                    func_name()
+                }
+            };
+        }
+        frames
+    }
+
     pub fn as_serializable<'a>(&'a self, categories: &'a [Category]) -> impl Serialize + 'a {
         SerializableFrameTable {
             table: self,
@@ -132,7 +182,7 @@ impl<'a> Serialize for SerializableFrameTable<'a> {
         map.serialize_entry("nativeSymbol", &self.table.native_symbols)?;
         map.serialize_entry("innerWindowID", &SerializableSingleValueColumn((), len))?;
         map.serialize_entry("implementation", &SerializableSingleValueColumn((), len))?;
-        map.serialize_entry("line", &SerializableSingleValueColumn((), len))?;
+        map.serialize_entry("line", &self.table.lines)?;
         map.serialize_entry("column", &SerializableSingleValueColumn((), len))?;
         map.end()
     }
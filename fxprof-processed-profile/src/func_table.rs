@@ -11,8 +11,8 @@ pub struct FuncTable {
     names: Vec<ThreadInternalStringIndex>,
     resources: Vec<Option<ResourceIndex>>,
     flags: Vec<FrameFlags>,
-    func_name_and_resource_and_flags_to_func_index:
-        FastHashMap<(ThreadInternalStringIndex, Option<ResourceIndex>, FrameFlags), usize>,
+    file_names: Vec<Option<ThreadInternalStringIndex>>,
+    func_key_to_func_index: FastHashMap<FuncKey, usize>,
     contains_js_function: bool,
 }
 
@@ -26,15 +26,17 @@ impl FuncTable {
         name: ThreadInternalStringIndex,
         resource: Option<ResourceIndex>,
         flags: FrameFlags,
+        file_name: Option<ThreadInternalStringIndex>,
     ) -> FuncIndex {
         let func_index = *self
-            .func_name_and_resource_and_flags_to_func_index
-            .entry((name, resource, flags))
+            .func_key_to_func_index
+            .entry((name, resource, flags, file_name))
             .or_insert_with(|| {
                 let func_index = self.names.len();
                 self.names.push(name);
                 self.resources.push(resource);
                 self.flags.push(flags);
+                self.file_names.push(file_name);
                 func_index
             });
         if flags.intersects(FrameFlags::IS_JS | FrameFlags::IS_RELEVANT_FOR_JS) {
@@ -43,11 +45,22 @@ impl FuncTable {
         FuncIndex(func_index as u32)
     }
 
+    pub fn name(&self, func: FuncIndex) -> ThreadInternalStringIndex {
+        self.names[func.0 as usize]
+    }
+
     pub fn contains_js_function(&self) -> bool {
         self.contains_js_function
     }
 }
 
+type FuncKey = (
+    ThreadInternalStringIndex,
+    Option<ResourceIndex>,
+    FrameFlags,
+    Option<ThreadInternalStringIndex>,
+);
+
 #[derive(Debug, Clone, Copy, PartialOrd, Ord, PartialEq, Eq, Hash)]
 pub struct FuncIndex(u32);
 
@@ -75,7 +88,7 @@ impl Serialize for FuncTable {
             "resource",
             &SerializableFuncTableResourceColumn(&self.resources),
         )?;
-        map.serialize_entry("fileName", &SerializableSingleValueColumn((), len))?;
+        map.serialize_entry("fileName", &self.file_names)?;
         map.serialize_entry("lineNumber", &SerializableSingleValueColumn((), len))?;
         map.serialize_entry("columnNumber", &SerializableSingleValueColumn((), len))?;
         map.end()
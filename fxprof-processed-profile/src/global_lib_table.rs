@@ -6,7 +6,7 @@ use serde::ser::{Serialize, Serializer};
 use crate::fast_hash_map::FastHashMap;
 use crate::{LibraryInfo, SymbolTable};
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct GlobalLibTable {
     /// All libraries added via `Profile::add_lib`. May or may not be used.
     /// Indexed by `LibraryHandle.0`.
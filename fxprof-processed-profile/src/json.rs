@@ -0,0 +1,20 @@
+use std::io::Write;
+
+use crate::Profile;
+
+/// Serialize the profile into the processed profile JSON format, as a string.
+///
+/// This is the format which the [Firefox Profiler](https://profiler.firefox.com/)
+/// loads. It includes the format version fields in the profile's `meta` object.
+pub fn profile_to_json_string(profile: &Profile) -> String {
+    serde_json::to_string(profile).expect("serializing a Profile into a string cannot fail")
+}
+
+/// Serialize the profile into the processed profile JSON format, and write it
+/// to `writer`.
+///
+/// The writer is not buffered by this function; wrap it in a
+/// [`BufWriter`](std::io::BufWriter) if it's a file.
+pub fn profile_to_json_writer<W: Write>(profile: &Profile, writer: W) -> serde_json::Result<()> {
+    serde_json::to_writer(writer, profile)
+}
@@ -4,13 +4,13 @@
 //! Specifically, this uses the ["Processed profile format"](https://github.com/firefox-devtools/profiler/blob/main/docs-developer/processed-profile-format.md).
 //!
 //! Use [`Profile::new`] to create a new [`Profile`] object. Then add all the
-//! information into it. To convert it to JSON, use [`serde_json`], for
-//! example [`serde_json::to_writer`] or [`serde_json::to_string`].
+//! information into it. To convert it to JSON, use [`profile_to_json_writer`]
+//! or [`profile_to_json_string`].
 //!
 //! ## Example
 //!
 //! ```
-//! use fxprof_processed_profile::{Profile, CategoryHandle, CpuDelta, Frame, FrameInfo, FrameFlags, SamplingInterval, Timestamp};
+//! use fxprof_processed_profile::{profile_to_json_writer, Profile, CategoryHandle, CpuDelta, Frame, FrameInfo, FrameFlags, SamplingInterval, Timestamp};
 //! use std::time::SystemTime;
 //!
 //! # fn write_profile(output_file: std::fs::File) -> Result<(), Box<dyn std::error::Error>> {
@@ -25,7 +25,7 @@
 //! profile.add_sample(thread, Timestamp::from_millis_since_reference(0.0), stack.into_iter(), CpuDelta::ZERO, 1);
 //!
 //! let writer = std::io::BufWriter::new(output_file);
-//! serde_json::to_writer(writer, &profile)?;
+//! profile_to_json_writer(&profile, writer)?;
 //! # Ok(())
 //! # }
 //! ```
@@ -41,6 +41,7 @@ mod frame;
 mod frame_table;
 mod func_table;
 mod global_lib_table;
+mod json;
 mod lib_mappings;
 mod library_info;
 mod marker_table;
@@ -49,6 +50,7 @@ mod native_symbols;
 mod process;
 mod profile;
 mod reference_timestamp;
+mod resolved_profile_builder;
 mod resource_table;
 mod sample_table;
 mod serialization_helpers;
@@ -62,10 +64,11 @@ pub use category::{CategoryHandle, CategoryPairHandle};
 pub use category_color::CategoryColor;
 pub use counters::CounterHandle;
 pub use cpu_delta::CpuDelta;
-pub use frame::{Frame, FrameFlags, FrameInfo};
+pub use frame::{Frame, FrameFlags, FrameInfo, SampleFrame};
 pub use global_lib_table::{LibraryHandle, UsedLibraryAddressesIterator};
+pub use json::{profile_to_json_string, profile_to_json_writer};
 pub use lib_mappings::LibMappings;
-pub use library_info::{LibraryInfo, Symbol, SymbolTable};
+pub use library_info::{LibraryInfo, SourceLine, Symbol, SymbolTable};
 pub use markers::{
     Marker, MarkerFieldFormat, MarkerFieldFormatKind, MarkerFieldSchema, MarkerHandle,
     MarkerLocation, MarkerSchema, MarkerStaticField, MarkerTiming, MarkerTypeHandle,
@@ -74,5 +77,6 @@ pub use markers::{
 pub use process::ThreadHandle;
 pub use profile::{Profile, SamplingInterval, StringHandle};
 pub use reference_timestamp::ReferenceTimestamp;
+pub use resolved_profile_builder::{ResolvedFrame, ResolvedProfileBuilder};
 pub use thread::ProcessHandle;
 pub use timestamp::*;
@@ -127,6 +127,59 @@ impl<T> LibMappings<T> {
     }
 }
 
+impl<T: Clone> LibMappings<T> {
+    /// Remove the address range `start_avma..end_avma` from this address space,
+    /// e.g. when it has been unmapped. Mappings which only partially overlap with
+    /// the range are shortened, and a mapping which contains the range is split
+    /// into two mappings.
+    ///
+    /// An empty or inverted range, e.g. from an unmap whose length wrapped
+    /// around the end of the address space, doesn't remove anything.
+    pub fn remove_range(&mut self, start_avma: u64, end_avma: u64) {
+        if start_avma >= end_avma {
+            return;
+        }
+        let first_key = match self.lookup_impl(start_avma) {
+            Some(mapping) => mapping.start_avma,
+            None => start_avma,
+        };
+        let overlapping_keys: Vec<u64> = self
+            .map
+            .range(first_key..end_avma)
+            .map(|(start_avma, _)| *start_avma)
+            .collect();
+        for key in overlapping_keys {
+            let mapping = self.map.remove(&key).unwrap();
+            // The remainder after the range is only kept if its relative
+            // address can still be represented.
+            let relative_address_at_end = u32::try_from(end_avma - mapping.start_avma)
+                .ok()
+                .and_then(|offset| mapping.relative_address_at_start.checked_add(offset))
+                .filter(|_| mapping.end_avma > end_avma);
+            if let Some(relative_address_at_end) = relative_address_at_end {
+                self.map.insert(
+                    end_avma,
+                    Mapping {
+                        start_avma: end_avma,
+                        end_avma: mapping.end_avma,
+                        relative_address_at_start: relative_address_at_end,
+                        value: mapping.value.clone(),
+                    },
+                );
+            }
+            if mapping.start_avma < start_avma {
+                self.map.insert(
+                    mapping.start_avma,
+                    Mapping {
+                        end_avma: start_avma,
+                        ..mapping
+                    },
+                );
+            }
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, PartialOrd, Ord, Eq)]
 struct Mapping<T> {
     start_avma: u64,
@@ -158,4 +211,64 @@ mod test {
         assert_eq!(m.lookup(200), Some(&"180..220"));
         assert_eq!(m.lookup(260), Some(&"255..270"));
     }
+
+    #[test]
+    fn test_remove_range() {
+        let mut m = LibMappings::new();
+        m.add_mapping(100, 200, 0, "a");
+        m.add_mapping(200, 300, 0, "b");
+        m.add_mapping(400, 500, 0, "c");
+
+        // Split "a", shorten "b" at its start.
+        m.remove_range(140, 220);
+        assert_eq!(m.convert_address(139), Some((39, &"a")));
+        assert_eq!(m.lookup(140), None);
+        assert_eq!(m.lookup(219), None);
+        assert_eq!(m.convert_address(220), Some((20, &"b")));
+
+        // Split "c" in the middle.
+        m.remove_range(420, 430);
+        assert_eq!(m.convert_address(419), Some((19, &"c")));
+        assert_eq!(m.lookup(425), None);
+        assert_eq!(m.convert_address(430), Some((30, &"c")));
+        assert_eq!(m.convert_address(499), Some((99, &"c")));
+
+        // Remove "b" entirely, and the end of the first part of "a".
+        m.remove_range(120, 300);
+        assert_eq!(m.lookup(119), Some(&"a"));
+        assert_eq!(m.lookup(120), None);
+        assert_eq!(m.lookup(250), None);
+        assert_eq!(m.lookup(150), None);
+    }
+
+    #[test]
+    fn test_remove_invalid_range() {
+        let mut m = LibMappings::new();
+        m.add_mapping(0x1000, 0x2000, 0, "a");
+        m.add_mapping(0xffff_ffff_ffff_0000, u64::MAX, 0, "b");
+
+        // A range whose end wrapped around the end of the address space.
+        let start = 0xffff_ffff_ffff_8000u64;
+        m.remove_range(start, start.wrapping_add(0x10000));
+        assert_eq!(m.lookup(0xffff_ffff_ffff_8000), Some(&"b"));
+        assert_eq!(m.lookup(0x1800), Some(&"a"));
+
+        // An inverted range and an empty range.
+        m.remove_range(0x1800, 0x1400);
+        m.remove_range(0x1400, 0x1400);
+        assert_eq!(m.lookup(0x1400), Some(&"a"));
+        assert_eq!(m.lookup(0x1800), Some(&"a"));
+    }
+
+    #[test]
+    fn test_remove_range_in_large_mapping() {
+        let mut m = LibMappings::new();
+        m.add_mapping(0, 0x2_0000_0000, 0x10, "a");
+
+        // The remainder's relative address doesn't fit into a u32, so only
+        // the part before the range is kept.
+        m.remove_range(0x1000, 0x1_8000_0000);
+        assert_eq!(m.convert_address(0xfff), Some((0x100f, &"a")));
+        assert_eq!(m.lookup(0x1_8000_0000), None);
+    }
 }
@@ -78,17 +78,52 @@ impl Serialize for LibraryInfo {
 }
 
 /// A symbol table which contains a list of [`Symbol`]s, used in [`LibraryInfo`].
+///
+/// It can also contain the [`SourceLine`]s of the symbols' code, for example
+/// from the line tables of JIT-compiled code.
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct SymbolTable {
     symbols: Vec<Symbol>,
+    source_lines: Vec<SourceLine>,
 }
 
 impl SymbolTable {
     /// Create a [`SymbolTable`] from a list of [`Symbol`]s.
-    pub fn new(mut symbols: Vec<Symbol>) -> Self {
+    pub fn new(symbols: Vec<Symbol>) -> Self {
+        Self::with_source_lines(symbols, Vec::new())
+    }
+
+    /// Create a [`SymbolTable`] from a list of [`Symbol`]s and the source
+    /// lines of their code. The address ranges of the source lines must not
+    /// overlap.
+    pub fn with_source_lines(mut symbols: Vec<Symbol>, mut source_lines: Vec<SourceLine>) -> Self {
         symbols.sort();
         symbols.dedup_by_key(|symbol| symbol.address);
-        Self { symbols }
+        source_lines.sort();
+        source_lines.dedup_by_key(|source_line| source_line.address);
+        Self {
+            symbols,
+            source_lines,
+        }
+    }
+
+    /// Look up the source line for an address. This address is relative to the
+    /// library's base address.
+    pub fn lookup_source_line(&self, address: u32) -> Option<&SourceLine> {
+        let index = match self
+            .source_lines
+            .binary_search_by_key(&address, |source_line| source_line.address)
+        {
+            Ok(i) => i,
+            Err(0) => return None,
+            Err(next_i) => next_i - 1,
+        };
+        let source_line = &self.source_lines[index];
+        if address < source_line.address.saturating_add(source_line.size) {
+            Some(source_line)
+        } else {
+            None
+        }
     }
 
     /// Look up the symbol for an address. This address is relative to the library's base address.
@@ -120,3 +155,16 @@ pub struct Symbol {
     /// The symbol name.
     pub name: String,
 }
+
+/// The source location of a range of code, used in [`SymbolTable`].
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SourceLine {
+    /// The start of the code range, as a "relative address", i.e. relative to the library's base address.
+    pub address: u32,
+    /// The size of the code range in bytes.
+    pub size: u32,
+    /// The path of the source file.
+    pub file_path: String,
+    /// The line number in the source file, starting at 1.
+    pub line: u32,
+}
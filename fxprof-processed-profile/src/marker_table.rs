@@ -100,6 +100,10 @@ impl MarkerTable {
         MarkerHandle(self.marker_categories.len() - 1)
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.marker_name_string_indexes.is_empty()
+    }
+
     pub fn set_marker_stack(&mut self, marker: MarkerHandle, stack_index: Option<usize>) {
         self.marker_stacks[marker.0] = stack_index;
     }
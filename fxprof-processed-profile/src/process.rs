@@ -10,7 +10,7 @@ use crate::Timestamp;
 #[derive(Debug, Clone, Copy, PartialOrd, Ord, PartialEq, Eq, Hash)]
 pub struct ThreadHandle(pub(crate) usize);
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Process {
     pid: String,
     name: String,
@@ -64,6 +64,10 @@ impl Process {
         self.threads.push(thread);
     }
 
+    pub fn retain_threads(&mut self, f: impl FnMut(&ThreadHandle) -> bool) {
+        self.threads.retain(f);
+    }
+
     pub fn pid(&self) -> &str {
         &self.pid
     }
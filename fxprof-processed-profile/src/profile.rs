@@ -10,7 +10,7 @@ use crate::category_color::CategoryColor;
 use crate::counters::{Counter, CounterHandle};
 use crate::cpu_delta::CpuDelta;
 use crate::fast_hash_map::FastHashMap;
-use crate::frame::{Frame, FrameInfo};
+use crate::frame::{Frame, FrameInfo, SampleFrame};
 use crate::frame_table::{InternalFrame, InternalFrameLocation};
 use crate::global_lib_table::{GlobalLibTable, LibraryHandle, UsedLibraryAddressesIterator};
 use crate::lib_mappings::LibMappings;
@@ -103,10 +103,11 @@ pub struct StringHandle(pub(crate) GlobalStringIndex);
 /// # Ok(())
 /// # }
 /// ```
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Profile {
     pub(crate) product: String,
     pub(crate) os_name: Option<String>,
+    pub(crate) meta_info: Vec<(String, String)>,
     pub(crate) interval: SamplingInterval,
     pub(crate) global_libs: GlobalLibTable,
     pub(crate) kernel_libs: LibMappings<LibraryHandle>,
@@ -138,6 +139,7 @@ impl Profile {
             interval,
             product: product.to_string(),
             os_name: None,
+            meta_info: Vec::new(),
             threads: Vec::new(),
             global_libs: GlobalLibTable::new(),
             kernel_libs: LibMappings::new(),
@@ -177,6 +179,19 @@ impl Profile {
         self.os_name = Some(os_name.to_string());
     }
 
+    /// Attach an arbitrary key/value pair to the profile metadata, for example
+    /// the command line that was used to record the profile.
+    ///
+    /// The pairs are displayed in the profile info panel of the Firefox Profiler,
+    /// in the order in which they were first added. Setting a key which already
+    /// exists replaces its value.
+    pub fn set_meta_info(&mut self, key: &str, value: &str) {
+        match self.meta_info.iter_mut().find(|(k, _)| k == key) {
+            Some((_, v)) => *v = value.to_string(),
+            None => self.meta_info.push((key.to_string(), value.to_string())),
+        }
+    }
+
     /// Add a category and return its handle.
     ///
     /// Categories are used for stack frames and markers, as part of a "category pair".
@@ -680,6 +695,37 @@ impl Profile {
         self.counters[counter.0].add_sample(timestamp, value_delta, number_of_operations_delta)
     }
 
+    /// Remove all threads which have neither samples nor markers from the profile.
+    ///
+    /// Main threads are always kept, so that every process stays visible and its
+    /// counters still have a thread to attach to. The `ThreadHandle`s of removed
+    /// threads stay valid, but anything added to them afterwards won't be
+    /// serialized, so call this once you're done adding data to the profile.
+    pub fn remove_idle_threads(&mut self) {
+        let threads = &self.threads;
+        for process in &mut self.processes {
+            process.retain_threads(|thread| {
+                let thread = &threads[thread.0];
+                thread.is_main() || !thread.is_idle()
+            });
+        }
+    }
+
+    /// Calls `f` with the stack and the weight of every sample in the profile,
+    /// across all threads. The frames are ordered from the root to the leaf,
+    /// i.e. the last frame is the sampled function.
+    ///
+    /// This lets you analyze a profile after it was created, for example to
+    /// compare the sample counts per function of two profiles. Library addresses
+    /// which weren't symbolicated with a symbol table are returned as
+    /// [`SampleFrame::AddressInLib`]; you can find all of them upfront with
+    /// [`lib_used_rva_iter`](Profile::lib_used_rva_iter).
+    pub fn for_each_sample_stack<'a>(&'a self, mut f: impl FnMut(&[SampleFrame<'a>], i32)) {
+        for thread in &self.threads {
+            thread.for_each_sample_stack(&self.global_libs, &mut f);
+        }
+    }
+
     // frames is ordered from caller to callee, i.e. root function first, pc last
     fn stack_index_for_frames(
         &mut self,
@@ -841,6 +887,27 @@ impl<'a> Serialize for SerializableProfileMeta<'a> {
         if let Some(os_name) = &self.0.os_name {
             map.serialize_entry("oscpu", os_name)?;
         }
+        if !self.0.meta_info.is_empty() {
+            let entries: Vec<_> = self
+                .0
+                .meta_info
+                .iter()
+                .map(|(key, value)| {
+                    json!({
+                        "label": key,
+                        "format": "string",
+                        "value": value,
+                    })
+                })
+                .collect();
+            map.serialize_entry(
+                "extra",
+                &json!([{
+                    "label": "Profile Information",
+                    "entries": entries,
+                }]),
+            )?;
+        }
         map.serialize_entry(
             "sampleUnits",
             &json!({
@@ -876,7 +943,7 @@ struct SerializableProfileThreadsProperty<'a> {
 
 impl<'a> Serialize for SerializableProfileThreadsProperty<'a> {
     fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
-        let mut seq = serializer.serialize_seq(Some(self.threads.len()))?;
+        let mut seq = serializer.serialize_seq(Some(self.sorted_threads.len()))?;
 
         for thread in self.sorted_threads {
             let categories = self.categories;
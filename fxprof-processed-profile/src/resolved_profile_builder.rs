@@ -0,0 +1,199 @@
+use std::sync::Arc;
+
+use debugid::DebugId;
+
+use crate::fast_hash_map::FastHashMap;
+use crate::{
+    CategoryColor, CategoryPairHandle, CpuDelta, Frame, FrameFlags, FrameInfo, LibraryHandle,
+    LibraryInfo, ProcessHandle, Profile, ReferenceTimestamp, SamplingInterval, Symbol, SymbolTable,
+    ThreadHandle, Timestamp,
+};
+
+/// A stack frame which has already been resolved to a function, for
+/// [`ResolvedProfileBuilder::add_sample_with_resolved_stack`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResolvedFrame<'a> {
+    /// The name of the function.
+    pub function: &'a str,
+    /// The name of the library or binary which contains the function, if known.
+    pub module: Option<&'a str>,
+}
+
+/// Creates a [`Profile`] from samples whose stacks were already resolved to
+/// function names, e.g. by a sampler which does its own symbolication.
+///
+/// Each module becomes a library in the profile, whose symbol table has one
+/// symbol per function, so that functions are grouped by module like the
+/// functions of regular libraries. Frames without a module become labels.
+///
+/// Samples are buffered until [`finish`](ResolvedProfileBuilder::finish),
+/// because frames are resolved with the symbol table when they're added to the
+/// profile, and the symbol tables are only complete once all samples are known.
+#[derive(Debug)]
+pub struct ResolvedProfileBuilder {
+    profile: Profile,
+    category: CategoryPairHandle,
+    processes: FastHashMap<i32, ProcessHandle>,
+    modules: FastHashMap<String, ResolvedModule>,
+    samples: Vec<BufferedSample>,
+}
+
+/// A module's library, and the relative addresses which were given to its
+/// functions.
+#[derive(Debug)]
+struct ResolvedModule {
+    lib: LibraryHandle,
+    function_addresses: FastHashMap<String, u32>,
+}
+
+#[derive(Debug)]
+struct BufferedSample {
+    thread: ThreadHandle,
+    timestamp: Timestamp,
+    frames: Vec<FrameInfo>,
+    cpu_delta: CpuDelta,
+    weight: i32,
+}
+
+impl ResolvedProfileBuilder {
+    /// Create a builder for a profile. The arguments are passed to [`Profile::new`].
+    pub fn new(
+        product: &str,
+        reference_timestamp: ReferenceTimestamp,
+        interval: SamplingInterval,
+    ) -> Self {
+        let mut profile = Profile::new(product, reference_timestamp, interval);
+        let category = profile.add_category("User", CategoryColor::Yellow).into();
+        Self {
+            profile,
+            category,
+            processes: FastHashMap::default(),
+            modules: FastHashMap::default(),
+            samples: Vec::new(),
+        }
+    }
+
+    /// The profile which is being built, e.g. for adding markers or counters.
+    pub fn profile_mut(&mut self) -> &mut Profile {
+        &mut self.profile
+    }
+
+    /// Add a process, or rename it if a process with this pid was added before.
+    pub fn add_process(&mut self, pid: i32, name: &str, start_time: Timestamp) -> ProcessHandle {
+        if let Some(process) = self.processes.get(&pid) {
+            self.profile.set_process_name(*process, name);
+            return *process;
+        }
+        let process = self.profile.add_process(name, pid as u32, start_time);
+        self.processes.insert(pid, process);
+        process
+    }
+
+    /// Add a thread to the process with the given pid. If no such process was
+    /// added, a process named after the pid is added.
+    pub fn add_thread(
+        &mut self,
+        pid: i32,
+        tid: i32,
+        name: Option<&str>,
+        start_time: Timestamp,
+        is_main: bool,
+    ) -> ThreadHandle {
+        let process = match self.processes.get(&pid) {
+            Some(process) => *process,
+            None => self.add_process(pid, &format!("PID {pid}"), start_time),
+        };
+        let thread = self
+            .profile
+            .add_thread(process, tid as u32, start_time, is_main);
+        if let Some(name) = name {
+            self.profile.set_thread_name(thread, name);
+        }
+        thread
+    }
+
+    /// Add a sample. The stack is ordered from the innermost frame to the
+    /// root, i.e. the sampled function comes first.
+    pub fn add_sample_with_resolved_stack(
+        &mut self,
+        thread: ThreadHandle,
+        timestamp: Timestamp,
+        stack: &[ResolvedFrame],
+        cpu_delta: CpuDelta,
+        weight: i32,
+    ) {
+        let frames = stack
+            .iter()
+            .rev()
+            .map(|frame| FrameInfo {
+                frame: self.frame_for(frame),
+                category_pair: self.category,
+                flags: FrameFlags::empty(),
+            })
+            .collect();
+        self.samples.push(BufferedSample {
+            thread,
+            timestamp,
+            frames,
+            cpu_delta,
+            weight,
+        });
+    }
+
+    fn frame_for(&mut self, frame: &ResolvedFrame) -> Frame {
+        let Some(module_name) = frame.module else {
+            return Frame::Label(self.profile.intern_string(frame.function));
+        };
+        let profile = &mut self.profile;
+        let module = self
+            .modules
+            .entry(module_name.to_owned())
+            .or_insert_with(|| ResolvedModule {
+                lib: profile.add_lib(LibraryInfo {
+                    name: module_name.to_owned(),
+                    debug_name: module_name.to_owned(),
+                    path: module_name.to_owned(),
+                    debug_path: module_name.to_owned(),
+                    debug_id: DebugId::nil(),
+                    code_id: None,
+                    arch: None,
+                    symbol_table: None,
+                }),
+                function_addresses: FastHashMap::default(),
+            });
+        let function_count = module.function_addresses.len() as u32;
+        let address = *module
+            .function_addresses
+            .entry(frame.function.to_owned())
+            .or_insert(function_count);
+        Frame::RelativeAddressFromInstructionPointer(module.lib, address)
+    }
+
+    /// Set the symbol tables of the modules, add the buffered samples to the
+    /// profile, and return it.
+    pub fn finish(mut self) -> Profile {
+        for (_name, module) in self.modules {
+            let symbols = module
+                .function_addresses
+                .into_iter()
+                .map(|(name, address)| Symbol {
+                    address,
+                    size: Some(1),
+                    name,
+                })
+                .collect();
+            self.profile
+                .set_lib_symbol_table(module.lib, Arc::new(SymbolTable::new(symbols)));
+        }
+        for sample in self.samples {
+            self.profile.add_sample(
+                sample.thread,
+                sample.timestamp,
+                sample.frames.into_iter(),
+                sample.cpu_delta,
+                sample.weight,
+            );
+        }
+        self.profile
+    }
+}
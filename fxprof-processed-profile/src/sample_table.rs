@@ -134,6 +134,18 @@ impl SampleTable {
         self.last_sample_timestamp = timestamp;
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.sample_timestamps.is_empty()
+    }
+
+    /// Returns the stack index and the weight of each sample, in insertion order.
+    pub fn stacks_and_weights(&self) -> impl Iterator<Item = (Option<usize>, i32)> + '_ {
+        self.sample_stack_indexes
+            .iter()
+            .copied()
+            .zip(self.sample_weights.iter().copied())
+    }
+
     pub fn modify_last_sample(&mut self, timestamp: Timestamp, weight: i32) {
         *self.sample_weights.last_mut().unwrap() += weight;
         *self.sample_timestamps.last_mut().unwrap() = timestamp;
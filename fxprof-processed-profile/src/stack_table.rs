@@ -86,6 +86,14 @@ impl StackTable {
         }
     }
 
+    pub fn prefix(&self, stack: usize) -> Option<usize> {
+        self.stack_prefixes[stack]
+    }
+
+    pub fn frame(&self, stack: usize) -> usize {
+        self.stack_frames[stack]
+    }
+
     pub fn serialize_with_categories<'a>(
         &'a self,
         categories: &'a [Category],
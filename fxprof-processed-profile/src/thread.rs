@@ -5,6 +5,7 @@ use serde::ser::{SerializeMap, Serializer};
 
 use crate::category::{Category, CategoryPairHandle};
 use crate::cpu_delta::CpuDelta;
+use crate::frame::SampleFrame;
 use crate::frame_table::{FrameTable, InternalFrame};
 use crate::func_table::FuncTable;
 use crate::global_lib_table::GlobalLibTable;
@@ -22,7 +23,7 @@ use crate::{CategoryHandle, Marker, MarkerHandle, MarkerTiming, MarkerTypeHandle
 #[derive(Debug, Clone, Copy, PartialOrd, Ord, PartialEq, Eq, Hash)]
 pub struct ProcessHandle(pub(crate) usize);
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Thread {
     process: ProcessHandle,
     tid: String,
@@ -185,6 +186,38 @@ impl Thread {
         self.markers.set_marker_stack(marker, stack_index);
     }
 
+    /// Calls `f` with the frames and the weight of each sample. The frames are
+    /// ordered from the root to the leaf.
+    pub fn for_each_sample_stack<'a>(
+        &'a self,
+        global_libs: &'a GlobalLibTable,
+        f: &mut impl FnMut(&[SampleFrame<'a>], i32),
+    ) {
+        let frames =
+            self.frame_table
+                .sample_frames(&self.func_table, &self.string_table, global_libs);
+        let mut stack_frames = Vec::new();
+        for (stack_index, weight) in self.samples.stacks_and_weights() {
+            stack_frames.clear();
+            let mut next = stack_index;
+            while let Some(stack) = next {
+                stack_frames.push(frames[self.stack_table.frame(stack)]);
+                next = self.stack_table.prefix(stack);
+            }
+            stack_frames.reverse();
+            f(&stack_frames, weight);
+        }
+    }
+
+    pub fn is_main(&self) -> bool {
+        self.is_main
+    }
+
+    /// Returns true if this thread has neither samples nor markers.
+    pub fn is_idle(&self) -> bool {
+        self.samples.is_empty() && self.markers.is_empty()
+    }
+
     pub fn contains_js_function(&self) -> bool {
         self.func_table.contains_js_function()
     }
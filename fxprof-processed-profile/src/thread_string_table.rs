@@ -27,6 +27,10 @@ impl ThreadStringTable {
         ThreadInternalStringIndex(self.table.index_for_string(s))
     }
 
+    pub fn get_string(&self, index: ThreadInternalStringIndex) -> Option<&str> {
+        self.table.get_string(index.0)
+    }
+
     pub fn index_for_global_string(
         &mut self,
         global_index: GlobalStringIndex,
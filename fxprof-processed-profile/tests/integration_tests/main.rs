@@ -4,10 +4,11 @@ use std::time::Duration;
 use assert_json_diff::assert_json_eq;
 use debugid::DebugId;
 use fxprof_processed_profile::{
-    CategoryColor, CategoryHandle, CpuDelta, Frame, FrameFlags, FrameInfo, LibraryInfo,
-    MarkerFieldFormat, MarkerFieldSchema, MarkerLocation, MarkerSchema, MarkerStaticField,
-    MarkerTiming, Profile, ReferenceTimestamp, SamplingInterval, StaticSchemaMarker, StringHandle,
-    Symbol, SymbolTable, Timestamp,
+    profile_to_json_string, CategoryColor, CategoryHandle, CpuDelta, Frame, FrameFlags, FrameInfo,
+    LibraryInfo, MarkerFieldFormat, MarkerFieldSchema, MarkerLocation, MarkerSchema,
+    MarkerStaticField, MarkerTiming, Profile, ReferenceTimestamp, ResolvedFrame,
+    ResolvedProfileBuilder, SampleFrame, SamplingInterval, SourceLine, StaticSchemaMarker,
+    StringHandle, Symbol, SymbolTable, Timestamp,
 };
 use serde_json::json;
 
@@ -1586,3 +1587,330 @@ fn profile_counters_with_sorted_processes() {
         )
     )
 }
+
+#[test]
+fn profile_with_meta_info() {
+    let mut profile = Profile::new(
+        "test",
+        ReferenceTimestamp::from_millis_since_unix_epoch(1636162232627.0),
+        SamplingInterval::from_millis(1),
+    );
+    profile.set_meta_info("Command line", "perf record -g ./app");
+    profile.set_meta_info("Host name", "old-host");
+    profile.set_meta_info("Host name", "build-box");
+
+    let json = serde_json::to_value(&profile).unwrap();
+    assert_json_eq!(
+        json["meta"]["extra"],
+        json!([
+          {
+            "label": "Profile Information",
+            "entries": [
+              {
+                "label": "Command line",
+                "format": "string",
+                "value": "perf record -g ./app"
+              },
+              {
+                "label": "Host name",
+                "format": "string",
+                "value": "build-box"
+              }
+            ]
+          }
+        ])
+    );
+}
+
+#[test]
+fn profile_without_idle_threads() {
+    let mut profile = Profile::new(
+        "test",
+        ReferenceTimestamp::from_millis_since_unix_epoch(1636162232627.0),
+        SamplingInterval::from_millis(1),
+    );
+    let process = profile.add_process("test", 123, Timestamp::from_millis_since_reference(0.0));
+    let start = Timestamp::from_millis_since_reference(0.0);
+    let _main_thread = profile.add_thread(process, 123, start, true);
+    let sampled_thread = profile.add_thread(process, 124, start, false);
+    let _idle_thread = profile.add_thread(process, 125, start, false);
+    let marker_thread = profile.add_thread(process, 126, start, false);
+
+    profile.add_sample(
+        sampled_thread,
+        Timestamp::from_millis_since_reference(1.0),
+        vec![].into_iter(),
+        CpuDelta::ZERO,
+        1,
+    );
+    let text_marker = TextMarker {
+        name: profile.intern_string("Hello"),
+        text: profile.intern_string("world"),
+    };
+    profile.add_marker(
+        marker_thread,
+        MarkerTiming::Instant(Timestamp::from_millis_since_reference(2.0)),
+        text_marker,
+    );
+
+    profile.remove_idle_threads();
+
+    let json = serde_json::to_value(&profile).unwrap();
+    let tids: Vec<_> = json["threads"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|thread| thread["tid"].clone())
+        .collect();
+    assert_eq!(tids, vec![json!("123"), json!("124"), json!("126")]);
+}
+
+#[test]
+fn profile_sample_stacks() {
+    let mut profile = Profile::new(
+        "test",
+        ReferenceTimestamp::from_millis_since_unix_epoch(1636162232627.0),
+        SamplingInterval::from_millis(1),
+    );
+    let process = profile.add_process("test", 123, Timestamp::from_millis_since_reference(0.0));
+    let thread = profile.add_thread(
+        process,
+        123,
+        Timestamp::from_millis_since_reference(0.0),
+        true,
+    );
+    let lib = LibraryInfo {
+        name: "libfoo.so".to_string(),
+        debug_name: "libfoo.so".to_string(),
+        path: "/usr/lib/libfoo.so".to_string(),
+        code_id: None,
+        debug_path: "/usr/lib/libfoo.so".to_string(),
+        debug_id: DebugId::nil(),
+        arch: None,
+        symbol_table: Some(Arc::new(SymbolTable::new(vec![Symbol {
+            address: 0x1000,
+            size: Some(0x100),
+            name: "foo_symbol".to_string(),
+        }]))),
+    };
+    let lib_handle = profile.add_lib(lib.clone());
+    profile.add_lib_mapping(process, lib_handle, 0x10000, 0x20000, 0);
+    let main_label = profile.intern_string("main");
+
+    let category = profile.add_category("Regular", CategoryColor::Blue);
+    let frames = [
+        Frame::Label(main_label),
+        Frame::ReturnAddress(0x11010),
+        Frame::InstructionPointer(0x12000),
+    ];
+    profile.add_sample(
+        thread,
+        Timestamp::from_millis_since_reference(1.0),
+        frames.iter().cloned().map(|frame| FrameInfo {
+            frame,
+            category_pair: category.into(),
+            flags: FrameFlags::empty(),
+        }),
+        CpuDelta::ZERO,
+        3,
+    );
+    profile.add_sample(
+        thread,
+        Timestamp::from_millis_since_reference(2.0),
+        [Frame::Label(main_label), Frame::InstructionPointer(0x30000)]
+            .into_iter()
+            .map(|frame| FrameInfo {
+                frame,
+                category_pair: category.into(),
+                flags: FrameFlags::empty(),
+            }),
+        CpuDelta::ZERO,
+        1,
+    );
+
+    let mut stacks = Vec::new();
+    profile.for_each_sample_stack(|frames, weight| stacks.push((frames.to_vec(), weight)));
+    assert_eq!(
+        stacks,
+        vec![
+            (
+                vec![
+                    SampleFrame::Function("main"),
+                    SampleFrame::Function("foo_symbol"),
+                    SampleFrame::AddressInLib(&lib, 0x2000),
+                ],
+                3
+            ),
+            (
+                vec![
+                    SampleFrame::Function("main"),
+                    SampleFrame::UnknownAddress(0x30000)
+                ],
+                1
+            ),
+        ]
+    );
+}
+
+#[test]
+fn profile_with_source_lines() {
+    let mut profile = Profile::new(
+        "test",
+        ReferenceTimestamp::from_millis_since_unix_epoch(1636162232627.0),
+        SamplingInterval::from_millis(1),
+    );
+    let process = profile.add_process("test", 123, Timestamp::from_millis_since_reference(0.0));
+    let thread = profile.add_thread(
+        process,
+        123,
+        Timestamp::from_millis_since_reference(0.0),
+        true,
+    );
+    let source_line = |address, line| SourceLine {
+        address,
+        size: 0x10,
+        file_path: "app.js".to_string(),
+        line,
+    };
+    let symbol_table = SymbolTable::with_source_lines(
+        vec![Symbol {
+            address: 0,
+            size: Some(0x30),
+            name: "render".to_string(),
+        }],
+        vec![source_line(0x10, 7), source_line(0, 3)],
+    );
+    let lib_handle = profile.add_lib(LibraryInfo {
+        name: "jit-123.dump".to_string(),
+        debug_name: "jit-123.dump".to_string(),
+        path: "/tmp/jit-123.dump".to_string(),
+        code_id: None,
+        debug_path: "/tmp/jit-123.dump".to_string(),
+        debug_id: DebugId::nil(),
+        arch: None,
+        symbol_table: Some(Arc::new(symbol_table)),
+    });
+    profile.add_lib_mapping(process, lib_handle, 0x10000, 0x10030, 0);
+
+    let category = profile.add_category("JIT", CategoryColor::Green);
+    for (i, address) in [0x10004, 0x10012, 0x10024].into_iter().enumerate() {
+        profile.add_sample(
+            thread,
+            Timestamp::from_millis_since_reference(i as f64),
+            std::iter::once(FrameInfo {
+                frame: Frame::InstructionPointer(address),
+                category_pair: category.into(),
+                flags: FrameFlags::empty(),
+            }),
+            CpuDelta::ZERO,
+            1,
+        );
+    }
+
+    let json = serde_json::to_value(&profile).unwrap();
+    let thread = &json["threads"][0];
+    let strings = thread["stringArray"].as_array().unwrap();
+    let frame_table = &thread["frameTable"];
+    let func_table = &thread["funcTable"];
+    let frames: Vec<_> = (0..3)
+        .map(|frame| {
+            let func = frame_table["func"][frame].as_u64().unwrap() as usize;
+            let file_name = func_table["fileName"][func]
+                .as_u64()
+                .map(|index| strings[index as usize].clone());
+            (
+                strings[func_table["name"][func].as_u64().unwrap() as usize].clone(),
+                file_name,
+                frame_table["line"][frame].clone(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        frames,
+        [
+            (json!("render"), Some(json!("app.js")), json!(3)),
+            (json!("render"), Some(json!("app.js")), json!(7)),
+            // This address is in the symbol, but not covered by a source line.
+            (json!("render"), None, json!(null)),
+        ]
+    );
+}
+
+#[test]
+fn profile_json_string_matches_serde_serialization() {
+    let mut profile = Profile::new(
+        "test",
+        ReferenceTimestamp::from_millis_since_unix_epoch(1636162232627.0),
+        SamplingInterval::from_millis(1),
+    );
+    let process = profile.add_process("test", 123, Timestamp::from_millis_since_reference(0.0));
+    profile.add_thread(
+        process,
+        123,
+        Timestamp::from_millis_since_reference(0.0),
+        true,
+    );
+
+    let json_string = profile_to_json_string(&profile);
+    let json: serde_json::Value = serde_json::from_str(&json_string).unwrap();
+    assert_eq!(json, serde_json::to_value(&profile).unwrap());
+    assert_eq!(json["meta"]["version"], json!(24));
+    assert_eq!(json["meta"]["preprocessedProfileVersion"], json!(49));
+}
+
+#[test]
+fn profile_from_resolved_stacks() {
+    let mut builder = ResolvedProfileBuilder::new(
+        "my sampler",
+        ReferenceTimestamp::from_millis_since_unix_epoch(0.0),
+        SamplingInterval::from_millis(1),
+    );
+    let start = Timestamp::from_millis_since_reference(0.0);
+    builder.add_process(10, "app", start);
+    let thread = builder.add_thread(10, 10, Some("main"), start, true);
+    let frame = |function, module| ResolvedFrame { function, module };
+    for (i, leaf) in ["parse", "render", "parse"].into_iter().enumerate() {
+        builder.add_sample_with_resolved_stack(
+            thread,
+            Timestamp::from_millis_since_reference(i as f64),
+            &[
+                frame(leaf, Some("libapp.so")),
+                frame("main", Some("app")),
+                frame("_start", None),
+            ],
+            CpuDelta::ZERO,
+            1,
+        );
+    }
+    let profile = builder.finish();
+
+    let mut stacks = Vec::new();
+    profile.for_each_sample_stack(|frames, weight| {
+        let names: Vec<_> = frames
+            .iter()
+            .map(|frame| match frame {
+                SampleFrame::Function(name) => name.to_string(),
+                frame => panic!("unresolved frame {frame:?}"),
+            })
+            .collect();
+        stacks.push((names, weight));
+    });
+    assert_eq!(
+        stacks,
+        [
+            (vec!["_start".into(), "main".into(), "parse".into()], 1),
+            (vec!["_start".into(), "main".into(), "render".into()], 1),
+            (vec!["_start".into(), "main".into(), "parse".into()], 1),
+        ]
+    );
+
+    // Each module is a library of the profile.
+    let json = serde_json::to_value(&profile).unwrap();
+    let lib_names: Vec<_> = json["libs"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|lib| lib["name"].clone())
+        .collect();
+    assert_eq!(lib_names, [json!("app"), json!("libapp.so")]);
+}
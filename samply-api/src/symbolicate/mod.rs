@@ -136,9 +136,14 @@ fn gather_requested_addresses(
     for job in request.jobs() {
         let mut requested_addresses_by_module_index: HashMap<u32, Vec<u32>> = HashMap::new();
         for stack in &job.stacks {
-            for frame in &stack.0 {
+            // Frames which are not in any module don't need to be looked up.
+            for frame in stack
+                .0
+                .iter()
+                .filter(|frame| frame.module_index().is_some())
+            {
                 requested_addresses_by_module_index
-                    .entry(frame.module_index)
+                    .entry(frame.module_index().unwrap())
                     .or_default()
                     .push(frame.address);
             }
@@ -218,8 +223,9 @@ fn create_response(
         memory_map: &[Lib],
         symbols_by_module_index: &HashMap<u32, &AddressResults>,
     ) -> StackFrame {
-        let symbol = symbols_by_module_index
-            .get(&frame.module_index)
+        let symbol = frame
+            .module_index()
+            .and_then(|module_index| symbols_by_module_index.get(&module_index))
             .and_then(|symbol_map| {
                 // If we have a symbol table for this library, then we know that
                 // this address is present in it.
@@ -253,7 +259,9 @@ fn create_response(
         StackFrame {
             frame: frame_index,
             module_offset: frame.address,
-            module: memory_map[frame.module_index as usize].debug_name.clone(),
+            module: frame
+                .module_index()
+                .map(|module_index| memory_map[module_index as usize].debug_name.clone()),
             symbol,
         }
     }
@@ -34,12 +34,19 @@ pub struct Stack(pub Vec<StackFrame>);
 
 #[derive(Deserialize, Debug)]
 pub struct StackFrame {
-    /// index into memory_map
-    pub module_index: u32,
+    /// index into memory_map, or -1 if the address is not in any module
+    pub module_index: i32,
     /// lib-relative memory offset
     pub address: u32,
 }
 
+impl StackFrame {
+    /// The index into memory_map, or `None` if the address is not in any module.
+    pub fn module_index(&self) -> Option<u32> {
+        u32::try_from(self.module_index).ok()
+    }
+}
+
 pub enum JobIterator<'a> {
     WithJobsList(std::slice::Iter<'a, Job>),
     JustOneJob(std::iter::Once<&'a Job>),
@@ -93,6 +100,32 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn parse_frame_without_module() -> Result<()> {
+        let data = r#"
+        {
+            "memoryMap": [
+              [
+                "xul.pdb",
+                "44E4EC8C2F41492B9369D6B9A059577C2"
+              ]
+            ],
+            "stacks": [
+              [
+                [0, 11723767],
+                [-1, 65802]
+              ]
+            ]
+          }
+          "#;
+
+        let r: Request = serde_json::from_str(data)?;
+        let job = r.jobs().next().unwrap();
+        assert_eq!(job.stacks[0].0[0].module_index(), Some(0));
+        assert_eq!(job.stacks[0].0[1].module_index(), None);
+        Ok(())
+    }
+
     #[test]
     fn parse_without_jobs_wrapper() -> Result<()> {
         let data = r#"
@@ -28,7 +28,9 @@ pub struct StackFrame {
     #[serde(serialize_with = "crate::hex::as_hex_string")]
     pub module_offset: u32,
 
-    pub module: String,
+    /// `None` for frames which are not in any module.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub module: Option<String>,
 
     #[serde(flatten)]
     pub symbol: Option<Symbol>,
@@ -114,7 +116,7 @@ mod test {
                     response_json::StackFrame {
                         frame: 0,
                         module_offset: 0xb2e3f7,
-                        module: String::from("xul.pdb"),
+                        module: Some(String::from("xul.pdb")),
                         symbol: Some(response_json::Symbol {
                             function: String::from("sctp_send_initiate"),
                             function_offset: 0x4ca,
@@ -125,7 +127,13 @@ mod test {
                     response_json::StackFrame {
                         frame: 1,
                         module_offset: 0x1010a,
-                        module: String::from("wntdll.pdb"),
+                        module: Some(String::from("wntdll.pdb")),
+                        symbol: None,
+                    },
+                    response_json::StackFrame {
+                        frame: 2,
+                        module_offset: 0x7ffe1234,
+                        module: None,
                         symbol: None,
                     },
                 ])],
@@ -156,6 +164,10 @@ mod test {
             "frame": 1,
             "module_offset": "0x1010a",
             "module": "wntdll.pdb"
+          },
+          {
+            "frame": 2,
+            "module_offset": "0x7ffe1234"
           }
         ]
       ],
@@ -345,6 +345,77 @@ mod test {
         );
     }
 
+    #[test]
+    fn nested_inlines_with_multiple_ranges() {
+        let sym = b"MODULE Linux x86_64 BE4E976C325246EE9D6B7847A670B2A90 example-linux
+FILE 0 main.c
+FILE 1 inl.h
+INLINE_ORIGIN 0 outer_inline
+INLINE_ORIGIN 1 inner_inline
+FUNC 1000 40 0 main
+INLINE 0 10 0 0 1010 10 1030 8
+INLINE 1 20 1 1 1014 4
+1000 10 5 0
+1010 4 21 1
+1014 4 30 1
+1018 8 22 1
+1020 10 6 0
+1030 8 23 1
+1038 8 7 0
+";
+        let fc = FileContentsWrapper::new(&sym[..]);
+        let symbol_map = get_symbol_map_for_breakpad_sym(fc, None).unwrap();
+        let lookup = |address| {
+            let frames = match symbol_map
+                .get_inner_symbol_map()
+                .lookup_sync(LookupAddress::Relative(address))
+                .unwrap()
+                .frames
+            {
+                Some(FramesLookupResult::Available(frames)) => frames,
+                _ => panic!("Frames should be available"),
+            };
+            frames
+                .into_iter()
+                .map(|frame| {
+                    (
+                        frame.function.unwrap(),
+                        frame.file_path.unwrap().raw_path().to_string(),
+                        frame.line_number.unwrap(),
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+        let frame =
+            |function: &str, file: &str, line| (function.to_string(), file.to_string(), line);
+
+        assert_eq!(lookup(0x1004), vec![frame("main", "main.c", 5)]);
+        assert_eq!(
+            lookup(0x1016),
+            vec![
+                frame("inner_inline", "inl.h", 30),
+                frame("outer_inline", "inl.h", 20),
+                frame("main", "main.c", 10),
+            ]
+        );
+        assert_eq!(
+            lookup(0x101a),
+            vec![
+                frame("outer_inline", "inl.h", 22),
+                frame("main", "main.c", 10)
+            ]
+        );
+        assert_eq!(lookup(0x1024), vec![frame("main", "main.c", 6)]);
+        // The second address range of the depth-0 inline call.
+        assert_eq!(
+            lookup(0x1032),
+            vec![
+                frame("outer_inline", "inl.h", 23),
+                frame("main", "main.c", 10)
+            ]
+        );
+    }
+
     #[test]
     fn lookup_with_index() {
         // This test simulates the case where an index is created independently, for
@@ -1,8 +1,10 @@
 use debugid::DebugId;
-use object::{Object, ObjectSection};
+use object::pe::{ImageNtHeaders32, ImageNtHeaders64};
+use object::read::pe::{ImageNtHeaders, ImageOptionalHeader, PeFile};
+use object::{FileKind, Object, ObjectSection};
 use uuid::Uuid;
 
-use crate::shared::{CodeId, ElfBuildId};
+use crate::shared::{CodeId, ElfBuildId, PeCodeId};
 
 pub trait DebugIdExt {
     /// Creates a DebugId from some identifier. The identifier could be
@@ -113,3 +115,29 @@ pub fn code_id_for_object<'data>(obj: &impl Object<'data>) -> Option<CodeId> {
 
     None
 }
+
+/// Reads the code ID from the headers of the PE binary in `data`, which has
+/// the image size. Returns `None` if `data` isn't a well-formed PE binary.
+///
+/// Only the headers and the section table are needed, so `data` can be the
+/// start of the file. `data` may come from an untrusted file, so malformed
+/// headers return `None` rather than panicking.
+pub fn pe_code_id_for_binary(data: &[u8]) -> Option<PeCodeId> {
+    fn inner<T: ImageNtHeaders>(data: &[u8]) -> Option<PeCodeId> {
+        let file = PeFile::<T>::parse(data).ok()?;
+        let header = file.nt_headers();
+        Some(PeCodeId {
+            timestamp: header
+                .file_header()
+                .time_date_stamp
+                .get(object::LittleEndian),
+            image_size: header.optional_header().size_of_image(),
+        })
+    }
+
+    match FileKind::parse(data).ok()? {
+        FileKind::Pe32 => inner::<ImageNtHeaders32>(data),
+        FileKind::Pe64 => inner::<ImageNtHeaders64>(data),
+        _ => None,
+    }
+}
@@ -46,9 +46,89 @@ pub fn demangle_any(name: &str) -> String {
     name.to_owned()
 }
 
+/// Reduces a demangled function name to its base name, by removing template
+/// arguments and the parameter list.
+///
+/// For example, `std::vector<int, std::allocator<int>>::push_back(int const&)`
+/// becomes `std::vector::push_back`. This gives all instantiations of a template
+/// function the same name. Angle brackets which don't follow an identifier, like
+/// in Rust's `<Type as Trait>::method`, are kept, as are operator names like
+/// `operator<<`.
+pub fn base_function_name(demangled: &str) -> String {
+    let mut result = String::with_capacity(demangled.len());
+    // For each currently open '<', whether it (and its contents) is being removed.
+    let mut open_angle_brackets: Vec<bool> = Vec::new();
+    // Whether the last thing we processed was an operator symbol or removed
+    // template arguments, like in `operator<< <char>(...)`. Any following
+    // template arguments or parameter lists belong to the name.
+    let mut after_name_part = false;
+    let mut chars = demangled.char_indices().peekable();
+    while let Some((index, c)) = chars.next() {
+        let removing = open_angle_brackets.contains(&true);
+        if !removing && result.ends_with("operator") {
+            // Copy operator symbols verbatim, e.g. `operator<<` or `operator()`.
+            let rest = &demangled[index..];
+            let operator_len = if rest.starts_with("()") || rest.starts_with("[]") {
+                2
+            } else {
+                rest.find(|c: char| !"<>=-+*/%!&|^~,".contains(c))
+                    .unwrap_or(rest.len())
+            };
+            if operator_len > 0 {
+                result.push_str(&rest[..operator_len]);
+                while chars.peek().is_some_and(|(i, _)| *i < index + operator_len) {
+                    chars.next();
+                }
+                after_name_part = true;
+                continue;
+            }
+        }
+        match c {
+            ' ' if after_name_part && chars.peek().is_some_and(|(_, next)| *next == '<') => {
+                continue;
+            }
+            '<' => {
+                let follows_identifier = after_name_part
+                    || result
+                        .chars()
+                        .next_back()
+                        .is_some_and(|prev| prev.is_alphanumeric() || prev == '_');
+                open_angle_brackets.push(removing || follows_identifier);
+                if !removing && !follows_identifier {
+                    result.push(c);
+                }
+            }
+            '>' => match open_angle_brackets.pop() {
+                Some(false) if !removing => result.push(c),
+                Some(true) if !open_angle_brackets.contains(&true) => {
+                    after_name_part = true;
+                    continue;
+                }
+                _ => {}
+            },
+            '(' if !removing => {
+                let follows_identifier = after_name_part
+                    || result
+                        .chars()
+                        .next_back()
+                        .is_some_and(|prev| prev.is_alphanumeric() || prev == '_');
+                if follows_identifier {
+                    // This is the parameter list.
+                    break;
+                }
+                result.push(c);
+            }
+            _ if !removing => result.push(c),
+            _ => {}
+        }
+        after_name_part = false;
+    }
+    result
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::demangle::demangle_any;
+    use crate::demangle::{base_function_name, demangle_any};
     #[test]
     fn cpp_demangling() {
         assert_eq!(
@@ -92,4 +172,32 @@ mod tests {
     fn no_demangling() {
         assert_eq!(demangle_any("_!!!!!!!bla"), "!!!!!!!bla")
     }
+
+    #[test]
+    fn base_function_names() {
+        assert_eq!(
+            base_function_name("std::vector<int, std::allocator<int>>::push_back(int const&)"),
+            "std::vector::push_back"
+        );
+        assert_eq!(
+            base_function_name(
+                "KxVector<KxfArcFileRecord, unsigned int>::operator[](unsigned int) const"
+            ),
+            "KxVector::operator[]"
+        );
+        assert_eq!(
+            base_function_name(
+                "std::operator<< <std::char_traits<char>>(std::basic_ostream<char>&, char const*)"
+            ),
+            "std::operator<<"
+        );
+        assert_eq!(
+            base_function_name("<std::path::PathBuf>::new"),
+            "<std::path::PathBuf>::new"
+        );
+        assert_eq!(
+            base_function_name("(anonymous namespace)::Foo<int>::bar()"),
+            "(anonymous namespace)::Foo::bar"
+        );
+    }
 }
@@ -0,0 +1,298 @@
+use object::macho::{DyldCacheHeader, Nlist32, Nlist64};
+use object::pod::Pod;
+use object::read::macho::Nlist;
+use object::read::{ReadRef, StringTable};
+use object::{Endian, Endianness, U32, U64};
+
+use crate::error::Error;
+use crate::symbol_map_object::ExtraSymbol;
+
+/// The header offset of `symbolFileUUID`. Caches whose header is at least this
+/// large use the 64-bit variant of the local symbols entries.
+const MIN_HEADER_SIZE_LOCAL_SYMBOLS_ENTRY_64: u32 = 0x190;
+
+/// Corresponds to struct dyld_cache_local_symbols_info from dyld_cache_format.h.
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+struct DyldCacheLocalSymbolsInfo<E: Endian> {
+    /// offset into this chunk of nlist entries
+    nlist_offset: U32<E>,
+    /// count of nlist entries
+    nlist_count: U32<E>,
+    /// offset into this chunk of string pool
+    strings_offset: U32<E>,
+    /// byte count of string pool
+    strings_size: U32<E>,
+    /// offset into this chunk of array of dyld_cache_local_symbols_entry
+    entries_offset: U32<E>,
+    /// number of elements in dyld_cache_local_symbols_entry array
+    entries_count: U32<E>,
+}
+
+/// Corresponds to struct dyld_cache_local_symbols_entry from dyld_cache_format.h.
+/// Used by caches from before macOS 12.
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+struct DyldCacheLocalSymbolsEntry32<E: Endian> {
+    /// file offset of the dylib's mach header
+    dylib_offset: U32<E>,
+    nlist_start_index: U32<E>,
+    nlist_count: U32<E>,
+}
+
+/// Corresponds to struct dyld_cache_local_symbols_entry_64 from dyld_cache_format.h.
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+struct DyldCacheLocalSymbolsEntry64<E: Endian> {
+    /// offset of the dylib's mach header from the cache's base address
+    dylib_offset: U64<E>,
+    nlist_start_index: U32<E>,
+    nlist_count: U32<E>,
+}
+
+unsafe impl<E: Endian> Pod for DyldCacheLocalSymbolsInfo<E> {}
+unsafe impl<E: Endian> Pod for DyldCacheLocalSymbolsEntry32<E> {}
+unsafe impl<E: Endian> Pod for DyldCacheLocalSymbolsEntry64<E> {}
+
+/// Where to find a dylib in the dyld shared cache, for matching it up with
+/// its local symbols entry.
+#[derive(Debug, Clone, Copy)]
+pub struct DyldCacheImageLocation {
+    /// The offset of the dylib's mach header from the cache's base address.
+    pub vm_offset: u64,
+    /// The file offset of the dylib's mach header. Only used for caches which
+    /// consist of a single file.
+    pub file_offset: u64,
+}
+
+/// Reads the local symbols of one dylib from the dyld shared cache.
+///
+/// When dylibs are put into the shared cache, their non-exported symbols are
+/// removed from the dylib's own symbol table and stored in a separate "local
+/// symbols" area. In old caches this area is part of the main cache file; in
+/// split caches (macOS 12+) it lives in the `.symbols` subcache.
+/// `symbols_file_data` needs to be the file which contains it.
+///
+/// Returns the defined, non-debugging symbols, with absolute (unslid) addresses.
+/// Returns an empty list if the cache has no local symbols for this dylib.
+pub fn read_local_symbols<'data, R: ReadRef<'data>>(
+    symbols_file_data: R,
+    image: DyldCacheImageLocation,
+    is_64: bool,
+) -> Result<Vec<ExtraSymbol>, Error> {
+    let header = DyldCacheHeader::<Endianness>::parse(symbols_file_data)
+        .map_err(Error::DyldCacheParseError)?;
+    let (_arch, endian) = header.parse_magic().map_err(Error::DyldCacheParseError)?;
+    let info_offset = header.local_symbols_offset.get(endian);
+    if info_offset == 0 {
+        return Ok(Vec::new());
+    }
+    let info = symbols_file_data
+        .read_at::<DyldCacheLocalSymbolsInfo<Endianness>>(info_offset)
+        .map_err(|_| Error::InvalidInputError("Invalid dyld cache local symbols info"))?;
+    let entries_offset = info_offset + u64::from(info.entries_offset.get(endian));
+    let entries_count = info.entries_count.get(endian) as usize;
+
+    let nlist_range = if header.mapping_offset.get(endian) >= MIN_HEADER_SIZE_LOCAL_SYMBOLS_ENTRY_64
+    {
+        symbols_file_data
+            .read_slice_at::<DyldCacheLocalSymbolsEntry64<Endianness>>(
+                entries_offset,
+                entries_count,
+            )
+            .map_err(|_| Error::InvalidInputError("Invalid dyld cache local symbols entries"))?
+            .iter()
+            .find(|entry| entry.dylib_offset.get(endian) == image.vm_offset)
+            .map(|entry| {
+                (
+                    entry.nlist_start_index.get(endian),
+                    entry.nlist_count.get(endian),
+                )
+            })
+    } else {
+        symbols_file_data
+            .read_slice_at::<DyldCacheLocalSymbolsEntry32<Endianness>>(
+                entries_offset,
+                entries_count,
+            )
+            .map_err(|_| Error::InvalidInputError("Invalid dyld cache local symbols entries"))?
+            .iter()
+            .find(|entry| u64::from(entry.dylib_offset.get(endian)) == image.file_offset)
+            .map(|entry| {
+                (
+                    entry.nlist_start_index.get(endian),
+                    entry.nlist_count.get(endian),
+                )
+            })
+    };
+    let Some((nlist_start_index, nlist_count)) = nlist_range else {
+        return Ok(Vec::new());
+    };
+    if nlist_start_index
+        .checked_add(nlist_count)
+        .map_or(true, |end| end > info.nlist_count.get(endian))
+    {
+        return Err(Error::InvalidInputError(
+            "Invalid dyld cache local symbols entry range",
+        ));
+    }
+
+    let strings_start = info_offset + u64::from(info.strings_offset.get(endian));
+    let strings_end = strings_start + u64::from(info.strings_size.get(endian));
+    let strings = StringTable::new(symbols_file_data, strings_start, strings_end);
+    let nlist_offset = info_offset + u64::from(info.nlist_offset.get(endian));
+    if is_64 {
+        read_nlists::<Nlist64<Endianness>, R>(
+            symbols_file_data,
+            endian,
+            strings,
+            nlist_offset,
+            nlist_start_index,
+            nlist_count,
+        )
+    } else {
+        read_nlists::<Nlist32<Endianness>, R>(
+            symbols_file_data,
+            endian,
+            strings,
+            nlist_offset,
+            nlist_start_index,
+            nlist_count,
+        )
+    }
+}
+
+fn read_nlists<'data, N: Nlist<Endian = Endianness>, R: ReadRef<'data>>(
+    data: R,
+    endian: Endianness,
+    strings: StringTable<'data, R>,
+    nlist_offset: u64,
+    nlist_start_index: u32,
+    nlist_count: u32,
+) -> Result<Vec<ExtraSymbol>, Error> {
+    let nlists = data
+        .read_slice_at::<N>(
+            nlist_offset + u64::from(nlist_start_index) * std::mem::size_of::<N>() as u64,
+            nlist_count as usize,
+        )
+        .map_err(|_| Error::InvalidInputError("Invalid dyld cache local symbols nlist range"))?;
+    let symbols = nlists
+        .iter()
+        .filter(|nlist| nlist.is_definition())
+        .filter_map(|nlist| {
+            let name = nlist.name(endian, strings).ok()?;
+            Some(ExtraSymbol {
+                address: nlist.n_value(endian).into(),
+                size: 0,
+                name: String::from_utf8_lossy(name).into_owned(),
+            })
+        })
+        .collect();
+    Ok(symbols)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn push_u32(buf: &mut Vec<u8>, value: u32) {
+        buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn push_u64(buf: &mut Vec<u8>, value: u64) {
+        buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn push_nlist64(buf: &mut Vec<u8>, strx: u32, n_type: u8, value: u64) {
+        push_u32(buf, strx);
+        buf.push(n_type);
+        buf.push(1); // n_sect
+        buf.extend_from_slice(&0u16.to_le_bytes()); // n_desc
+        push_u64(buf, value);
+    }
+
+    fn macho_n_sect() -> u8 {
+        object::macho::N_SECT | object::macho::N_EXT
+    }
+
+    /// Builds a minimal cache file which contains only a header and the local symbols.
+    fn make_cache(mapping_offset: u32, entries_64: bool) -> Vec<u8> {
+        const INFO_OFFSET: usize = 0x200;
+        let mut buf = vec![0u8; INFO_OFFSET];
+        buf[..16].copy_from_slice(b"dyld_v1   arm64\0");
+        buf[0x10..0x14].copy_from_slice(&mapping_offset.to_le_bytes());
+        buf[0x48..0x50].copy_from_slice(&(INFO_OFFSET as u64).to_le_bytes());
+
+        let strings = b"\0_first_lib_func\0_second_lib_func\0ltmp0\0";
+        let nlist_offset = 24u32;
+        let strings_offset = nlist_offset + 4 * 16;
+        let entries_offset = strings_offset + strings.len() as u32;
+        push_u32(&mut buf, nlist_offset);
+        push_u32(&mut buf, 4);
+        push_u32(&mut buf, strings_offset);
+        push_u32(&mut buf, strings.len() as u32);
+        push_u32(&mut buf, entries_offset);
+        push_u32(&mut buf, 2);
+
+        // Symbols for the first dylib.
+        push_nlist64(&mut buf, 1, macho_n_sect(), 0x1_8000_1000);
+        // Symbols for the second dylib: a stab, a function and an undefined symbol.
+        push_nlist64(&mut buf, 34, object::macho::N_FUN, 0x1_8000_2000);
+        push_nlist64(&mut buf, 17, macho_n_sect(), 0x1_8000_2010);
+        push_nlist64(&mut buf, 34, 0, 0);
+        buf.extend_from_slice(strings);
+
+        if entries_64 {
+            push_u64(&mut buf, 0x1000);
+            push_u32(&mut buf, 0);
+            push_u32(&mut buf, 1);
+            push_u64(&mut buf, 0x2000);
+            push_u32(&mut buf, 1);
+            push_u32(&mut buf, 3);
+        } else {
+            push_u32(&mut buf, 0x1000);
+            push_u32(&mut buf, 0);
+            push_u32(&mut buf, 1);
+            push_u32(&mut buf, 0x2000);
+            push_u32(&mut buf, 1);
+            push_u32(&mut buf, 3);
+        }
+        buf
+    }
+
+    fn names(symbols: &[ExtraSymbol]) -> Vec<(u64, &str)> {
+        symbols
+            .iter()
+            .map(|symbol| (symbol.address, symbol.name.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn split_cache_entries() {
+        let data = make_cache(0x1d0, true);
+        let image = DyldCacheImageLocation {
+            vm_offset: 0x2000,
+            file_offset: 0,
+        };
+        let symbols = read_local_symbols(&data[..], image, true).unwrap();
+        assert_eq!(names(&symbols), vec![(0x1_8000_2010, "_second_lib_func")]);
+
+        let image = DyldCacheImageLocation {
+            vm_offset: 0x3000,
+            file_offset: 0x2000,
+        };
+        let symbols = read_local_symbols(&data[..], image, true).unwrap();
+        assert!(symbols.is_empty());
+    }
+
+    #[test]
+    fn legacy_cache_entries() {
+        let data = make_cache(0x140, false);
+        let image = DyldCacheImageLocation {
+            vm_offset: 0,
+            file_offset: 0x1000,
+        };
+        let symbols = read_local_symbols(&data[..], image, true).unwrap();
+        assert_eq!(names(&symbols), vec![(0x1_8000_1000, "_first_lib_func")]);
+    }
+}
@@ -4,7 +4,9 @@ use std::sync::Arc;
 use debugid::DebugId;
 use elsa::sync::FrozenVec;
 use gimli::{CieOrFde, Dwarf, EhFrame, EndianSlice, RunTimeEndian, UnwindSection};
-use object::{File, FileKind, Object, ObjectSection, ReadRef};
+use object::{
+    File, FileKind, Object, ObjectSection, ObjectSymbol, ReadRef, SectionKind, SymbolKind,
+};
 use yoke::Yoke;
 use yoke_derive::Yokeable;
 
@@ -13,7 +15,7 @@ use crate::error::Error;
 use crate::shared::{FileAndPathHelper, FileContents, FileContentsWrapper, FileLocation};
 use crate::symbol_map::SymbolMap;
 use crate::symbol_map_object::{
-    DwoDwarfMaker, ObjectSymbolMap, ObjectSymbolMapInnerWrapper, ObjectSymbolMapOuter,
+    DwoDwarfMaker, ExtraSymbol, ObjectSymbolMap, ObjectSymbolMapInnerWrapper, ObjectSymbolMapOuter,
 };
 use crate::{debug_id_for_object, ElfBuildId};
 
@@ -51,6 +53,7 @@ pub async fn load_symbol_map_for_elf<H: FileAndPathHelper>(
             dwp_file_contents,
             file_kind,
             None,
+            Vec::new(),
         )?;
         let symbol_map = ObjectSymbolMap::new(owner)?;
         return Ok(SymbolMap::new_plain(file_location, Box::new(symbol_map)));
@@ -63,8 +66,14 @@ pub async fn load_symbol_map_for_elf<H: FileAndPathHelper>(
         return Ok(symbol_map);
     }
 
-    let owner =
-        ElfSymbolMapDataAndObjects::new(file_contents, None, dwp_file_contents, file_kind, None)?;
+    let owner = ElfSymbolMapDataAndObjects::new(
+        file_contents,
+        None,
+        dwp_file_contents,
+        file_kind,
+        None,
+        Vec::new(),
+    )?;
     let symbol_map = ObjectSymbolMap::new(owner)?;
     Ok(SymbolMap::new_with_external_file_support(
         file_location,
@@ -73,6 +82,10 @@ pub async fn load_symbol_map_for_elf<H: FileAndPathHelper>(
     ))
 }
 
+/// How many `.gnu_debuglink` hops we follow, e.g. for distros which package a
+/// stripped `.debug` file that links to another file with the DWARF data.
+const MAX_DEBUG_LINK_DEPTH: usize = 4;
+
 async fn try_to_get_symbol_map_from_debug_link<'data, H, R>(
     original_file_location: &H::FL,
     elf_file: &File<'data, R>,
@@ -86,50 +99,84 @@ where
     let (name, crc) = elf_file.gnu_debuglink().ok().flatten()?;
     let debug_id = debug_id_for_object(elf_file)?;
     let name = std::str::from_utf8(name).ok()?;
-    let candidate_paths = helper
-        .get_candidate_paths_for_gnu_debug_link_dest(original_file_location, name)
-        .ok()?;
-
-    for candidate_path in candidate_paths {
-        let symbol_map = get_symbol_map_for_debug_link_candidate(
-            original_file_location,
-            &candidate_path,
-            debug_id,
-            crc,
-            file_kind,
-            helper,
-        )
-        .await;
-        if let Ok(symbol_map) = symbol_map {
-            return Some(symbol_map);
+    let extra_symbols = function_symbols_of_stripped_binary(elf_file);
+
+    // Follow the chains of debug links, depth first, until we find a file with
+    // DWARF data. Every candidate with a matching CRC is tried, so that a bad
+    // candidate doesn't hide a good one further down the list. The locations
+    // we've visited are tracked to guard against cycles.
+    let mut visited = vec![original_file_location.to_string()];
+    let mut pending = Vec::new();
+    push_debug_link_candidates(&mut pending, original_file_location, name, crc, 0, helper);
+    // The first destination without DWARF data, in case no chain leads to any.
+    let mut fallback = None;
+    while let Some((path, expected_crc, depth)) = pending.pop() {
+        let candidate_key = path.to_string();
+        if visited.contains(&candidate_key) {
+            continue;
+        }
+        let Some(file_contents) = load_debug_link_candidate(&path, expected_crc, helper).await
+        else {
+            continue;
+        };
+        visited.push(candidate_key);
+        match next_debug_link_if_no_dwarf(&file_contents) {
+            Some((next_name, next_crc)) if depth + 1 < MAX_DEBUG_LINK_DEPTH => {
+                push_debug_link_candidates(
+                    &mut pending,
+                    &path,
+                    &next_name,
+                    next_crc,
+                    depth + 1,
+                    helper,
+                );
+                if fallback.is_none() {
+                    fallback = Some((path, file_contents));
+                }
+            }
+            _ => {
+                let symbol_map = get_symbol_map_for_debug_link_dest(
+                    original_file_location,
+                    &path,
+                    file_contents,
+                    debug_id,
+                    file_kind,
+                    &extra_symbols,
+                    helper,
+                )
+                .await;
+                if symbol_map.is_some() {
+                    return symbol_map;
+                }
+            }
         }
     }
 
-    None
+    let (path, file_contents) = fallback?;
+    get_symbol_map_for_debug_link_dest(
+        original_file_location,
+        &path,
+        file_contents,
+        debug_id,
+        file_kind,
+        &extra_symbols,
+        helper,
+    )
+    .await
 }
 
-async fn get_symbol_map_for_debug_link_candidate<H>(
+async fn get_symbol_map_for_debug_link_dest<H>(
     original_file_location: &H::FL,
     path: &H::FL,
+    file_contents: FileContentsWrapper<H::F>,
     debug_id: DebugId,
-    expected_crc: u32,
     file_kind: FileKind,
+    extra_symbols: &[ExtraSymbol],
     helper: &H,
-) -> Result<SymbolMap<H>, Error>
+) -> Option<SymbolMap<H>>
 where
     H: FileAndPathHelper,
 {
-    let file_contents = helper
-        .load_file(path.clone())
-        .await
-        .map_err(|e| Error::HelperErrorDuringOpenFile(path.to_string(), e))?;
-    let file_contents = FileContentsWrapper::new(file_contents);
-    let actual_crc = compute_debug_link_crc_of_file_contents(&file_contents)?;
-
-    if actual_crc != expected_crc {
-        return Err(Error::DebugLinkCrcMismatch(actual_crc, expected_crc));
-    }
-
     let dwp_file_contents = if let Some(dwp_file_location) = path.location_for_dwp() {
         helper
             .load_file(dwp_file_location)
@@ -145,14 +192,71 @@ where
         dwp_file_contents,
         file_kind,
         Some(debug_id),
-    )?;
-    let symbol_map = ObjectSymbolMap::new(owner)?;
-    Ok(SymbolMap::new_plain(
+        extra_symbols.to_vec(),
+    )
+    .ok()?;
+    let symbol_map = ObjectSymbolMap::new(owner).ok()?;
+    Some(SymbolMap::new_plain(
         original_file_location.clone(),
         Box::new(symbol_map),
     ))
 }
 
+/// Adds the candidate paths for the debug link `name` in the file at
+/// `location` to `pending`, so that the first candidate is popped first.
+fn push_debug_link_candidates<H>(
+    pending: &mut Vec<(H::FL, u32, usize)>,
+    location: &H::FL,
+    name: &str,
+    expected_crc: u32,
+    depth: usize,
+    helper: &H,
+) where
+    H: FileAndPathHelper,
+{
+    if let Ok(candidate_paths) = helper.get_candidate_paths_for_gnu_debug_link_dest(location, name)
+    {
+        pending.extend(
+            candidate_paths
+                .into_iter()
+                .rev()
+                .map(|path| (path, expected_crc, depth)),
+        );
+    }
+}
+
+/// Loads the debug link candidate at `path` if its CRC matches `expected_crc`.
+async fn load_debug_link_candidate<H>(
+    path: &H::FL,
+    expected_crc: u32,
+    helper: &H,
+) -> Option<FileContentsWrapper<H::F>>
+where
+    H: FileAndPathHelper,
+{
+    let file_contents = helper.load_file(path.clone()).await.ok()?;
+    let file_contents = FileContentsWrapper::new(file_contents);
+    let actual_crc = compute_debug_link_crc_of_file_contents(&file_contents).ok()?;
+    (actual_crc == expected_crc).then_some(file_contents)
+}
+
+/// Returns the debug link of a debug link destination which has no DWARF data
+/// itself, so that we can check the next file in the chain.
+fn next_debug_link_if_no_dwarf<T: FileContents>(
+    file_contents: &FileContentsWrapper<T>,
+) -> Option<(String, u32)> {
+    let elf_file = File::parse(file_contents).ok()?;
+    let has_dwarf = elf_file
+        .section_by_name(".debug_info")
+        .and_then(|section| section.file_range())
+        .is_some_and(|(_offset, size)| size != 0);
+    if has_dwarf {
+        return None;
+    }
+    let (name, crc) = elf_file.gnu_debuglink().ok().flatten()?;
+    Some((std::str::from_utf8(name).ok()?.to_owned(), crc))
+}
+
 // https://www-zeuthen.desy.de/unix/unixguide/infohtml/gdb/Separate-Debug-Files.html
 struct GnuDebugLinkCrc32Computer(pub u32);
 
@@ -301,7 +405,19 @@ fn try_get_symbol_map_from_mini_debug_info<'data, R: ReadRef<'data>, H: FileAndP
     let mut objdata = Vec::new();
     lzma_rs::xz_decompress(&mut cursor, &mut objdata).ok()?;
     let file_contents = FileContentsWrapper::new(objdata);
-    let owner = ElfSymbolMapDataAndObjects::new(file_contents, None, None, file_kind, None).ok()?;
+    let extra_symbols = function_symbols_of_stripped_binary(elf_file);
+    // The embedded object doesn't necessarily keep the build ID note, and it
+    // has no .text contents to hash, so use the debug ID of the stripped binary.
+    let debug_id = debug_id_for_object(elf_file);
+    let owner = ElfSymbolMapDataAndObjects::new(
+        file_contents,
+        None,
+        None,
+        file_kind,
+        debug_id,
+        extra_symbols,
+    )
+    .ok()?;
     let symbol_map = ObjectSymbolMap::new(owner).ok()?;
     Some(SymbolMap::new_plain(
         debug_file_location.clone(),
@@ -309,6 +425,32 @@ fn try_get_symbol_map_from_mini_debug_info<'data, R: ReadRef<'data>, H: FileAndP
     ))
 }
 
+/// Collects the function symbols of a stripped binary, i.e. usually the
+/// symbols from its `.dynsym` table, so that they can be merged with the
+/// symbols from a separate debug file.
+fn function_symbols_of_stripped_binary<'data, R: ReadRef<'data>>(
+    elf_file: &File<'data, R>,
+) -> Vec<ExtraSymbol> {
+    elf_file
+        .symbols()
+        .chain(elf_file.dynamic_symbols())
+        .filter(|symbol| symbol.kind() == SymbolKind::Text && symbol.address() != 0)
+        .filter(|symbol| {
+            let section = symbol
+                .section_index()
+                .and_then(|index| elf_file.section_by_index(index).ok());
+            section.map(|section| section.kind()) == Some(SectionKind::Text)
+        })
+        .filter_map(|symbol| {
+            Some(ExtraSymbol {
+                address: symbol.address(),
+                size: symbol.size(),
+                name: symbol.name().ok()?.to_owned(),
+            })
+        })
+        .collect()
+}
+
 struct ElfSymbolMapData<T>
 where
     T: FileContents,
@@ -332,6 +474,7 @@ struct ElfObjects<'data, T: FileContents> {
     dwp_file_data: Option<&'data FileContentsWrapper<T>>,
     dwo_file_data: &'data FrozenVec<Box<FileContentsWrapper<T>>>,
     override_debug_id: Option<DebugId>,
+    extra_symbols: Vec<ExtraSymbol>,
     addr2line_context_data: Addr2lineContextData,
     object: File<'data, &'data FileContentsWrapper<T>>,
     supplementary_object: Option<File<'data, &'data FileContentsWrapper<T>>>,
@@ -415,6 +558,7 @@ impl<'data, T: FileContents + 'static> ElfObjectsTrait<T> for ElfObjects<'data,
             debug_id,
             function_starts.as_deref(),
             function_ends.as_deref(),
+            &self.extra_symbols,
             self,
         );
 
@@ -433,6 +577,7 @@ impl<T: FileContents + 'static> ElfSymbolMapDataAndObjects<T> {
         dwp_file_data: Option<FileContentsWrapper<T>>,
         file_kind: FileKind,
         override_debug_id: Option<DebugId>,
+        extra_symbols: Vec<ExtraSymbol>,
     ) -> Result<Self, Error> {
         let data = ElfSymbolMapData {
             file_data,
@@ -468,6 +613,7 @@ impl<T: FileContents + 'static> ElfSymbolMapDataAndObjects<T> {
                     supplementary_file_data: data.supplementary_file_data.as_ref(),
                     dwp_file_data: data.dwp_file_data.as_ref(),
                     override_debug_id,
+                    extra_symbols,
                     addr2line_context_data: Addr2lineContextData::new(),
                 };
                 Ok(ElfObjectsWrapper(Box::new(elf_objects)))
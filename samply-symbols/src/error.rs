@@ -22,6 +22,9 @@ pub enum Error {
     #[error("Unmatched CodeId: Expected {0}, but received {}", .1.as_ref().map_or("<none>".into(), ToString::to_string))]
     UnmatchedCodeId(CodeId, Option<CodeId>),
 
+    #[error("Unmatched arch: Expected {0}, but the dyld shared cache image is {1}")]
+    UnmatchedArch(String, String),
+
     #[error("The Breakpad sym file was malformed, causing a parsing error: {0}")]
     BreakpadParsing(#[from] BreakpadParseError),
 
@@ -243,6 +246,7 @@ impl Error {
             Error::UnmatchedDebugIdOptional(_, _) => "UnmatchedDebugIdOptional",
             Error::DebugLinkCrcMismatch(_, _) => "DebugLinkCrcMismatch",
             Error::UnmatchedCodeId(_, _) => "UnmatchedCodeId",
+            Error::UnmatchedArch(_, _) => "UnmatchedArch",
             Error::InvalidBreakpadId(_) => "InvalidBreakpadId",
             Error::EmptyFatArchive => "EmptyFatArchive",
             Error::CouldNotDetermineExternalFileFileKind => "CouldNotDetermineExternalFileFileKind",
@@ -0,0 +1,529 @@
+//! Parsing of the Go runtime's function table (`.gopclntab`).
+//!
+//! Go binaries carry their own function and line table, which the Go runtime
+//! needs for stack traces. It is present even in binaries which were built
+//! without DWARF (`-ldflags=-w`) and usually also in stripped binaries, so we
+//! can use it to get function names and file / line information.
+//!
+//! The layout of the table changed a few times. This follows the implementation
+//! in Go's `debug/gosym` package and supports the formats used by Go 1.2 - 1.15,
+//! Go 1.16 - 1.17, Go 1.18 - 1.19, and Go 1.20 and later.
+
+use object::{Object, ObjectSection};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Version {
+    Go12,
+    Go116,
+    Go118,
+    Go120,
+}
+
+impl Version {
+    fn from_magic(magic: u32) -> Option<Self> {
+        match magic {
+            0xfffffffb => Some(Version::Go12),
+            0xfffffffa => Some(Version::Go116),
+            0xfffffff0 => Some(Version::Go118),
+            0xfffffff1 => Some(Version::Go120),
+            _ => None,
+        }
+    }
+}
+
+/// The Go function and line table of a binary.
+pub struct GoPclnTab<'data> {
+    data: &'data [u8],
+    version: Version,
+    big_endian: bool,
+    quantum: u32,
+    ptr_size: usize,
+    /// The address of the text section. Go 1.18+ stores function addresses
+    /// as offsets from this address.
+    text_start: u64,
+    function_count: usize,
+    functab: &'data [u8],
+    funcdata: &'data [u8],
+    funcnametab: &'data [u8],
+    cutab: &'data [u8],
+    filetab: &'data [u8],
+    pctab: &'data [u8],
+}
+
+/// The information we have about an address in a Go function.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GoFunctionLocation<'data> {
+    pub name: &'data str,
+    pub file: Option<&'data str>,
+    pub line: Option<u32>,
+}
+
+impl<'data> GoPclnTab<'data> {
+    /// Finds and parses the `.gopclntab` section of an ELF or Mach-O binary.
+    pub fn from_object<O: Object<'data>>(object_file: &O) -> Option<Self> {
+        let section = object_file
+            .section_by_name(".gopclntab")
+            .or_else(|| object_file.section_by_name("__gopclntab"))?;
+        let text_section = object_file
+            .section_by_name(".text")
+            .or_else(|| object_file.section_by_name("__text"))?;
+        Self::parse(section.data().ok()?, text_section.address())
+    }
+
+    /// Parses the table in `data`. `text_start` is the address of the text
+    /// section, it is used for the function addresses in Go 1.18+ binaries.
+    pub fn parse(data: &'data [u8], text_start: u64) -> Option<Self> {
+        let header = data.get(..8)?;
+        let le_magic = u32::from_le_bytes(header[..4].try_into().unwrap());
+        let be_magic = u32::from_be_bytes(header[..4].try_into().unwrap());
+        let (version, big_endian) = match Version::from_magic(le_magic) {
+            Some(version) => (version, false),
+            None => (Version::from_magic(be_magic)?, true),
+        };
+        if header[4] != 0 || header[5] != 0 {
+            return None;
+        }
+        let quantum = u32::from(header[6]);
+        let ptr_size = usize::from(header[7]);
+        if ptr_size != 4 && ptr_size != 8 {
+            return None;
+        }
+
+        let mut table = GoPclnTab {
+            data,
+            version,
+            big_endian,
+            quantum,
+            ptr_size,
+            text_start,
+            function_count: 0,
+            functab: &[],
+            funcdata: &[],
+            funcnametab: &[],
+            cutab: &[],
+            filetab: &[],
+            pctab: &[],
+        };
+
+        // The header is followed by a list of pointer-sized words. In Go 1.16+,
+        // they contain the function count, the file count, and the offsets of
+        // the sub-tables.
+        let word = |index: usize| read_uint(data, 8 + index * ptr_size, ptr_size, big_endian);
+        let sub_table = |index: usize| -> Option<&'data [u8]> {
+            data.get(usize::try_from(word(index)?).ok()?..)
+        };
+        match version {
+            Version::Go118 | Version::Go120 => {
+                table.function_count = usize::try_from(word(0)?).ok()?;
+                // word(2) is the text start address, but it may not have been
+                // relocated, so we use the address of the text section instead.
+                table.funcnametab = sub_table(3)?;
+                table.cutab = sub_table(4)?;
+                table.filetab = sub_table(5)?;
+                table.pctab = sub_table(6)?;
+                table.funcdata = sub_table(7)?;
+                table.functab = sub_table(7)?;
+            }
+            Version::Go116 => {
+                table.function_count = usize::try_from(word(0)?).ok()?;
+                table.funcnametab = sub_table(2)?;
+                table.cutab = sub_table(3)?;
+                table.filetab = sub_table(4)?;
+                table.pctab = sub_table(5)?;
+                table.funcdata = sub_table(6)?;
+                table.functab = sub_table(6)?;
+            }
+            Version::Go12 => {
+                table.function_count = usize::try_from(word(0)?).ok()?;
+                table.funcdata = data;
+                table.funcnametab = data;
+                table.pctab = data;
+                table.functab = data.get(8 + ptr_size..)?;
+            }
+        }
+        let functab_size = table
+            .function_count
+            .checked_mul(2)?
+            .checked_add(1)?
+            .checked_mul(table.functab_field_size())?;
+        if version == Version::Go12 {
+            // In Go 1.2, the function table is followed by the offset of the file table.
+            let filetab_offset = table.read_u32(table.functab, functab_size)?;
+            table.filetab = data.get(filetab_offset as usize..)?;
+        }
+        table.functab = table.functab.get(..functab_size)?;
+        Some(table)
+    }
+
+    /// Iterates over the start addresses and names of all functions.
+    pub fn functions(&self) -> impl Iterator<Item = (u64, &'data str)> + '_ {
+        (0..self.function_count).filter_map(|index| {
+            let entry = self.function_entry(index)?;
+            let func = self.func_data(index)?;
+            Some((entry, self.function_name(func)?))
+        })
+    }
+
+    /// Looks up the function, file and line for the address `pc`. Returns the
+    /// start address of the function together with the location.
+    pub fn lookup(&self, pc: u64) -> Option<(u64, GoFunctionLocation<'data>)> {
+        let index = self.find_function_index(pc)?;
+        let entry = self.function_entry(index)?;
+        let func = self.func_data(index)?;
+        let name = self.function_name(func)?;
+        let line = self
+            .pc_value(self.func_field(func, 6)?, entry, pc)
+            .and_then(|line| u32::try_from(line).ok());
+        let file = self
+            .pc_value(self.func_field(func, 5)?, entry, pc)
+            .and_then(|file_index| self.file_name(func, file_index));
+        Some((entry, GoFunctionLocation { name, file, line }))
+    }
+
+    fn functab_field_size(&self) -> usize {
+        if self.version >= Version::Go118 {
+            4
+        } else {
+            self.ptr_size
+        }
+    }
+
+    /// The start address of the function with the given index. For index
+    /// `function_count`, this is the end address of the last function.
+    fn function_entry(&self, index: usize) -> Option<u64> {
+        let size = self.functab_field_size();
+        let value = self.read_uint(self.functab, 2 * index * size, size)?;
+        if self.version >= Version::Go118 {
+            self.text_start.checked_add(value)
+        } else {
+            Some(value)
+        }
+    }
+
+    fn find_function_index(&self, pc: u64) -> Option<usize> {
+        let count = self.function_count;
+        if count == 0 || pc < self.function_entry(0)? || pc >= self.function_entry(count)? {
+            return None;
+        }
+        // Find the last function whose entry is <= pc.
+        let (mut low, mut high) = (0, count);
+        while high - low > 1 {
+            let mid = low + (high - low) / 2;
+            if self.function_entry(mid)? <= pc {
+                low = mid;
+            } else {
+                high = mid;
+            }
+        }
+        Some(low)
+    }
+
+    /// Returns the `_func` struct of the function with the given index.
+    fn func_data(&self, index: usize) -> Option<&'data [u8]> {
+        let size = self.functab_field_size();
+        let offset = self.read_uint(self.functab, (2 * index + 1) * size, size)?;
+        self.funcdata.get(usize::try_from(offset).ok()?..)
+    }
+
+    /// Reads field `n` of a `_func` struct. Field 0 is the entry address, and
+    /// the subsequent fields are 4 bytes each: 1 is the name offset, 5 the
+    /// pcfile table, 6 the pcln table, and 8 the compilation unit offset.
+    fn func_field(&self, func: &[u8], n: usize) -> Option<u32> {
+        let first_field_size = if self.version >= Version::Go118 {
+            4
+        } else {
+            self.ptr_size
+        };
+        self.read_u32(func, first_field_size + (n - 1) * 4)
+    }
+
+    fn function_name(&self, func: &[u8]) -> Option<&'data str> {
+        let name_offset = self.func_field(func, 1)?;
+        read_c_string(self.funcnametab, name_offset as usize)
+    }
+
+    fn file_name(&self, func: &[u8], file_index: i32) -> Option<&'data str> {
+        if self.version == Version::Go12 {
+            if file_index <= 0 {
+                return None;
+            }
+            let name_offset = self.read_u32(self.filetab, 4 * file_index as usize)?;
+            return read_c_string(self.data, name_offset as usize);
+        }
+        let cu_offset = self.func_field(func, 8)?;
+        let file_index = u32::try_from(file_index).ok()?;
+        let cutab_index = cu_offset.checked_add(file_index)? as usize;
+        let name_offset = self.read_u32(self.cutab, cutab_index * 4)?;
+        if name_offset == u32::MAX {
+            return None;
+        }
+        read_c_string(self.filetab, name_offset as usize)
+    }
+
+    /// Evaluates the pc-value table at `offset` for the address `target_pc`
+    /// in the function which starts at `entry`.
+    fn pc_value(&self, offset: u32, entry: u64, target_pc: u64) -> Option<i32> {
+        let mut data = self.pctab.get(offset as usize..)?;
+        let mut value: i32 = -1;
+        let mut pc = entry;
+        let mut first = true;
+        loop {
+            let encoded_delta = read_varint(&mut data)?;
+            if encoded_delta == 0 && !first {
+                return None;
+            }
+            first = false;
+            // The value delta is zig-zag encoded.
+            let value_delta = if encoded_delta & 1 != 0 {
+                !(encoded_delta >> 1)
+            } else {
+                encoded_delta >> 1
+            };
+            let pc_delta = read_varint(&mut data)?.checked_mul(self.quantum)?;
+            pc = pc.checked_add(u64::from(pc_delta))?;
+            value = value.wrapping_add(value_delta as i32);
+            if target_pc < pc {
+                return (value >= 0).then_some(value);
+            }
+        }
+    }
+
+    fn read_u32(&self, data: &[u8], offset: usize) -> Option<u32> {
+        read_u32(data, offset, self.big_endian)
+    }
+
+    fn read_uint(&self, data: &[u8], offset: usize, size: usize) -> Option<u64> {
+        read_uint(data, offset, size, self.big_endian)
+    }
+}
+
+fn read_u32(data: &[u8], offset: usize, big_endian: bool) -> Option<u32> {
+    let bytes: [u8; 4] = data.get(offset..offset.checked_add(4)?)?.try_into().ok()?;
+    Some(if big_endian {
+        u32::from_be_bytes(bytes)
+    } else {
+        u32::from_le_bytes(bytes)
+    })
+}
+
+/// Reads a 4 or 8 byte unsigned integer.
+fn read_uint(data: &[u8], offset: usize, size: usize, big_endian: bool) -> Option<u64> {
+    if size == 4 {
+        return read_u32(data, offset, big_endian).map(u64::from);
+    }
+    let bytes: [u8; 8] = data.get(offset..offset.checked_add(8)?)?.try_into().ok()?;
+    Some(if big_endian {
+        u64::from_be_bytes(bytes)
+    } else {
+        u64::from_le_bytes(bytes)
+    })
+}
+
+fn read_c_string(data: &[u8], offset: usize) -> Option<&str> {
+    let bytes = data.get(offset..)?;
+    let len = bytes.iter().position(|b| *b == 0)?;
+    std::str::from_utf8(&bytes[..len]).ok()
+}
+
+fn read_varint(data: &mut &[u8]) -> Option<u32> {
+    let mut value: u32 = 0;
+    let mut shift = 0;
+    loop {
+        let (&byte, rest) = data.split_first()?;
+        *data = rest;
+        value |= u32::from(byte & 0x7f).checked_shl(shift)?;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+        shift += 7;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn push_varint(buf: &mut Vec<u8>, mut value: u32) {
+        loop {
+            let byte = (value & 0x7f) as u8;
+            value >>= 7;
+            if value == 0 {
+                buf.push(byte);
+                return;
+            }
+            buf.push(byte | 0x80);
+        }
+    }
+
+    /// Appends a pc-value table with (value, pc_delta) steps.
+    fn push_pc_value_table(buf: &mut Vec<u8>, steps: &[(i32, u32)]) {
+        let mut prev = -1;
+        for &(value, pc_delta) in steps {
+            let delta = value - prev;
+            let encoded = if delta < 0 {
+                (!(delta as u32) << 1) | 1
+            } else {
+                (delta as u32) << 1
+            };
+            push_varint(buf, encoded);
+            push_varint(buf, pc_delta);
+            prev = value;
+        }
+        buf.push(0);
+    }
+
+    /// Builds a Go 1.20 table with two functions at text offsets 0x0 and 0x40.
+    fn make_go120_table() -> Vec<u8> {
+        let funcnametab = b"main.main\0main.helper\0".to_vec();
+        let filetab = b"/src/main.go\0/src/helper.go\0".to_vec();
+        let cutab: Vec<u8> = [0u32, 13].iter().flat_map(|v| v.to_le_bytes()).collect();
+
+        let mut pctab = vec![0];
+        let main_pcfile = pctab.len() as u32;
+        push_pc_value_table(&mut pctab, &[(0, 0x40)]);
+        let main_pcln = pctab.len() as u32;
+        push_pc_value_table(&mut pctab, &[(10, 0x10), (12, 0x30)]);
+        let helper_pcfile = pctab.len() as u32;
+        push_pc_value_table(&mut pctab, &[(1, 0x20)]);
+        let helper_pcln = pctab.len() as u32;
+        push_pc_value_table(&mut pctab, &[(3, 0x20)]);
+
+        let func = |entry_off: u32, name_off: u32, pcfile: u32, pcln: u32| -> Vec<u8> {
+            // entryoff, nameoff, args, deferreturn, pcsp, pcfile, pcln, npcdata, cuOffset
+            [entry_off, name_off, 0, 0, 0, pcfile, pcln, 0, 0]
+                .iter()
+                .flat_map(|v| v.to_le_bytes())
+                .collect()
+        };
+        // functab: (entry, funcoff) * 2, end
+        let functab_len = 5 * 4;
+        let main_func = func(0, 0, main_pcfile, main_pcln);
+        let helper_func = func(0x40, 10, helper_pcfile, helper_pcln);
+        let mut functab = vec![];
+        for v in [
+            0u32,
+            functab_len,
+            0x40,
+            functab_len + main_func.len() as u32,
+            0x60,
+        ] {
+            functab.extend_from_slice(&v.to_le_bytes());
+        }
+
+        let header_len = 8 + 8 * 8;
+        let mut offsets = vec![];
+        let mut data = vec![];
+        for sub_table in [&funcnametab, &cutab, &filetab, &pctab] {
+            offsets.push((header_len + data.len()) as u64);
+            data.extend_from_slice(sub_table);
+        }
+        offsets.push((header_len + data.len()) as u64);
+        data.extend_from_slice(&functab);
+        data.extend_from_slice(&main_func);
+        data.extend_from_slice(&helper_func);
+
+        let mut table = vec![];
+        table.extend_from_slice(&0xfffffff1u32.to_le_bytes());
+        table.extend_from_slice(&[0, 0, 1, 8]);
+        for word in [2u64, 2, 0x1000] {
+            table.extend_from_slice(&word.to_le_bytes());
+        }
+        for offset in offsets {
+            table.extend_from_slice(&offset.to_le_bytes());
+        }
+        table.extend_from_slice(&data);
+        table
+    }
+
+    #[test]
+    fn go120_lookup() {
+        let data = make_go120_table();
+        let table = GoPclnTab::parse(&data, 0x1000).unwrap();
+        assert_eq!(
+            table.functions().collect::<Vec<_>>(),
+            vec![(0x1000, "main.main"), (0x1040, "main.helper")]
+        );
+        assert_eq!(
+            table.lookup(0x1008),
+            Some((
+                0x1000,
+                GoFunctionLocation {
+                    name: "main.main",
+                    file: Some("/src/main.go"),
+                    line: Some(10),
+                }
+            ))
+        );
+        assert_eq!(table.lookup(0x1010).unwrap().1.line, Some(12));
+        assert_eq!(
+            table.lookup(0x1050),
+            Some((
+                0x1040,
+                GoFunctionLocation {
+                    name: "main.helper",
+                    file: Some("/src/helper.go"),
+                    line: Some(3),
+                }
+            ))
+        );
+        assert_eq!(table.lookup(0xfff), None);
+        assert_eq!(table.lookup(0x1060), None);
+    }
+
+    #[test]
+    fn overflowing_function_count() {
+        let mut data = make_go120_table();
+        data[8..16].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert!(GoPclnTab::parse(&data, 0x1000).is_none());
+    }
+
+    #[test]
+    fn go12_lookup() {
+        // Go 1.2 layout: header, nfunctab, functab (absolute addresses),
+        // filetab offset, then _func structs, names, pc tables and the filetab.
+        let mut table = vec![];
+        table.extend_from_slice(&0xfffffffbu32.to_le_bytes());
+        table.extend_from_slice(&[0, 0, 1, 8]);
+        table.extend_from_slice(&1u64.to_le_bytes());
+        let functab_pos = table.len();
+        table.resize(functab_pos + 3 * 8 + 4, 0);
+        let func_pos = table.len();
+        table.resize(func_pos + 8 + 8 * 4, 0);
+        let name_pos = table.len();
+        table.extend_from_slice(b"main.main\0");
+        let file_name_pos = table.len();
+        table.extend_from_slice(b"/src/main.go\0");
+        let pcfile_pos = table.len();
+        push_pc_value_table(&mut table, &[(1, 0x20)]);
+        let pcln_pos = table.len();
+        push_pc_value_table(&mut table, &[(7, 0x20)]);
+        let filetab_pos = table.len();
+        table.extend_from_slice(&2u32.to_le_bytes());
+        table.extend_from_slice(&(file_name_pos as u32).to_le_bytes());
+
+        let mut write = |pos: usize, bytes: &[u8]| {
+            table[pos..pos + bytes.len()].copy_from_slice(bytes);
+        };
+        write(functab_pos, &0x401000u64.to_le_bytes());
+        write(functab_pos + 8, &(func_pos as u64).to_le_bytes());
+        write(functab_pos + 16, &0x401020u64.to_le_bytes());
+        write(functab_pos + 24, &(filetab_pos as u32).to_le_bytes());
+        write(func_pos, &0x401000u64.to_le_bytes());
+        write(func_pos + 8, &(name_pos as u32).to_le_bytes());
+        write(func_pos + 8 + 4 * 4, &(pcfile_pos as u32).to_le_bytes());
+        write(func_pos + 8 + 5 * 4, &(pcln_pos as u32).to_le_bytes());
+
+        let table = GoPclnTab::parse(&table, 0).unwrap();
+        assert_eq!(
+            table.lookup(0x401010),
+            Some((
+                0x401000,
+                GoFunctionLocation {
+                    name: "main.main",
+                    file: Some("/src/main.go"),
+                    line: Some(7),
+                }
+            ))
+        );
+    }
+}
@@ -204,9 +204,11 @@
 //! }
 //! ```
 
+use std::collections::HashMap;
 use std::sync::Arc;
 
 use binary_image::BinaryImageInner;
+use debugid::DebugId;
 use jitdump::JitDumpIndex;
 use linux_perf_data::jitdump::JitDumpReader;
 use object::read::FileKind;
@@ -223,9 +225,11 @@ mod debugid_util;
 mod demangle;
 mod demangle_ocaml;
 mod dwarf;
+mod dyld_cache_local_symbols;
 mod elf;
 mod error;
 mod external_file;
+mod gopclntab;
 mod jitdump;
 mod macho;
 mod mapped_path;
@@ -233,6 +237,7 @@ mod path_mapper;
 mod shared;
 mod symbol_map;
 mod symbol_map_object;
+mod symbol_table_symbol_map;
 mod windows;
 
 pub use crate::binary_image::{BinaryImage, CodeByteReadingError};
@@ -241,8 +246,8 @@ pub use crate::breakpad::{
 };
 pub use crate::cache::{FileByteSource, FileContentsWithChunkedCaching};
 pub use crate::compact_symbol_table::CompactSymbolTable;
-pub use crate::debugid_util::{debug_id_for_object, DebugIdExt};
-pub use crate::demangle::demangle_any;
+pub use crate::debugid_util::{debug_id_for_object, pe_code_id_for_binary, DebugIdExt};
+pub use crate::demangle::{base_function_name, demangle_any};
 pub use crate::error::Error;
 pub use crate::external_file::{load_external_file, ExternalFileSymbolMap};
 pub use crate::jitdump::debug_id_and_code_id_for_jitdump;
@@ -256,10 +261,14 @@ pub use crate::shared::{
     MultiArchDisambiguator, OptionallySendFuture, PeCodeId, SourceFilePath, SymbolInfo,
     SyncAddressInfo,
 };
-pub use crate::symbol_map::{SymbolMap, SymbolMapTrait};
+pub use crate::symbol_map::{SymbolMap, SymbolMapTrait, SymbolNameOverrideKey};
+pub use crate::symbol_table_symbol_map::SymbolTableSymbolMap;
 
 pub struct SymbolManager<H: FileAndPathHelper> {
     helper: Arc<H>,
+    /// The symbol name overrides for each library, see
+    /// [`SymbolManager::set_symbol_name_overrides`].
+    symbol_name_overrides: HashMap<DebugId, HashMap<SymbolNameOverrideKey, String>>,
 }
 
 impl<H, F, FL> SymbolManager<H>
@@ -272,6 +281,7 @@ where
     pub fn with_helper(helper: H) -> Self {
         Self {
             helper: Arc::new(helper),
+            symbol_name_overrides: HashMap::new(),
         }
     }
 
@@ -280,6 +290,23 @@ where
         self.helper.clone()
     }
 
+    /// Replace the names of specific symbols in all symbol maps which this
+    /// `SymbolManager` loads from now on, keyed by the debug ID of the library.
+    /// See [`SymbolMap::set_symbol_name_overrides`].
+    pub fn set_symbol_name_overrides(
+        &mut self,
+        overrides: HashMap<DebugId, HashMap<SymbolNameOverrideKey, String>>,
+    ) {
+        self.symbol_name_overrides = overrides;
+    }
+
+    fn with_symbol_name_overrides(&self, mut symbol_map: SymbolMap<H>) -> SymbolMap<H> {
+        if let Some(overrides) = self.symbol_name_overrides.get(&symbol_map.debug_id()) {
+            symbol_map.set_symbol_name_overrides(overrides.clone());
+        }
+        symbol_map
+    }
+
     pub async fn load_source_file(
         &self,
         debug_file_location: &H::FL,
@@ -309,7 +336,8 @@ where
             .as_ref()
             .get_symbol_map_for_library(library_info)
         {
-            return Ok(SymbolMap::with_symbol_map_trait(fl, symbol_map));
+            let symbol_map = SymbolMap::with_symbol_map_trait(fl, symbol_map);
+            return Ok(self.with_symbol_name_overrides(symbol_map));
         }
 
         let debug_id = match library_info.debug_id {
@@ -331,7 +359,7 @@ where
         for candidate_info in candidate_paths {
             let symbol_map = match candidate_info {
                 CandidatePathInfo::SingleFile(file_location) => {
-                    self.load_symbol_map_from_location(
+                    self.load_symbol_map_from_location_impl(
                         file_location,
                         Some(MultiArchDisambiguator::DebugId(debug_id)),
                     )
@@ -351,7 +379,9 @@ where
             };
 
             match symbol_map {
-                Ok(symbol_map) if symbol_map.debug_id() == debug_id => return Ok(symbol_map),
+                Ok(symbol_map) if symbol_map.debug_id() == debug_id => {
+                    return Ok(self.with_symbol_name_overrides(symbol_map))
+                }
                 Ok(symbol_map) => {
                     all_errors.push(Error::UnmatchedDebugId(symbol_map.debug_id(), debug_id));
                 }
@@ -434,10 +464,10 @@ where
                 CandidatePathInfo::InDyldCache {
                     dyld_cache_path,
                     dylib_path,
-                } => {
-                    self.load_binary_from_dyld_cache(dyld_cache_path, dylib_path)
-                        .await
-                }
+                } => self
+                    .load_binary_from_dyld_cache(dyld_cache_path, dylib_path)
+                    .await
+                    .and_then(|image| check_dyld_cache_image_arch(image, info.arch.as_deref())),
             };
 
             match image {
@@ -530,14 +560,14 @@ where
             match (&multi_arch_disambiguator, symbol_map_res) {
                 (Some(MultiArchDisambiguator::DebugId(expected_debug_id)), Ok(symbol_map)) => {
                     if &symbol_map.debug_id() == expected_debug_id {
-                        return Ok(symbol_map);
+                        return Ok(self.with_symbol_name_overrides(symbol_map));
                     }
                     err = Some(Error::UnmatchedDebugId(
                         symbol_map.debug_id(),
                         *expected_debug_id,
                     ));
                 }
-                (_, Ok(symbol_map)) => return Ok(symbol_map),
+                (_, Ok(symbol_map)) => return Ok(self.with_symbol_name_overrides(symbol_map)),
                 (_, Err(e)) => err = Some(e),
             }
         }
@@ -548,6 +578,17 @@ where
         &self,
         file_location: FL,
         multi_arch_disambiguator: Option<MultiArchDisambiguator>,
+    ) -> Result<SymbolMap<H>, Error> {
+        let symbol_map = self
+            .load_symbol_map_from_location_impl(file_location, multi_arch_disambiguator)
+            .await?;
+        Ok(self.with_symbol_name_overrides(symbol_map))
+    }
+
+    async fn load_symbol_map_from_location_impl(
+        &self,
+        file_location: FL,
+        multi_arch_disambiguator: Option<MultiArchDisambiguator>,
     ) -> Result<SymbolMap<H>, Error> {
         let file_contents = self
             .helper
@@ -555,6 +596,53 @@ where
             .await
             .map_err(|e| Error::HelperErrorDuringOpenFile(file_location.to_string(), e))?;
 
+        self.load_symbol_map_from_file_contents(
+            file_location,
+            file_contents,
+            multi_arch_disambiguator,
+        )
+        .await
+    }
+
+    /// Obtain a symbol map for the library described by `library_info`, from file
+    /// contents which the caller already has, e.g. bytes in memory.
+    ///
+    /// The primary file isn't loaded through the helper; `file_location` is only
+    /// used to name the file and to find auxiliary files, such as split DWARF
+    /// files or PDBs. If `library_info` has a debug ID, it must match the
+    /// debug ID of the resulting symbol map.
+    pub async fn load_symbol_map_for_library_from_contents(
+        &self,
+        library_info: &LibraryInfo,
+        file_location: FL,
+        file_contents: F,
+    ) -> Result<SymbolMap<H>, Error> {
+        let multi_arch_disambiguator = match (library_info.debug_id, &library_info.arch) {
+            (Some(debug_id), _) => Some(MultiArchDisambiguator::DebugId(debug_id)),
+            (None, Some(arch)) => Some(MultiArchDisambiguator::Arch(arch.clone())),
+            (None, None) => None,
+        };
+        let symbol_map = self
+            .load_symbol_map_from_file_contents(
+                file_location,
+                file_contents,
+                multi_arch_disambiguator,
+            )
+            .await?;
+        match library_info.debug_id {
+            Some(debug_id) if symbol_map.debug_id() != debug_id => {
+                Err(Error::UnmatchedDebugId(symbol_map.debug_id(), debug_id))
+            }
+            _ => Ok(self.with_symbol_name_overrides(symbol_map)),
+        }
+    }
+
+    async fn load_symbol_map_from_file_contents(
+        &self,
+        file_location: FL,
+        file_contents: F,
+        multi_arch_disambiguator: Option<MultiArchDisambiguator>,
+    ) -> Result<SymbolMap<H>, Error> {
         let file_contents = FileContentsWrapper::new(file_contents);
 
         if let Ok(file_kind) = FileKind::parse(&file_contents) {
@@ -686,3 +774,21 @@ where
         BinaryImage::new(inner, name, path)
     }
 }
+
+/// Rejects an image from a dyld shared cache whose architecture doesn't match
+/// the library's architecture, if both are known. The same dylib path exists
+/// in the caches of all architectures, so the path alone doesn't identify it.
+fn check_dyld_cache_image_arch<F: FileContents>(
+    image: BinaryImage<F>,
+    expected_arch: Option<&str>,
+) -> Result<BinaryImage<F>, Error> {
+    match (expected_arch, image.arch()) {
+        // x86_64 libraries can be in the x86_64h cache.
+        (Some("x86_64"), Some("x86_64h")) => Ok(image),
+        (Some(expected_arch), Some(arch)) if expected_arch != arch => Err(Error::UnmatchedArch(
+            expected_arch.to_owned(),
+            arch.to_owned(),
+        )),
+        _ => Ok(image),
+    }
+}
@@ -8,6 +8,7 @@ use object::read::macho::{
     FatArch, LoadCommandIterator, MachHeader, MachOFatFile32, MachOFatFile64,
 };
 use object::read::{File, Object, ObjectSection};
+use object::SectionKind;
 use object::{Endianness, FileKind, ReadRef};
 use uuid::Uuid;
 use yoke::Yoke;
@@ -16,6 +17,7 @@ use yoke_derive::Yokeable;
 use crate::binary_image::{BinaryImage, BinaryImageInner};
 use crate::debugid_util::debug_id_for_object;
 use crate::dwarf::Addr2lineContextData;
+use crate::dyld_cache_local_symbols::{read_local_symbols, DyldCacheImageLocation};
 use crate::error::Error;
 use crate::shared::{
     FileAndPathHelper, FileContents, FileContentsWrapper, FileLocation, MultiArchDisambiguator,
@@ -23,7 +25,7 @@ use crate::shared::{
 };
 use crate::symbol_map::SymbolMap;
 use crate::symbol_map_object::{
-    ObjectSymbolMap, ObjectSymbolMapInnerWrapper, ObjectSymbolMapOuter,
+    ExtraSymbol, ObjectSymbolMap, ObjectSymbolMapInnerWrapper, ObjectSymbolMapOuter,
 };
 
 /// Converts a cpu type/subtype pair into the architecture name.
@@ -306,6 +308,9 @@ pub struct ObjectAndMachOData<'data, T: FileContents + 'static> {
     object: File<'data, FileContentsRange<'data, T>>,
     macho_data: MachOData<'data, FileContentsRange<'data, T>>,
     addr2line_context: Addr2lineContextData,
+    /// Symbols which aren't in the object's own symbol table, i.e. the local
+    /// symbols of a dylib in the dyld shared cache.
+    extra_symbols: Vec<ExtraSymbol>,
 }
 
 impl<'data, T: FileContents + 'static> ObjectAndMachOData<'data, T> {
@@ -317,9 +322,15 @@ impl<'data, T: FileContents + 'static> ObjectAndMachOData<'data, T> {
             object,
             macho_data,
             addr2line_context: Addr2lineContextData::new(),
+            extra_symbols: Vec::new(),
         }
     }
 
+    pub fn with_extra_symbols(mut self, extra_symbols: Vec<ExtraSymbol>) -> Self {
+        self.extra_symbols = extra_symbols;
+        self
+    }
+
     pub fn into_parts(
         self,
     ) -> (
@@ -373,7 +384,61 @@ impl<T: FileContents + 'static> DyldCacheFileData<T> {
             .image_data_and_offset()
             .map_err(Error::MachOHeaderParseError)?;
         let macho_data = MachOData::new(data, header_offset, object.is_64());
-        Ok(ObjectAndMachOData::new(object, macho_data))
+        let extra_symbols = self
+            .read_local_symbols(&object, header_offset)
+            .unwrap_or_default();
+        Ok(ObjectAndMachOData::new(object, macho_data).with_extra_symbols(extra_symbols))
+    }
+
+    /// Reads the symbols which were removed from the dylib's symbol table when
+    /// the cache was built. Only symbols in executable sections are returned.
+    fn read_local_symbols<'data>(
+        &'data self,
+        object: &File<'data, FileContentsRange<'data, T>>,
+        header_offset: u64,
+    ) -> Result<Vec<ExtraSymbol>, Error> {
+        let root_data = self.root_file_data.full_range();
+        let root_header = macho::DyldCacheHeader::<Endianness>::parse(root_data)
+            .map_err(Error::DyldCacheParseError)?;
+        let (_arch, endian) = root_header
+            .parse_magic()
+            .map_err(Error::DyldCacheParseError)?;
+        let first_mapping = root_header
+            .mappings(endian, root_data)
+            .map_err(Error::DyldCacheParseError)?
+            .first()
+            .ok_or(Error::InvalidInputError("No mappings in dyld cache"))?;
+
+        // In split caches, the local symbols are in the .symbols subcache, which
+        // is always the last subcache.
+        let symbols_data = match (
+            root_header.symbols_subcache_uuid(endian),
+            self.subcache_file_data.last(),
+        ) {
+            (Some(_), Some(symbols_file_data)) => symbols_file_data.full_range(),
+            (Some(_), None) => return Ok(Vec::new()),
+            (None, _) => root_data,
+        };
+        let image = DyldCacheImageLocation {
+            vm_offset: object
+                .relative_address_base()
+                .wrapping_sub(first_mapping.address.get(endian)),
+            file_offset: header_offset,
+        };
+        let symbols = read_local_symbols(symbols_data, image, object.is_64())?;
+        let executable_ranges: Vec<_> = object
+            .sections()
+            .filter(|section| section.kind() == SectionKind::Text)
+            .map(|section| section.address()..section.address() + section.size())
+            .collect();
+        Ok(symbols
+            .into_iter()
+            .filter(|symbol| {
+                executable_ranges
+                    .iter()
+                    .any(|range| range.contains(&symbol.address))
+            })
+            .collect())
     }
 }
 
@@ -403,6 +468,7 @@ impl<T: FileContents + 'static> ObjectSymbolMapOuter<T> for FileDataAndObject<T>
             object,
             macho_data,
             addr2line_context,
+            extra_symbols,
         } = self.0.get();
         let (function_starts, function_ends) = compute_function_addresses_macho(macho_data, object);
         let debug_id = debug_id_for_object(object)
@@ -416,6 +482,7 @@ impl<T: FileContents + 'static> ObjectSymbolMapOuter<T> for FileDataAndObject<T>
             debug_id,
             function_starts.as_deref(),
             function_ends.as_deref(),
+            extra_symbols,
             &(),
         );
 
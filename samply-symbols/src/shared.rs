@@ -692,6 +692,12 @@ pub struct AddressInfo {
     /// The Vec begins with the callee-most ("innermost") inlinee, followed by
     /// its caller, and so on. The last element is always the outer function.
     pub frames: Option<Vec<FrameDebugInfo>>,
+    /// Set if no symbol contains the looked up address and the symbol map's
+    /// nearest-symbol fallback is enabled, see
+    /// [`SymbolMap::set_nearest_symbol_fallback`](crate::SymbolMap::set_nearest_symbol_fallback).
+    /// Then `symbol` is the nearest preceding symbol, its name has the offset
+    /// appended, e.g. `memcpy+0x123`, and this is the offset in bytes.
+    pub nearest_symbol_offset: Option<u32>,
 }
 
 /// The lookup result from `lookup_sync`.
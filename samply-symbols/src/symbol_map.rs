@@ -1,12 +1,15 @@
 use std::borrow::Cow;
-use std::sync::Arc;
+use std::collections::HashMap;
+use std::ops::Range;
+use std::sync::{Arc, OnceLock};
 
 use debugid::DebugId;
 
+use crate::demangle::{base_function_name, demangle_any};
 use crate::shared::LookupAddress;
 use crate::{
     AddressInfo, ExternalFileAddressRef, ExternalFileRef, FileAndPathHelper, FileLocation,
-    FrameDebugInfo, FramesLookupResult, SyncAddressInfo,
+    FrameDebugInfo, FramesLookupResult, SymbolInfo, SyncAddressInfo,
 };
 
 pub trait SymbolMapTrait {
@@ -45,10 +48,27 @@ enum InnerSymbolMap<FC> {
     Direct(Arc<dyn SymbolMapTrait + Send + Sync>),
 }
 
+/// Identifies the symbol whose name is replaced by a name override, see
+/// [`SymbolMap::set_symbol_name_overrides`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum SymbolNameOverrideKey {
+    /// The relative address of the start of the symbol.
+    Address(u32),
+    /// The name of the symbol before demangling, as it appears in the symbol table.
+    MangledName(String),
+}
+
 pub struct SymbolMap<H: FileAndPathHelper> {
     debug_file_location: H::FL,
     inner: InnerSymbolMap<H::F>,
     helper: Option<Arc<H>>,
+    nearest_symbol_fallback: bool,
+    /// The sorted symbol addresses, for the nearest-symbol fallback. Computed on first use.
+    sorted_symbol_addresses: OnceLock<Vec<u32>>,
+    symbol_name_overrides: HashMap<SymbolNameOverrideKey, String>,
+    /// The override names by symbol address, with the mangled-name overrides
+    /// resolved to addresses. Computed on first use.
+    symbol_name_overrides_by_address: OnceLock<HashMap<u32, String>>,
 }
 
 impl<H: FileAndPathHelper> SymbolMap<H> {
@@ -60,6 +80,10 @@ impl<H: FileAndPathHelper> SymbolMap<H> {
             debug_file_location,
             inner: InnerSymbolMap::WithoutAddFile(inner),
             helper: None,
+            nearest_symbol_fallback: false,
+            sorted_symbol_addresses: OnceLock::new(),
+            symbol_name_overrides: HashMap::new(),
+            symbol_name_overrides_by_address: OnceLock::new(),
         }
     }
 
@@ -72,6 +96,10 @@ impl<H: FileAndPathHelper> SymbolMap<H> {
             debug_file_location,
             inner: InnerSymbolMap::WithAddFile(inner),
             helper: Some(helper),
+            nearest_symbol_fallback: false,
+            sorted_symbol_addresses: OnceLock::new(),
+            symbol_name_overrides: HashMap::new(),
+            symbol_name_overrides_by_address: OnceLock::new(),
         }
     }
 
@@ -83,6 +111,10 @@ impl<H: FileAndPathHelper> SymbolMap<H> {
             debug_file_location,
             inner: InnerSymbolMap::Direct(inner),
             helper: None,
+            nearest_symbol_fallback: false,
+            sorted_symbol_addresses: OnceLock::new(),
+            symbol_name_overrides: HashMap::new(),
+            symbol_name_overrides_by_address: OnceLock::new(),
         }
     }
 
@@ -111,23 +143,216 @@ impl<H: FileAndPathHelper> SymbolMap<H> {
     }
 
     pub fn lookup_sync(&self, address: LookupAddress) -> Option<SyncAddressInfo> {
-        self.inner().lookup_sync(address)
+        let mut info = self.inner().lookup_sync(address)?;
+        let frames = match &mut info.frames {
+            Some(FramesLookupResult::Available(frames)) => Some(frames),
+            _ => None,
+        };
+        self.apply_symbol_name_override(&mut info.symbol, frames);
+        Some(info)
+    }
+
+    /// Iterates over all functions, in address order, with their relative address
+    /// ranges and names.
+    ///
+    /// This covers the same entries as [`iter_symbols`](Self::iter_symbols), but
+    /// also determines the end address of each function. If the debug info has
+    /// a name for the function, that name is used instead of the symbol name;
+    /// this gives names to functions which are only known from unwind info or
+    /// function start tables. A function whose end address cannot be determined,
+    /// i.e. the last function if it has no size, gets an empty range.
+    pub fn iter_functions(&self) -> impl Iterator<Item = (Range<u32>, String)> + '_ {
+        let inner = self.inner();
+        let mut addresses: Vec<u32> = inner.iter_symbols().map(|(address, _)| address).collect();
+        addresses.sort_unstable();
+        addresses.dedup();
+        let next_addresses: Vec<Option<u32>> = addresses
+            .iter()
+            .skip(1)
+            .copied()
+            .map(Some)
+            .chain(std::iter::once(None))
+            .collect();
+        addresses
+            .into_iter()
+            .zip(next_addresses)
+            .filter_map(move |(address, next_address)| {
+                let info = inner.lookup_sync(LookupAddress::Relative(address))?;
+                if info.symbol.address != address {
+                    return None;
+                }
+                let end = match (info.symbol.size, next_address) {
+                    (Some(size), _) => address.saturating_add(size),
+                    (None, Some(next_address)) => next_address,
+                    (None, None) => address,
+                };
+                let debug_info_name = match info.frames {
+                    Some(FramesLookupResult::Available(frames)) => {
+                        frames.into_iter().last().and_then(|frame| frame.function)
+                    }
+                    _ => None,
+                };
+                Some((address..end, debug_info_name.unwrap_or(info.symbol.name)))
+            })
+    }
+
+    /// Finds all symbols whose demangled name matches `base_name`, ignoring
+    /// template arguments and parameter lists on both sides, see
+    /// [`base_function_name`]. A match can also be a suffix which starts at a
+    /// `::` boundary, so `vector::push_back` finds `std::vector<int>::push_back(int&&)`.
+    ///
+    /// The returned symbols have demangled names and are sorted by address.
+    pub fn find_symbols_by_base_name(&self, base_name: &str) -> Vec<SymbolInfo> {
+        let base_name = base_function_name(base_name);
+        let suffix = format!("::{base_name}");
+        let inner = self.inner();
+        let mut symbols: Vec<SymbolInfo> = inner
+            .iter_symbols()
+            .filter_map(|(address, name)| {
+                let name = demangle_any(&name);
+                let symbol_base_name = base_function_name(&name);
+                if symbol_base_name != base_name && !symbol_base_name.ends_with(&suffix) {
+                    return None;
+                }
+                let size = inner
+                    .lookup_sync(LookupAddress::Relative(address))
+                    .filter(|info| info.symbol.address == address)
+                    .and_then(|info| info.symbol.size);
+                Some(SymbolInfo {
+                    address,
+                    size,
+                    name,
+                })
+            })
+            .collect();
+        symbols.sort_by_key(|symbol| symbol.address);
+        symbols
+    }
+
+    /// Make [`lookup`](Self::lookup) fall back to the nearest preceding symbol
+    /// if no symbol contains the address, e.g. for addresses in the gaps between
+    /// functions or in hand-written assembly without symbol sizes. The result
+    /// then has the offset from that symbol, see [`AddressInfo::nearest_symbol_offset`].
+    ///
+    /// This is off by default, so that strict callers get `None` for gaps. The
+    /// fallback only applies to [`LookupAddress::Relative`] addresses and doesn't
+    /// affect [`lookup_sync`](Self::lookup_sync).
+    pub fn set_nearest_symbol_fallback(&mut self, enabled: bool) {
+        self.nearest_symbol_fallback = enabled;
+    }
+
+    /// Replace the demangled names of specific symbols, e.g. to clean up names
+    /// which demangle badly or are too noisy. The overrides take precedence
+    /// over the names from both the symbol table and the debug info, and apply
+    /// to [`lookup`](Self::lookup) and [`lookup_sync`](Self::lookup_sync).
+    pub fn set_symbol_name_overrides(&mut self, overrides: HashMap<SymbolNameOverrideKey, String>) {
+        self.symbol_name_overrides = overrides;
+        self.symbol_name_overrides_by_address = OnceLock::new();
+    }
+
+    fn symbol_name_override(&self, symbol_address: u32) -> Option<&str> {
+        if self.symbol_name_overrides.is_empty() {
+            return None;
+        }
+        let overrides_by_address = self.symbol_name_overrides_by_address.get_or_init(|| {
+            let mut overrides_by_address = HashMap::new();
+            for (address, name) in self.inner().iter_symbols() {
+                let key = SymbolNameOverrideKey::MangledName(name.into_owned());
+                if let Some(display_name) = self.symbol_name_overrides.get(&key) {
+                    overrides_by_address.insert(address, display_name.clone());
+                }
+            }
+            // Address overrides win over mangled-name overrides.
+            for (key, display_name) in &self.symbol_name_overrides {
+                if let SymbolNameOverrideKey::Address(address) = key {
+                    overrides_by_address.insert(*address, display_name.clone());
+                }
+            }
+            overrides_by_address
+        });
+        overrides_by_address
+            .get(&symbol_address)
+            .map(String::as_str)
+    }
+
+    /// Replaces the symbol name, and the function name of the outermost frame,
+    /// i.e. the non-inlined function, if there is an override for the symbol.
+    fn apply_symbol_name_override(
+        &self,
+        symbol: &mut SymbolInfo,
+        frames: Option<&mut Vec<FrameDebugInfo>>,
+    ) {
+        let Some(display_name) = self.symbol_name_override(symbol.address) else {
+            return;
+        };
+        symbol.name = display_name.to_string();
+        if let Some(outer_frame) = frames.and_then(|frames| frames.last_mut()) {
+            outer_frame.function = Some(display_name.to_string());
+        }
+    }
+
+    fn lookup_nearest_preceding_symbol(&self, address: LookupAddress) -> Option<AddressInfo> {
+        let LookupAddress::Relative(address) = address else {
+            return None;
+        };
+        let addresses = self.sorted_symbol_addresses.get_or_init(|| {
+            let mut addresses: Vec<u32> = self
+                .inner()
+                .iter_symbols()
+                .map(|(address, _)| address)
+                .collect();
+            addresses.sort_unstable();
+            addresses.dedup();
+            addresses
+        });
+        let index = addresses
+            .partition_point(|symbol_address| *symbol_address <= address)
+            .checked_sub(1)?;
+        let symbol_address = addresses[index];
+        let symbol = self
+            .lookup_sync(LookupAddress::Relative(symbol_address))?
+            .symbol;
+        let offset = address - symbol_address;
+        Some(AddressInfo {
+            symbol: SymbolInfo {
+                address: symbol_address,
+                size: None,
+                name: format!("{}+{offset:#x}", symbol.name),
+            },
+            frames: None,
+            nearest_symbol_offset: Some(offset),
+        })
     }
 
     pub async fn lookup(&self, address: LookupAddress) -> Option<AddressInfo> {
-        let address_info = self.inner().lookup_sync(address)?;
+        let mut address_info = self.lookup_inner(address).await?;
+        if address_info.nearest_symbol_offset.is_none() {
+            self.apply_symbol_name_override(&mut address_info.symbol, address_info.frames.as_mut());
+        }
+        Some(address_info)
+    }
+
+    async fn lookup_inner(&self, address: LookupAddress) -> Option<AddressInfo> {
+        let Some(address_info) = self.inner().lookup_sync(address) else {
+            if self.nearest_symbol_fallback {
+                return self.lookup_nearest_preceding_symbol(address);
+            }
+            return None;
+        };
         let symbol = address_info.symbol;
         let (mut external, inner) = match (address_info.frames, &self.inner) {
             (Some(FramesLookupResult::Available(frames)), _) => {
                 return Some(AddressInfo {
                     symbol,
                     frames: Some(frames),
+                    nearest_symbol_offset: None,
                 });
             }
             (None, _) | (_, InnerSymbolMap::WithoutAddFile(_)) | (_, InnerSymbolMap::Direct(_)) => {
                 return Some(AddressInfo {
                     symbol,
                     frames: None,
+                    nearest_symbol_offset: None,
                 });
             }
             (Some(FramesLookupResult::External(external)), InnerSymbolMap::WithAddFile(inner)) => {
@@ -155,12 +380,14 @@ impl<H: FileAndPathHelper> SymbolMap<H> {
                     return Some(AddressInfo {
                         symbol,
                         frames: Some(frames),
+                        nearest_symbol_offset: None,
                     });
                 }
                 None => {
                     return Some(AddressInfo {
                         symbol,
                         frames: None,
+                        nearest_symbol_offset: None,
                     });
                 }
                 Some(FramesLookupResult::External(external)) => external,
@@ -13,10 +13,11 @@ use yoke::Yoke;
 use yoke_derive::Yokeable;
 
 use crate::dwarf::convert_frames;
+use crate::gopclntab::GoPclnTab;
 use crate::path_mapper::PathMapper;
 use crate::shared::{
     relative_address_base, ExternalFileAddressInFileRef, ExternalFileAddressRef, ExternalFileRef,
-    FramesLookupResult, LookupAddress, SymbolInfo,
+    FrameDebugInfo, FramesLookupResult, LookupAddress, SourceFilePath, SymbolInfo,
 };
 use crate::symbol_map::{
     GetInnerSymbolMap, GetInnerSymbolMapWithLookupFramesExt, SymbolMapTrait,
@@ -32,6 +33,10 @@ enum FullSymbolListEntry<'a, Symbol> {
     SynthesizedEntryPoint,
     Symbol(Symbol),
     Export(object::Export<'a>),
+    /// A function from the Go pclntab.
+    GoFunction(&'a str),
+    /// A function symbol from the stripped binary, see [`ExtraSymbol`].
+    Extra(&'a str),
     EndAddress,
 }
 
@@ -48,6 +53,8 @@ impl<'a, Symbol: object::ObjectSymbol<'a>> std::fmt::Debug for FullSymbolListEnt
                 .debug_tuple("Export")
                 .field(&std::str::from_utf8(arg0.name()).unwrap())
                 .finish(),
+            Self::GoFunction(arg0) => f.debug_tuple("GoFunction").field(arg0).finish(),
+            Self::Extra(arg0) => f.debug_tuple("Extra").field(arg0).finish(),
             Self::EndAddress => write!(f, "EndAddress"),
         }
     }
@@ -63,13 +70,19 @@ impl<'a, Symbol: object::ObjectSymbol<'a>> FullSymbolListEntry<'a, Symbol> {
                 String::from_utf8_lossy(symbol.name_bytes().ok()?)
             }
             FullSymbolListEntry::Export(export) => String::from_utf8_lossy(export.name()),
+            FullSymbolListEntry::GoFunction(name) | FullSymbolListEntry::Extra(name) => {
+                Cow::Borrowed(*name)
+            }
         };
         Some(name)
     }
 
     fn counts_as_proper_symbol(&self) -> bool {
         match self {
-            FullSymbolListEntry::Symbol(_) | FullSymbolListEntry::Export(_) => true,
+            FullSymbolListEntry::Symbol(_)
+            | FullSymbolListEntry::Export(_)
+            | FullSymbolListEntry::GoFunction(_)
+            | FullSymbolListEntry::Extra(_) => true,
             FullSymbolListEntry::EndAddress
             | FullSymbolListEntry::Synthesized
             | FullSymbolListEntry::SynthesizedEntryPoint => false,
@@ -77,6 +90,34 @@ impl<'a, Symbol: object::ObjectSymbol<'a>> FullSymbolListEntry<'a, Symbol> {
     }
 }
 
+/// A function symbol which comes from a different file than the object whose
+/// symbols we're listing.
+///
+/// This is used when the symbol map is made from a separate debug file, such
+/// as a `.gnu_debuglink` target or the MiniDebugInfo in `.gnu_debugdata`. The
+/// stripped binary still has its `.dynsym` table, and MiniDebugInfo in
+/// particular only contains the symbols which are *not* in `.dynsym`, so the
+/// two need to be merged.
+#[derive(Debug, Clone)]
+pub struct ExtraSymbol {
+    pub address: u64,
+    pub size: u64,
+    pub name: String,
+}
+
+/// The list of function start addresses, sorted by address.
+///
+/// Entries from several sources are merged. If more than one source has a
+/// function at the same address, the entry from the earlier source wins:
+///
+///  1. `.symtab` of the object (this is the debug file, if there is one)
+///  2. `.dynsym` of the object
+///  3. Symbols from the stripped binary (only if the object is a separate
+///     debug file, see [`ExtraSymbol`])
+///  4. Exports (PE only)
+///  5. Functions from the Go pclntab
+///  6. Placeholder `fun_` symbols for function starts from `.eh_frame` / `.pdata`
+///  7. The entry point
 struct SymbolList<'a, Symbol> {
     entries: Vec<(u32, FullSymbolListEntry<'a, Symbol>)>,
 }
@@ -87,6 +128,8 @@ impl<'a, Symbol: object::ObjectSymbol<'a> + 'a> SymbolList<'a, Symbol> {
         base_address: u64,
         function_start_addresses: Option<&[u32]>,
         function_end_addresses: Option<&[u32]>,
+        go_pclntab: Option<&GoPclnTab<'a>>,
+        extra_symbols: &'a [ExtraSymbol],
     ) -> Self
     where
         'a: 'file,
@@ -157,7 +200,15 @@ impl<'a, Symbol: object::ObjectSymbol<'a> + 'a> SymbolList<'a, Symbol> {
                 }),
         );
 
-        // 3. Exports (only used by exe / dll objects)
+        // 3. Symbols from the stripped binary, if the object is a separate debug file
+        entries.extend(extra_symbols.iter().filter_map(|symbol| {
+            Some((
+                u32::try_from(symbol.address.checked_sub(base_address)?).ok()?,
+                FullSymbolListEntry::Extra(symbol.name.as_str()),
+            ))
+        }));
+
+        // 4. Exports (only used by exe / dll objects)
         if let Ok(exports) = object_file.exports() {
             for export in exports {
                 entries.push((
@@ -167,7 +218,18 @@ impl<'a, Symbol: object::ObjectSymbol<'a> + 'a> SymbolList<'a, Symbol> {
             }
         }
 
-        // 4. Placeholder symbols based on function start addresses
+        // 5. Functions from the Go pclntab (only used by Go binaries)
+        // These are useful if the binary was stripped.
+        if let Some(go_pclntab) = go_pclntab {
+            entries.extend(go_pclntab.functions().filter_map(|(address, name)| {
+                Some((
+                    u32::try_from(address.checked_sub(base_address)?).ok()?,
+                    FullSymbolListEntry::GoFunction(name),
+                ))
+            }));
+        }
+
+        // 6. Placeholder symbols based on function start addresses
         if let Some(function_start_addresses) = function_start_addresses {
             // Use function start addresses with synthesized symbols of the form fun_abcdef
             // as the ultimate fallback.
@@ -181,7 +243,7 @@ impl<'a, Symbol: object::ObjectSymbol<'a> + 'a> SymbolList<'a, Symbol> {
             );
         }
 
-        // 5. A placeholder symbol for the entry point.
+        // 7. A placeholder symbol for the entry point.
         if let Some(entry_point) = object_file.entry().checked_sub(base_address) {
             entries.push((
                 entry_point as u32,
@@ -189,7 +251,7 @@ impl<'a, Symbol: object::ObjectSymbol<'a> + 'a> SymbolList<'a, Symbol> {
             ));
         }
 
-        // 6. End addresses from text section ends
+        // 8. End addresses from text section ends
         // These entries serve to "terminate" the last function of each section,
         // so that addresses in the following section are not considered
         // to be part of the last function of that previous section.
@@ -205,7 +267,7 @@ impl<'a, Symbol: object::ObjectSymbol<'a> + 'a> SymbolList<'a, Symbol> {
                 }),
         );
 
-        // 7. End addresses for sized symbols
+        // 9. End addresses for sized symbols
         // These addresses serve to "terminate" functions symbols.
         entries.extend(
             object_file
@@ -226,8 +288,25 @@ impl<'a, Symbol: object::ObjectSymbol<'a> + 'a> SymbolList<'a, Symbol> {
                     ))
                 }),
         );
+        entries.extend(
+            extra_symbols
+                .iter()
+                .filter(|symbol| symbol.size != 0)
+                .filter_map(|symbol| {
+                    Some((
+                        u32::try_from(
+                            symbol
+                                .address
+                                .checked_add(symbol.size)?
+                                .checked_sub(base_address)?,
+                        )
+                        .ok()?,
+                        FullSymbolListEntry::EndAddress,
+                    ))
+                }),
+        );
 
-        // 8. End addresses for known functions ends
+        // 10. End addresses for known functions ends
         // These addresses serve to "terminate" functions from function_start_addresses.
         // They come from .eh_frame or .pdata info, which has the function size.
         if let Some(function_end_addresses) = function_end_addresses {
@@ -362,6 +441,7 @@ pub struct ObjectSymbolMapInner<'a, Symbol, FC: FileContents + 'static, DDM> {
     image_base_address: u64,
     dwo_dwarf_maker: &'a DDM,
     cached_external_file: Mutex<Option<ExternalFileSymbolMap<FC>>>,
+    go_pclntab: Option<GoPclnTab<'a>>,
     _phantom: PhantomData<FC>,
 }
 
@@ -404,6 +484,21 @@ where
         }))
     }
 
+    /// Gets file and line information from the Go pclntab, for Go binaries
+    /// without DWARF. The pclntab doesn't know about inlined functions.
+    fn frames_lookup_for_go_pclntab(&self, svma: u64) -> Option<FramesLookupResult> {
+        let (_entry, location) = self.go_pclntab.as_ref()?.lookup(svma)?;
+        let file_path = location.file.map(|file| {
+            let mapped_path = self.path_mapper.lock().unwrap().map_path(file);
+            SourceFilePath::new(file.into(), mapped_path)
+        });
+        Some(FramesLookupResult::Available(vec![FrameDebugInfo {
+            function: Some(location.name.to_owned()),
+            file_path,
+            line_number: location.line,
+        }]))
+    }
+
     fn try_lookup_external_impl(
         &self,
         external: &ExternalFileAddressRef,
@@ -579,6 +674,9 @@ where
         if frames.is_none() {
             frames = self.frames_lookup_for_object_map_references(svma);
         }
+        if frames.is_none() {
+            frames = self.frames_lookup_for_go_pclntab(svma);
+        }
         Some(SyncAddressInfo { symbol, frames })
     }
 }
@@ -645,6 +743,7 @@ pub struct ObjectSymbolMapInnerWrapper<'data, FC>(
 );
 
 impl<'a, FC: FileContents + 'static> ObjectSymbolMapInnerWrapper<'a, FC> {
+    #[allow(clippy::too_many_arguments)]
     pub fn new<'file, O, Symbol, DDM>(
         object_file: &'file O,
         addr2line_context: Option<addr2line::Context<EndianSlice<'a, RunTimeEndian>>>,
@@ -652,6 +751,7 @@ impl<'a, FC: FileContents + 'static> ObjectSymbolMapInnerWrapper<'a, FC> {
         debug_id: DebugId,
         function_start_addresses: Option<&[u32]>,
         function_end_addresses: Option<&[u32]>,
+        extra_symbols: &'a [ExtraSymbol],
         dwo_dwarf_maker: &'a DDM,
     ) -> Self
     where
@@ -661,11 +761,14 @@ impl<'a, FC: FileContents + 'static> ObjectSymbolMapInnerWrapper<'a, FC> {
         DDM: DwoDwarfMaker<FC> + Sync,
     {
         let base_address = relative_address_base(object_file);
+        let go_pclntab = GoPclnTab::from_object(object_file);
         let list = SymbolList::new(
             object_file,
             base_address,
             function_start_addresses,
             function_end_addresses,
+            go_pclntab.as_ref(),
+            extra_symbols,
         );
 
         let inner = ObjectSymbolMapInner {
@@ -679,6 +782,7 @@ impl<'a, FC: FileContents + 'static> ObjectSymbolMapInnerWrapper<'a, FC> {
             svma_file_ranges: SvmaFileRanges::from_object(object_file),
             dwo_dwarf_maker,
             cached_external_file: Mutex::new(None),
+            go_pclntab,
             _phantom: PhantomData,
         };
         Self(Box::new(inner))
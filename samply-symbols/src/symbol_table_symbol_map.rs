@@ -0,0 +1,81 @@
+use std::borrow::Cow;
+
+use debugid::DebugId;
+
+use crate::{LookupAddress, SymbolInfo, SymbolMapTrait, SyncAddressInfo};
+
+/// A symbol map which is backed by nothing but a list of symbols, without
+/// any binary or debug file.
+///
+/// This is useful if the symbols were obtained in some other way, for example
+/// from a language runtime's own symbol dump. Only
+/// [`LookupAddress::Relative`] lookups are supported, and lookups never
+/// return inline frames or file / line information.
+#[derive(Debug, Clone)]
+pub struct SymbolTableSymbolMap {
+    debug_id: DebugId,
+    /// Sorted by address.
+    symbols: Vec<SymbolInfo>,
+}
+
+impl SymbolTableSymbolMap {
+    /// Create a symbol map from a list of symbols with relative addresses.
+    ///
+    /// The symbols don't need to be sorted. Symbols without a size are
+    /// assumed to extend up to the next symbol.
+    pub fn new(debug_id: DebugId, mut symbols: Vec<SymbolInfo>) -> Self {
+        symbols.sort_by_key(|symbol| symbol.address);
+        symbols.dedup_by_key(|symbol| symbol.address);
+        Self { debug_id, symbols }
+    }
+}
+
+impl SymbolMapTrait for SymbolTableSymbolMap {
+    fn debug_id(&self) -> DebugId {
+        self.debug_id
+    }
+
+    fn symbol_count(&self) -> usize {
+        self.symbols.len()
+    }
+
+    fn iter_symbols(&self) -> Box<dyn Iterator<Item = (u32, Cow<'_, str>)> + '_> {
+        Box::new(
+            self.symbols
+                .iter()
+                .map(|symbol| (symbol.address, Cow::Borrowed(symbol.name.as_str()))),
+        )
+    }
+
+    fn lookup_sync(&self, address: LookupAddress) -> Option<SyncAddressInfo> {
+        let LookupAddress::Relative(address) = address else {
+            return None;
+        };
+        let index = match self
+            .symbols
+            .binary_search_by_key(&address, |symbol| symbol.address)
+        {
+            Ok(index) => index,
+            Err(0) => return None,
+            Err(insertion_index) => insertion_index - 1,
+        };
+        let symbol = &self.symbols[index];
+        let size = symbol.size.or_else(|| {
+            let next_symbol = self.symbols.get(index + 1)?;
+            Some(next_symbol.address - symbol.address)
+        });
+        if let Some(size) = size {
+            if address - symbol.address >= size {
+                return None;
+            }
+        }
+        Some(SyncAddressInfo {
+            symbol: SymbolInfo {
+                address: symbol.address,
+                size,
+                name: symbol.name.clone(),
+            },
+            frames: None,
+        })
+    }
+}
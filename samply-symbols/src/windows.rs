@@ -134,6 +134,7 @@ impl<T: FileContents + 'static> ObjectSymbolMapOuter<T> for PeSymbolMapDataAndOb
             debug_id,
             function_starts.as_deref(),
             function_ends.as_deref(),
+            &[],
             &(),
         );
 
@@ -294,13 +295,16 @@ impl<'object> SymbolMapTrait for PdbSymbolMapInner<'object> {
                 let mapped_path = path_mapper.map_path(&path);
                 SourceFilePath::new(path.into_owned(), mapped_path)
             };
+            // The frames are ordered from the innermost inlinee to the outer
+            // function, same as in the DWARF path. Line 0 is used in the PDB
+            // for "no line information", which DWARF represents as None.
             let frames: Vec<_> = function_frames
                 .frames
                 .into_iter()
                 .map(|frame| FrameDebugInfo {
                     function: frame.function,
                     file_path: frame.file.map(&mut map_path),
-                    line_number: frame.line,
+                    line_number: frame.line.filter(|line| *line != 0),
                 })
                 .collect();
             Some(FramesLookupResult::Available(frames))
@@ -5,8 +5,8 @@ use std::path::{Path, PathBuf};
 use samply_symbols::debugid::DebugId;
 use samply_symbols::{
     self, CandidatePathInfo, CompactSymbolTable, Error, FileAndPathHelper, FileAndPathHelperResult,
-    FileLocation, LibraryInfo, LookupAddress, MultiArchDisambiguator, OptionallySendFuture,
-    SymbolManager, SymbolMap,
+    FileLocation, FramesLookupResult, LibraryInfo, LookupAddress, MultiArchDisambiguator,
+    OptionallySendFuture, SymbolManager, SymbolMap,
 };
 
 async fn get_symbol_map_with_dyld_cache_fallback(
@@ -532,6 +532,55 @@ fn example_linux() {
     );
 }
 
+#[test]
+fn minidebuginfo_merged_with_dynsym() {
+    // example-linux-minidebuginfo is stripped and has MiniDebugInfo in .gnu_debugdata.
+    // The MiniDebugInfo only contains the symbols which aren't in .dynsym, i.e.
+    // local_helper but not exported_function.
+    let helper = Helper {
+        symbol_directory: fixtures_dir().join("other"),
+    };
+    let symbol_manager = SymbolManager::with_helper(helper);
+    let symbol_map = futures::executor::block_on(
+        symbol_manager.load_symbol_map_from_location(
+            FileLocationType(
+                fixtures_dir()
+                    .join("other")
+                    .join("example-linux-minidebuginfo"),
+            ),
+            None,
+        ),
+    )
+    .unwrap();
+    assert_eq!(
+        &symbol_map
+            .lookup_sync(LookupAddress::Relative(0x10f9))
+            .unwrap()
+            .symbol
+            .name,
+        "local_helper"
+    );
+    assert_eq!(
+        &symbol_map
+            .lookup_sync(LookupAddress::Relative(0x1100))
+            .unwrap()
+            .symbol
+            .name,
+        "exported_function"
+    );
+    let binary = std::fs::read(
+        fixtures_dir()
+            .join("other")
+            .join("example-linux-minidebuginfo"),
+    )
+    .unwrap();
+    let binary = samply_symbols::object::File::parse(&binary[..]).unwrap();
+    assert_eq!(
+        Some(symbol_map.debug_id()),
+        samply_symbols::debug_id_for_object(&binary)
+    );
+}
+
 #[test]
 fn example_linux_fallback() {
     let helper = Helper {
@@ -551,6 +600,54 @@ fn example_linux_fallback() {
     assert_eq!(symbol_map.lookup_sync(LookupAddress::Relative(0x6)), None);
 }
 
+#[test]
+fn pdb_inline_frames() {
+    let helper = Helper {
+        symbol_directory: fixtures_dir().join("win64-ci"),
+    };
+    let symbol_manager = SymbolManager::with_helper(helper);
+    let symbol_map = futures::executor::block_on(symbol_manager.load_symbol_map_from_location(
+        FileLocationType(fixtures_dir().join("win64-ci").join("mozglue.pdb")),
+        None,
+    ))
+    .unwrap();
+    let frames = match symbol_map
+        .lookup_sync(LookupAddress::Relative(0x1021))
+        .unwrap()
+        .frames
+    {
+        Some(FramesLookupResult::Available(frames)) => frames,
+        other => panic!("expected inline frames, got {other:?}"),
+    };
+    let frames: Vec<_> = frames
+        .iter()
+        .map(|frame| {
+            (
+                frame.function.as_deref().unwrap(),
+                frame.file_path.as_ref().unwrap().raw_path(),
+                frame.line_number,
+            )
+        })
+        .collect();
+    let file = "/builds/worker/checkouts/gecko/mozglue/build/SSE.cpp";
+    assert_eq!(
+        frames,
+        vec![
+            (
+                "`anonymous namespace'::has_cpuid_bits(unsigned int, `anonymous namespace'::CPUIDRegister, unsigned int)",
+                file,
+                Some(34)
+            ),
+            (
+                "mozilla::sse_private::`dynamic initializer for 'mmx_enabled'()",
+                file,
+                Some(119)
+            ),
+            ("_GLOBAL__sub_I_SSE.cpp()", file, None),
+        ]
+    );
+}
+
 #[test]
 fn compare_snapshot() {
     let table = futures::executor::block_on(crate::get_table(
@@ -587,3 +684,45 @@ fn compare_snapshot() {
 
     assert_eq!(output, expected);
 }
+
+#[test]
+fn pe_code_id_for_truncated_and_malformed_binaries() {
+    use samply_symbols::pe_code_id_for_binary;
+
+    let data = std::fs::read(fixtures_dir().join("win64-ci").join("WriteArgument.exe")).unwrap();
+    let code_id = pe_code_id_for_binary(&data).unwrap();
+    assert!(code_id.image_size != 0 && code_id.image_size % 0x1000 == 0);
+
+    // Only the headers up to the end of the section table are needed.
+    let u16_at = |offset: usize| u16::from_le_bytes([data[offset], data[offset + 1]]) as usize;
+    let pe_header_offset = u32::from_le_bytes(data[0x3c..0x40].try_into().unwrap()) as usize;
+    let section_count = u16_at(pe_header_offset + 6);
+    let optional_header_size = u16_at(pe_header_offset + 20);
+    let headers_end = pe_header_offset + 24 + optional_header_size + 40 * section_count;
+    for len in 0..headers_end {
+        assert_eq!(pe_code_id_for_binary(&data[..len]), None, "length {len}");
+    }
+    assert_eq!(
+        pe_code_id_for_binary(&data[..headers_end]),
+        Some(code_id.clone())
+    );
+
+    let corrupt = |offset: usize, bytes: &[u8]| {
+        let mut data = data.clone();
+        data[offset..offset + bytes.len()].copy_from_slice(bytes);
+        pe_code_id_for_binary(&data)
+    };
+    // The PE header offset points past the end of the file.
+    assert_eq!(corrupt(0x3c, &u32::MAX.to_le_bytes()), None);
+    // The PE signature is wrong.
+    assert_eq!(corrupt(pe_header_offset, b"PX\0\0"), None);
+    // The section table extends past the end of the file.
+    assert_eq!(corrupt(pe_header_offset + 6, &u16::MAX.to_le_bytes()), None);
+    // The optional header is too small for its magic.
+    assert_eq!(corrupt(pe_header_offset + 20, &8u16.to_le_bytes()), None);
+    // The optional header magic is neither PE32 nor PE32+.
+    assert_eq!(
+        corrupt(pe_header_offset + 24, &0x1234u16.to_le_bytes()),
+        None
+    );
+}
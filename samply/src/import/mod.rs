@@ -1 +1,3 @@
 pub mod perf;
+pub mod perf_pipe;
+mod reorder_window;
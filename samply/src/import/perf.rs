@@ -1,17 +1,22 @@
 use std::collections::HashMap;
 use std::fmt::Write;
-use std::io::{Read, Seek};
+use std::io::{Read, Seek, SeekFrom};
 use std::path::PathBuf;
 use std::time::SystemTime;
 
 use framehop::{Module, Unwinder};
-use fxprof_processed_profile::{Profile, ReferenceTimestamp};
-use linux_perf_data::{linux_perf_event_reader, DsoInfo, DsoKey, PerfFileReader, PerfFileRecord};
-use linux_perf_event_reader::EventRecord;
+use fxprof_processed_profile::{Profile, ReferenceTimestamp, SamplingInterval};
+use linux_perf_data::{
+    linux_perf_event_reader, DsoInfo, DsoKey, PerfFileReader, PerfFileRecord, UserRecordType,
+};
+use linux_perf_event_reader::{
+    Endianness, EventRecord, RawData, RawEventRecord, RecordParseInfo, RecordType,
+};
 
+use super::reorder_window::ReorderWindow;
 use crate::linux_shared::{
-    ConvertRegs, ConvertRegsAarch64, ConvertRegsX86_64, Converter, EventInterpretation, KnownEvent,
-    MmapRangeOrVec,
+    attr_read_format_has_lost, ConvertRegs, ConvertRegsAarch64, ConvertRegsX86_64, Converter,
+    EventInterpretation, KnownEvent, MmapRangeOrVec, SampleExtraFields,
 };
 use crate::shared::recording_props::ProfileCreationProps;
 
@@ -25,12 +30,13 @@ pub enum Error {
 }
 
 pub fn convert<C: Read + Seek>(
-    cursor: C,
+    mut cursor: C,
     file_mod_time: Option<SystemTime>,
     binary_lookup_dirs: Vec<PathBuf>,
     aux_file_lookup_dirs: Vec<PathBuf>,
     profile_creation_props: ProfileCreationProps,
 ) -> Result<Profile, Error> {
+    let main_event_read_format_has_lost = first_attr_read_format_has_lost(&mut cursor)?;
     let perf_file = PerfFileReader::parse_file(cursor)?;
 
     let arch = perf_file.perf_file.arch().ok().flatten();
@@ -45,6 +51,7 @@ pub fn convert<C: Read + Seek>(
                 aux_file_lookup_dirs,
                 cache,
                 profile_creation_props,
+                main_event_read_format_has_lost,
             )
         }
         _ => {
@@ -62,12 +69,121 @@ pub fn convert<C: Read + Seek>(
                 aux_file_lookup_dirs,
                 cache,
                 profile_creation_props,
+                main_event_read_format_has_lost,
             )
         }
     };
     Ok(profile)
 }
 
+/// Reads whether the first event attr in the perf.data file has
+/// `PERF_FORMAT_LOST`, see `EventInterpretation::main_event_read_format_has_lost`.
+/// The parsed attrs don't have this flag, so it's read from the file header
+/// directly. The cursor is left at the start of the file.
+fn first_attr_read_format_has_lost<C: Read + Seek>(cursor: &mut C) -> std::io::Result<bool> {
+    // perf_file_header: magic, size, attr_size, attrs { offset, size }, ...
+    let mut header = [0; 40];
+    cursor.seek(SeekFrom::Start(0))?;
+    cursor.read_exact(&mut header)?;
+    let endian = match &header[..8] {
+        b"PERFILE2" => Endianness::LittleEndian,
+        b"2ELIFREP" => Endianness::BigEndian,
+        _ => {
+            cursor.seek(SeekFrom::Start(0))?;
+            return Ok(false);
+        }
+    };
+    let read_u64 = |bytes: &[u8]| match endian {
+        Endianness::LittleEndian => u64::from_le_bytes(bytes.try_into().unwrap()),
+        Endianness::BigEndian => u64::from_be_bytes(bytes.try_into().unwrap()),
+    };
+    let attrs_offset = read_u64(&header[24..32]);
+    let attrs_size = read_u64(&header[32..40]);
+    let mut attr = [0; 40];
+    let has_lost = if attrs_size >= attr.len() as u64 {
+        cursor.seek(SeekFrom::Start(attrs_offset))?;
+        cursor.read_exact(&mut attr)?;
+        attr_read_format_has_lost(&attr, endian)
+    } else {
+        false
+    };
+    cursor.seek(SeekFrom::Start(0))?;
+    Ok(has_lost)
+}
+
+/// If the user gave us an explicit profile name, use it as the product name
+/// right away, instead of waiting for the name of the first profiled process.
+pub(crate) fn set_explicit_profile_name<U>(
+    converter: &mut Converter<U>,
+    profile_creation_props: &ProfileCreationProps,
+) where
+    U: Unwinder<Module = Module<MmapRangeOrVec>> + Default,
+{
+    if let Some(profile_name) = &profile_creation_props.profile_name {
+        converter.set_product_name(profile_name);
+    }
+}
+
+/// Returns the initial profile name for a recording from Linux perf, and the
+/// postfix which should be appended to the name of the first profiled process
+/// once it is known. An explicit profile name from the user is applied
+/// separately, see `set_explicit_profile_name`.
+pub(super) fn linux_perf_profile_name(
+    profile_creation_props: &ProfileCreationProps,
+    hostname: Option<&str>,
+    perf_version: Option<&str>,
+) -> (String, Option<String>) {
+    let mut profile_name_postfix = String::new();
+    if let Some(host) = hostname {
+        write!(profile_name_postfix, " on {host}").unwrap();
+    }
+    if let Some(perf_version) = perf_version {
+        write!(profile_name_postfix, " (perf version {perf_version})").unwrap();
+    }
+    // We would like the profile name to start with the name of the process / executable
+    // that has been profiled. However, we don't know this name yet.
+    // Start with the name of the imported perf.data file, but also store the
+    // profile name "postfix" so that we can change the profile name later, once
+    // we see the first profiled process.
+    let imported_file_filename = profile_creation_props.fallback_profile_name.clone();
+    let initial_profile_name = format!("{imported_file_filename}{profile_name_postfix}");
+    (initial_profile_name, Some(profile_name_postfix))
+}
+
+/// The feature sections of a perf.data file or stream which describe the
+/// machine that the recording was made on.
+#[derive(Debug, Clone, Copy, Default)]
+pub(super) struct PerfHeaderInfo<'a> {
+    pub hostname: Option<&'a str>,
+    pub os_release: Option<&'a str>,
+    pub arch: Option<&'a str>,
+    pub cpu_desc: Option<&'a str>,
+    pub perf_version: Option<&'a str>,
+}
+
+/// Adds the machine description from the perf.data header to the profile's
+/// meta information.
+pub(super) fn set_perf_header_meta_info<U>(converter: &mut Converter<U>, info: PerfHeaderInfo)
+where
+    U: Unwinder<Module = Module<MmapRangeOrVec>> + Default,
+{
+    if let Some(hostname) = info.hostname {
+        converter.set_meta_info("Host name", hostname);
+    }
+    if let Some(os_release) = info.os_release {
+        converter.set_meta_info("Kernel version", os_release);
+    }
+    if let Some(arch) = info.arch {
+        converter.set_meta_info("Architecture", arch);
+    }
+    if let Some(cpu_desc) = info.cpu_desc {
+        converter.set_meta_info("CPU", cpu_desc);
+    }
+    if let Some(perf_version) = info.perf_version {
+        converter.set_meta_info("perf version", perf_version);
+    }
+}
+
 fn convert_impl<U, C, R>(
     file: PerfFileReader<R>,
     file_mod_time: Option<SystemTime>,
@@ -75,6 +191,7 @@ fn convert_impl<U, C, R>(
     aux_file_lookup_dirs: Vec<PathBuf>,
     cache: U::Cache,
     profile_creation_props: ProfileCreationProps,
+    main_event_read_format_has_lost: bool,
 ) -> Profile
 where
     U: Unwinder<Module = Module<MmapRangeOrVec>> + Default,
@@ -104,7 +221,8 @@ where
     for event_name in attributes.iter().filter_map(|attr| attr.name()) {
         eprintln!("event {event_name}");
     }
-    let interpretation = EventInterpretation::divine_from_attrs(attributes);
+    let mut interpretation = EventInterpretation::divine_from_attrs(attributes);
+    interpretation.main_event_read_format_has_lost = main_event_read_format_has_lost;
     let simpleperf_symbol_tables = perf_file.simpleperf_symbol_tables().ok().flatten();
     let reference_timestamp = if let Some(seconds_since_unix_epoch) =
         get_simpleperf_timestamp(simpleperf_meta_info.as_ref())
@@ -116,12 +234,9 @@ where
         ReferenceTimestamp::from_system_time(SystemTime::now())
     };
 
-    let (profile_name, mut profile_name_postfix_for_first_process) = if let Some(profile_name) =
-        profile_creation_props.profile_name.clone()
+    let (profile_name, profile_name_postfix_for_first_process) = if let Some(simpleperf_meta_info) =
+        simpleperf_meta_info.as_ref()
     {
-        // The user gave us an explicit profile name. Use it.
-        (profile_name, None)
-    } else if let Some(simpleperf_meta_info) = simpleperf_meta_info.as_ref() {
         // perf.data from simpleperf
         let mut profile_name_postfix = String::new();
         if let Some(profile_name_props) = simpleperf_meta_info.get("product_props") {
@@ -147,21 +262,11 @@ where
         }
     } else {
         // perf.data from Linux perf
-        let mut profile_name_postfix = String::new();
-        if let Some(host) = perf_file.hostname().ok().flatten() {
-            write!(profile_name_postfix, " on {host}").unwrap();
-        }
-        if let Some(perf_version) = perf_file.perf_version().ok().flatten() {
-            write!(profile_name_postfix, " (perf version {perf_version})").unwrap();
-        }
-        // We would like the profile name to start with the name of the process / executable
-        // that has been profiled. However, we don't know this name yet.
-        // Start with the name of the imported perf.data file, but also store the
-        // profile name "postfix" so that we can change the profile name later, once
-        // we see the first profiled process.
-        let imported_file_filename = profile_creation_props.fallback_profile_name.clone();
-        let initial_profile_name = format!("{imported_file_filename}{profile_name_postfix}");
-        (initial_profile_name, Some(profile_name_postfix))
+        linux_perf_profile_name(
+            &profile_creation_props,
+            perf_file.hostname().ok().flatten(),
+            perf_file.perf_version().ok().flatten(),
+        )
     };
 
     let mut converter = Converter::<U>::new(
@@ -179,6 +284,12 @@ where
         simpleperf_symbol_tables,
         call_chain_return_addresses_are_preadjusted,
     );
+    if let Some(postfix) = profile_name_postfix_for_first_process {
+        converter.set_delayed_product_name_generator(move |first_process_name| {
+            format!("{first_process_name}{postfix}")
+        });
+    }
+    set_explicit_profile_name(&mut converter, &profile_creation_props);
 
     if let Some(android_version) = simpleperf_meta_info
         .as_ref()
@@ -187,83 +298,252 @@ where
         converter.set_os_name(&format!("Android {android_version}"));
     }
 
+    converter.set_meta_info(
+        "perf.data file",
+        &profile_creation_props.fallback_profile_name,
+    );
+    if let Ok(Some(cmd_line)) = perf_file.cmdline() {
+        converter.set_meta_info("Command line", &cmd_line.join(" "));
+    }
+    set_perf_header_meta_info(
+        &mut converter,
+        PerfHeaderInfo {
+            hostname: perf_file.hostname().ok().flatten(),
+            os_release: perf_file.os_release().ok().flatten(),
+            arch: perf_file.arch().ok().flatten(),
+            cpu_desc: perf_file.cpu_desc().ok().flatten(),
+            perf_version: perf_file.perf_version().ok().flatten(),
+        },
+    );
+    if let Some(interval) = profile_creation_props.sampling_interval_override {
+        converter.set_sampling_interval(SamplingInterval::from_nanos(interval.as_nanos() as u64));
+    }
+
+    let mut reorder_window = profile_creation_props
+        .reorder_window
+        .map(ReorderWindow::<OwnedEventRecord>::new);
     let mut last_timestamp = 0;
 
-    while let Ok(Some(record)) = record_iter.next_record(&mut perf_file) {
-        let (record, parsed_record, attr_index) = match record {
-            PerfFileRecord::EventRecord { attr_index, record } => match record.parse() {
-                Ok(r) => (record, r, attr_index),
-                Err(_) => continue,
-            },
-            PerfFileRecord::UserRecord(_) => continue,
+    loop {
+        let record = match record_iter.next_record(&mut perf_file) {
+            Ok(Some(record)) => record,
+            Ok(None) => break,
+            Err(err) => {
+                // This usually means that the recording was killed before it
+                // could finish writing the file. Keep what we have so far.
+                converter.handle_truncated_input(err.to_string());
+                break;
+            }
         };
-        if let Some(timestamp) = record.timestamp() {
-            if timestamp < last_timestamp {
-                eprintln!(
-                    "bad timestamp ordering; {timestamp} is earlier but arrived after {last_timestamp}"
-                );
+        let (attr_index, record) = match record {
+            PerfFileRecord::EventRecord { attr_index, record } => (attr_index, record),
+            PerfFileRecord::UserRecord(record) => {
+                // The trace data of PERF_RECORD_AUXTRACE records follows the
+                // record in the file, and the perf.data reader doesn't return
+                // it, so AUX area traces are only decoded in pipe mode.
+                if record.record_type == UserRecordType::PERF_AUXTRACE_INFO {
+                    converter.handle_auxtrace_info(&record.data.as_slice());
+                }
+                continue;
             }
-            last_timestamp = timestamp;
+        };
+        let Some(reorder_window) = reorder_window.as_mut() else {
+            handle_raw_record::<U, C>(
+                &mut converter,
+                &interpretation,
+                &record,
+                attr_index,
+                &mut last_timestamp,
+            );
+            continue;
+        };
+        let owned_record = OwnedEventRecord::new(&record, attr_index);
+        if let Some(ready) = reorder_window.push(record.timestamp(), owned_record) {
+            handle_raw_record::<U, C>(
+                &mut converter,
+                &interpretation,
+                &ready.raw_record(),
+                ready.attr_index,
+                &mut last_timestamp,
+            );
         }
+    }
 
-        match parsed_record {
-            EventRecord::Sample(e) => {
-                if attr_index == interpretation.main_event_attr_index {
-                    converter.handle_main_event_sample::<C>(&e);
-                } else if Some(attr_index) == interpretation.sched_switch_attr_index {
-                    converter.handle_sched_switch_sample::<C>(&e);
-                }
+    if let Some(reorder_window) = reorder_window.as_mut() {
+        while let Some(ready) = reorder_window.pop() {
+            handle_raw_record::<U, C>(
+                &mut converter,
+                &interpretation,
+                &ready.raw_record(),
+                ready.attr_index,
+                &mut last_timestamp,
+            );
+        }
+    }
 
-                match interpretation.known_event_indices.get(&attr_index) {
-                    Some(KnownEvent::RssStat) => converter.handle_rss_stat_sample::<C>(&e),
-                    _ => {
-                        // the main event and sched_switch are already covered by regular samples so don't add other event markers
-                        if !(attr_index == interpretation.main_event_attr_index
-                            || Some(attr_index) == interpretation.sched_switch_attr_index)
-                        {
-                            converter.handle_other_event_sample::<C>(&e, attr_index)
-                        }
-                    }
-                }
-            }
-            EventRecord::Fork(e) => {
-                converter.handle_fork(e);
+    let (profile, report) = converter.finish_with_report();
+    if !report.is_empty() {
+        eprint!("{report}");
+    }
+    profile
+}
+
+/// A copy of an event record which can outlive the record iterator's buffer,
+/// so that it can be held back in the reorder window.
+struct OwnedEventRecord {
+    record_type: RecordType,
+    misc: u16,
+    data: Vec<u8>,
+    parse_info: RecordParseInfo,
+    attr_index: usize,
+}
+
+impl OwnedEventRecord {
+    fn new(record: &RawEventRecord, attr_index: usize) -> Self {
+        Self {
+            record_type: record.record_type,
+            misc: record.misc,
+            data: record.data.as_slice().into_owned(),
+            parse_info: record.parse_info,
+            attr_index,
+        }
+    }
+
+    fn raw_record(&self) -> RawEventRecord<'_> {
+        RawEventRecord::new(
+            self.record_type,
+            self.misc,
+            RawData::from(&self.data[..]),
+            self.parse_info,
+        )
+    }
+}
+
+/// Parses an event record, checks that records arrive in timestamp order, and
+/// dispatches the record to the converter.
+fn handle_raw_record<U, C>(
+    converter: &mut Converter<U>,
+    interpretation: &EventInterpretation,
+    record: &RawEventRecord,
+    attr_index: usize,
+    last_timestamp: &mut u64,
+) where
+    U: Unwinder<Module = Module<MmapRangeOrVec>> + Default,
+    C: ConvertRegs<UnwindRegs = U::UnwindRegs>,
+{
+    let Ok(parsed_record) = record.parse() else {
+        return;
+    };
+    if let Some(timestamp) = record.timestamp() {
+        if timestamp < *last_timestamp {
+            eprintln!(
+                "bad timestamp ordering; {timestamp} is earlier but arrived after {last_timestamp}"
+            );
+        }
+        *last_timestamp = timestamp;
+    }
+
+    handle_record::<U, C>(
+        converter,
+        interpretation,
+        record,
+        parsed_record,
+        attr_index,
+        *last_timestamp,
+    );
+}
+
+/// Dispatches a single parsed record to the matching `Converter` handler.
+///
+/// This is shared between the perf.data file importer and the pipe-mode
+/// importer in `perf_pipe`.
+pub(super) fn handle_record<U, C>(
+    converter: &mut Converter<U>,
+    interpretation: &EventInterpretation,
+    record: &RawEventRecord,
+    parsed_record: EventRecord,
+    attr_index: usize,
+    last_timestamp: u64,
+) where
+    U: Unwinder<Module = Module<MmapRangeOrVec>> + Default,
+    C: ConvertRegs<UnwindRegs = U::UnwindRegs>,
+{
+    if let Some(timestamp) = record.timestamp() {
+        converter.anchor_time_range(timestamp);
+    }
+    match parsed_record {
+        EventRecord::Sample(e) => {
+            if attr_index == interpretation.main_event_attr_index {
+                let extra_fields = SampleExtraFields::parse(
+                    record,
+                    interpretation.main_event_read_format_has_lost,
+                )
+                .unwrap_or_default();
+                converter.handle_main_event_sample::<C>(&e, attr_index, &extra_fields);
+            } else if Some(attr_index) == interpretation.sched_switch_attr_index {
+                converter.handle_sched_switch_sample::<C>(&e);
             }
-            EventRecord::Comm(e) => {
-                if profile_name_postfix_for_first_process.is_some()
-                    && &e.name.as_slice()[..] != b"perf-exec"
-                {
-                    let postfix = profile_name_postfix_for_first_process.take().unwrap();
-                    let first_process_name =
-                        String::from_utf8_lossy(&e.name.as_slice()).to_string();
-                    let profile_name = format!("{first_process_name}{postfix}");
-                    converter.set_profile_name(&profile_name);
+
+            match interpretation.known_event_indices.get(&attr_index) {
+                Some(KnownEvent::RssStat) => converter.handle_rss_stat_sample::<C>(&e),
+                Some(KnownEvent::SchedWaking | KnownEvent::SchedWakeup) => {
+                    converter.handle_sched_wakeup_sample::<C>(&e, attr_index)
+                }
+                Some(KnownEvent::RawSyscallEnter) => {
+                    converter.handle_raw_syscall_sample::<C>(&e, attr_index, true)
+                }
+                Some(KnownEvent::RawSyscallExit) => {
+                    converter.handle_raw_syscall_sample::<C>(&e, attr_index, false)
+                }
+                _ => {
+                    // the main event and sched_switch are already covered by regular samples so don't add other event markers
+                    if !(attr_index == interpretation.main_event_attr_index
+                        || Some(attr_index) == interpretation.sched_switch_attr_index)
+                    {
+                        converter.handle_other_event_sample::<C>(&e, attr_index)
+                    }
                 }
-                converter.handle_comm(e, record.timestamp());
-            }
-            EventRecord::Exit(e) => {
-                converter.handle_exit(e);
-            }
-            EventRecord::Mmap(e) => {
-                converter.handle_mmap(e, last_timestamp);
-            }
-            EventRecord::Mmap2(e) => {
-                converter.handle_mmap2(e, last_timestamp);
-            }
-            EventRecord::ContextSwitch(e) => {
-                let common = match record.common_data() {
-                    Ok(common) => common,
-                    Err(_) => continue,
-                };
-                converter.handle_context_switch(e, common);
-            }
-            _ => {
-                // println!("{:?}", record.record_type);
             }
         }
+        EventRecord::Fork(e) => {
+            converter.handle_fork(e);
+        }
+        EventRecord::Comm(e) => {
+            converter.handle_comm(e, record.timestamp());
+        }
+        EventRecord::Exit(e) => {
+            converter.handle_exit(e);
+        }
+        EventRecord::Mmap(e) => {
+            converter.handle_mmap(e, last_timestamp);
+        }
+        EventRecord::Mmap2(e) => {
+            converter.handle_mmap2(e, last_timestamp);
+        }
+        EventRecord::Lost(e) => {
+            converter.handle_lost(e);
+        }
+        EventRecord::ContextSwitch(e) => {
+            let common = match record.common_data() {
+                Ok(common) => common,
+                Err(_) => return,
+            };
+            converter.handle_context_switch(e, common);
+        }
+        EventRecord::Raw(raw) if raw.record_type == RecordType::AUX => {
+            converter.handle_aux(&raw);
+        }
+        EventRecord::Raw(raw) if raw.record_type == RecordType::TEXT_POKE => {
+            let common = match record.common_data() {
+                Ok(common) => common,
+                Err(_) => return,
+            };
+            converter.handle_text_poke(&raw, common);
+        }
+        _ => {
+            // println!("{:?}", record.record_type);
+        }
     }
-
-    converter.finish()
 }
 
 fn get_simpleperf_timestamp(meta_info: Option<&HashMap<&str, &str>>) -> Option<f64> {
@@ -293,10 +573,113 @@ fn get_simpleperf_timestamp(meta_info: Option<&HashMap<&str, &str>>) -> Option<f
 /// whether the build ID length is "real" or guessed.
 fn fixup_perf_jit_build_ids(build_ids: &mut HashMap<DsoKey, DsoInfo>) {
     for (key, info) in build_ids {
-        let name = key.name();
-        if name.starts_with("jitted-") && name.ends_with(".so") && info.build_id.len() == 16 {
-            // Extend to 20 bytes.
-            info.build_id.extend_from_slice(&[0, 0, 0, 0]);
+        fixup_perf_jit_build_id(key, info);
+    }
+}
+
+/// See [`fixup_perf_jit_build_ids`].
+pub(super) fn fixup_perf_jit_build_id(key: &DsoKey, info: &mut DsoInfo) {
+    let name = key.name();
+    if name.starts_with("jitted-") && name.ends_with(".so") && info.build_id.len() == 16 {
+        // Extend to 20 bytes.
+        info.build_id.extend_from_slice(&[0, 0, 0, 0]);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+    use std::time::Duration;
+
+    use super::*;
+
+    const PERF_RECORD_SAMPLE: u32 = 9;
+    const PERF_RECORD_MISC_USER: u16 = 2;
+
+    /// A perf.data file with a single cpu-clock event and the given sample
+    /// records, but without any feature sections, i.e. without
+    /// `HEADER_SAMPLE_TIME`.
+    fn perf_data_without_features(records: &[Vec<u8>]) -> Vec<u8> {
+        // struct perf_event_attr, PERF_ATTR_SIZE_VER0
+        let mut attr = vec![];
+        attr.extend_from_slice(&1u32.to_le_bytes()); // PERF_TYPE_SOFTWARE
+        attr.extend_from_slice(&64u32.to_le_bytes()); // size
+        attr.extend_from_slice(&0u64.to_le_bytes()); // PERF_COUNT_SW_CPU_CLOCK
+        attr.extend_from_slice(&1_000_000u64.to_le_bytes()); // sample_period
+        attr.extend_from_slice(&0b111u64.to_le_bytes()); // IP | TID | TIME
+        attr.resize(64, 0);
+        let data: Vec<u8> = records.concat();
+
+        const HEADER_SIZE: u64 = 104;
+        let attr_section_offset = HEADER_SIZE;
+        let attr_section_size = attr.len() as u64 + 16;
+        let data_section_offset = attr_section_offset + attr_section_size;
+        let mut file = vec![];
+        file.extend_from_slice(b"PERFILE2");
+        file.extend_from_slice(&HEADER_SIZE.to_le_bytes());
+        file.extend_from_slice(&attr_section_size.to_le_bytes()); // attr_size
+        for (offset, size) in [
+            (attr_section_offset, attr_section_size),
+            (data_section_offset, data.len() as u64),
+            (0, 0), // event types
+        ] {
+            file.extend_from_slice(&offset.to_le_bytes());
+            file.extend_from_slice(&size.to_le_bytes());
         }
+        file.extend_from_slice(&[0; 32]); // no features
+        file.extend_from_slice(&attr);
+        file.extend_from_slice(&[0; 16]); // no event ids
+        file.extend_from_slice(&data);
+        file
+    }
+
+    fn sample_record(pid: u32, timestamp: u64) -> Vec<u8> {
+        let mut record = vec![];
+        record.extend_from_slice(&PERF_RECORD_SAMPLE.to_le_bytes());
+        record.extend_from_slice(&PERF_RECORD_MISC_USER.to_le_bytes());
+        record.extend_from_slice(&32u16.to_le_bytes());
+        record.extend_from_slice(&0x1000u64.to_le_bytes()); // ip
+        record.extend_from_slice(&pid.to_le_bytes());
+        record.extend_from_slice(&pid.to_le_bytes());
+        record.extend_from_slice(&timestamp.to_le_bytes());
+        record
+    }
+
+    fn sample_count(profile: &Profile) -> u64 {
+        let json = serde_json::to_value(profile).unwrap();
+        json["threads"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|thread| thread["samples"]["length"].as_u64().unwrap())
+            .sum()
+    }
+
+    #[test]
+    fn time_range_without_sample_time_header() {
+        let start = 5_000_000_000;
+        let records: Vec<Vec<u8>> = (0..10)
+            .map(|i| sample_record(100, start + i * 1_000_000))
+            .collect();
+        let file = perf_data_without_features(&records);
+        let mut props = ProfileCreationProps::for_tests();
+        props.time_range = Some((Duration::from_millis(2), Duration::from_millis(5)));
+        let profile = convert(Cursor::new(file), None, vec![], vec![], props).unwrap();
+        // The range is relative to the first sample, not to the clock's epoch.
+        assert_eq!(sample_count(&profile), 3);
+    }
+
+    #[test]
+    fn read_format_lost_flag_from_file_header() {
+        let mut file = perf_data_without_features(&[]);
+        let mut cursor = Cursor::new(&file);
+        assert!(!first_attr_read_format_has_lost(&mut cursor).unwrap());
+
+        // read_format of the first attr, which starts right after the header.
+        let read_format_offset = 104 + 32;
+        file[read_format_offset..read_format_offset + 8].copy_from_slice(&0x10u64.to_le_bytes());
+        let mut cursor = Cursor::new(&file);
+        assert!(first_attr_read_format_has_lost(&mut cursor).unwrap());
+        assert_eq!(cursor.position(), 0);
     }
 }
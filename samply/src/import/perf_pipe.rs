@@ -0,0 +1,1083 @@
+//! Conversion of perf.data streams in "pipe mode", i.e. the output of
+//! `perf record -o -`.
+//!
+//! Pipe-mode streams don't have a file header with section offsets, and they
+//! can't be seeked. Instead, the event attributes and the feature sections
+//! are sent as special records at the start of the stream, followed by the
+//! regular event records. This lets us process records while perf is still
+//! recording.
+//!
+//! While perf is still recording, the caller is kept informed through a
+//! [`PipeConversionObserver`], which can also ask for snapshots of the profile
+//! so far.
+
+use std::collections::HashMap;
+use std::io::{Cursor, Read};
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
+use framehop::{Module, Unwinder};
+use fxprof_processed_profile::{Profile, ReferenceTimestamp, SamplingInterval};
+use linux_perf_data::{
+    linux_perf_event_reader, AttributeDescription, DsoInfo, DsoKey, Endianness, Feature,
+    UserRecordType,
+};
+use linux_perf_event_reader::constants::PERF_RECORD_MISC_BUILD_ID_SIZE;
+use linux_perf_event_reader::{
+    get_record_id, get_record_identifier, get_record_timestamp, AttrFlags, CpuMode, PerfEventAttr,
+    RawData, RawEventRecord, RecordIdParseInfo, RecordParseInfo, RecordType,
+};
+
+use super::perf::{
+    fixup_perf_jit_build_id, handle_record, linux_perf_profile_name, set_explicit_profile_name,
+    set_perf_header_meta_info, Error, PerfHeaderInfo,
+};
+use crate::linux_shared::{
+    attr_read_format_has_lost, new_aux_decoder, AuxTraceChunk, ConvertRegs, ConvertRegsAarch64,
+    ConvertRegsX86_64, Converter, EventInterpretation, MmapRangeOrVec,
+};
+use crate::shared::recording_props::ProfileCreationProps;
+
+const PERF_RECORD_HEADER_SIZE: usize = 8;
+const PERF_RECORD_HEADER_TRACING_DATA: u32 = 66;
+
+/// Progress information which is passed to the progress callback of
+/// [`convert_pipe`] every time a batch of records has been converted.
+#[derive(Debug, Clone, Default)]
+pub struct PipeConversionProgress {
+    /// The number of event records which have been converted so far.
+    pub record_count: u64,
+    /// The number of sample records which have been converted so far.
+    pub sample_count: u64,
+    /// The timestamp of the most recently converted record, in nanoseconds.
+    pub last_timestamp: u64,
+}
+
+/// Receives progress updates and profile snapshots from [`convert_pipe`].
+///
+/// This is implemented for closures which only care about progress.
+pub trait PipeConversionObserver {
+    /// Called after every batch of converted records.
+    fn on_progress(&mut self, progress: &PipeConversionProgress);
+
+    /// Called after `on_progress`. If this returns true, a snapshot of the
+    /// profile so far is created and passed to `on_snapshot`.
+    fn wants_snapshot(&mut self, _progress: &PipeConversionProgress) -> bool {
+        false
+    }
+
+    /// Called with a snapshot of the profile, see [`Converter::snapshot`] for
+    /// what is included in it.
+    fn on_snapshot(&mut self, _profile: Profile) {}
+}
+
+impl<F: FnMut(&PipeConversionProgress)> PipeConversionObserver for F {
+    fn on_progress(&mut self, progress: &PipeConversionProgress) {
+        self(progress)
+    }
+}
+
+/// Convert a pipe-mode perf.data stream into a profile.
+///
+/// Records are converted as they arrive. `observer` is notified after every
+/// batch of converted records (perf flushes a batch for each
+/// `FINISHED_ROUND` record), so that callers can report progress, and save
+/// snapshots of the profile, while perf is still recording. The final profile
+/// is returned once `reader` reaches EOF.
+///
+/// If the stream ends in the middle of a record, for example because perf was
+/// killed, the incomplete record is dropped and the profile is created from
+/// the records which were received completely.
+pub fn convert_pipe<R: Read>(
+    reader: R,
+    binary_lookup_dirs: Vec<PathBuf>,
+    aux_file_lookup_dirs: Vec<PathBuf>,
+    profile_creation_props: ProfileCreationProps,
+    observer: impl PipeConversionObserver,
+) -> Result<Profile, Error> {
+    let mut reader = PipeRecordReader::new(reader)?;
+    let header = PipeHeader::read(&mut reader)?;
+
+    let profile = match header.arch.as_deref() {
+        Some("aarch64") => {
+            let cache = framehop::aarch64::CacheAarch64::new();
+            convert_pipe_impl::<
+                framehop::aarch64::UnwinderAarch64<MmapRangeOrVec>,
+                ConvertRegsAarch64,
+                _,
+            >(
+                reader,
+                header,
+                binary_lookup_dirs,
+                aux_file_lookup_dirs,
+                cache,
+                profile_creation_props,
+                observer,
+            )?
+        }
+        arch => {
+            if arch != Some("x86_64") {
+                eprintln!(
+                    "Unknown arch {}, dwarf-based unwinding may be incorrect.",
+                    arch.unwrap_or_default()
+                );
+            }
+            let cache = framehop::x86_64::CacheX86_64::new();
+            convert_pipe_impl::<
+                framehop::x86_64::UnwinderX86_64<MmapRangeOrVec>,
+                ConvertRegsX86_64,
+                _,
+            >(
+                reader,
+                header,
+                binary_lookup_dirs,
+                aux_file_lookup_dirs,
+                cache,
+                profile_creation_props,
+                observer,
+            )?
+        }
+    };
+    Ok(profile)
+}
+
+fn convert_pipe_impl<U, C, R>(
+    mut reader: PipeRecordReader<R>,
+    mut header: PipeHeader,
+    binary_lookup_dirs: Vec<PathBuf>,
+    aux_file_lookup_dirs: Vec<PathBuf>,
+    cache: U::Cache,
+    profile_creation_props: ProfileCreationProps,
+    mut observer: impl PipeConversionObserver,
+) -> Result<Profile, Error>
+where
+    U: Unwinder<Module = Module<MmapRangeOrVec>> + Default,
+    C: ConvertRegs<UnwindRegs = U::UnwindRegs>,
+    R: Read,
+{
+    let endian = reader.endian;
+    let mut interpretation = EventInterpretation::divine_from_attrs(&header.attributes);
+    interpretation.main_event_read_format_has_lost = header.main_event_read_format_has_lost;
+    let parse_infos: Vec<RecordParseInfo> = header
+        .attributes
+        .iter()
+        .map(|attr| RecordParseInfo::new(&attr.attr, endian))
+        .collect();
+    let attr_index_lookup = AttrIndexLookup::new(&header.attributes, endian);
+
+    let (profile_name, mut profile_name_postfix_for_first_process) = linux_perf_profile_name(
+        &profile_creation_props,
+        header.hostname.as_deref(),
+        header.perf_version.as_deref(),
+    );
+
+    // The converter needs to know the first sample time up front, so we only
+    // create it once the first batch of records is ready to be converted.
+    let mut converter: Option<Converter<U>> = None;
+    let mut cache = Some(cache);
+    let mut binary_lookup_dirs = Some(binary_lookup_dirs);
+    let mut aux_file_lookup_dirs = Some(aux_file_lookup_dirs);
+    let reference_timestamp = ReferenceTimestamp::from_system_time(SystemTime::now());
+    // Build IDs which arrive before the converter is created are collected here.
+    let mut build_ids = std::mem::take(&mut header.build_ids);
+
+    // AUX area trace records don't have a timestamp. They are handled after
+    // the batch of event records that arrived before them.
+    let mut aux_records: Vec<PipeRecord> = Vec::new();
+
+    let mut sorter = RoundSorter::default();
+    let mut progress = PipeConversionProgress::default();
+    let mut last_timestamp = 0;
+    let mut stream_ended = false;
+
+    while !stream_ended {
+        match reader.next_record()? {
+            Some(record) if record.record_type.is_builtin_type() => {
+                let attr_index = attr_index_lookup.attr_index(&record);
+                let timestamp = get_timestamp(&record, &parse_infos[attr_index], endian);
+                sorter.insert(record, attr_index, timestamp);
+                continue;
+            }
+            Some(record)
+                if UserRecordType::try_from(record.record_type)
+                    == Some(UserRecordType::PERF_FINISHED_ROUND) =>
+            {
+                sorter.finish_round();
+            }
+            Some(record)
+                if UserRecordType::try_from(record.record_type)
+                    == Some(UserRecordType::PERF_HEADER_BUILD_ID) =>
+            {
+                if let Some((dso_key, dso_info)) = parse_build_id_record(record.misc, &record.data)
+                {
+                    match &mut converter {
+                        Some(converter) => converter.add_build_id(dso_key, dso_info),
+                        None => {
+                            build_ids.insert(dso_key, dso_info);
+                        }
+                    }
+                }
+                continue;
+            }
+            Some(record)
+                if UserRecordType::try_from(record.record_type)
+                    == Some(UserRecordType::PERF_AUXTRACE)
+                    && !reader.has_aux_decoder =>
+            {
+                // The reader skipped the trace data, there's nothing to decode.
+                continue;
+            }
+            Some(record)
+                if matches!(
+                    UserRecordType::try_from(record.record_type),
+                    Some(UserRecordType::PERF_AUXTRACE_INFO | UserRecordType::PERF_AUXTRACE)
+                ) =>
+            {
+                aux_records.push(record);
+                continue;
+            }
+            Some(_) => continue,
+            None => {
+                sorter.finish();
+                stream_ended = true;
+            }
+        }
+
+        let batch = sorter.take_ready();
+        if batch.is_empty() {
+            continue;
+        }
+
+        let converter = converter.get_or_insert_with(|| {
+            let first_sample_time = batch
+                .iter()
+                .filter(|r| r.record.record_type == RecordType::SAMPLE)
+                .find_map(|r| r.timestamp)
+                .or_else(|| batch.iter().find_map(|r| r.timestamp.filter(|t| *t != 0)))
+                .unwrap_or_default();
+            let mut converter = Converter::<U>::new(
+                &profile_creation_props,
+                reference_timestamp,
+                &profile_name,
+                std::mem::take(&mut build_ids),
+                header.linux_version.as_deref(),
+                first_sample_time,
+                endian,
+                cache.take().unwrap(),
+                binary_lookup_dirs.take().unwrap(),
+                aux_file_lookup_dirs.take().unwrap(),
+                interpretation.clone(),
+                None,
+                false,
+            );
+            set_perf_header_meta_info(
+                &mut converter,
+                PerfHeaderInfo {
+                    hostname: header.hostname.as_deref(),
+                    os_release: header.linux_version.as_deref(),
+                    arch: header.arch.as_deref(),
+                    cpu_desc: header.cpu_desc.as_deref(),
+                    perf_version: header.perf_version.as_deref(),
+                },
+            );
+            if let Some(interval) = profile_creation_props.sampling_interval_override {
+                converter.set_sampling_interval(SamplingInterval::from_nanos(
+                    interval.as_nanos() as u64
+                ));
+            }
+            if let Some(auxtrace_info) = header.auxtrace_info.take() {
+                converter.handle_auxtrace_info(&auxtrace_info.data);
+            }
+            if let Some(postfix) = profile_name_postfix_for_first_process.take() {
+                converter.set_delayed_product_name_generator(move |first_process_name| {
+                    format!("{first_process_name}{postfix}")
+                });
+            }
+            set_explicit_profile_name(&mut converter, &profile_creation_props);
+            converter
+        });
+
+        for pending in batch {
+            let PendingRecord {
+                record,
+                attr_index,
+                timestamp,
+            } = pending;
+            let raw_record = RawEventRecord::new(
+                record.record_type,
+                record.misc,
+                RawData::from(&record.data[..]),
+                parse_infos[attr_index],
+            );
+            let parsed_record = match raw_record.parse() {
+                Ok(r) => r,
+                Err(_) => continue,
+            };
+            if let Some(timestamp) = timestamp {
+                last_timestamp = timestamp;
+            }
+            progress.record_count += 1;
+            if record.record_type == RecordType::SAMPLE {
+                progress.sample_count += 1;
+            }
+            handle_record::<U, C>(
+                converter,
+                &interpretation,
+                &raw_record,
+                parsed_record,
+                attr_index,
+                last_timestamp,
+            );
+        }
+        for record in aux_records.drain(..) {
+            if UserRecordType::try_from(record.record_type)
+                == Some(UserRecordType::PERF_AUXTRACE_INFO)
+            {
+                converter.handle_auxtrace_info(&record.data);
+            } else {
+                converter.handle_auxtrace(&record.data);
+            }
+        }
+        progress.last_timestamp = last_timestamp;
+        observer.on_progress(&progress);
+        if !stream_ended && observer.wants_snapshot(&progress) {
+            observer.on_snapshot(converter.snapshot());
+        }
+    }
+
+    let profile = match converter {
+        Some(converter) => {
+            let (profile, report) = converter.finish_with_report();
+            if !report.is_empty() {
+                eprint!("{report}");
+            }
+            profile
+        }
+        None => Profile::new(
+            &profile_name,
+            reference_timestamp,
+            fxprof_processed_profile::SamplingInterval::from_millis(1),
+        ),
+    };
+    Ok(profile)
+}
+
+fn get_timestamp(
+    record: &PipeRecord,
+    parse_info: &RecordParseInfo,
+    endian: Endianness,
+) -> Option<u64> {
+    let data = RawData::from(&record.data[..]);
+    match endian {
+        Endianness::LittleEndian => {
+            get_record_timestamp::<LittleEndian>(record.record_type, data, parse_info)
+        }
+        Endianness::BigEndian => {
+            get_record_timestamp::<BigEndian>(record.record_type, data, parse_info)
+        }
+    }
+}
+
+/// A record from the stream, with an owned copy of its body.
+struct PipeRecord {
+    record_type: RecordType,
+    misc: u16,
+    data: Vec<u8>,
+}
+
+/// Reads records from a pipe-mode stream.
+struct PipeRecordReader<R: Read> {
+    reader: R,
+    endian: Endianness,
+    /// A record which was read by [`PipeHeader::read`] but which belongs to
+    /// the event stream.
+    peeked: Option<PipeRecord>,
+    /// Whether a `PERF_RECORD_AUXTRACE_INFO` record selected a trace type for
+    /// which we have a decoder. Without one, the AUX trace data is skipped.
+    has_aux_decoder: bool,
+}
+
+impl<R: Read> PipeRecordReader<R> {
+    fn new(mut reader: R) -> Result<Self, Error> {
+        // struct perf_pipe_file_header {
+        //     u64 magic;
+        //     u64 size;
+        // };
+        let mut header = [0; 16];
+        reader.read_exact(&mut header)?;
+        let magic: [u8; 8] = header[..8].try_into().unwrap();
+        let endian = match &magic {
+            b"PERFILE2" => Endianness::LittleEndian,
+            b"2ELIFREP" => Endianness::BigEndian,
+            _ => return Err(linux_perf_data::Error::UnrecognizedMagicValue(magic).into()),
+        };
+        let header_size = read_u64(endian, &header[8..]);
+        if header_size != 16 {
+            // This is a regular perf.data file, which needs to be seekable.
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "not a pipe-mode perf.data stream",
+            )
+            .into());
+        }
+        Ok(Self {
+            reader,
+            endian,
+            peeked: None,
+            has_aux_decoder: false,
+        })
+    }
+
+    /// Returns the next record, or `None` once the stream has ended.
+    ///
+    /// An incomplete record at the end of the stream is treated like the end
+    /// of the stream.
+    fn next_record(&mut self) -> Result<Option<PipeRecord>, Error> {
+        if let Some(record) = self.peeked.take() {
+            return Ok(Some(record));
+        }
+
+        let mut header = [0; PERF_RECORD_HEADER_SIZE];
+        let header_len = read_fully(&mut self.reader, &mut header)?;
+        if header_len == 0 {
+            return Ok(None);
+        }
+        if header_len < header.len() {
+            eprintln!("The perf.data stream ended in the middle of a record header.");
+            return Ok(None);
+        }
+        let record_type = read_u32(self.endian, &header[0..4]);
+        let misc = read_u16(self.endian, &header[4..6]);
+        let size = read_u16(self.endian, &header[6..8]) as usize;
+        if size < PERF_RECORD_HEADER_SIZE {
+            return Err(linux_perf_data::Error::InvalidPerfEventSize.into());
+        }
+        let mut data = vec![0; size - PERF_RECORD_HEADER_SIZE];
+        if read_fully(&mut self.reader, &mut data)? < data.len() {
+            eprintln!("The perf.data stream ended in the middle of a record.");
+            return Ok(None);
+        }
+
+        if record_type == PERF_RECORD_HEADER_TRACING_DATA && data.len() >= 4 {
+            // The tracing data itself follows the record, padded to 8 bytes.
+            let tracing_data_size = read_u32(self.endian, &data[0..4]) as u64;
+            let padded_size = (tracing_data_size + 7) & !7;
+            let skipped = std::io::copy(
+                &mut (&mut self.reader).take(padded_size),
+                &mut std::io::sink(),
+            )?;
+            if skipped < padded_size {
+                eprintln!("The perf.data stream ended in the middle of the tracing data.");
+                return Ok(None);
+            }
+        }
+
+        match UserRecordType::try_from(RecordType(record_type)) {
+            Some(UserRecordType::PERF_AUXTRACE_INFO) if !self.has_aux_decoder => {
+                self.has_aux_decoder = new_aux_decoder(&data, self.endian).is_some();
+            }
+            Some(UserRecordType::PERF_AUXTRACE) => {
+                // The trace data follows the record. If it can be decoded, keep
+                // it with the record. Otherwise skip it without buffering it,
+                // it can be hundreds of megabytes.
+                let trace_data_size =
+                    AuxTraceChunk::trace_data_size(&data, self.endian).unwrap_or_default();
+                let read = if self.has_aux_decoder {
+                    (&mut self.reader)
+                        .take(trace_data_size)
+                        .read_to_end(&mut data)? as u64
+                } else {
+                    std::io::copy(
+                        &mut (&mut self.reader).take(trace_data_size),
+                        &mut std::io::sink(),
+                    )?
+                };
+                if read < trace_data_size {
+                    eprintln!("The perf.data stream ended in the middle of the AUX trace data.");
+                    return Ok(None);
+                }
+            }
+            _ => {}
+        }
+
+        Ok(Some(PipeRecord {
+            record_type: RecordType(record_type),
+            misc,
+            data,
+        }))
+    }
+}
+
+/// Reads into `buf` until it is full or until the reader reaches EOF.
+/// Pipes often return fewer bytes than requested, so a single `read` call
+/// isn't enough to get a complete record.
+fn read_fully(reader: &mut impl Read, buf: &mut [u8]) -> std::io::Result<usize> {
+    let mut len = 0;
+    while len < buf.len() {
+        match reader.read(&mut buf[len..]) {
+            Ok(0) => break,
+            Ok(n) => len += n,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(len)
+}
+
+fn read_u16(endian: Endianness, bytes: &[u8]) -> u16 {
+    match endian {
+        Endianness::LittleEndian => LittleEndian::read_u16(bytes),
+        Endianness::BigEndian => BigEndian::read_u16(bytes),
+    }
+}
+
+fn read_u32(endian: Endianness, bytes: &[u8]) -> u32 {
+    match endian {
+        Endianness::LittleEndian => LittleEndian::read_u32(bytes),
+        Endianness::BigEndian => BigEndian::read_u32(bytes),
+    }
+}
+
+fn read_u64(endian: Endianness, bytes: &[u8]) -> u64 {
+    match endian {
+        Endianness::LittleEndian => LittleEndian::read_u64(bytes),
+        Endianness::BigEndian => BigEndian::read_u64(bytes),
+    }
+}
+
+/// The information from the records at the start of a pipe-mode stream,
+/// i.e. from the `HEADER_ATTR` and `HEADER_FEATURE` records.
+struct PipeHeader {
+    attributes: Vec<AttributeDescription>,
+    /// Whether the first attr has `PERF_FORMAT_LOST`, see
+    /// `EventInterpretation::main_event_read_format_has_lost`.
+    main_event_read_format_has_lost: bool,
+    hostname: Option<String>,
+    linux_version: Option<String>,
+    perf_version: Option<String>,
+    arch: Option<String>,
+    cpu_desc: Option<String>,
+    build_ids: HashMap<DsoKey, DsoInfo>,
+    /// The `PERF_RECORD_AUXTRACE_INFO` record, if the recording has AUX area
+    /// trace data.
+    auxtrace_info: Option<PipeRecord>,
+}
+
+impl PipeHeader {
+    /// Reads records until the first event record. The event record is kept
+    /// in the reader so that it's returned by the next call to `next_record`.
+    fn read<R: Read>(reader: &mut PipeRecordReader<R>) -> Result<Self, Error> {
+        let endian = reader.endian;
+        let mut attributes = Vec::new();
+        let mut main_event_read_format_has_lost = false;
+        let mut event_desc = Vec::new();
+        let mut hostname = None;
+        let mut linux_version = None;
+        let mut perf_version = None;
+        let mut arch = None;
+        let mut cpu_desc = None;
+        let mut build_ids = HashMap::new();
+        let mut auxtrace_info = None;
+
+        while let Some(record) = reader.next_record()? {
+            if record.record_type.is_builtin_type() {
+                reader.peeked = Some(record);
+                break;
+            }
+            match UserRecordType::try_from(record.record_type) {
+                Some(UserRecordType::PERF_HEADER_ATTR) => {
+                    if attributes.is_empty() {
+                        main_event_read_format_has_lost =
+                            attr_read_format_has_lost(&record.data, endian);
+                    }
+                    attributes.push(parse_header_attr(&record.data, endian)?);
+                }
+                Some(UserRecordType::PERF_HEADER_BUILD_ID) => {
+                    build_ids.extend(parse_build_id_record(record.misc, &record.data));
+                }
+                Some(UserRecordType::PERF_AUXTRACE_INFO) => {
+                    auxtrace_info = Some(record);
+                }
+                Some(UserRecordType::PERF_HEADER_FEATURE) if record.data.len() >= 8 => {
+                    let feature = Feature(read_u64(endian, &record.data[..8]) as u32);
+                    let feature_data = &record.data[8..];
+                    match feature {
+                        Feature::HOSTNAME => hostname = parse_header_string(feature_data, endian),
+                        Feature::OSRELEASE => {
+                            linux_version = parse_header_string(feature_data, endian)
+                        }
+                        Feature::VERSION => {
+                            perf_version = parse_header_string(feature_data, endian)
+                        }
+                        Feature::ARCH => arch = parse_header_string(feature_data, endian),
+                        Feature::CPUDESC => cpu_desc = parse_header_string(feature_data, endian),
+                        Feature::BUILD_ID => {
+                            build_ids.extend(parse_build_id_section(feature_data, endian));
+                        }
+                        Feature::EVENT_DESC => {
+                            event_desc = match endian {
+                                Endianness::LittleEndian => {
+                                    AttributeDescription::parse_event_desc_section::<
+                                        _,
+                                        LittleEndian,
+                                    >(Cursor::new(feature_data))
+                                }
+                                Endianness::BigEndian => {
+                                    AttributeDescription::parse_event_desc_section::<_, BigEndian>(
+                                        Cursor::new(feature_data),
+                                    )
+                                }
+                            }
+                            .unwrap_or_default();
+                        }
+                        _ => {}
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        if attributes.is_empty() {
+            // Older versions of perf only send the event descriptions.
+            attributes = event_desc;
+        } else {
+            // Take the event names from the event descriptions.
+            for attr in &mut attributes {
+                attr.name = event_desc
+                    .iter()
+                    .find(|desc| desc.ids().iter().any(|id| attr.event_ids.contains(id)))
+                    .and_then(|desc| desc.name.clone());
+            }
+        }
+        if attributes.is_empty() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "no event attributes in the perf.data stream",
+            )
+            .into());
+        }
+
+        Ok(Self {
+            attributes,
+            main_event_read_format_has_lost,
+            hostname,
+            linux_version,
+            perf_version,
+            arch,
+            cpu_desc,
+            build_ids,
+            auxtrace_info,
+        })
+    }
+}
+
+/// Parses the body of a `PERF_RECORD_HEADER_ATTR` record: a `perf_event_attr`
+/// followed by the event IDs.
+fn parse_header_attr(data: &[u8], endian: Endianness) -> Result<AttributeDescription, Error> {
+    let (attr, attr_size) = match endian {
+        Endianness::LittleEndian => PerfEventAttr::parse::<_, LittleEndian>(data)?,
+        Endianness::BigEndian => PerfEventAttr::parse::<_, BigEndian>(data)?,
+    };
+    let ids_data = data.get(attr_size as usize..).unwrap_or_default();
+    let event_ids = ids_data
+        .chunks_exact(8)
+        .map(|id_bytes| read_u64(endian, id_bytes))
+        .collect();
+    Ok(AttributeDescription {
+        attr,
+        name: None,
+        event_ids,
+    })
+}
+
+/// Parses the body of a `PERF_RECORD_HEADER_BUILD_ID` record: a pid, a
+/// 24-byte build ID field and the NUL-terminated path of the DSO.
+fn parse_build_id_record(misc: u16, data: &[u8]) -> Option<(DsoKey, DsoInfo)> {
+    let build_id_bytes = data.get(4..28)?;
+    let path = data.get(28..).unwrap_or_default();
+    let path_len = path.iter().position(|b| *b == 0).unwrap_or(path.len());
+    let path = path[..path_len].to_vec();
+    let build_id_len = if misc & PERF_RECORD_MISC_BUILD_ID_SIZE != 0 {
+        usize::from(build_id_bytes[20].min(20))
+    } else {
+        // Old versions of perf don't write down the length. Strip trailing
+        // 4-byte groups of zeros, like linux-perf-data does for perf.data files.
+        let mut len = 20;
+        while len > 0 && build_id_bytes[len - 4..len].iter().all(|b| *b == 0) {
+            len -= 4;
+        }
+        len
+    };
+    let dso_key = DsoKey::detect(&path, CpuMode::from_misc(misc))?;
+    let mut dso_info = DsoInfo {
+        path,
+        build_id: build_id_bytes[..build_id_len].to_vec(),
+    };
+    fixup_perf_jit_build_id(&dso_key, &mut dso_info);
+    Some((dso_key, dso_info))
+}
+
+/// Parses the `HEADER_BUILD_ID` feature section, which is a sequence of
+/// build ID records, each with its own record header.
+fn parse_build_id_section(mut data: &[u8], endian: Endianness) -> HashMap<DsoKey, DsoInfo> {
+    let mut build_ids = HashMap::new();
+    while data.len() >= PERF_RECORD_HEADER_SIZE {
+        let misc = read_u16(endian, &data[4..6]);
+        let size = read_u16(endian, &data[6..8]) as usize;
+        if size < PERF_RECORD_HEADER_SIZE || size > data.len() {
+            break;
+        }
+        build_ids.extend(parse_build_id_record(
+            misc,
+            &data[PERF_RECORD_HEADER_SIZE..size],
+        ));
+        data = &data[size..];
+    }
+    build_ids
+}
+
+/// Parses a `perf_header_string`, i.e. a u32 length followed by a
+/// NUL-padded string.
+fn parse_header_string(data: &[u8], endian: Endianness) -> Option<String> {
+    let len = read_u32(endian, data.get(..4)?) as usize;
+    let s = data.get(4..4 + len)?;
+    let actual_len = s.iter().position(|b| *b == 0).unwrap_or(s.len());
+    String::from_utf8(s[..actual_len].to_vec()).ok()
+}
+
+/// Maps event records to the index of the event attr that describes them.
+enum AttrIndexLookup {
+    OnlyOneEvent,
+    /// All attrs have the same ID position.
+    Same(RecordIdParseInfo, HashMap<u64, usize>, Endianness),
+    /// All attrs have `PERF_SAMPLE_IDENTIFIER`.
+    Identifier(bool, HashMap<u64, usize>, Endianness),
+}
+
+impl AttrIndexLookup {
+    fn new(attributes: &[AttributeDescription], endian: Endianness) -> Self {
+        if attributes.len() == 1 {
+            return Self::OnlyOneEvent;
+        }
+        let mut event_id_to_attr_index = HashMap::new();
+        for (attr_index, attr) in attributes.iter().enumerate() {
+            for id in &attr.event_ids {
+                event_id_to_attr_index.insert(*id, attr_index);
+            }
+        }
+        let id_parse_infos: Vec<RecordIdParseInfo> = attributes
+            .iter()
+            .map(|attr| RecordIdParseInfo::new(&attr.attr))
+            .collect();
+        if id_parse_infos.iter().all(|info| *info == id_parse_infos[0]) {
+            Self::Same(id_parse_infos[0], event_id_to_attr_index, endian)
+        } else {
+            let sample_id_all = attributes[0].attr.flags.contains(AttrFlags::SAMPLE_ID_ALL);
+            Self::Identifier(sample_id_all, event_id_to_attr_index, endian)
+        }
+    }
+
+    fn attr_index(&self, record: &PipeRecord) -> usize {
+        let data = RawData::from(&record.data[..]);
+        let (id, event_id_to_attr_index) = match self {
+            AttrIndexLookup::OnlyOneEvent => return 0,
+            AttrIndexLookup::Same(id_parse_info, map, endian) => {
+                let id = match endian {
+                    Endianness::LittleEndian => {
+                        get_record_id::<LittleEndian>(record.record_type, data, id_parse_info)
+                    }
+                    Endianness::BigEndian => {
+                        get_record_id::<BigEndian>(record.record_type, data, id_parse_info)
+                    }
+                };
+                (id, map)
+            }
+            AttrIndexLookup::Identifier(sample_id_all, map, endian) => {
+                let id = match endian {
+                    Endianness::LittleEndian => get_record_identifier::<LittleEndian>(
+                        record.record_type,
+                        data,
+                        *sample_id_all,
+                    ),
+                    Endianness::BigEndian => {
+                        get_record_identifier::<BigEndian>(record.record_type, data, *sample_id_all)
+                    }
+                };
+                (id, map)
+            }
+        };
+        id.and_then(|id| event_id_to_attr_index.get(&id).copied())
+            .unwrap_or(0)
+    }
+}
+
+struct PendingRecord {
+    record: PipeRecord,
+    attr_index: usize,
+    timestamp: Option<u64>,
+}
+
+/// Puts records into timestamp order, using the guarantees that perf gives
+/// for `FINISHED_ROUND` records: Once a round is finished, all records with
+/// timestamps up to the highest timestamp of the *previous* round have been
+/// written.
+///
+/// Records without a timestamp are sorted as if they had the timestamp of
+/// the record which arrived before them, so that they stay in arrival order.
+#[derive(Default)]
+struct RoundSorter {
+    pending: Vec<(u64, u64, PendingRecord)>,
+    next_sequence_number: u64,
+    last_timestamp: u64,
+    max_timestamp: u64,
+    max_timestamp_at_previous_round: u64,
+    ready: Vec<PendingRecord>,
+}
+
+impl RoundSorter {
+    fn insert(&mut self, record: PipeRecord, attr_index: usize, timestamp: Option<u64>) {
+        if let Some(timestamp) = timestamp {
+            self.last_timestamp = timestamp;
+            self.max_timestamp = self.max_timestamp.max(timestamp);
+        }
+        let sequence_number = self.next_sequence_number;
+        self.next_sequence_number += 1;
+        self.pending.push((
+            self.last_timestamp,
+            sequence_number,
+            PendingRecord {
+                record,
+                attr_index,
+                timestamp,
+            },
+        ));
+    }
+
+    fn finish_round(&mut self) {
+        let limit = self.max_timestamp_at_previous_round;
+        self.max_timestamp_at_previous_round = self.max_timestamp;
+        self.flush(|timestamp| timestamp <= limit);
+    }
+
+    fn finish(&mut self) {
+        self.flush(|_| true);
+    }
+
+    fn flush(&mut self, is_ready: impl Fn(u64) -> bool) {
+        self.pending
+            .sort_by_key(|(timestamp, seq, _)| (*timestamp, *seq));
+        let split_index = self
+            .pending
+            .iter()
+            .position(|(timestamp, _, _)| !is_ready(*timestamp))
+            .unwrap_or(self.pending.len());
+        let remaining = self.pending.split_off(split_index);
+        let ready = std::mem::replace(&mut self.pending, remaining);
+        self.ready
+            .extend(ready.into_iter().map(|(_, _, record)| record));
+    }
+
+    fn take_ready(&mut self) -> Vec<PendingRecord> {
+        std::mem::take(&mut self.ready)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn record(record_type: u32, data: &[u8]) -> Vec<u8> {
+        let mut bytes = vec![];
+        bytes.extend_from_slice(&record_type.to_le_bytes());
+        bytes.extend_from_slice(&0u16.to_le_bytes());
+        bytes.extend_from_slice(&((data.len() + 8) as u16).to_le_bytes());
+        bytes.extend_from_slice(data);
+        bytes
+    }
+
+    #[test]
+    fn pipe_records_and_truncation() {
+        let mut stream = vec![];
+        stream.extend_from_slice(b"PERFILE2");
+        stream.extend_from_slice(&16u64.to_le_bytes());
+        stream.extend(record(68, &[]));
+        stream.extend(record(3, &[1, 2, 3, 4, 5, 6, 7, 8]));
+        // A truncated record: the header says 16 bytes of data, but only 4 follow.
+        stream.extend(&record(3, &[0; 16])[..12]);
+
+        let mut reader = PipeRecordReader::new(&stream[..]).unwrap();
+        let r = reader.next_record().unwrap().unwrap();
+        assert_eq!(r.record_type, RecordType(68));
+        let r = reader.next_record().unwrap().unwrap();
+        assert_eq!(r.record_type, RecordType(3));
+        assert_eq!(r.data, [1, 2, 3, 4, 5, 6, 7, 8]);
+        assert!(reader.next_record().unwrap().is_none());
+    }
+
+    #[test]
+    fn auxtrace_record_with_trace_data() {
+        let mut auxtrace = vec![0; AuxTraceChunk::HEADER_SIZE];
+        auxtrace[..8].copy_from_slice(&8u64.to_le_bytes());
+        let mut stream = vec![];
+        stream.extend_from_slice(b"PERFILE2");
+        stream.extend_from_slice(&16u64.to_le_bytes());
+        stream.extend(record(71, &auxtrace)); // PERF_RECORD_AUXTRACE
+        stream.extend_from_slice(&[0xaa; 8]); // trace data
+        stream.extend(record(68, &[]));
+
+        let mut reader = PipeRecordReader::new(&stream[..]).unwrap();
+        reader.has_aux_decoder = true;
+        let r = reader.next_record().unwrap().unwrap();
+        assert_eq!(r.record_type, RecordType(71));
+        let chunk = AuxTraceChunk::parse(&r.data, Endianness::LittleEndian).unwrap();
+        assert_eq!(chunk.data, [0xaa; 8]);
+        let r = reader.next_record().unwrap().unwrap();
+        assert_eq!(r.record_type, RecordType(68));
+        assert!(reader.next_record().unwrap().is_none());
+
+        // Without a decoder, the trace data is skipped.
+        let mut reader = PipeRecordReader::new(&stream[..]).unwrap();
+        let r = reader.next_record().unwrap().unwrap();
+        assert_eq!(r.record_type, RecordType(71));
+        assert_eq!(r.data.len(), AuxTraceChunk::HEADER_SIZE);
+        let r = reader.next_record().unwrap().unwrap();
+        assert_eq!(r.record_type, RecordType(68));
+        assert!(reader.next_record().unwrap().is_none());
+    }
+
+    #[test]
+    fn round_sorter_orders_across_rounds() {
+        let mut sorter = RoundSorter::default();
+        let rec = || PipeRecord {
+            record_type: RecordType::SAMPLE,
+            misc: 0,
+            data: vec![],
+        };
+        sorter.insert(rec(), 0, Some(20));
+        sorter.insert(rec(), 0, Some(10));
+        sorter.finish_round();
+        // Nothing is safe to flush after the first round.
+        assert!(sorter.take_ready().is_empty());
+        sorter.insert(rec(), 0, Some(15));
+        sorter.insert(rec(), 0, Some(30));
+        sorter.finish_round();
+        let ready: Vec<_> = sorter.take_ready().iter().map(|r| r.timestamp).collect();
+        assert_eq!(ready, [Some(10), Some(15), Some(20)]);
+        sorter.finish();
+        let ready: Vec<_> = sorter.take_ready().iter().map(|r| r.timestamp).collect();
+        assert_eq!(ready, [Some(30)]);
+    }
+
+    #[test]
+    fn round_sorter_keeps_untimestamped_records_in_place() {
+        let mut sorter = RoundSorter::default();
+        let rec = |misc| PipeRecord {
+            record_type: RecordType::MMAP,
+            misc,
+            data: vec![],
+        };
+        sorter.insert(rec(1), 0, Some(20));
+        sorter.insert(rec(2), 0, None);
+        sorter.insert(rec(3), 0, Some(10));
+        sorter.finish();
+        let ready: Vec<_> = sorter.take_ready().iter().map(|r| r.record.misc).collect();
+        assert_eq!(ready, [3, 1, 2]);
+    }
+
+    #[test]
+    fn build_id_record() {
+        let mut data = vec![];
+        data.extend_from_slice(&(-1i32).to_le_bytes());
+        data.extend_from_slice(&[0xab; 16]);
+        data.extend_from_slice(&[0, 0, 0, 0, 16, 0, 0, 0]);
+        data.extend_from_slice(b"/usr/lib/libfoo.so\0\0");
+        let (dso_key, dso_info) =
+            parse_build_id_record(PERF_RECORD_MISC_BUILD_ID_SIZE | 2, &data).unwrap();
+        assert_eq!(dso_key.name(), "libfoo.so");
+        assert_eq!(dso_info.build_id, [0xab; 16]);
+        assert_eq!(dso_info.path, b"/usr/lib/libfoo.so");
+
+        let mut section = record(67, &data);
+        section[4..6].copy_from_slice(&(PERF_RECORD_MISC_BUILD_ID_SIZE | 2).to_le_bytes());
+        let build_ids = parse_build_id_section(&section, Endianness::LittleEndian);
+        assert_eq!(build_ids[&dso_key].build_id, [0xab; 16]);
+    }
+
+    struct SnapshotCounter<'a> {
+        snapshot_sample_counts: &'a mut Vec<u64>,
+    }
+
+    impl PipeConversionObserver for SnapshotCounter<'_> {
+        fn on_progress(&mut self, _progress: &PipeConversionProgress) {}
+
+        fn wants_snapshot(&mut self, _progress: &PipeConversionProgress) -> bool {
+            true
+        }
+
+        fn on_snapshot(&mut self, profile: Profile) {
+            self.snapshot_sample_counts.push(sample_count(&profile));
+        }
+    }
+
+    fn sample_count(profile: &Profile) -> u64 {
+        let json = serde_json::to_value(profile).unwrap();
+        json["threads"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|thread| thread["samples"]["length"].as_u64().unwrap())
+            .sum()
+    }
+
+    fn sample_record(pid: u32, timestamp: u64) -> Vec<u8> {
+        let mut data = vec![];
+        data.extend_from_slice(&0x1000u64.to_le_bytes()); // ip
+        data.extend_from_slice(&pid.to_le_bytes());
+        data.extend_from_slice(&pid.to_le_bytes());
+        data.extend_from_slice(&timestamp.to_le_bytes());
+        let mut record = record(9, &data);
+        record[4..6].copy_from_slice(&2u16.to_le_bytes()); // PERF_RECORD_MISC_USER
+        record
+    }
+
+    #[test]
+    fn snapshots_while_streaming() {
+        // struct perf_event_attr, PERF_ATTR_SIZE_VER0, for a cpu-clock event
+        let mut attr = vec![];
+        attr.extend_from_slice(&1u32.to_le_bytes()); // PERF_TYPE_SOFTWARE
+        attr.extend_from_slice(&64u32.to_le_bytes()); // size
+        attr.extend_from_slice(&0u64.to_le_bytes()); // PERF_COUNT_SW_CPU_CLOCK
+        attr.extend_from_slice(&1_000_000u64.to_le_bytes()); // sample_period
+        attr.extend_from_slice(&0b111u64.to_le_bytes()); // IP | TID | TIME
+        attr.resize(64, 0);
+
+        let mut stream = vec![];
+        stream.extend_from_slice(b"PERFILE2");
+        stream.extend_from_slice(&16u64.to_le_bytes());
+        stream.extend(record(64, &attr)); // PERF_RECORD_HEADER_ATTR
+        for round in 0..3u64 {
+            for i in 0..2 {
+                stream.extend(sample_record(100, 1_000_000 * (round * 2 + i + 1)));
+            }
+            stream.extend(record(68, &[])); // PERF_RECORD_FINISHED_ROUND
+        }
+
+        let mut snapshot_sample_counts = vec![];
+        let observer = SnapshotCounter {
+            snapshot_sample_counts: &mut snapshot_sample_counts,
+        };
+        let profile = convert_pipe(
+            &stream[..],
+            vec![],
+            vec![],
+            ProfileCreationProps::for_tests(),
+            observer,
+        )
+        .unwrap();
+        // A round is only converted once the next round has been received, and
+        // there is no snapshot once the stream has ended.
+        assert_eq!(snapshot_sample_counts, [2, 4]);
+        assert_eq!(sample_count(&profile), 6);
+    }
+}
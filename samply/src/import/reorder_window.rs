@@ -0,0 +1,116 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+/// Restores timestamp order for records which arrive slightly out of order.
+///
+/// perf writes the records from each CPU's ring buffer in batches, so records
+/// from different CPUs can be interleaved out of order in the file. Each CPU's
+/// records are ordered among themselves, so holding back a bounded number of
+/// records and always releasing the earliest one merges the per-CPU sequences
+/// back into a single timestamp-ordered sequence, as long as no record is
+/// displaced by more than `capacity` positions.
+///
+/// Records without a timestamp are given the timestamp of the previous record,
+/// so that they keep their position relative to their neighbors. Records with
+/// the same timestamp are released in arrival order.
+pub struct ReorderWindow<T> {
+    heap: BinaryHeap<Reverse<WindowEntry<T>>>,
+    capacity: usize,
+    next_sequence_number: u64,
+    last_timestamp: u64,
+}
+
+struct WindowEntry<T> {
+    timestamp: u64,
+    sequence_number: u64,
+    item: T,
+}
+
+impl<T> PartialEq for WindowEntry<T> {
+    fn eq(&self, other: &Self) -> bool {
+        (self.timestamp, self.sequence_number) == (other.timestamp, other.sequence_number)
+    }
+}
+
+impl<T> Eq for WindowEntry<T> {}
+
+impl<T> PartialOrd for WindowEntry<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T> Ord for WindowEntry<T> {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        (self.timestamp, self.sequence_number).cmp(&(other.timestamp, other.sequence_number))
+    }
+}
+
+impl<T> ReorderWindow<T> {
+    /// Creates a window which holds back up to `capacity` records. A capacity
+    /// of zero releases every record immediately.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            heap: BinaryHeap::with_capacity(capacity.saturating_add(1)),
+            capacity,
+            next_sequence_number: 0,
+            last_timestamp: 0,
+        }
+    }
+
+    /// Adds a record to the window. Returns the earliest record in the window
+    /// if the window is full.
+    pub fn push(&mut self, timestamp: Option<u64>, item: T) -> Option<T> {
+        let timestamp = timestamp.unwrap_or(self.last_timestamp);
+        self.last_timestamp = timestamp;
+        let sequence_number = self.next_sequence_number;
+        self.next_sequence_number += 1;
+        self.heap.push(Reverse(WindowEntry {
+            timestamp,
+            sequence_number,
+            item,
+        }));
+        if self.heap.len() > self.capacity {
+            self.pop()
+        } else {
+            None
+        }
+    }
+
+    /// Removes the earliest record from the window. Call this repeatedly at
+    /// the end of the input to release the remaining records.
+    pub fn pop(&mut self) -> Option<T> {
+        self.heap.pop().map(|Reverse(entry)| entry.item)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn reorder(capacity: usize, timestamps: &[Option<u64>]) -> Vec<usize> {
+        let mut window = ReorderWindow::new(capacity);
+        let mut output = Vec::new();
+        for (index, timestamp) in timestamps.iter().enumerate() {
+            output.extend(window.push(*timestamp, index));
+        }
+        while let Some(index) = window.pop() {
+            output.push(index);
+        }
+        output
+    }
+
+    #[test]
+    fn merges_interleaved_cpu_buffers() {
+        // Two CPUs whose batches were written as [10, 30, 50] and [20, 40, 60].
+        let timestamps = [10, 30, 50, 20, 40, 60].map(Some);
+        assert_eq!(reorder(0, &timestamps), vec![0, 1, 2, 3, 4, 5]);
+        assert_eq!(reorder(2, &timestamps), vec![0, 3, 1, 4, 2, 5]);
+    }
+
+    #[test]
+    fn keeps_arrival_order_for_equal_and_missing_timestamps() {
+        let timestamps = [Some(20), None, Some(10), Some(20), Some(10)];
+        assert_eq!(reorder(8, &timestamps), vec![2, 4, 0, 1, 3]);
+    }
+}
@@ -5,7 +5,7 @@ use std::os::unix::io::RawFd;
 use std::time::Duration;
 use std::{fs, io};
 
-use byteorder::LittleEndian;
+use byteorder::NativeEndian;
 use linux_perf_data::linux_perf_event_reader::get_record_timestamp;
 use mio::unix::SourceFd;
 use mio::{Events, Interest, Poll, Token};
@@ -265,7 +265,7 @@ impl PerfGroup {
 
                 self.event_sorter.extend(perf.iter().map(|event| {
                     let rec = event.get();
-                    let timestamp = get_record_timestamp::<LittleEndian>(
+                    let timestamp = get_record_timestamp::<NativeEndian>(
                         rec.record_type,
                         rec.data,
                         &rec.parse_info,
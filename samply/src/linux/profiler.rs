@@ -19,9 +19,10 @@ use super::perf_event::EventSource;
 use super::perf_group::{AttachMode, PerfGroup};
 use super::proc_maps;
 use super::process::SuspendedLaunchedProcess;
+use crate::import::perf::set_explicit_profile_name;
 use crate::linux_shared::vdso::VdsoObject;
 use crate::linux_shared::{
-    ConvertRegs, Converter, EventInterpretation, MmapRangeOrVec, OffCpuIndicator,
+    ConvertRegs, Converter, EventInterpretation, MmapRangeOrVec, OffCpuIndicator, SampleExtraFields,
 };
 use crate::server::{start_server_main, ServerProps};
 use crate::shared::ctrl_c::CtrlC;
@@ -384,6 +385,8 @@ fn make_converter(
         sched_switch_attr_index: None,
         known_event_indices: HashMap::new(),
         event_names: vec!["cycles".to_string()],
+        attr_indices_by_event_id: HashMap::new(),
+        main_event_read_format_has_lost: false,
     };
 
     let mut converter = Converter::<
@@ -391,7 +394,7 @@ fn make_converter(
     >::new(
         &profile_creation_props,
         ReferenceTimestamp::from_system_time(SystemTime::now()),
-        profile_creation_props.profile_name(),
+        &profile_creation_props.fallback_profile_name,
         HashMap::new(),
         machine_info.as_ref().map(|info| info.release.as_str()),
         first_sample_time,
@@ -403,6 +406,7 @@ fn make_converter(
         None,
         false,
     );
+    set_explicit_profile_name(&mut converter, &profile_creation_props);
     if let Ok(os_release) = os_release::OsRelease::new() {
         converter.set_os_name(&os_release.pretty_name);
     }
@@ -659,7 +663,11 @@ fn run_profiler(
 
             match parsed_record {
                 EventRecord::Sample(e) => {
-                    converter.handle_main_event_sample::<ConvertRegsNative>(&e);
+                    converter.handle_main_event_sample::<ConvertRegsNative>(
+                        &e,
+                        0,
+                        &SampleExtraFields::default(),
+                    );
                     /*
                     } else if interpretation.sched_switch_attr_index == Some(attr_index) {
                         converter.handle_sched_switch_sample::<C>(e);
@@ -728,7 +736,10 @@ fn run_profiler(
         eprintln!("Lost {total_lost_events} events.");
     }
 
-    let profile = converter.finish();
+    let (profile, report) = converter.finish_with_report();
+    if !report.is_empty() {
+        eprint!("{report}");
+    }
 
     save_profile_to_file(&profile, output_filename).expect("Couldn't write JSON");
 
@@ -0,0 +1,229 @@
+//! Hardware trace data from the perf AUX area, e.g. ARM CoreSight ETM.
+//!
+//! PMUs like CoreSight ETM write an instruction-level trace into a separate
+//! ring buffer, the AUX area. The kernel only emits `PERF_RECORD_AUX` records
+//! to say that new data landed there; perf then stores the data itself in
+//! `PERF_RECORD_AUXTRACE` records, and the trace configuration in a
+//! `PERF_RECORD_AUXTRACE_INFO` record.
+//!
+//! The trace formats are complex and specific to the hardware, so decoding is
+//! left to an [`AuxDecoder`], which [`new_aux_decoder`] picks based on the
+//! trace type in `PERF_RECORD_AUXTRACE_INFO`. The decoder turns the trace into
+//! [`DecodedAuxSample`]s, which are added to the profile like regular samples.
+
+use byteorder::ByteOrder;
+use linux_perf_data::{linux_perf_event_reader, Endianness};
+use linux_perf_event_reader::RawData;
+
+use crate::shared::types::StackMode;
+
+/// The AUX data was truncated because the AUX buffer was full.
+pub const PERF_AUX_FLAG_TRUNCATED: u64 = 0x01;
+/// The record contains gaps.
+pub const PERF_AUX_FLAG_PARTIAL: u64 = 0x04;
+/// The trace of several sources collided in the buffer.
+pub const PERF_AUX_FLAG_COLLISION: u64 = 0x08;
+
+/// A `PERF_RECORD_AUX` record, which says that new data landed in the AUX area.
+///
+/// ```c
+/// struct {
+///     struct perf_event_header header;
+///     u64 aux_offset;
+///     u64 aux_size;
+///     u64 flags;
+///     struct sample_id sample_id;
+/// };
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AuxRecord {
+    /// The offset of the new data in the AUX area.
+    pub aux_offset: u64,
+    /// The size of the new data in bytes.
+    pub aux_size: u64,
+    /// A combination of the `PERF_AUX_FLAG_*` flags.
+    pub flags: u64,
+}
+
+impl AuxRecord {
+    pub fn parse(data: RawData, endian: Endianness) -> Result<Self, std::io::Error> {
+        match endian {
+            Endianness::LittleEndian => Self::parse_impl::<byteorder::LittleEndian>(data),
+            Endianness::BigEndian => Self::parse_impl::<byteorder::BigEndian>(data),
+        }
+    }
+
+    fn parse_impl<O: ByteOrder>(mut data: RawData) -> Result<Self, std::io::Error> {
+        let aux_offset = data.read_u64::<O>()?;
+        let aux_size = data.read_u64::<O>()?;
+        let flags = data.read_u64::<O>()?;
+        Ok(Self {
+            aux_offset,
+            aux_size,
+            flags,
+        })
+    }
+
+    /// Whether some of the trace data was lost.
+    pub fn lost_data(&self) -> bool {
+        self.flags & (PERF_AUX_FLAG_TRUNCATED | PERF_AUX_FLAG_PARTIAL | PERF_AUX_FLAG_COLLISION)
+            != 0
+    }
+}
+
+/// A chunk of trace data from the AUX area, i.e. the payload of a
+/// `PERF_RECORD_AUXTRACE` record.
+///
+/// ```c
+/// struct {
+///     struct perf_event_header header;
+///     u64 size;
+///     u64 offset;
+///     u64 reference;
+///     u32 idx;
+///     u32 tid;
+///     u32 cpu;
+///     u32 reserved__;
+/// };
+/// ```
+///
+/// The record is followed by `size` bytes of trace data, which aren't
+/// included in the size in the record header.
+// The fields are only read by decoders, and none are built in yet.
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy)]
+pub struct AuxTraceChunk<'a> {
+    /// The offset of the data in the AUX area.
+    pub offset: u64,
+    /// The CPU whose AUX area the data is from, for per-CPU recordings.
+    pub cpu: Option<u32>,
+    /// The thread whose AUX area the data is from, for per-thread recordings.
+    pub tid: Option<i32>,
+    pub data: &'a [u8],
+}
+
+impl<'a> AuxTraceChunk<'a> {
+    /// The size of the record body before the trace data.
+    pub const HEADER_SIZE: usize = 40;
+
+    /// Parses the body of a `PERF_RECORD_AUXTRACE` record, followed by the
+    /// trace data. Returns `None` if the trace data is incomplete.
+    pub fn parse(data: &'a [u8], endian: Endianness) -> Option<Self> {
+        match endian {
+            Endianness::LittleEndian => Self::parse_impl::<byteorder::LittleEndian>(data),
+            Endianness::BigEndian => Self::parse_impl::<byteorder::BigEndian>(data),
+        }
+    }
+
+    fn parse_impl<O: ByteOrder>(data: &'a [u8]) -> Option<Self> {
+        let header = data.get(..Self::HEADER_SIZE)?;
+        let size = usize::try_from(O::read_u64(&header[0..8])).ok()?;
+        let offset = O::read_u64(&header[8..16]);
+        let tid = O::read_u32(&header[28..32]) as i32;
+        let cpu = O::read_u32(&header[32..36]);
+        let trace_data = data[Self::HEADER_SIZE..].get(..size)?;
+        Some(Self {
+            offset,
+            cpu: (cpu != u32::MAX).then_some(cpu),
+            tid: (tid != -1).then_some(tid),
+            data: trace_data,
+        })
+    }
+
+    /// Returns the number of trace data bytes which follow a
+    /// `PERF_RECORD_AUXTRACE` record with the body `data`.
+    pub fn trace_data_size(data: &[u8], endian: Endianness) -> Option<u64> {
+        let size_bytes = data.get(0..8)?;
+        Some(match endian {
+            Endianness::LittleEndian => byteorder::LittleEndian::read_u64(size_bytes),
+            Endianness::BigEndian => byteorder::BigEndian::read_u64(size_bytes),
+        })
+    }
+}
+
+/// The trace type in a `PERF_RECORD_AUXTRACE_INFO` record, from
+/// `enum auxtrace_type` in perf.
+pub const PERF_AUXTRACE_CS_ETM: u32 = 3;
+
+/// Creates the decoder for the trace in the `PERF_RECORD_AUXTRACE_INFO` record
+/// `info`, which starts with the trace type, e.g. [`PERF_AUXTRACE_CS_ETM`].
+///
+/// This is where decoders for specific PMUs are plugged in. None are built in
+/// yet, so the trace data is reported as undecoded.
+pub fn new_aux_decoder(info: &[u8], endian: Endianness) -> Option<Box<dyn AuxDecoder + Send>> {
+    let type_bytes = info.get(0..4)?;
+    let auxtrace_type = match endian {
+        Endianness::LittleEndian => byteorder::LittleEndian::read_u32(type_bytes),
+        Endianness::BigEndian => byteorder::BigEndian::read_u32(type_bytes),
+    };
+    match auxtrace_type {
+        // A CoreSight ETM decoder, once there is one, is created here.
+        PERF_AUXTRACE_CS_ETM => None,
+        _ => None,
+    }
+}
+
+/// A sample which a decoder reconstructed from the trace, e.g. for a taken
+/// branch.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodedAuxSample {
+    /// The raw perf timestamp, in the same clock as the other records.
+    pub timestamp: u64,
+    pub pid: i32,
+    pub tid: i32,
+    /// The executed instruction, followed by the return addresses of the
+    /// calls which the decoder could reconstruct, innermost first.
+    pub frames: Vec<(u64, StackMode)>,
+}
+
+/// Decodes the AUX area trace of one kind of PMU, e.g. CoreSight ETM.
+pub trait AuxDecoder {
+    /// Called with the payload of the `PERF_RECORD_AUXTRACE_INFO` record, which
+    /// has the PMU type and the trace configuration, e.g. the ETM registers of
+    /// each CPU. This is called before any trace data is decoded.
+    fn handle_info(&mut self, _info: &[u8]) {}
+
+    /// Decodes a chunk of trace data and appends the resulting samples to `samples`.
+    fn decode(&mut self, chunk: &AuxTraceChunk, samples: &mut Vec<DecodedAuxSample>);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_aux_record() {
+        let mut data = Vec::new();
+        data.extend_from_slice(&0x4000u64.to_le_bytes());
+        data.extend_from_slice(&0x1000u64.to_le_bytes());
+        data.extend_from_slice(&(0x0100 | PERF_AUX_FLAG_TRUNCATED).to_le_bytes());
+        let record = AuxRecord::parse(RawData::Single(&data), Endianness::LittleEndian).unwrap();
+        assert_eq!(record.aux_offset, 0x4000);
+        assert_eq!(record.aux_size, 0x1000);
+        assert!(record.lost_data());
+    }
+
+    #[test]
+    fn parse_auxtrace_chunk() {
+        let mut data = Vec::new();
+        data.extend_from_slice(&4u64.to_le_bytes()); // size
+        data.extend_from_slice(&0x200u64.to_le_bytes()); // offset
+        data.extend_from_slice(&0u64.to_le_bytes()); // reference
+        data.extend_from_slice(&0u32.to_le_bytes()); // idx
+        data.extend_from_slice(&(-1i32).to_le_bytes()); // tid
+        data.extend_from_slice(&2u32.to_le_bytes()); // cpu
+        data.extend_from_slice(&0u32.to_le_bytes()); // reserved
+        assert_eq!(
+            AuxTraceChunk::trace_data_size(&data, Endianness::LittleEndian),
+            Some(4)
+        );
+        assert!(AuxTraceChunk::parse(&data, Endianness::LittleEndian).is_none());
+
+        data.extend_from_slice(&[1, 2, 3, 4]);
+        let chunk = AuxTraceChunk::parse(&data, Endianness::LittleEndian).unwrap();
+        assert_eq!(chunk.offset, 0x200);
+        assert_eq!(chunk.cpu, Some(2));
+        assert_eq!(chunk.tid, None);
+        assert_eq!(chunk.data, [1, 2, 3, 4]);
+    }
+}
@@ -23,4 +23,7 @@ impl AvmaRange {
     pub fn encompasses(&self, other: &AvmaRange) -> bool {
         self.start <= other.start && self.end >= other.end
     }
+    pub fn overlaps(&self, other: &AvmaRange) -> bool {
+        self.start < other.end && other.start < self.end
+    }
 }
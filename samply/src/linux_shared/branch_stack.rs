@@ -0,0 +1,193 @@
+use std::collections::HashMap;
+use std::io::Write;
+
+/// One entry of a `PERF_SAMPLE_BRANCH_STACK`, i.e. a `struct perf_branch_entry`.
+///
+/// ```c
+/// struct perf_branch_entry {
+///     __u64 from;
+///     __u64 to;
+///     __u64 mispred:1,  /* target mispredicted */
+///           predicted:1,/* target predicted */
+///           in_tx:1,    /* in transaction */
+///           abort:1,    /* transaction abort */
+///           cycles:16,  /* cycle count to last branch */
+///           type:4,     /* branch type */
+///           ...
+/// };
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BranchEntry {
+    /// The address of the branch instruction.
+    pub from: u64,
+    /// The branch target.
+    pub to: u64,
+    pub mispredicted: bool,
+    pub predicted: bool,
+    /// The number of cycles since the previous branch, or 0 if unknown.
+    pub cycles: u16,
+}
+
+impl BranchEntry {
+    /// Decodes the flags bitfield. Bitfields are allocated from the most
+    /// significant bit on big-endian machines.
+    pub fn new(from: u64, to: u64, flags: u64, is_big_endian: bool) -> Self {
+        let (mispredicted, predicted, cycles) = if is_big_endian {
+            (
+                flags >> 63 != 0,
+                (flags >> 62) & 1 != 0,
+                (flags >> 44) & 0xffff,
+            )
+        } else {
+            (flags & 1 != 0, (flags >> 1) & 1 != 0, (flags >> 4) & 0xffff)
+        };
+        Self {
+            from,
+            to,
+            mispredicted,
+            predicted,
+            cycles: cycles as u16,
+        }
+    }
+}
+
+/// The branch stack of a sample, with the most recent branch first.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BranchStack {
+    /// Whether the branch stack was recorded with `PERF_SAMPLE_BRANCH_CALL_STACK`,
+    /// e.g. with `perf record --call-graph lbr`. In that case the entries are
+    /// the calls which are still on the stack, i.e. each `from` is a call
+    /// instruction in a caller, innermost caller first.
+    pub is_call_stack: bool,
+    pub entries: Vec<BranchEntry>,
+}
+
+/// A taken branch in a process, identified by its absolute addresses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BranchEdge {
+    pub pid: i32,
+    pub from: u64,
+    pub to: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BranchEdgeCounts {
+    /// The number of branch stack entries for this edge.
+    pub count: u64,
+    /// How many of these entries had a mispredicted target.
+    pub mispredicted_count: u64,
+}
+
+/// An edge profile, i.e. how often each branch was taken, accumulated from
+/// the branch stacks of all samples.
+///
+/// For branch stacks which were recorded with `PERF_SAMPLE_BRANCH_CALL_STACK`,
+/// the entries are the calls which were on the stack at the time of the
+/// sample, so the count of a call edge is the number of samples it was
+/// on the stack for.
+#[derive(Debug, Clone, Default)]
+pub struct BranchEdges {
+    edges: HashMap<BranchEdge, BranchEdgeCounts>,
+}
+
+impl BranchEdges {
+    pub fn add_branch_stack(&mut self, pid: i32, branch_stack: &BranchStack) {
+        for entry in &branch_stack.entries {
+            let edge = BranchEdge {
+                pid,
+                from: entry.from,
+                to: entry.to,
+            };
+            let counts = self.edges.entry(edge).or_default();
+            counts.count += 1;
+            if entry.mispredicted {
+                counts.mispredicted_count += 1;
+            }
+        }
+    }
+
+    /// All edges, with the most frequently taken edge first.
+    pub fn sorted_by_count(&self) -> Vec<(BranchEdge, BranchEdgeCounts)> {
+        let mut edges: Vec<_> = self
+            .edges
+            .iter()
+            .map(|(edge, counts)| (*edge, *counts))
+            .collect();
+        edges.sort_by(|(edge_a, counts_a), (edge_b, counts_b)| {
+            counts_b.count.cmp(&counts_a.count).then(edge_a.cmp(edge_b))
+        });
+        edges
+    }
+
+    /// Writes all edges as CSV, with the most frequently taken edge first.
+    /// Addresses are absolute addresses in the process, in hex.
+    pub fn write_csv(&self, mut writer: impl Write) -> std::io::Result<()> {
+        writeln!(writer, "pid,from,to,count,mispredicted")?;
+        for (edge, counts) in self.sorted_by_count() {
+            writeln!(
+                writer,
+                "{},{:#x},{:#x},{},{}",
+                edge.pid, edge.from, edge.to, counts.count, counts.mispredicted_count
+            )?;
+        }
+        writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn flags() {
+        let entry = BranchEntry::new(0x1000, 0x2000, 0x1 | (37 << 4), false);
+        assert!(entry.mispredicted);
+        assert!(!entry.predicted);
+        assert_eq!(entry.cycles, 37);
+
+        let entry = BranchEntry::new(0x1000, 0x2000, (1 << 62) | (37 << 44), true);
+        assert!(!entry.mispredicted);
+        assert!(entry.predicted);
+        assert_eq!(entry.cycles, 37);
+    }
+
+    #[test]
+    fn edge_counts() {
+        let branch_stack = |entries: &[(u64, u64, u64)]| BranchStack {
+            is_call_stack: false,
+            entries: entries
+                .iter()
+                .map(|(from, to, flags)| BranchEntry::new(*from, *to, *flags, false))
+                .collect(),
+        };
+        let mut edges = BranchEdges::default();
+        edges.add_branch_stack(1, &branch_stack(&[(0x10, 0x20, 0), (0x30, 0x40, 1)]));
+        edges.add_branch_stack(1, &branch_stack(&[(0x30, 0x40, 1), (0x30, 0x40, 0)]));
+        edges.add_branch_stack(2, &branch_stack(&[(0x10, 0x20, 0)]));
+
+        let edge = |pid, from, to| BranchEdge { pid, from, to };
+        let sorted: Vec<_> = edges
+            .sorted_by_count()
+            .into_iter()
+            .map(|(edge, counts)| (edge, counts.count))
+            .collect();
+        assert_eq!(
+            sorted,
+            [
+                (edge(1, 0x30, 0x40), 3),
+                (edge(1, 0x10, 0x20), 1),
+                (edge(2, 0x10, 0x20), 1),
+            ]
+        );
+
+        let mut csv = Vec::new();
+        edges.write_csv(&mut csv).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "pid,from,to,count,mispredicted\n\
+             1,0x30,0x40,3,2\n\
+             1,0x10,0x20,1,0\n\
+             2,0x10,0x20,1,0\n"
+        );
+    }
+}
@@ -0,0 +1,230 @@
+use std::collections::HashSet;
+use std::fmt::{Display, Formatter};
+
+/// Non-fatal problems which were encountered while converting perf events
+/// into a profile, collected so that they can be presented together once
+/// the conversion is finished.
+#[derive(Debug, Clone, Default)]
+pub struct ConversionReport {
+    warnings: Vec<ConversionWarning>,
+    seen_warnings: HashSet<ConversionWarning>,
+    lost_event_count: u64,
+    skipped_record_count: u64,
+    duplicate_sample_count: u64,
+    truncated_stack_count: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ConversionWarning {
+    /// A mapped binary could not be found, so its addresses can't be unwound
+    /// or symbolicated.
+    MissingFile { path: String },
+    /// A mapped binary was found but could not be read.
+    UnreadableFile { path: String, reason: String },
+    /// An extra output file, such as the branch edge profile, could not be
+    /// written.
+    UnwritableFile { path: String, reason: String },
+    /// A mapped binary was found but had a different build ID than the one
+    /// recorded in the perf.data file, so it was skipped.
+    BuildIdMismatch {
+        path: String,
+        expected: String,
+        found: Option<String>,
+    },
+    /// Kernel symbols could not be loaded.
+    NoKernelSymbols { reason: String },
+    /// The input ended in the middle of a record, for example because the
+    /// recording was killed. Everything up to that point was converted.
+    TruncatedFile { reason: String },
+    /// The recording has hardware trace data in the AUX area, e.g. from
+    /// CoreSight ETM, but no decoder for it was registered.
+    UndecodedAuxTrace,
+    /// Some hardware trace data was lost, e.g. because the AUX area was full.
+    AuxTraceDataLost,
+}
+
+impl ConversionReport {
+    /// Records a warning. Warnings which were already recorded, for example
+    /// for the same file being mapped into several processes, are ignored.
+    pub fn add(&mut self, warning: ConversionWarning) {
+        if self.seen_warnings.insert(warning.clone()) {
+            self.warnings.push(warning);
+        }
+    }
+
+    /// Records that the kernel dropped `count` events, e.g. because the perf
+    /// ring buffer was full.
+    pub fn add_lost_events(&mut self, count: u64) {
+        self.lost_event_count += count;
+    }
+
+    /// Records that a record was skipped because it was missing information
+    /// we need, such as the pid, tid or timestamp.
+    pub fn add_skipped_record(&mut self) {
+        self.skipped_record_count += 1;
+    }
+
+    /// Records that a sample was dropped because it was identical to the
+    /// previous sample on the same thread, i.e. it had the same timestamp,
+    /// event and stack. This is expected and not reported as a warning.
+    pub fn add_duplicate_sample(&mut self) {
+        self.duplicate_sample_count += 1;
+    }
+
+    /// Records that unwinding failed for a sample before it reached the root
+    /// of the stack. This is common with DWARF unwinding, so it's reported as a
+    /// note rather than a warning.
+    pub fn add_truncated_stack(&mut self) {
+        self.truncated_stack_count += 1;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.warnings.is_empty()
+            && self.lost_event_count == 0
+            && self.skipped_record_count == 0
+            && self.truncated_stack_count == 0
+    }
+
+    #[cfg(test)]
+    pub fn warnings(&self) -> &[ConversionWarning] {
+        &self.warnings
+    }
+
+    #[cfg(test)]
+    pub fn lost_event_count(&self) -> u64 {
+        self.lost_event_count
+    }
+
+    #[cfg(test)]
+    pub fn skipped_record_count(&self) -> u64 {
+        self.skipped_record_count
+    }
+
+    #[cfg(test)]
+    pub fn duplicate_sample_count(&self) -> u64 {
+        self.duplicate_sample_count
+    }
+}
+
+impl Display for ConversionWarning {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConversionWarning::MissingFile { path } => write!(f, "File {path} was not found"),
+            ConversionWarning::UnreadableFile { path, reason } => {
+                write!(f, "File {path} could not be read: {reason}")
+            }
+            ConversionWarning::UnwritableFile { path, reason } => {
+                write!(f, "File {path} could not be written: {reason}")
+            }
+            ConversionWarning::BuildIdMismatch {
+                path,
+                expected,
+                found: Some(found),
+            } => write!(
+                f,
+                "File {path} has non-matching build ID {found} (expected {expected})"
+            ),
+            ConversionWarning::BuildIdMismatch {
+                path,
+                expected,
+                found: None,
+            } => write!(
+                f,
+                "File {path} does not contain a build ID, but we expected it to have build ID {expected}"
+            ),
+            ConversionWarning::NoKernelSymbols { reason } => {
+                write!(f, "Could not obtain kernel symbols: {reason}")
+            }
+            ConversionWarning::TruncatedFile { reason } => write!(
+                f,
+                "The file is truncated, only the records before the truncation were converted: {reason}"
+            ),
+            ConversionWarning::UndecodedAuxTrace => write!(
+                f,
+                "The recording contains hardware trace data in the AUX area, which was ignored because no decoder is available"
+            ),
+            ConversionWarning::AuxTraceDataLost => {
+                write!(f, "Some hardware trace data in the AUX area was lost")
+            }
+        }
+    }
+}
+
+impl Display for ConversionReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let warning_count = self.warnings.len()
+            + usize::from(self.lost_event_count != 0)
+            + usize::from(self.skipped_record_count != 0);
+        if warning_count != 0 {
+            writeln!(
+                f,
+                "The conversion finished with {warning_count} warning(s):"
+            )?;
+        }
+        for warning in &self.warnings {
+            writeln!(f, "  - {warning}")?;
+        }
+        if self.lost_event_count != 0 {
+            writeln!(
+                f,
+                "  - {} events were lost, the profile may be missing samples",
+                self.lost_event_count
+            )?;
+        }
+        if self.skipped_record_count != 0 {
+            writeln!(
+                f,
+                "  - {} records were skipped because they were missing the pid, tid or timestamp",
+                self.skipped_record_count
+            )?;
+        }
+        if self.truncated_stack_count != 0 {
+            writeln!(
+                f,
+                "Note: The stacks of {} samples are incomplete because unwinding stopped before reaching the root",
+                self.truncated_stack_count
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn report_deduplicates_warnings() {
+        let mut report = ConversionReport::default();
+        assert!(report.is_empty());
+        let missing = ConversionWarning::MissingFile {
+            path: "/usr/lib/libfoo.so".into(),
+        };
+        report.add(missing.clone());
+        report.add(missing.clone());
+        report.add_lost_events(3);
+        report.add_lost_events(4);
+        report.add_skipped_record();
+        report.add_truncated_stack();
+        assert_eq!(report.warnings(), &[missing]);
+        assert_eq!(report.lost_event_count(), 7);
+        assert_eq!(report.skipped_record_count(), 1);
+        assert_eq!(report.truncated_stack_count, 1);
+        assert_eq!(
+            report.to_string(),
+            "The conversion finished with 3 warning(s):\n  - File /usr/lib/libfoo.so was not found\n  - 7 events were lost, the profile may be missing samples\n  - 1 records were skipped because they were missing the pid, tid or timestamp\nNote: The stacks of 1 samples are incomplete because unwinding stopped before reaching the root\n"
+        );
+    }
+
+    #[test]
+    fn report_with_only_truncated_stacks() {
+        let mut report = ConversionReport::default();
+        report.add_truncated_stack();
+        report.add_truncated_stack();
+        assert!(!report.is_empty());
+        assert_eq!(
+            report.to_string(),
+            "Note: The stacks of 2 samples are incomplete because unwinding stopped before reaching the root\n"
+        );
+    }
+}
@@ -7,12 +7,17 @@ use linux_perf_event_reader::constants::{
 };
 use linux_perf_event_reader::Regs;
 
+use super::raw_syscall::{syscall_name_aarch64, syscall_name_x86_64};
+
 pub trait ConvertRegs {
     type UnwindRegs;
     fn convert_regs(regs: &Regs) -> (u64, u64, Self::UnwindRegs);
 
     #[allow(unused)]
     fn regs_mask() -> u64;
+
+    /// The name of the syscall with the number `id` on this architecture, if known.
+    fn syscall_name(id: i64) -> Option<&'static str>;
 }
 
 pub struct ConvertRegsX86_64;
@@ -29,6 +34,10 @@ impl ConvertRegs for ConvertRegsX86_64 {
     fn regs_mask() -> u64 {
         1 << PERF_REG_X86_IP | 1 << PERF_REG_X86_SP | 1 << PERF_REG_X86_BP
     }
+
+    fn syscall_name(id: i64) -> Option<&'static str> {
+        syscall_name_x86_64(id)
+    }
 }
 
 pub struct ConvertRegsAarch64;
@@ -49,4 +58,8 @@ impl ConvertRegs for ConvertRegsAarch64 {
             | 1 << PERF_REG_ARM64_SP
             | 1 << PERF_REG_ARM64_X29
     }
+
+    fn syscall_name(id: i64) -> Option<&'static str> {
+        syscall_name_aarch64(id)
+    }
 }
@@ -2,15 +2,15 @@ use std::collections::HashMap;
 use std::ops::Range;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::Duration;
 
-use byteorder::LittleEndian;
 use debugid::DebugId;
 use framehop::{ExplicitModuleSectionInfo, FrameAddress, Module, Unwinder};
 use fxprof_processed_profile::{
     CategoryColor, CategoryHandle, CategoryPairHandle, CpuDelta, LibraryHandle, LibraryInfo,
-    MarkerFieldFormat, MarkerFieldSchema, MarkerLocation, MarkerSchema, MarkerTiming, Profile,
-    ReferenceTimestamp, SamplingInterval, StaticSchemaMarker, StringHandle, SymbolTable,
-    ThreadHandle,
+    MarkerFieldFormat, MarkerFieldSchema, MarkerLocation, MarkerSchema, MarkerStaticField,
+    MarkerTiming, Profile, ReferenceTimestamp, SamplingInterval, StaticSchemaMarker, StringHandle,
+    SymbolTable, ThreadHandle, Timestamp,
 };
 use linux_perf_data::linux_perf_event_reader::TaskWasPreempted;
 use linux_perf_data::simpleperf_dso_type::{DSO_DEX_FILE, DSO_KERNEL, DSO_KERNEL_MODULE};
@@ -20,8 +20,8 @@ use linux_perf_data::{
 };
 use linux_perf_event_reader::constants::PERF_CONTEXT_MAX;
 use linux_perf_event_reader::{
-    CommOrExecRecord, CommonData, ContextSwitchRecord, ForkOrExitRecord, Mmap2FileId, Mmap2Record,
-    MmapRecord, RawDataU64, SampleRecord,
+    CommOrExecRecord, CommonData, ContextSwitchRecord, ForkOrExitRecord, LostRecord, Mmap2FileId,
+    Mmap2Record, MmapRecord, RawData, RawDataU64, RawEventRecord, SampleRecord,
 };
 use memmap2::Mmap;
 use object::{CompressedFileRange, CompressionFormat, Object, ObjectSection};
@@ -29,25 +29,43 @@ use samply_symbols::{debug_id_for_object, DebugIdExt};
 use wholesym::samply_symbols::demangle_any;
 use wholesym::{samply_symbols, CodeId, ElfBuildId};
 
+use super::aux_trace::{new_aux_decoder, AuxDecoder, AuxRecord, AuxTraceChunk, DecodedAuxSample};
 use super::avma_range::AvmaRange;
+use super::branch_stack::{BranchEdges, BranchEntry};
+use super::conversion_report::{ConversionReport, ConversionWarning};
 use super::convert_regs::ConvertRegs;
 use super::event_interpretation::{EventInterpretation, OffCpuIndicator};
 use super::injected_jit_object::{correct_bad_perf_jit_so_file, jit_function_name};
 use super::kernel_symbols::{kernel_module_build_id, KernelSymbols};
 use super::mmap_range_or_vec::MmapRangeOrVec;
+use super::module_info::{
+    write_module_list_csv, ControlFlowProtection, MappingProtection, ModuleInfo,
+};
+use super::on_cpu_counter::OnCpuThreadCounter;
 use super::pe_mappings::{PeMappings, SuspectedPeMapping};
+use super::perf_stat_intervals::parse_perf_stat_intervals;
 use super::processes::Processes;
+use super::raw_syscall::RawSyscall;
 use super::rss_stat::{RssStat, MM_ANONPAGES, MM_FILEPAGES, MM_SHMEMPAGES, MM_SWAPENTS};
+use super::sample_extra_fields::{SampleExtraFields, SampleReadValues};
+use super::sched_wakeup::SchedWakeup;
 use super::svma_file_range::compute_vma_bias;
-use super::vdso::VdsoObject;
+use super::syscall_tracepoint::{format_syscall_tracepoint, parse_munmap_tracepoint};
+use super::text_poke::{TextPoke, TextPokeMarker};
+use super::time_range::RawTimeRange;
+use super::unwind_sections::ModuleUnwindSections;
+use super::vdso::{find_vdso_image_in_build_id_cache, VdsoObject};
 use crate::shared::context_switch::{ContextSwitchHandler, OffCpuSampleGroup};
 use crate::shared::jit_category_manager::JitCategoryManager;
+use crate::shared::jitdump_manager::is_jitdump_file_name;
 use crate::shared::lib_mappings::{AndroidArtInfo, LibMappingInfo};
+use crate::shared::native_lib_category_manager::NativeLibCategoryManager;
 use crate::shared::per_cpu::Cpus;
 use crate::shared::process_name::make_process_name;
 use crate::shared::process_sample_data::{
-    OtherEventMarker, RssStatMarker, RssStatMember, SchedSwitchMarkerOnCpuTrack,
-    SchedSwitchMarkerOnThreadTrack,
+    CounterValuesMarker, MemoryAccessMarker, OtherEventMarker, RssStatMarker, RssStatMember,
+    SampleIpMarker, SchedSwitchMarkerOnCpuTrack, SchedSwitchMarkerOnThreadTrack, SchedWakeupMarker,
+    SyscallTracepointMarker,
 };
 use crate::shared::recording_props::ProfileCreationProps;
 use crate::shared::synthetic_jit_library::SyntheticJitLibrary;
@@ -56,7 +74,10 @@ use crate::shared::types::{StackFrame, StackMode};
 use crate::shared::unresolved_samples::{
     UnresolvedSamples, UnresolvedStackHandle, UnresolvedStacks,
 };
-use crate::shared::utils::open_file_with_fallback;
+use crate::shared::utils::{open_file_with_fallback, ExecutableFinder};
+
+/// Makes the product name from the name of the first process.
+type ProductNameGenerator = Box<dyn FnOnce(&str) -> String + Send>;
 
 pub struct Converter<U>
 where
@@ -68,15 +89,22 @@ where
     timestamp_converter: TimestampConverter,
     current_sample_time: u64,
     build_ids: HashMap<DsoKey, DsoInfo>,
+    /// Paths which were registered for specific build IDs, from
+    /// [`ProfileCreationProps::build_id_path_overrides`]. These take precedence
+    /// over the mapping path.
+    build_id_path_overrides: HashMap<Vec<u8>, PathBuf>,
     endian: Endianness,
     linux_version: Option<String>,
+    vmlinux_debug_path: Option<String>,
     binary_lookup_dirs: Vec<PathBuf>,
+    executable_finder: ExecutableFinder,
     aux_file_lookup_dirs: Vec<PathBuf>,
     context_switch_handler: ContextSwitchHandler,
     unresolved_stacks: UnresolvedStacks,
     off_cpu_weight_per_sample: i32,
     off_cpu_indicator: Option<OffCpuIndicator>,
     event_names: Vec<String>,
+    attr_indices_by_event_id: HashMap<u64, usize>,
     kernel_symbols: Option<KernelSymbols>,
     kernel_image_mapping: Option<KernelImageMapping>,
     simpleperf_symbol_tables_user: HashMap<Vec<u8>, SymbolTableFromSimpleperf>,
@@ -85,14 +113,81 @@ where
     simpleperf_symbol_tables_kernel_modules: HashMap<Vec<u8>, SymbolTableFromSimpleperf>,
     simpleperf_jit_app_cache_library: SyntheticJitLibrary,
     pe_mappings: PeMappings,
+
+    /// Non-fatal problems encountered during the conversion.
+    report: ConversionReport,
     jit_category_manager: JitCategoryManager,
+    native_lib_category_manager: NativeLibCategoryManager,
     arg_count_to_include_in_process_name: usize,
     cpus: Option<Cpus>,
+    on_cpu_thread_counter: Option<OnCpuThreadCounter>,
+
+    /// For cpu-wide context switch records: the tid which was most recently
+    /// switched in on each CPU, keyed by CPU index.
+    cpu_wide_switch_current_tids: HashMap<u32, i32>,
+
+    /// What we determined about each module that was added to a process, keyed by path.
+    modules_by_path: HashMap<String, ModuleInfo>,
+
+    /// Applied to all thread and process names before they are stored in
+    /// the profile, e.g. to redact sensitive information. None means identity.
+    name_transform: Option<NameTransform>,
 
     /// Whether repeated frames at the base of the stack should be folded
     /// into one frame.
     fold_recursive_prefix: bool,
 
+    /// Whether kernel frames should be included in sample stacks.
+    include_kernel_frames: bool,
+
+    /// Whether stacks on which unwinding failed end in a truncation marker
+    /// frame.
+    include_truncated_stack_marker: bool,
+
+    /// If set, samples, off-CPU samples and markers outside of this range are
+    /// skipped. Mappings and process / thread lifecycle events outside of the
+    /// range are still processed, so that symbolication works, but the start
+    /// and end times of processes and threads are clamped to the range.
+    time_range: Option<RawTimeRange>,
+
+    /// The `--time-range` offsets, if they couldn't be turned into
+    /// `time_range` yet because the file doesn't say when the first sample
+    /// was taken. They are anchored to the first timestamp we see instead.
+    unanchored_time_range: Option<(Duration, Duration)>,
+
+    /// Whether threads without samples and markers are removed at the end.
+    prune_idle_threads: bool,
+
+    /// Whether off-CPU stacks get the current syscall as their leaf frame.
+    off_cpu_syscalls: bool,
+
+    /// Whether off-CPU samples are created for the time that threads are blocked.
+    include_off_cpu_samples: bool,
+
+    /// Whether each sample gets a marker with its raw instruction pointer.
+    sample_ip_markers: bool,
+
+    /// Whether samples in the idle task get a "swapper/N" thread per CPU.
+    idle_threads: bool,
+
+    /// The output of `perf stat -I`, whose readings are added as counters at the end.
+    perf_stat_intervals_path: Option<PathBuf>,
+
+    /// The edge profile from the samples' branch stacks, which is written to
+    /// `branch_edges_path` at the end.
+    branch_edges: Option<BranchEdges>,
+    branch_edges_path: Option<PathBuf>,
+
+    /// Where to write the list of modules at the end, see `module_infos`.
+    module_list_path: Option<PathBuf>,
+
+    /// Decodes hardware trace data from the AUX area, see `handle_auxtrace_info`.
+    aux_decoder: Option<Box<dyn AuxDecoder + Send>>,
+
+    /// Makes the product name from the name of the first process, see
+    /// `set_delayed_product_name_generator`.
+    delayed_product_name_generator: Option<ProductNameGenerator>,
+
     /// Determines how the addresses in sample call chains should be interpreted.
     /// Any addresses after the first frame address are either "return addresses"
     /// (i.e. they are the address of the instruction *after* the call instruction),
@@ -106,6 +201,25 @@ where
     call_chain_return_addresses_are_preadjusted: bool,
 }
 
+/// A callback which takes a raw thread or process name and returns the name
+/// that should be stored in the profile.
+pub type NameTransform = Box<dyn Fn(&str) -> String + Send>;
+
+/// Returns a name transform which applies the substring replacements from
+/// [`ProfileCreationProps::name_replacements`] in order, or `None` if there
+/// are no replacements.
+fn name_transform_for_replacements(replacements: &[(String, String)]) -> Option<NameTransform> {
+    if replacements.is_empty() {
+        return None;
+    }
+    let replacements = replacements.to_vec();
+    Some(Box::new(move |name| {
+        replacements
+            .iter()
+            .fold(name.to_owned(), |name, (from, to)| name.replace(from, to))
+    }))
+}
+
 const DEFAULT_OFF_CPU_SAMPLING_INTERVAL_NS: u64 = 1_000_000; // 1ms
 
 impl<U> Converter<U>
@@ -141,12 +255,27 @@ where
                 Some(interval_ns) => (*interval_ns, 1),
                 None => (DEFAULT_OFF_CPU_SAMPLING_INTERVAL_NS, 0),
             };
-        let kernel_symbols = match KernelSymbols::new_for_running_kernel() {
-            Ok(kernel_symbols) => Some(kernel_symbols),
-            Err(_err) => {
-                // eprintln!("Could not obtain kernel symbols: {err}");
-                None
-            }
+        let off_cpu_weight_per_sample = profile_creation_props
+            .off_cpu_weight
+            .unwrap_or(off_cpu_weight_per_sample);
+        let mut report = ConversionReport::default();
+        let kernel_symbols = match &profile_creation_props.kallsyms_path {
+            Some(kallsyms_path) => match KernelSymbols::new_from_kallsyms_file(kallsyms_path) {
+                Ok(kernel_symbols) => Some(kernel_symbols),
+                Err(err) => {
+                    report.add(ConversionWarning::NoKernelSymbols {
+                        reason: err.to_string(),
+                    });
+                    None
+                }
+            },
+            None => match KernelSymbols::new_for_running_kernel() {
+                Ok(kernel_symbols) => Some(kernel_symbols),
+                Err(_err) => {
+                    // eprintln!("Could not obtain kernel symbols: {err}");
+                    None
+                }
+            },
         };
 
         let mut simpleperf_symbol_tables_user = HashMap::new();
@@ -234,25 +363,53 @@ where
             None
         };
 
+        let on_cpu_thread_counter = if profile_creation_props.cpu_usage_counter {
+            let process = match &cpus {
+                Some(cpus) => cpus.process_handle(),
+                None => {
+                    let start_timestamp = timestamp_converter.convert_time(first_sample_time);
+                    profile.add_process("CPU", 0, start_timestamp)
+                }
+            };
+            Some(OnCpuThreadCounter::new(process, &mut profile))
+        } else {
+            None
+        };
+
+        let unresolved_stacks =
+            UnresolvedStacks::new(profile_creation_props.fold_leaf_recursion, &mut profile);
+
         Self {
             profile,
             cache,
             processes: Processes::new(
                 profile_creation_props.reuse_threads,
+                profile_creation_props.suffix_reused_tids,
                 profile_creation_props.unlink_aux_files,
+                profile_creation_props.spill_samples_threshold,
+                aux_file_lookup_dirs.clone(),
+                profile_creation_props.reuse_numbered_threads,
             ),
             timestamp_converter,
             current_sample_time: first_sample_time,
             build_ids,
+            build_id_path_overrides: profile_creation_props
+                .build_id_path_overrides
+                .iter()
+                .cloned()
+                .collect(),
             endian,
             linux_version: linux_version.map(ToOwned::to_owned),
+            vmlinux_debug_path: profile_creation_props.vmlinux_debug_path.clone(),
             binary_lookup_dirs,
+            executable_finder: ExecutableFinder::default(),
             aux_file_lookup_dirs,
             off_cpu_weight_per_sample,
             context_switch_handler: ContextSwitchHandler::new(off_cpu_sampling_interval_ns),
-            unresolved_stacks: UnresolvedStacks::default(),
+            unresolved_stacks,
             off_cpu_indicator: interpretation.off_cpu_indicator,
             event_names: interpretation.event_names,
+            attr_indices_by_event_id: interpretation.attr_indices_by_event_id,
             kernel_symbols,
             kernel_image_mapping: None,
             simpleperf_symbol_tables_user,
@@ -260,17 +417,117 @@ where
             simpleperf_symbol_tables_kernel_image,
             simpleperf_symbol_tables_kernel_modules,
             simpleperf_jit_app_cache_library,
-            pe_mappings: PeMappings::new(),
+            report,
+            modules_by_path: HashMap::new(),
+            pe_mappings: PeMappings::new(
+                &profile_creation_props.extra_pe_extensions,
+                profile_creation_props.sniff_pe_magic,
+            ),
             jit_category_manager: JitCategoryManager::new(),
+            native_lib_category_manager: NativeLibCategoryManager::new(
+                &profile_creation_props.dynamic_linker_names,
+            ),
             fold_recursive_prefix: profile_creation_props.fold_recursive_prefix,
+            include_kernel_frames: profile_creation_props.include_kernel_frames,
+            include_truncated_stack_marker: profile_creation_props.include_truncated_stack_marker,
+            prune_idle_threads: profile_creation_props.prune_idle_threads,
+            off_cpu_syscalls: profile_creation_props.off_cpu_syscalls,
+            include_off_cpu_samples: profile_creation_props.include_off_cpu_samples,
+            sample_ip_markers: profile_creation_props.sample_ip_markers,
+            idle_threads: profile_creation_props.idle_threads,
+            perf_stat_intervals_path: profile_creation_props.perf_stat_intervals_path.clone(),
+            branch_edges: profile_creation_props
+                .branch_edges_path
+                .as_ref()
+                .map(|_| BranchEdges::default()),
+            branch_edges_path: profile_creation_props.branch_edges_path.clone(),
+            module_list_path: profile_creation_props.module_list_path.clone(),
+            aux_decoder: None,
+            delayed_product_name_generator: None,
+            time_range: profile_creation_props
+                .time_range
+                .filter(|_| first_sample_time != 0)
+                .map(|(start, end)| RawTimeRange::from_relative(first_sample_time, start, end)),
+            unanchored_time_range: profile_creation_props
+                .time_range
+                .filter(|_| first_sample_time == 0),
             arg_count_to_include_in_process_name: profile_creation_props
                 .arg_count_to_include_in_process_name,
             cpus,
+            on_cpu_thread_counter,
+            cpu_wide_switch_current_tids: HashMap::new(),
+            name_transform: name_transform_for_replacements(
+                &profile_creation_props.name_replacements,
+            ),
             call_chain_return_addresses_are_preadjusted,
         }
     }
 
-    pub fn finish(mut self) -> Profile {
+    /// Returns the information that was computed for all modules which were
+    /// added to a process so far, including the debug name and debug ID which
+    /// their symbols can be fetched by. This also covers modules whose file
+    /// couldn't be opened during conversion.
+    pub fn module_infos(&self) -> impl Iterator<Item = &ModuleInfo> {
+        self.modules_by_path.values()
+    }
+
+    /// Apply the name transform, if one was set.
+    pub fn transform_name(&self, name: &str) -> String {
+        match &self.name_transform {
+            Some(name_transform) => name_transform(name),
+            None => name.to_owned(),
+        }
+    }
+
+    #[allow(unused)]
+    pub fn finish(self) -> Profile {
+        self.finish_with_report().0
+    }
+
+    /// Returns a profile with all records which have been handled so far,
+    /// without ending the conversion. This is used to show a profile while the
+    /// recording is still going on.
+    ///
+    /// Only the samples and lib mappings are included; jitdump, perf map,
+    /// marker and `perf stat` files are only read by `finish`.
+    pub fn snapshot(&mut self) -> Profile {
+        let mut profile = self.profile.clone();
+        self.processes
+            .snapshot(&mut profile, &self.unresolved_stacks);
+        if self.prune_idle_threads {
+            profile.remove_idle_threads();
+        }
+        profile
+    }
+
+    /// Like `finish`, but also returns the non-fatal problems which were
+    /// encountered during the conversion, so that the caller can present them
+    /// in one place.
+    pub fn finish_with_report(mut self) -> (Profile, ConversionReport) {
+        if let Some(path) = self.perf_stat_intervals_path.take() {
+            self.add_perf_stat_interval_counters(&path);
+        }
+        if let (Some(branch_edges), Some(path)) = (&self.branch_edges, &self.branch_edges_path) {
+            let result = std::fs::File::create(path)
+                .and_then(|file| branch_edges.write_csv(std::io::BufWriter::new(file)));
+            if let Err(err) = result {
+                self.report.add(ConversionWarning::UnwritableFile {
+                    path: path.to_string_lossy().into(),
+                    reason: err.to_string(),
+                });
+            }
+        }
+        if let Some(path) = &self.module_list_path {
+            let result = std::fs::File::create(path).and_then(|file| {
+                write_module_list_csv(self.module_infos(), std::io::BufWriter::new(file))
+            });
+            if let Err(err) = result {
+                self.report.add(ConversionWarning::UnwritableFile {
+                    path: path.to_string_lossy().into(),
+                    reason: err.to_string(),
+                });
+            }
+        }
         let mut profile = self.profile;
         self.simpleperf_jit_app_cache_library
             .finish_and_set_symbol_table(&mut profile);
@@ -280,31 +537,281 @@ where
             &mut self.jit_category_manager,
             &self.timestamp_converter,
         );
-        profile
+        if self.prune_idle_threads {
+            // Thread reuse decisions have all been made by now, so idle threads
+            // have already had their chance to be merged with a later thread.
+            profile.remove_idle_threads();
+        }
+        (profile, self.report)
+    }
+
+    /// Adds a counter for each event in the `perf stat -I` output at `path`.
+    ///
+    /// The interval times in this output are relative to the start of
+    /// `perf stat`, which is assumed to coincide with the first sample.
+    fn add_perf_stat_interval_counters(&mut self, path: &Path) {
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(err) => {
+                self.report.add(ConversionWarning::UnreadableFile {
+                    path: path.to_string_lossy().into(),
+                    reason: err.to_string(),
+                });
+                return;
+            }
+        };
+        let series = parse_perf_stat_intervals(&text);
+        if series.is_empty() {
+            return;
+        }
+        let start = Timestamp::from_nanos_since_reference(0);
+        let process = match &self.cpus {
+            Some(cpus) => cpus.process_handle(),
+            None => self.profile.add_process("perf stat", 0, start),
+        };
+        for series in series {
+            let description = match series.unit.as_str() {
+                "" => format!("{} per interval, from perf stat", series.event),
+                unit => format!("{} per interval in {unit}, from perf stat", series.event),
+            };
+            let counter =
+                self.profile
+                    .add_counter(process, &series.event, "perf stat", &description);
+            self.profile.add_counter_sample(counter, start, 0.0, 0);
+            for (time, count) in series.readings {
+                let nanos = u64::try_from(time.as_nanos()).unwrap_or(u64::MAX);
+                let timestamp = Timestamp::from_nanos_since_reference(nanos);
+                self.profile
+                    .add_counter_sample(counter, timestamp, count, 1);
+            }
+        }
+    }
+
+    pub fn handle_lost(&mut self, e: LostRecord) {
+        self.report.add_lost_events(e.count);
+    }
+
+    /// Called when a range of a process's address space is unmapped. perf.data
+    /// files have no records for unmaps, so this only happens if the recording
+    /// has the `syscalls:sys_enter_munmap` tracepoint.
+    ///
+    /// Libraries in the range are unmapped from `timestamp` on, so that their
+    /// addresses aren't attributed to them if something else is mapped there
+    /// later, e.g. after a plugin was unloaded with `dlclose`.
+    ///
+    /// The tracepoint fires before the kernel checks the arguments, so calls
+    /// which are bound to fail, like empty, unaligned or wrapping ranges, are
+    /// skipped.
+    pub fn handle_munmap(&mut self, pid: i32, address: u64, length: u64, timestamp: u64) {
+        const MIN_PAGE_SIZE: u64 = 4096;
+        if length == 0 || address % MIN_PAGE_SIZE != 0 || address.checked_add(length).is_none() {
+            self.report.add_skipped_record();
+            return;
+        }
+        let avma_range = AvmaRange::with_start_size(address, length);
+        self.pe_mappings.handle_munmap(pid, &avma_range);
+        let process = self.processes.get_by_pid(pid, &mut self.profile);
+        process.remove_lib_mapping_range(timestamp, avma_range.start(), avma_range.end());
+    }
+
+    /// Called for `PERF_RECORD_TEXT_POKE` records, which the kernel emits when it
+    /// modifies its own code. Each poke becomes a marker on the thread which
+    /// caused it, so that the kernel code at the time of a sample can be
+    /// reconstructed.
+    pub fn handle_text_poke(&mut self, record: &RawEventRecord, common: CommonData) {
+        let (Some(pid), Some(tid), Some(timestamp)) = (common.pid, common.tid, common.timestamp)
+        else {
+            self.report.add_skipped_record();
+            return;
+        };
+        let Ok(poke) = TextPoke::parse(record.data, record.parse_info.endian) else {
+            self.report.add_skipped_record();
+            return;
+        };
+        if !self.is_in_time_range(timestamp) {
+            return;
+        }
+        let timestamp = self.timestamp_converter.convert_time(timestamp);
+        let marker = TextPokeMarker::new(&poke, &mut self.profile);
+        let process = self.processes.get_by_pid(pid, &mut self.profile);
+        let thread = process.threads.get_thread_by_tid(tid, &mut self.profile);
+        self.profile.add_marker(
+            thread.profile_thread,
+            MarkerTiming::Instant(timestamp),
+            marker,
+        );
+    }
+
+    /// Called for `PERF_RECORD_AUX` records, which the kernel emits when new
+    /// trace data landed in the AUX area. The data itself arrives separately,
+    /// see [`Converter::handle_auxtrace`].
+    pub fn handle_aux(&mut self, record: &RawEventRecord) {
+        let Ok(aux) = AuxRecord::parse(record.data, record.parse_info.endian) else {
+            self.report.add_skipped_record();
+            return;
+        };
+        if self.aux_decoder.is_none() {
+            self.report.add(ConversionWarning::UndecodedAuxTrace);
+        }
+        if aux.lost_data() {
+            self.report.add(ConversionWarning::AuxTraceDataLost);
+        }
+    }
+
+    /// Called with the payload of the `PERF_RECORD_AUXTRACE_INFO` record, which
+    /// selects the decoder for the trace data.
+    pub fn handle_auxtrace_info(&mut self, info: &[u8]) {
+        if self.aux_decoder.is_none() {
+            self.aux_decoder = new_aux_decoder(info, self.endian);
+        }
+        if let Some(decoder) = &mut self.aux_decoder {
+            decoder.handle_info(info);
+        }
+    }
+
+    /// Called with the body of a `PERF_RECORD_AUXTRACE` record, followed by
+    /// its trace data. The trace is decoded with the decoder for the trace
+    /// type, and the resulting samples are added to the profile.
+    pub fn handle_auxtrace(&mut self, data: &[u8]) {
+        let Some(chunk) = AuxTraceChunk::parse(data, self.endian) else {
+            self.report.add_skipped_record();
+            return;
+        };
+        let Some(decoder) = &mut self.aux_decoder else {
+            return;
+        };
+        let mut samples = Vec::new();
+        decoder.decode(&chunk, &mut samples);
+        for sample in samples {
+            self.add_decoded_aux_sample(sample);
+        }
+    }
+
+    fn add_decoded_aux_sample(&mut self, sample: DecodedAuxSample) {
+        if !self.is_in_time_range(sample.timestamp) {
+            return;
+        }
+        let profile_timestamp = self.timestamp_converter.convert_time(sample.timestamp);
+        let stack: Vec<StackFrame> = sample
+            .frames
+            .iter()
+            .enumerate()
+            .filter(|(_, (_, mode))| *mode != StackMode::Kernel || self.include_kernel_frames)
+            .map(|(i, &(address, mode))| match i {
+                0 => StackFrame::InstructionPointer(address, mode),
+                _ => StackFrame::ReturnAddress(address, mode),
+            })
+            .collect();
+        let stack_index = self.unresolved_stacks.convert(stack.into_iter().rev());
+
+        let process = self.processes.get_by_pid(sample.pid, &mut self.profile);
+        let thread = process
+            .threads
+            .get_thread_by_tid(sample.tid, &mut self.profile);
+        process.unresolved_samples.add_sample(
+            thread.profile_thread,
+            profile_timestamp,
+            sample.timestamp,
+            stack_index,
+            CpuDelta::ZERO,
+            1,
+            None,
+        );
+    }
+
+    /// Called when the input ends in the middle of a record. The records
+    /// which were handled so far are still converted by `finish`.
+    pub fn handle_truncated_input(&mut self, reason: String) {
+        self.report.add(ConversionWarning::TruncatedFile { reason });
+    }
+
+    /// Sets the product name, i.e. the name of the profile. This replaces a
+    /// delayed product name from `set_delayed_product_name_generator`.
+    pub fn set_product_name(&mut self, product_name: &str) {
+        self.delayed_product_name_generator = None;
+        self.profile.set_product(product_name);
     }
 
-    pub fn set_profile_name(&mut self, profile_name: &str) {
-        self.profile.set_product(profile_name);
+    /// Sets the product name once we know which process was profiled, i.e. on
+    /// the first COMM record which isn't for `perf-exec`. The generator is
+    /// called with the transformed name of that process.
+    pub fn set_delayed_product_name_generator(
+        &mut self,
+        generator: impl FnOnce(&str) -> String + Send + 'static,
+    ) {
+        self.delayed_product_name_generator = Some(Box::new(generator));
     }
 
     pub fn set_os_name(&mut self, os_name: &str) {
         self.profile.set_os_name(os_name);
     }
 
+    /// Registers the build ID of a DSO, for streams which send build IDs as
+    /// records instead of in a feature section. This only affects mappings
+    /// which are added after this call.
+    pub fn add_build_id(&mut self, dso_key: DsoKey, dso_info: DsoInfo) {
+        self.build_ids.insert(dso_key, dso_info);
+    }
+
+    /// Adds a key/value pair to the profile metadata, e.g. to record where the
+    /// profile came from.
+    pub fn set_meta_info(&mut self, key: &str, value: &str) {
+        self.profile.set_meta_info(key, value);
+    }
+
+    /// Overrides the sampling interval which is stored in the profile. By default
+    /// it is derived from the recording's sampling frequency or period. This only
+    /// changes the declared interval, e.g. for when the samples have been
+    /// resampled or filtered; it doesn't affect how samples are processed.
+    pub fn set_sampling_interval(&mut self, interval: SamplingInterval) {
+        self.profile.set_interval(interval);
+    }
+
+    /// Called with the timestamp of each record before the record is handled.
+    /// If the time range is still waiting for a reference point, the first
+    /// timestamp becomes that reference point.
+    pub fn anchor_time_range(&mut self, timestamp: u64) {
+        if let Some((start, end)) = self.unanchored_time_range.take() {
+            self.time_range = Some(RawTimeRange::from_relative(timestamp, start, end));
+        }
+    }
+
+    fn is_in_time_range(&self, timestamp: u64) -> bool {
+        self.time_range
+            .map_or(true, |time_range| time_range.contains(timestamp))
+    }
+
+    /// Converts a timestamp of a process / thread lifecycle event, clamped to
+    /// the time range.
+    fn convert_lifecycle_time(&self, timestamp: u64) -> Timestamp {
+        let timestamp = match self.time_range {
+            Some(time_range) => time_range.clamp(timestamp),
+            None => timestamp,
+        };
+        self.timestamp_converter.convert_time(timestamp)
+    }
+
     pub fn handle_main_event_sample<C: ConvertRegs<UnwindRegs = U::UnwindRegs>>(
         &mut self,
         e: &SampleRecord,
+        attr_index: usize,
+        extra_fields: &SampleExtraFields,
     ) {
-        let pid = e.pid.expect("Can't handle samples without pids");
-        let tid = e.tid.expect("Can't handle samples without tids");
+        let (Some(pid), Some(tid), Some(timestamp)) = (e.pid, e.tid, e.timestamp) else {
+            self.report.add_skipped_record();
+            return;
+        };
         if tid == 0 {
-            // Ignore samples in the idle thread.
+            // Samples in the idle task are ignored unless requested.
+            if let (true, Some(cpu)) = (self.idle_threads, e.cpu) {
+                self.handle_idle_task_sample::<C>(e, cpu, timestamp);
+            }
             return;
         }
-        let timestamp = e
-            .timestamp
-            .expect("Can't handle samples without timestamps");
         self.current_sample_time = timestamp;
+        if !self.is_in_time_range(timestamp) {
+            return;
+        }
 
         let profile_timestamp = self.timestamp_converter.convert_time(timestamp);
 
@@ -315,6 +822,17 @@ where
             &self.timestamp_converter,
         );
 
+        if let (Some(branch_edges), Some(branch_stack)) =
+            (&mut self.branch_edges, &extra_fields.branch_stack)
+        {
+            branch_edges.add_branch_stack(pid, branch_stack);
+        }
+
+        let lbr_call_stack = extra_fields
+            .branch_stack
+            .as_ref()
+            .filter(|branch_stack| branch_stack.is_call_stack)
+            .map(|branch_stack| &branch_stack.entries[..]);
         let mut stack = Vec::new();
         Self::get_sample_stack::<C>(
             e,
@@ -322,23 +840,36 @@ where
             &mut self.cache,
             &mut stack,
             self.fold_recursive_prefix,
+            self.include_kernel_frames,
+            self.include_truncated_stack_marker,
             self.call_chain_return_addresses_are_preadjusted,
+            self.endian,
+            lbr_call_stack,
+            &mut self.report,
         );
 
         let thread = process.threads.get_thread_by_tid(tid, &mut self.profile);
+        let stack_index = self.unresolved_stacks.convert(stack.iter().rev().cloned());
 
-        if thread.last_sample_timestamp == Some(timestamp) {
-            // Duplicate sample. Ignore.
+        // Some recordings emit multiple distinct samples with the same timestamp,
+        // e.g. for different events. Only drop a sample if it is identical to the
+        // previous one. Comparing timestamps first keeps this cheap.
+        if thread.last_sample_timestamp == Some(timestamp)
+            && thread.last_sample_attr_index_and_stack == Some((attr_index, stack_index))
+        {
+            self.report.add_duplicate_sample();
             return;
         }
 
         thread.last_sample_timestamp = Some(timestamp);
+        thread.last_sample_attr_index_and_stack = Some((attr_index, stack_index));
         let thread_handle = thread.profile_thread;
 
         // Consume off-cpu time and clear any saved off-CPU stack.
         let off_cpu_sample = self
             .context_switch_handler
             .handle_on_cpu_sample(timestamp, &mut thread.context_switch_data);
+        let off_cpu_sample = off_cpu_sample.filter(|group| overlaps(self.time_range, group));
         if let (Some(off_cpu_sample), Some(off_cpu_stack)) =
             (off_cpu_sample, thread.off_cpu_stack.take())
         {
@@ -369,17 +900,68 @@ where
             CpuDelta::from_nanos(0)
         };
 
-        let stack_index = self.unresolved_stacks.convert(stack.iter().rev().cloned());
+        // Events like memory loads carry a per-sample weight, e.g. the access
+        // latency. Use it so that expensive samples count proportionally.
+        let weight = match extra_fields.weight {
+            Some(weight) => i32::try_from(weight).unwrap_or(i32::MAX),
+            None => 1,
+        };
+
         process.unresolved_samples.add_sample(
             thread_handle,
             profile_timestamp,
             timestamp,
             stack_index,
             cpu_delta,
-            1,
+            weight,
             None,
         );
 
+        if let (true, Some(ip)) = (self.sample_ip_markers, e.ip) {
+            // The unresolved stack only keeps the frames after unwinding, so
+            // store the exact address of the leaf separately.
+            let name = self.profile.intern_string("Sample IP");
+            let ip = self.profile.intern_string(&format!("{ip:#x}"));
+            self.profile.add_marker(
+                thread_handle,
+                MarkerTiming::Instant(profile_timestamp),
+                SampleIpMarker { name, ip },
+            );
+        }
+
+        if let Some(data_src) = extra_fields.data_src {
+            let op = self.profile.intern_string(data_src.op());
+            let level = self.profile.intern_string(&data_src.level());
+            let tlb = self.profile.intern_string(&data_src.tlb());
+            let snoop = self.profile.intern_string(data_src.snoop());
+            self.profile.add_marker(
+                thread_handle,
+                MarkerTiming::Instant(profile_timestamp),
+                MemoryAccessMarker {
+                    op,
+                    level,
+                    tlb,
+                    snoop,
+                },
+            );
+        }
+
+        if let Some(read_values) = &extra_fields.read_values {
+            let values = Self::format_read_values(
+                read_values,
+                attr_index,
+                &self.event_names,
+                &self.attr_indices_by_event_id,
+            );
+            let name = self.profile.intern_string("Counter values");
+            let values = self.profile.intern_string(&values);
+            self.profile.add_marker(
+                thread_handle,
+                MarkerTiming::Instant(profile_timestamp),
+                CounterValuesMarker { name, values },
+            );
+        }
+
         if let (Some(cpu_index), Some(cpus)) = (e.cpu, &mut self.cpus) {
             let cpu = cpus.get_mut(cpu_index as usize, &mut self.profile);
 
@@ -405,7 +987,7 @@ where
                 timestamp,
                 stack_index,
                 cpu_delta,
-                1,
+                weight,
                 Some(thread.thread_label_frame.clone()),
             );
 
@@ -415,18 +997,69 @@ where
                 timestamp,
                 stack_index,
                 CpuDelta::ZERO,
-                1,
+                weight,
                 Some(thread.thread_label_frame.clone()),
             );
         }
     }
 
+    /// Adds a sample in the idle task, pid 0. The idle task has one thread per
+    /// CPU, all with tid 0, so the sample goes to the "swapper/N" thread of the
+    /// sample's CPU.
+    fn handle_idle_task_sample<C: ConvertRegs<UnwindRegs = U::UnwindRegs>>(
+        &mut self,
+        e: &SampleRecord,
+        cpu: u32,
+        timestamp: u64,
+    ) {
+        if !self.is_in_time_range(timestamp) {
+            return;
+        }
+        let profile_timestamp = self.timestamp_converter.convert_time(timestamp);
+        let process = self.processes.get_by_pid(0, &mut self.profile);
+
+        let mut stack = Vec::new();
+        Self::get_sample_stack::<C>(
+            e,
+            &process.unwinder,
+            &mut self.cache,
+            &mut stack,
+            self.fold_recursive_prefix,
+            self.include_kernel_frames,
+            self.include_truncated_stack_marker,
+            self.call_chain_return_addresses_are_preadjusted,
+            self.endian,
+            None,
+            &mut self.report,
+        );
+        let stack_index = self.unresolved_stacks.convert(stack.into_iter().rev());
+
+        let thread = process
+            .threads
+            .get_idle_thread_for_cpu(cpu, &mut self.profile);
+        let cpu_delta = CpuDelta::from_nanos(e.period.unwrap_or(0));
+        process.unresolved_samples.add_sample(
+            thread.profile_thread,
+            profile_timestamp,
+            timestamp,
+            stack_index,
+            cpu_delta,
+            1,
+            None,
+        );
+    }
+
     pub fn handle_sched_switch_sample<C: ConvertRegs<UnwindRegs = U::UnwindRegs>>(
         &mut self,
         e: &SampleRecord,
     ) {
-        let pid = e.pid.expect("Can't handle samples without pids");
-        let tid = e.tid.expect("Can't handle samples without tids");
+        let (Some(pid), Some(tid), Some(timestamp_mono)) = (e.pid, e.tid, e.timestamp) else {
+            self.report.add_skipped_record();
+            return;
+        };
+        if !self.is_in_time_range(timestamp_mono) {
+            return;
+        }
         let process = self.processes.get_by_pid(pid, &mut self.profile);
         process.check_jitdump(
             &mut self.jit_category_manager,
@@ -441,18 +1074,36 @@ where
             &mut self.cache,
             &mut stack,
             self.fold_recursive_prefix,
+            self.include_kernel_frames,
+            self.include_truncated_stack_marker,
             self.call_chain_return_addresses_are_preadjusted,
+            self.endian,
+            None,
+            &mut self.report,
         );
 
-        let stack_index = self
-            .unresolved_stacks
-            .convert_no_kernel(stack.iter().rev().cloned());
         let thread = process.threads.get_thread_by_tid(tid, &mut self.profile);
-        thread.off_cpu_stack = Some(stack_index);
+        // Without an off-CPU stack, no off-CPU samples are created when the
+        // thread comes back on-CPU. The CPU delta of the time before the
+        // switch-out goes to the thread's next on-CPU sample instead.
+        if self.include_off_cpu_samples {
+            let mut stack_index = self
+                .unresolved_stacks
+                .convert_no_kernel(stack.iter().rev().cloned());
+            if let Some(syscall_id) = thread.current_syscall {
+                // Show why the thread is going off-CPU.
+                let name = match C::syscall_name(syscall_id) {
+                    Some(name) => self.profile.intern_string(name),
+                    None => self.profile.intern_string(&format!("syscall {syscall_id}")),
+                };
+                stack_index = self.unresolved_stacks.convert_with_prefix(
+                    stack_index,
+                    std::iter::once(StackFrame::Label(name, StackMode::Kernel)),
+                );
+            }
+            thread.off_cpu_stack = Some(stack_index);
+        }
 
-        let timestamp_mono = e
-            .timestamp
-            .expect("Can't handle context switch without time");
         if self.off_cpu_indicator == Some(OffCpuIndicator::SchedSwitchAndSamples) {
             // Treat this sched_switch sample as a switch-out.
             // Sometimes we have sched_switch samples but no context switch records; for
@@ -496,8 +1147,17 @@ where
         &mut self,
         e: &SampleRecord,
     ) {
-        let pid = e.pid.expect("Can't handle samples without pids");
-        // let tid = e.tid.expect("Can't handle samples without tids");
+        let Some(pid) = e.pid else {
+            self.report.add_skipped_record();
+            return;
+        };
+        let Some(timestamp_mono) = e.timestamp else {
+            self.report.add_skipped_record();
+            return;
+        };
+        if !self.is_in_time_range(timestamp_mono) {
+            return;
+        }
         let process = self.processes.get_by_pid(pid, &mut self.profile);
 
         let Some(raw) = e.raw else { return };
@@ -505,10 +1165,6 @@ where
             return;
         };
 
-        let Some(timestamp_mono) = e.timestamp else {
-            eprintln!("rss_stat record doesn't have a timestamp");
-            return;
-        };
         let timestamp = self.timestamp_converter.convert_time(timestamp_mono);
 
         let (prev_size_of_this_member, member) = match rss_stat.member {
@@ -553,7 +1209,12 @@ where
             &mut self.cache,
             &mut stack,
             self.fold_recursive_prefix,
+            self.include_kernel_frames,
+            self.include_truncated_stack_marker,
             self.call_chain_return_addresses_are_preadjusted,
+            self.endian,
+            None,
+            &mut self.report,
         );
         let unresolved_stack = self.unresolved_stacks.convert(stack.into_iter().rev());
         let thread_handle = process.threads.main_thread.profile_thread;
@@ -579,17 +1240,59 @@ where
         );
     }
 
-    pub fn handle_other_event_sample<C: ConvertRegs<UnwindRegs = U::UnwindRegs>>(
+    /// Called for raw_syscalls:sys_enter and raw_syscalls:sys_exit samples.
+    ///
+    /// If off-CPU syscall labels are enabled, this remembers which syscall the
+    /// thread is in, so that a subsequent sched_switch can use it.
+    pub fn handle_raw_syscall_sample<C: ConvertRegs<UnwindRegs = U::UnwindRegs>>(
+        &mut self,
+        e: &SampleRecord,
+        attr_index: usize,
+        is_enter: bool,
+    ) {
+        if self.off_cpu_syscalls {
+            if let (Some(pid), Some(tid)) = (e.pid, e.tid) {
+                let current_syscall = match (is_enter, e.raw) {
+                    (true, Some(raw)) => RawSyscall::parse(raw, self.endian)
+                        .ok()
+                        .map(|syscall| syscall.id),
+                    _ => None,
+                };
+                let process = self.processes.get_by_pid(pid, &mut self.profile);
+                let thread = process.threads.get_thread_by_tid(tid, &mut self.profile);
+                thread.current_syscall = current_syscall;
+            }
+        }
+        self.handle_other_event_sample::<C>(e, attr_index);
+    }
+
+    /// Called for `sched:sched_waking` and `sched:sched_wakeup` samples.
+    ///
+    /// The sample is taken on the waking thread, so the marker is put on that
+    /// thread and records which thread is being woken up.
+    pub fn handle_sched_wakeup_sample<C: ConvertRegs<UnwindRegs = U::UnwindRegs>>(
         &mut self,
         e: &SampleRecord,
         attr_index: usize,
     ) {
-        let pid = e.pid.expect("Can't handle samples without pids");
-        let timestamp_mono = e
-            .timestamp
-            .expect("Can't handle samples without timestamps");
+        let Some(pid) = e.pid else {
+            self.report.add_skipped_record();
+            return;
+        };
+        let Some(raw) = e.raw else { return };
+        let Ok(wakeup) = SchedWakeup::parse(raw, self.endian) else {
+            return;
+        };
+
+        let Some(timestamp_mono) = e.timestamp else {
+            self.report.add_skipped_record();
+            return;
+        };
+        if !self.is_in_time_range(timestamp_mono) {
+            return;
+        }
         let timestamp = self.timestamp_converter.convert_time(timestamp_mono);
-        // let tid = e.tid.expect("Can't handle samples without tids");
+
         let process = self.processes.get_by_pid(pid, &mut self.profile);
         process.check_jitdump(
             &mut self.jit_category_manager,
@@ -604,7 +1307,12 @@ where
             &mut self.cache,
             &mut stack,
             self.fold_recursive_prefix,
+            self.include_kernel_frames,
+            self.include_truncated_stack_marker,
             self.call_chain_return_addresses_are_preadjusted,
+            self.endian,
+            None,
+            &mut self.report,
         );
 
         let thread_handle = match e.tid {
@@ -618,13 +1326,130 @@ where
         };
 
         let unresolved_stack = self.unresolved_stacks.convert(stack.into_iter().rev());
-        if let Some(name) = self.event_names.get(attr_index) {
-            let timing = MarkerTiming::Instant(timestamp);
-            let name = self.profile.intern_string(name);
-            let marker_handle =
-                self.profile
-                    .add_marker(thread_handle, timing, OtherEventMarker(name));
-            process.unresolved_samples.attach_stack_to_marker(
+        let name = match self.event_names.get(attr_index) {
+            Some(name) => name.strip_prefix("sched:").unwrap_or(name),
+            None => "sched_wakeup",
+        };
+        let name = self.profile.intern_string(name);
+        let wakee_name = self.profile.intern_string(&wakeup.comm);
+        let marker_handle = self.profile.add_marker(
+            thread_handle,
+            MarkerTiming::Instant(timestamp),
+            SchedWakeupMarker {
+                name,
+                wakee_name,
+                wakee_tid: wakeup.pid,
+                target_cpu: wakeup.target_cpu,
+            },
+        );
+        process.unresolved_samples.attach_stack_to_marker(
+            thread_handle,
+            timestamp,
+            timestamp_mono,
+            unresolved_stack,
+            marker_handle,
+        );
+    }
+
+    /// Formats the counter values of a sample, e.g.
+    /// `cycles=20000, instructions=31234`. Values without an event ID are
+    /// assumed to be in the order of the attrs, starting with the sampled event.
+    fn format_read_values(
+        read_values: &SampleReadValues,
+        attr_index: usize,
+        event_names: &[String],
+        attr_indices_by_event_id: &HashMap<u64, usize>,
+    ) -> String {
+        let values: Vec<String> = read_values
+            .values
+            .iter()
+            .enumerate()
+            .map(|(i, read_value)| {
+                let value_attr_index = match read_value.id {
+                    Some(id) => attr_indices_by_event_id.get(&id).copied(),
+                    None => Some(attr_index + i),
+                };
+                let value = read_values.scaled(read_value.value);
+                match value_attr_index.and_then(|index| event_names.get(index)) {
+                    Some(name) => format!("{name}={value}"),
+                    None => format!("<event {i}>={value}"),
+                }
+            })
+            .collect();
+        values.join(", ")
+    }
+
+    pub fn handle_other_event_sample<C: ConvertRegs<UnwindRegs = U::UnwindRegs>>(
+        &mut self,
+        e: &SampleRecord,
+        attr_index: usize,
+    ) {
+        let (Some(pid), Some(timestamp_mono)) = (e.pid, e.timestamp) else {
+            self.report.add_skipped_record();
+            return;
+        };
+        if let (Some(name), Some(raw)) = (self.event_names.get(attr_index), e.raw) {
+            if let Some((address, length)) = parse_munmap_tracepoint(name, raw, self.endian) {
+                self.handle_munmap(pid, address, length, timestamp_mono);
+            }
+        }
+        if !self.is_in_time_range(timestamp_mono) {
+            return;
+        }
+        let timestamp = self.timestamp_converter.convert_time(timestamp_mono);
+        let process = self.processes.get_by_pid(pid, &mut self.profile);
+        process.check_jitdump(
+            &mut self.jit_category_manager,
+            &mut self.profile,
+            &self.timestamp_converter,
+        );
+
+        let mut stack = Vec::new();
+        Self::get_sample_stack::<C>(
+            e,
+            &process.unwinder,
+            &mut self.cache,
+            &mut stack,
+            self.fold_recursive_prefix,
+            self.include_kernel_frames,
+            self.include_truncated_stack_marker,
+            self.call_chain_return_addresses_are_preadjusted,
+            self.endian,
+            None,
+            &mut self.report,
+        );
+
+        let thread_handle = match e.tid {
+            Some(tid) => {
+                process
+                    .threads
+                    .get_thread_by_tid(tid, &mut self.profile)
+                    .profile_thread
+            }
+            None => process.threads.main_thread.profile_thread,
+        };
+
+        let unresolved_stack = self.unresolved_stacks.convert(stack.into_iter().rev());
+        if let Some(name) = self.event_names.get(attr_index) {
+            let timing = MarkerTiming::Instant(timestamp);
+            let args = e
+                .raw
+                .and_then(|raw| format_syscall_tracepoint(name, raw, self.endian));
+            let name = self.profile.intern_string(name);
+            let marker_handle = match args {
+                Some(args) => {
+                    let args = self.profile.intern_string(&args);
+                    self.profile.add_marker(
+                        thread_handle,
+                        timing,
+                        SyscallTracepointMarker { name, args },
+                    )
+                }
+                None => self
+                    .profile
+                    .add_marker(thread_handle, timing, OtherEventMarker(name)),
+            };
+            process.unresolved_samples.attach_stack_to_marker(
                 thread_handle,
                 timestamp,
                 timestamp_mono,
@@ -653,22 +1478,40 @@ where
     ///    bytes on the stack are just copied into the perf.data file, and we
     ///    need to do the unwinding now, based on the register values in
     ///    `e.user_regs` and the raw stack bytes in `e.user_stack`.
+    ///
+    ///  - With LBR call stacks (`perf record --call-graph lbr`), the user stack
+    ///    is in the sample's branch stack, which is passed as `lbr_call_stack`.
+    ///    It's only used if neither of the above produced user frames.
+    ///
+    /// If `include_kernel_frames` is false, the kernel stack is skipped.
+    ///
+    /// If DWARF unwinding fails before reaching the root of the stack, this is
+    /// recorded in `report`, and a [`StackFrame::TruncatedStackMarker`] is
+    /// appended to the stack, unless `include_truncated_stack_marker` is false.
+    #[allow(clippy::too_many_arguments)]
     fn get_sample_stack<C: ConvertRegs<UnwindRegs = U::UnwindRegs>>(
         e: &SampleRecord,
         unwinder: &U,
         cache: &mut U::Cache,
         stack: &mut Vec<StackFrame>,
         fold_recursive_prefix: bool,
+        include_kernel_frames: bool,
+        include_truncated_stack_marker: bool,
         call_chain_return_addresses_are_preadjusted: bool,
+        endian: Endianness,
+        lbr_call_stack: Option<&[BranchEntry]>,
+        report: &mut ConversionReport,
     ) {
         stack.truncate(0);
 
         // CpuMode::from_misc(e.raw.misc)
 
+        let ip_mode = ip_stack_mode(e.callchain, StackMode::from(e.cpu_mode));
+
         // Get the first fragment of the stack from e.callchain.
         if let Some(callchain) = e.callchain {
             let mut is_first_frame = true;
-            let mut mode = StackMode::from(e.cpu_mode);
+            let mut mode = ip_mode;
             for i in 0..callchain.len() {
                 let address = callchain.get(i).unwrap();
                 if address >= PERF_CONTEXT_MAX {
@@ -677,6 +1520,9 @@ where
                     }
                     continue;
                 }
+                if mode == StackMode::Kernel && !include_kernel_frames {
+                    continue;
+                }
 
                 let stack_frame =
                     match (is_first_frame, call_chain_return_addresses_are_preadjusted) {
@@ -692,7 +1538,7 @@ where
 
         // Append the user stack with the help of DWARF unwinding.
         if let (Some(regs), Some((user_stack, _))) = (&e.user_regs, e.user_stack) {
-            let ustack_bytes = RawDataU64::from_raw_data::<LittleEndian>(user_stack);
+            let ustack_bytes = raw_data_u64(user_stack, endian);
             let (pc, sp, regs) = C::convert_regs(regs);
             let mut read_stack = |addr: u64| {
                 // ustack_bytes has the stack bytes starting from the current stack pointer.
@@ -708,7 +1554,10 @@ where
                     Ok(Some(frame)) => frame,
                     Ok(None) => break,
                     Err(_) => {
-                        stack.push(StackFrame::TruncatedStackMarker);
+                        report.add_truncated_stack();
+                        if include_truncated_stack_marker {
+                            stack.push(StackFrame::TruncatedStackMarker);
+                        }
                         break;
                     }
                 };
@@ -724,9 +1573,14 @@ where
             }
         }
 
+        if let Some(lbr_call_stack) = lbr_call_stack {
+            append_lbr_call_stack(stack, e.ip, ip_mode, lbr_call_stack);
+        }
+
         if stack.is_empty() {
-            if let Some(ip) = e.ip {
-                stack.push(StackFrame::InstructionPointer(ip, e.cpu_mode.into()));
+            if let (Some(ip), true) = (e.ip, ip_mode != StackMode::Kernel || include_kernel_frames)
+            {
+                stack.push(StackFrame::InstructionPointer(ip, ip_mode));
             }
         } else if fold_recursive_prefix {
             let last_frame = *stack.last().unwrap();
@@ -746,7 +1600,7 @@ where
         }
 
         if e.page_offset == 0 {
-            self.pe_mappings.check_mmap(&path, e.address);
+            self.pe_mappings.check_mmap(e.pid, &path, e.address);
         }
 
         if !e.is_executable {
@@ -779,6 +1633,7 @@ where
                 e.address,
                 e.length,
                 build_id.as_deref(),
+                None,
                 timestamp,
             );
         }
@@ -800,12 +1655,11 @@ where
         }
 
         if e.page_offset == 0 {
-            self.pe_mappings.check_mmap(&path, e.address);
+            self.pe_mappings.check_mmap(e.pid, &path, e.address);
         }
 
-        const PROT_EXEC: u32 = 0b100;
-        if e.protection & PROT_EXEC == 0 && !self.simpleperf_symbol_tables_user.contains_key(&*path)
-        {
+        let protection = MappingProtection(e.protection);
+        if !protection.is_executable() && !self.simpleperf_symbol_tables_user.contains_key(&*path) {
             // Ignore non-executable mappings.
             // Don't ignore mappings that simpleperf found symbols for, even if they're
             // non-executable. TODO: Find out why .vdex and .jar mappings with symbols aren't
@@ -836,6 +1690,7 @@ where
             e.address,
             e.length,
             build_id.as_deref(),
+            Some(protection),
             timestamp,
         );
     }
@@ -850,7 +1705,7 @@ where
             None => path,
         };
 
-        if filename.starts_with("jit-") && filename.ends_with(".dump") {
+        if is_jitdump_file_name(filename) {
             let jitdump_path = Path::new(path);
             let process = self.processes.get_by_pid(pid, &mut self.profile);
             let thread = process.threads.get_thread_by_tid(tid, &mut self.profile);
@@ -880,112 +1735,226 @@ where
     }
 
     pub fn handle_context_switch(&mut self, e: ContextSwitchRecord, common: CommonData) {
-        let pid = common.pid.expect("Can't handle samples without pids");
-        let tid = common.tid.expect("Can't handle samples without tids");
+        let Some(timestamp) = common.timestamp else {
+            self.report.add_skipped_record();
+            return;
+        };
+        let current = common.pid.zip(common.tid);
+        match (e, common.cpu) {
+            (
+                ContextSwitchRecord::In {
+                    prev_pid: Some(prev_pid),
+                    prev_tid: Some(prev_tid),
+                },
+                Some(cpu),
+            ) => {
+                self.handle_cpu_wide_switch(
+                    cpu,
+                    Some((prev_pid, prev_tid)),
+                    current,
+                    timestamp,
+                    false,
+                );
+            }
+            (
+                ContextSwitchRecord::Out {
+                    next_pid: Some(next_pid),
+                    next_tid: Some(next_tid),
+                    preempted,
+                },
+                Some(cpu),
+            ) => {
+                self.handle_cpu_wide_switch(
+                    cpu,
+                    current,
+                    Some((next_pid, next_tid)),
+                    timestamp,
+                    preempted == TaskWasPreempted::Yes,
+                );
+            }
+            (e, cpu) => {
+                let Some((pid, tid)) = current else {
+                    self.report.add_skipped_record();
+                    return;
+                };
+                match e {
+                    ContextSwitchRecord::In { .. } => {
+                        self.switch_thread_in(pid, tid, timestamp, cpu);
+                    }
+                    ContextSwitchRecord::Out { preempted, .. } => {
+                        self.switch_thread_out(
+                            pid,
+                            tid,
+                            timestamp,
+                            cpu,
+                            preempted == TaskWasPreempted::Yes,
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    /// Handles a cpu-wide switch record, as emitted in system-wide recordings
+    /// (`perf record -a`). These come in pairs: the outgoing thread gets a
+    /// switch-out record which names the next thread, and the incoming thread
+    /// gets a switch-in record which names the previous thread.
+    ///
+    /// Whichever record of the pair we see first applies both transitions, so
+    /// that the accounting is still correct if the other record was lost or if
+    /// it was emitted without a pid / tid.
+    fn handle_cpu_wide_switch(
+        &mut self,
+        cpu: u32,
+        prev: Option<(i32, i32)>,
+        next: Option<(i32, i32)>,
+        timestamp: u64,
+        preempted: bool,
+    ) {
+        let next_tid = next.map(|(_pid, tid)| tid);
+        if next_tid.is_some() && self.cpu_wide_switch_current_tids.get(&cpu) == next_tid.as_ref() {
+            // The other record of this pair has already been handled.
+            return;
+        }
+        if let Some((pid, tid)) = prev {
+            self.switch_thread_out(pid, tid, timestamp, Some(cpu), preempted);
+        }
+        match next {
+            Some((pid, tid)) => {
+                self.cpu_wide_switch_current_tids.insert(cpu, tid);
+                self.switch_thread_in(pid, tid, timestamp, Some(cpu));
+            }
+            None => {
+                self.cpu_wide_switch_current_tids.remove(&cpu);
+            }
+        }
+    }
+
+    fn switch_thread_in(&mut self, pid: i32, tid: i32, timestamp: u64, cpu: Option<u32>) {
         if tid == 0 {
             // Thread 0 is the idle thread. Ignore switch-in and switch-outs.
             return;
         }
-        let timestamp = common
-            .timestamp
-            .expect("Can't handle context switch without time");
         let process = self.processes.get_by_pid(pid, &mut self.profile);
         let thread = process.threads.get_thread_by_tid(tid, &mut self.profile);
 
-        match e {
-            ContextSwitchRecord::In { .. } => {
-                // Consume off-cpu time and clear the saved off-CPU stack.
-                let off_cpu_sample = self
+        if let Some(on_cpu_thread_counter) = &mut self.on_cpu_thread_counter {
+            let profile_timestamp = self.timestamp_converter.convert_time(timestamp);
+            on_cpu_thread_counter.notify_switch_in(tid, profile_timestamp, &mut self.profile);
+        }
+
+        // Consume off-cpu time and clear the saved off-CPU stack.
+        let off_cpu_sample = self
+            .context_switch_handler
+            .handle_switch_in(timestamp, &mut thread.context_switch_data);
+        let off_cpu_sample = off_cpu_sample.filter(|group| overlaps(self.time_range, group));
+        if let (Some(off_cpu_sample), Some(off_cpu_stack)) =
+            (off_cpu_sample, thread.off_cpu_stack.take())
+        {
+            let cpu_delta_ns = self
+                .context_switch_handler
+                .consume_cpu_delta(&mut thread.context_switch_data);
+            process_off_cpu_sample_group(
+                off_cpu_sample,
+                thread.profile_thread,
+                cpu_delta_ns,
+                &self.timestamp_converter,
+                self.off_cpu_weight_per_sample,
+                off_cpu_stack,
+                &mut process.unresolved_samples,
+            );
+        }
+        if let (Some(cpus), Some(cpu_index)) = (&mut self.cpus, cpu) {
+            let combined_thread = cpus.combined_thread_handle();
+            let idle_frame_label = cpus.idle_frame_label();
+            let cpu = cpus.get_mut(cpu_index as usize, &mut self.profile);
+            if let Some(idle_cpu_sample) = self
+                .context_switch_handler
+                .handle_switch_in(timestamp, &mut cpu.context_switch_data)
+                .filter(|group| overlaps(self.time_range, group))
+            {
+                // Add two samples with a stack saying "<Idle>", with zero weight.
+                // This will correctly break up the stack chart to show that nothing was running in the idle time.
+                // This first sample will carry any leftover accumulated running time ("cpu delta"),
+                // and the second sample is placed at the end of the paused time.
+                let cpu_delta_ns = self
                     .context_switch_handler
-                    .handle_switch_in(timestamp, &mut thread.context_switch_data);
-                if let (Some(off_cpu_sample), Some(off_cpu_stack)) =
-                    (off_cpu_sample, thread.off_cpu_stack.take())
-                {
-                    let cpu_delta_ns = self
-                        .context_switch_handler
-                        .consume_cpu_delta(&mut thread.context_switch_data);
-                    process_off_cpu_sample_group(
-                        off_cpu_sample,
-                        thread.profile_thread,
-                        cpu_delta_ns,
-                        &self.timestamp_converter,
-                        self.off_cpu_weight_per_sample,
-                        off_cpu_stack,
-                        &mut process.unresolved_samples,
-                    );
-                }
-                if let (Some(cpus), Some(cpu_index)) = (&mut self.cpus, common.cpu) {
-                    let combined_thread = cpus.combined_thread_handle();
-                    let idle_frame_label = cpus.idle_frame_label();
-                    let cpu = cpus.get_mut(cpu_index as usize, &mut self.profile);
-                    if let Some(idle_cpu_sample) = self
-                        .context_switch_handler
-                        .handle_switch_in(timestamp, &mut cpu.context_switch_data)
-                    {
-                        // Add two samples with a stack saying "<Idle>", with zero weight.
-                        // This will correctly break up the stack chart to show that nothing was running in the idle time.
-                        // This first sample will carry any leftover accumulated running time ("cpu delta"),
-                        // and the second sample is placed at the end of the paused time.
-                        let cpu_delta_ns = self
-                            .context_switch_handler
-                            .consume_cpu_delta(&mut cpu.context_switch_data);
-                        let cpu_delta = CpuDelta::from_nanos(cpu_delta_ns);
-                        let begin_timestamp = self
-                            .timestamp_converter
-                            .convert_time(idle_cpu_sample.begin_timestamp);
-                        process.unresolved_samples.add_sample(
-                            cpu.thread_handle,
-                            begin_timestamp,
-                            idle_cpu_sample.begin_timestamp,
-                            UnresolvedStackHandle::EMPTY,
-                            cpu_delta,
-                            0,
-                            Some(idle_frame_label.clone()),
-                        );
+                    .consume_cpu_delta(&mut cpu.context_switch_data);
+                let cpu_delta = CpuDelta::from_nanos(cpu_delta_ns);
+                let begin_timestamp = self
+                    .timestamp_converter
+                    .convert_time(idle_cpu_sample.begin_timestamp);
+                process.unresolved_samples.add_sample(
+                    cpu.thread_handle,
+                    begin_timestamp,
+                    idle_cpu_sample.begin_timestamp,
+                    UnresolvedStackHandle::EMPTY,
+                    cpu_delta,
+                    0,
+                    Some(idle_frame_label.clone()),
+                );
 
-                        // Emit a "rest sample" with a CPU delta of zero covering the rest of the paused range.
-                        let end_timestamp = self
-                            .timestamp_converter
-                            .convert_time(idle_cpu_sample.end_timestamp);
-                        process.unresolved_samples.add_sample(
-                            cpu.thread_handle,
-                            end_timestamp,
-                            idle_cpu_sample.end_timestamp,
-                            UnresolvedStackHandle::EMPTY,
-                            CpuDelta::from_nanos(0),
-                            0,
-                            Some(idle_frame_label),
-                        );
-                    }
-                    cpu.notify_switch_in(
-                        tid,
-                        thread.thread_label(),
-                        timestamp,
-                        &self.timestamp_converter,
-                        &[cpu.thread_handle, combined_thread],
-                        &mut self.profile,
-                    );
-                }
-            }
-            ContextSwitchRecord::Out { preempted, .. } => {
-                self.context_switch_handler
-                    .handle_switch_out(timestamp, &mut thread.context_switch_data);
-                if let (Some(cpus), Some(cpu_index)) = (&mut self.cpus, Some(common.cpu.unwrap())) {
-                    let combined_thread = cpus.combined_thread_handle();
-                    let cpu = cpus.get_mut(cpu_index as usize, &mut self.profile);
-                    self.context_switch_handler
-                        .handle_switch_out(timestamp, &mut cpu.context_switch_data);
-                    cpu.notify_switch_out(
-                        tid,
-                        timestamp,
-                        &self.timestamp_converter,
-                        &[cpu.thread_handle, combined_thread],
-                        thread.profile_thread,
-                        preempted == TaskWasPreempted::Yes,
-                        &mut self.profile,
-                    );
-                }
+                // Emit a "rest sample" with a CPU delta of zero covering the rest of the paused range.
+                let end_timestamp = self
+                    .timestamp_converter
+                    .convert_time(idle_cpu_sample.end_timestamp);
+                process.unresolved_samples.add_sample(
+                    cpu.thread_handle,
+                    end_timestamp,
+                    idle_cpu_sample.end_timestamp,
+                    UnresolvedStackHandle::EMPTY,
+                    CpuDelta::from_nanos(0),
+                    0,
+                    Some(idle_frame_label),
+                );
             }
+            cpu.notify_switch_in(
+                tid,
+                thread.thread_label(),
+                timestamp,
+                &self.timestamp_converter,
+                &[cpu.thread_handle, combined_thread],
+                &mut self.profile,
+            );
+        }
+    }
+
+    fn switch_thread_out(
+        &mut self,
+        pid: i32,
+        tid: i32,
+        timestamp: u64,
+        cpu: Option<u32>,
+        preempted: bool,
+    ) {
+        if tid == 0 {
+            // Thread 0 is the idle thread. Ignore switch-in and switch-outs.
+            return;
+        }
+        let process = self.processes.get_by_pid(pid, &mut self.profile);
+        let thread = process.threads.get_thread_by_tid(tid, &mut self.profile);
+
+        if let Some(on_cpu_thread_counter) = &mut self.on_cpu_thread_counter {
+            let profile_timestamp = self.timestamp_converter.convert_time(timestamp);
+            on_cpu_thread_counter.notify_switch_out(tid, profile_timestamp, &mut self.profile);
+        }
+
+        self.context_switch_handler
+            .handle_switch_out(timestamp, &mut thread.context_switch_data);
+        if let (Some(cpus), Some(cpu_index)) = (&mut self.cpus, cpu) {
+            let combined_thread = cpus.combined_thread_handle();
+            let cpu = cpus.get_mut(cpu_index as usize, &mut self.profile);
+            self.context_switch_handler
+                .handle_switch_out(timestamp, &mut cpu.context_switch_data);
+            cpu.notify_switch_out(
+                tid,
+                timestamp,
+                &self.timestamp_converter,
+                &[cpu.thread_handle, combined_thread],
+                thread.profile_thread,
+                preempted,
+                &mut self.profile,
+            );
         }
     }
 
@@ -994,7 +1963,7 @@ where
     /// FORK records are emitted if a new thread is started or if a new
     /// process is created. The name is inherited from the forking thread.
     pub fn handle_fork(&mut self, e: ForkOrExitRecord) {
-        let start_time = self.timestamp_converter.convert_time(e.timestamp);
+        let start_time = self.convert_lifecycle_time(e.timestamp);
 
         let is_main = e.pid == e.tid;
         let parent_process = self.processes.get_by_pid(e.ppid, &mut self.profile);
@@ -1032,7 +2001,10 @@ where
     /// Called for an EXIT record.
     pub fn handle_exit(&mut self, e: ForkOrExitRecord) {
         let is_main = e.pid == e.tid;
-        let end_time = self.timestamp_converter.convert_time(e.timestamp);
+        let end_time = self.convert_lifecycle_time(e.timestamp);
+        if let Some(on_cpu_thread_counter) = &mut self.on_cpu_thread_counter {
+            on_cpu_thread_counter.notify_switch_out(e.tid, end_time, &mut self.profile);
+        }
         if is_main {
             self.processes.remove(
                 e.pid,
@@ -1050,6 +2022,12 @@ where
     }
 
     pub fn handle_comm(&mut self, e: CommOrExecRecord, timestamp: Option<u64>) {
+        if self.delayed_product_name_generator.is_some() && &e.name.as_slice()[..] != b"perf-exec" {
+            let generator = self.delayed_product_name_generator.take().unwrap();
+            let first_process_name =
+                self.transform_name(&String::from_utf8_lossy(&e.name.as_slice()));
+            self.profile.set_product(&generator(&first_process_name));
+        }
         if e.is_execve {
             self.handle_exec(e, timestamp, None);
         } else {
@@ -1072,13 +2050,14 @@ where
             Some(0) | None => self.current_sample_time,
             Some(ts) => ts,
         };
-        let timestamp = self.timestamp_converter.convert_time(timestamp_mono);
+        let timestamp = self.convert_lifecycle_time(timestamp_mono);
 
         let name = if let Some((exec_name, args)) = exec_name_and_cmdline {
             make_process_name(&exec_name, args, self.arg_count_to_include_in_process_name)
         } else {
             comm_name.clone()
         };
+        let name = self.transform_name(&name);
 
         // eprintln!("Process execve: pid={}, tid={}, new name: {}", e.pid, e.tid, name);
 
@@ -1118,7 +2097,7 @@ where
     pub fn handle_thread_rename(&mut self, e: CommOrExecRecord, timestamp: Option<u64>) {
         let is_main = e.pid == e.tid;
         let name = e.name.as_slice();
-        let name = String::from_utf8_lossy(&name);
+        let name = self.transform_name(&String::from_utf8_lossy(&name));
 
         // If the COMM record doesn't have a timestamp, take the last seen
         // timestamp from the previous sample.
@@ -1126,7 +2105,7 @@ where
             Some(0) | None => self.current_sample_time,
             Some(ts) => ts,
         };
-        let timestamp = self.timestamp_converter.convert_time(timestamp_mono);
+        let timestamp = self.convert_lifecycle_time(timestamp_mono);
 
         if is_main {
             // eprintln!("Process rename: pid={}, new name: {}", e.pid, name);
@@ -1152,10 +2131,12 @@ where
         exe_name: &str,
         args: Vec<String>,
     ) {
+        let name = make_process_name(exe_name, args, self.arg_count_to_include_in_process_name);
+        let name = self.transform_name(&name);
+
         let process = self.processes.get_by_pid(pid, &mut self.profile);
         let process_handle = process.profile_process;
 
-        let name = make_process_name(exe_name, args, self.arg_count_to_include_in_process_name);
         self.profile.set_process_name(process_handle, &name);
         process.name = Some(name.to_owned());
 
@@ -1169,6 +2150,7 @@ where
     #[allow(unused)]
     pub fn register_existing_thread(&mut self, pid: i32, tid: i32, name: &str) {
         let is_main = pid == tid;
+        let name = self.transform_name(name);
 
         let process = self.processes.get_by_pid(pid, &mut self.profile);
         let process_handle = process.profile_process;
@@ -1176,8 +2158,8 @@ where
         let thread = process.threads.get_thread_by_tid(tid, &mut self.profile);
         let thread_handle = thread.profile_thread;
 
-        self.profile.set_thread_name(thread_handle, name);
-        thread.name = Some(name.to_owned());
+        self.profile.set_thread_name(thread_handle, &name);
+        thread.name = Some(name);
 
         // Mark this as the start time of the new thread / process.
         let time = self
@@ -1194,20 +2176,39 @@ where
         build_id: Option<&[u8]>,
         path_slice: &[u8],
     ) {
-        let path = std::str::from_utf8(path_slice).unwrap().to_string();
+        let path = kernel_module_path(path_slice);
+        let recorded_build_id = build_id;
         let build_id: Option<Vec<u8>> = match (build_id, self.kernel_symbols.as_ref()) {
-            (None, Some(kernel_symbols)) if kernel_symbols.base_avma == base_address => {
-                Some(kernel_symbols.build_id.clone())
+            (None, Some(kernel_symbols))
+                if kernel_symbols.base_avma == base_address
+                    && kernel_symbols.build_id.is_some() =>
+            {
+                kernel_symbols.build_id.clone()
             }
-            (None, _) => kernel_module_build_id(Path::new(&path), &self.binary_lookup_dirs),
+            (None, _) => path_from_unix_bytes(path_slice)
+                .and_then(|path| kernel_module_build_id(path, &self.binary_lookup_dirs)),
             (Some(build_id), _) => Some(build_id.to_owned()),
         };
         let debug_id = build_id
             .as_deref()
-            .map(|id| DebugId::from_identifier(id, self.endian == Endianness::LittleEndian));
-
-        let debug_path = match self.linux_version.as_deref() {
-            Some(linux_version) if dso_key == DsoKey::Kernel => {
+            .map(|id| debug_id_from_build_id(id, self.endian));
+
+        let debug_path = match (
+            self.vmlinux_debug_path.as_deref(),
+            self.linux_version.as_deref(),
+        ) {
+            (Some(vmlinux_debug_path), Some(linux_version)) if dso_key == DsoKey::Kernel => {
+                // The user told us where the vmlinux debug file is.
+                vmlinux_debug_path.replace("{linux_version}", linux_version)
+            }
+            (Some(vmlinux_debug_path), None)
+                if dso_key == DsoKey::Kernel && !vmlinux_debug_path.contains("{linux_version}") =>
+            {
+                // Without a known kernel version, only a path without the
+                // placeholder can be used.
+                vmlinux_debug_path.to_owned()
+            }
+            (None, Some(linux_version)) if dso_key == DsoKey::Kernel => {
                 // Take a guess at the vmlinux debug file path.
                 format!("/usr/lib/debug/boot/vmlinux-{linux_version}")
             }
@@ -1215,9 +2216,10 @@ where
         };
 
         let symbol_table = if dso_key == DsoKey::Kernel {
-            match (&build_id, self.kernel_symbols.as_ref()) {
-                (Some(build_id), Some(kernel_symbols))
-                    if build_id == &kernel_symbols.build_id && kernel_symbols.base_avma != 0 =>
+            match self.kernel_symbols.as_ref() {
+                Some(kernel_symbols)
+                    if kernel_symbols.matches_build_id(build_id.as_deref())
+                        && kernel_symbols.base_avma != 0 =>
                 {
                     // Run `echo '0' | sudo tee /proc/sys/kernel/kptr_restrict` to get here without root.
                     Some(kernel_symbols.symbol_table.clone())
@@ -1247,6 +2249,21 @@ where
             self.simpleperf_symbol_tables_kernel_modules
                 .get(path_slice)
                 .map(|s| s.symbol_table.clone())
+                .or_else(|| {
+                    // Only use the module symbols from kallsyms if they belong to the
+                    // kernel in this profile: either the user passed a kallsyms file
+                    // explicitly, or the recorded kernel build ID matches.
+                    let kernel_symbols = self.kernel_symbols.as_ref()?;
+                    let kernel_image_mapping = self.kernel_image_mapping.as_ref();
+                    if kernel_symbols.build_id.is_some()
+                        && !kernel_image_mapping.is_some_and(|m| m.has_recorded_matching_build_id)
+                    {
+                        return None;
+                    }
+                    let symbol_table =
+                        kernel_symbols.symbol_table_for_module(dso_key.name(), base_address)?;
+                    Some(Arc::new(symbol_table))
+                })
         };
 
         let lib_handle = self.profile.add_lib(LibraryInfo {
@@ -1267,10 +2284,18 @@ where
         if dso_key == DsoKey::Kernel {
             // Store information about this mapping so that we can later adjust the mapping,
             // if we find that other kernel modules overlap with it.
+            let has_recorded_matching_build_id = match self.kernel_symbols.as_ref() {
+                Some(kernel_symbols) => {
+                    recorded_build_id.is_some()
+                        && kernel_symbols.build_id.as_deref() == recorded_build_id
+                }
+                None => false,
+            };
             self.kernel_image_mapping = Some(KernelImageMapping {
                 lib_handle,
                 base_address,
                 end_address,
+                has_recorded_matching_build_id,
             });
         } else if let Some(kernel_image_mapping) = &self.kernel_image_mapping {
             // We added a kernel module which is not the main kernel image.
@@ -1349,6 +2374,7 @@ where
         mapping_start_avma: u64,
         mapping_size: u64,
         build_id: Option<&[u8]>,
+        protection: Option<MappingProtection>,
         timestamp: u64,
     ) {
         let avma_range = AvmaRange::with_start_size(mapping_start_avma, mapping_size);
@@ -1362,7 +2388,7 @@ where
 
         let mut mapping_info = MappingInfo::new_elf(path, avma_range);
         if path_slice.is_empty() {
-            if let Some(pe_mapping) = self.pe_mappings.find_mapping(&avma_range) {
+            if let Some(pe_mapping) = self.pe_mappings.find_mapping(process_pid, &avma_range) {
                 mapping_info = MappingInfo::new_pe(pe_mapping);
             }
         }
@@ -1370,7 +2396,38 @@ where
         let mut file = None;
         let mut path = mapping_info.path.to_string_lossy().to_string();
 
-        if let Ok((f, p)) = open_file_with_fallback(&mapping_info.path, &self.binary_lookup_dirs) {
+        let mut used_override_path = false;
+        if let Some((build_id, override_path)) = build_id
+            .and_then(|build_id| Some((build_id, self.build_id_path_overrides.get(build_id)?)))
+        {
+            match std::fs::File::open(override_path) {
+                Ok(f) => {
+                    // Only use the override if it's really the same binary.
+                    let found = elf_build_id_of_file(&f);
+                    if found.as_deref() == Some(build_id) {
+                        file = Some(f);
+                        path = override_path.to_string_lossy().to_string();
+                        used_override_path = true;
+                    } else {
+                        self.report.add(ConversionWarning::BuildIdMismatch {
+                            path: override_path.to_string_lossy().into(),
+                            expected: ElfBuildId::from_bytes(build_id).to_string(),
+                            found: found.map(|id| ElfBuildId::from_bytes(&id).to_string()),
+                        });
+                    }
+                }
+                Err(err) => self.report.add(ConversionWarning::UnreadableFile {
+                    path: override_path.to_string_lossy().into(),
+                    reason: err.to_string(),
+                }),
+            }
+        }
+
+        if file.is_some() {
+            // We already have the file from the build ID override.
+        } else if let Ok((f, p)) =
+            open_file_with_fallback(&mapping_info.path, &self.binary_lookup_dirs)
+        {
             // Fix up bad files from `perf inject --jit`.
             if let Some((fixed_file, fixed_path)) = correct_bad_perf_jit_so_file(&f, &path) {
                 file = Some(fixed_file);
@@ -1381,11 +2438,61 @@ where
             }
         }
 
-        let name = match path.rfind('/') {
-            Some(pos) => path[pos + 1..].to_owned(),
-            None => path.clone(),
+        // Name the module after the mapped file, even if we use an override path.
+        let name_source = match file {
+            Some(_) if !used_override_path => path.clone(),
+            _ => mapping_info.path.to_string_lossy().to_string(),
+        };
+        let mut name = match name_source.rfind('/') {
+            Some(pos) => name_source[pos + 1..].to_owned(),
+            None => name_source.clone(),
         };
 
+        if file.is_none() && name == "[vdso]" {
+            // The VDSO has no file on disk, but perf may have saved its image in the
+            // build-id cache. Treat that image like a regular file, so that we can
+            // use it for unwinding and symbolication even if the profile was
+            // recorded on a different machine or kernel.
+            if let Some(vdso_path) = build_id.and_then(|build_id| {
+                find_vdso_image_in_build_id_cache(build_id, &self.binary_lookup_dirs)
+            }) {
+                if let Ok(f) = std::fs::File::open(&vdso_path) {
+                    file = Some(f);
+                    path = vdso_path.to_string_lossy().to_string();
+                    name = "[vdso]".to_string();
+                }
+            }
+        }
+
+        if file.is_none() && name != "[vdso]" && !name.is_empty() {
+            // If this is the main executable, the mapping may not have a usable path
+            // (e.g. a [bracketed] pseudo path), or the path may not exist on this
+            // machine. Try to find a binary with the process's comm name, and only
+            // use it if its build ID matches.
+            if let Some(build_id) = build_id {
+                let comm = self
+                    .processes
+                    .get_by_pid(process_pid, &mut self.profile)
+                    .name
+                    .clone();
+                if let Some(comm) = comm.filter(|comm| {
+                    !original_path.starts_with(b"/") || name.starts_with(comm.as_str())
+                }) {
+                    if let Some((f, p)) =
+                        self.executable_finder
+                            .find(&comm, build_id, &self.binary_lookup_dirs)
+                    {
+                        file = Some(f);
+                        path = p.to_string_lossy().to_string();
+                        name = comm;
+                    }
+                }
+            }
+        }
+
+        let native_lib_category = self
+            .native_lib_category_manager
+            .category_for_lib(&name, &mut self.profile);
         let process = self.processes.get_by_pid(process_pid, &mut self.profile);
 
         // Case 1: There are symbols in the file, if we are importing a perf.data file
@@ -1415,11 +2522,27 @@ where
 
             // If we have a build ID, convert it to a debug_id and a code_id.
             let debug_id = build_id
-                .map(|id| DebugId::from_identifier(id, true)) // TODO: endian
+                .map(|id| debug_id_from_build_id(id, self.endian))
                 .unwrap_or_default();
             let code_id = build_id
                 .map(|build_id| CodeId::ElfBuildId(ElfBuildId::from_bytes(build_id)).to_string());
 
+            // The relative addresses in simpleperf's symbol tables are SVMAs.
+            self.modules_by_path.insert(
+                path.clone(),
+                ModuleInfo {
+                    path: path.clone(),
+                    code_id: expected_code_id,
+                    debug_name: name.clone(),
+                    debug_id,
+                    avma_range: avma_range.start()..avma_range.end(),
+                    base_avma: mapping_start_avma.wrapping_sub(relative_address_at_start.into()),
+                    base_svma: 0,
+                    unwind_sections: ModuleUnwindSections::None,
+                    protection,
+                    control_flow_protection: ControlFlowProtection::default(),
+                },
+            );
             let lib_handle = self.profile.add_lib(LibraryInfo {
                 debug_id,
                 code_id,
@@ -1435,7 +2558,9 @@ where
                 Some(AndroidArtInfo::JavaFrame) => {
                     LibMappingInfo::new_java_mapping(lib_handle, symbol_table.category)
                 }
-                None => LibMappingInfo::new_lib(lib_handle),
+                None => {
+                    LibMappingInfo::new_lib_with_optional_category(lib_handle, native_lib_category)
+                }
             };
             process.add_regular_lib_mapping(
                 timestamp,
@@ -1448,97 +2573,156 @@ where
         }
 
         // Case 2: We have access to the file that was loaded into the process.
+        let have_file = file.is_some();
         if let Some(file) = file {
             let mmap = match unsafe { memmap2::MmapOptions::new().map(&file) } {
                 Ok(mmap) => Arc::new(mmap),
                 Err(err) => {
-                    eprintln!("Could not mmap file {path}: {err:?}");
+                    self.report.add(ConversionWarning::UnreadableFile {
+                        path,
+                        reason: err.to_string(),
+                    });
                     return;
                 }
             };
 
-            let file = match object::File::parse(&mmap[..]) {
-                Ok(file) => file,
-                Err(_) => {
-                    eprintln!("File {path} has unrecognized format");
-                    return;
+            if let Ok(file) = object::File::parse(&mmap[..]) {
+                let file_code_id = mapping_info.code_id.clone().or_else(|| {
+                    Some(CodeId::ElfBuildId(ElfBuildId::from_bytes(
+                        file.build_id().ok()??,
+                    )))
+                });
+                if let Some(expected_code_id) = &expected_code_id {
+                    if !Self::code_id_matches(file_code_id.as_ref(), expected_code_id) {
+                        self.report.add(ConversionWarning::BuildIdMismatch {
+                            path: path.clone(),
+                            expected: expected_code_id.to_string(),
+                            found: file_code_id.as_ref().map(ToString::to_string),
+                        });
+                        add_build_id_mismatch_marker(
+                            &mut self.profile,
+                            process.threads.main_thread.profile_thread,
+                            self.timestamp_converter.convert_time(
+                                timestamp.max(self.timestamp_converter.reference_raw),
+                            ),
+                            &path,
+                            expected_code_id,
+                            file_code_id.as_ref(),
+                        );
+                        return;
+                    }
                 }
-            };
-
-            let file_code_id = mapping_info.code_id.clone().or_else(|| {
-                Some(CodeId::ElfBuildId(ElfBuildId::from_bytes(
-                    file.build_id().ok()??,
-                )))
-            });
-            if expected_code_id.as_ref().is_some_and(|expected_code_id| {
-                !Self::code_id_matches(file_code_id.as_ref(), expected_code_id, &path)
-            }) {
-                return;
-            }
-
-            let module_section_info =
-                Self::module_section_info_with_object(Some(mmap.clone()), &file);
-            let Some(library_info) =
-                Self::library_info_with_object(&name, &path, &file, file_code_id)
-            else {
-                return;
-            };
-
-            let Some(base_avma) = mapping_info.compute_base_avma(&file, mapping_start_file_offset)
-            else {
-                return;
-            };
-            let module = Module::new(
-                path.to_string(),
-                avma_range.start()..avma_range.end(),
-                base_avma,
-                module_section_info,
-            );
 
-            let relative_address_at_start = (mapping_start_avma - module.base_avma()) as u32;
-            process.unwinder.add_module(module);
-            let lib_handle = self.profile.add_lib(library_info);
+                let module_section_info =
+                    Self::module_section_info_with_object(Some(mmap.clone()), &file);
+                let Some(library_info) =
+                    Self::library_info_with_object(&name, &path, &file, file_code_id.clone())
+                else {
+                    return;
+                };
 
-            if name.starts_with("jitted-") && name.ends_with(".so") {
-                let symbol_name = jit_function_name(&file);
-                process.add_lib_mapping_for_injected_jit_lib(
-                    timestamp,
-                    self.timestamp_converter.convert_time(timestamp),
-                    symbol_name,
-                    avma_range.start(),
-                    avma_range.end(),
-                    relative_address_at_start,
-                    lib_handle,
-                    &mut self.jit_category_manager,
-                    &mut self.profile,
+                let Some(base_avma) =
+                    mapping_info.compute_base_avma(&file, mapping_start_file_offset)
+                else {
+                    return;
+                };
+                self.modules_by_path.insert(
+                    path.clone(),
+                    ModuleInfo {
+                        path: path.clone(),
+                        code_id: file_code_id,
+                        debug_name: library_info.debug_name.clone(),
+                        debug_id: library_info.debug_id,
+                        avma_range: avma_range.start()..avma_range.end(),
+                        base_avma,
+                        base_svma: module_section_info.base_svma,
+                        unwind_sections: ModuleUnwindSections::for_section_info(
+                            &module_section_info,
+                        ),
+                        protection,
+                        control_flow_protection: ControlFlowProtection::from_object(&file),
+                    },
                 );
-            } else {
-                process.add_regular_lib_mapping(
-                    timestamp,
-                    avma_range.start(),
-                    avma_range.end(),
-                    relative_address_at_start,
-                    LibMappingInfo::new_lib(lib_handle),
+                let module = Module::new(
+                    path.to_string(),
+                    avma_range.start()..avma_range.end(),
+                    base_avma,
+                    module_section_info,
                 );
+
+                let relative_address_at_start = (mapping_start_avma - module.base_avma()) as u32;
+                process.add_unwinder_module(avma_range.start()..avma_range.end(), module);
+                let lib_handle = self.profile.add_lib(library_info);
+
+                if name.starts_with("jitted-") && name.ends_with(".so") {
+                    let symbol_name = jit_function_name(&file);
+                    process.add_lib_mapping_for_injected_jit_lib(
+                        timestamp,
+                        self.timestamp_converter.convert_time(timestamp),
+                        symbol_name,
+                        avma_range.start(),
+                        avma_range.end(),
+                        relative_address_at_start,
+                        lib_handle,
+                        &mut self.jit_category_manager,
+                        &mut self.profile,
+                    );
+                } else {
+                    process.add_regular_lib_mapping(
+                        timestamp,
+                        avma_range.start(),
+                        avma_range.end(),
+                        relative_address_at_start,
+                        LibMappingInfo::new_lib_with_optional_category(
+                            lib_handle,
+                            native_lib_category,
+                        ),
+                    );
+                }
+                return;
             }
-            return;
+
+            // Fall through and register the mapping without the file's
+            // section information, like for a missing file.
+            self.report.add(ConversionWarning::UnreadableFile {
+                path: path.clone(),
+                reason: "unrecognized format".to_string(),
+            });
         }
 
         // Case 3: This is the VDSO mapping.
         if name == "[vdso]" {
             if let Some(vdso) = VdsoObject::shared_instance_for_this_process() {
-                if expected_code_id.as_ref().is_some_and(|expected_code_id| {
-                    !Self::code_id_matches(Some(vdso.code_id()), expected_code_id, &path)
-                }) {
-                    return;
+                if let Some(expected_code_id) = &expected_code_id {
+                    if !Self::code_id_matches(Some(vdso.code_id()), expected_code_id) {
+                        self.report.add(ConversionWarning::BuildIdMismatch {
+                            path: path.clone(),
+                            expected: expected_code_id.to_string(),
+                            found: Some(vdso.code_id().to_string()),
+                        });
+                        add_build_id_mismatch_marker(
+                            &mut self.profile,
+                            process.threads.main_thread.profile_thread,
+                            self.timestamp_converter.convert_time(
+                                timestamp.max(self.timestamp_converter.reference_raw),
+                            ),
+                            &path,
+                            expected_code_id,
+                            Some(vdso.code_id()),
+                        );
+                        return;
+                    }
                 }
 
                 let module_section_info =
                     Self::module_section_info_with_object(None, vdso.object());
                 let code_id = vdso.code_id().clone();
-                let Some(library_info) =
-                    Self::library_info_with_object(&name, &path, vdso.object(), Some(code_id))
-                else {
+                let Some(library_info) = Self::library_info_with_object(
+                    &name,
+                    &path,
+                    vdso.object(),
+                    Some(code_id.clone()),
+                ) else {
                     return;
                 };
 
@@ -1547,6 +2731,23 @@ where
                 else {
                     return;
                 };
+                self.modules_by_path.insert(
+                    path.clone(),
+                    ModuleInfo {
+                        path: path.clone(),
+                        code_id: Some(code_id),
+                        debug_name: library_info.debug_name.clone(),
+                        debug_id: library_info.debug_id,
+                        avma_range: avma_range.start()..avma_range.end(),
+                        base_avma,
+                        base_svma: module_section_info.base_svma,
+                        unwind_sections: ModuleUnwindSections::for_section_info(
+                            &module_section_info,
+                        ),
+                        protection,
+                        control_flow_protection: ControlFlowProtection::from_object(vdso.object()),
+                    },
+                );
                 let module = Module::new(
                     path.clone(),
                     avma_range.start()..avma_range.end(),
@@ -1555,7 +2756,7 @@ where
                 );
 
                 let relative_address_at_start = (mapping_start_avma - module.base_avma()) as u32;
-                process.unwinder.add_module(module);
+                process.add_unwinder_module(avma_range.start()..avma_range.end(), module);
                 let lib_handle = self.profile.add_lib(library_info);
 
                 process.add_regular_lib_mapping(
@@ -1569,7 +2770,11 @@ where
             }
         }
 
-        // Case 4: We don't have access to the file.
+        // Case 4: We don't have access to the file, or we couldn't parse it.
+        if !have_file && original_path.starts_with(b"/") && !original_path.starts_with(b"/memfd:") {
+            self.report
+                .add(ConversionWarning::MissingFile { path: path.clone() });
+        }
 
         // Without access to the binary file, make some guesses. We can't really
         // know what the right base address is because we don't have the section
@@ -1580,8 +2785,23 @@ where
 
         // If we have a build ID, convert it to a debug_id and a code_id.
         let debug_id = build_id
-            .map(|id| DebugId::from_identifier(id, true)) // TODO: endian
+            .map(|id| debug_id_from_build_id(id, self.endian))
             .unwrap_or_default();
+        self.modules_by_path.insert(
+            path.clone(),
+            ModuleInfo {
+                path: path.clone(),
+                code_id: expected_code_id,
+                debug_name: name.clone(),
+                debug_id,
+                avma_range: avma_range.start()..avma_range.end(),
+                base_avma,
+                base_svma: 0,
+                unwind_sections: ModuleUnwindSections::None,
+                protection,
+                control_flow_protection: ControlFlowProtection::default(),
+            },
+        );
         let code_id = build_id
             .map(|build_id| CodeId::ElfBuildId(ElfBuildId::from_bytes(build_id)).to_string());
 
@@ -1595,38 +2815,30 @@ where
             arch: None,
             symbol_table: None,
         });
+
+        // Anonymous writable and executable memory is most likely code from a JIT
+        // which doesn't write a jitdump file. Put it into the JIT category rather
+        // than treating it like a native library.
+        let category = if protection.is_some_and(MappingProtection::is_likely_jit) {
+            Some(
+                self.jit_category_manager
+                    .default_category(&mut self.profile)
+                    .into(),
+            )
+        } else {
+            native_lib_category
+        };
         process.add_regular_lib_mapping(
             timestamp,
             avma_range.start(),
             avma_range.end(),
             relative_address_at_start,
-            LibMappingInfo::new_lib(lib_handle),
+            LibMappingInfo::new_lib_with_optional_category(lib_handle, category),
         );
     }
 
-    fn code_id_matches(
-        file_code_id: Option<&CodeId>,
-        expected_code_id: &CodeId,
-        path: &str,
-    ) -> bool {
-        match file_code_id {
-            Some(file_code_id) if expected_code_id == file_code_id => {
-                // Build IDs match. Good.
-                true
-            }
-            Some(file_code_id) => {
-                eprintln!(
-                        "File {path} has non-matching build ID {file_code_id} (expected {expected_code_id})"
-                    );
-                false
-            }
-            None => {
-                eprintln!(
-                    "File {path} does not contain a build ID, but we expected it to have one"
-                );
-                false
-            }
-        }
+    fn code_id_matches(file_code_id: Option<&CodeId>, expected_code_id: &CodeId) -> bool {
+        file_code_id == Some(expected_code_id)
     }
 
     fn library_info_with_object<'data, R: object::ReadRef<'data>>(
@@ -1668,6 +2880,13 @@ where
                 (CompressionFormat::None, Some(mmap)) => {
                     MmapRangeOrVec::new_mmap_range(mmap.clone(), offset, uncompressed_size)
                 }
+                (CompressionFormat::Zstandard, _) => {
+                    // Decompress zstd sections (ELFCOMPRESS_ZSTD) ourselves, so that we
+                    // don't depend on the object crate's optional decompression support.
+                    let compressed_data = section.compressed_data().ok()?;
+                    let data = decompress_zstd(compressed_data.data, uncompressed_size)?;
+                    Some(MmapRangeOrVec::Vec(Arc::new(data)))
+                }
                 _ => Some(MmapRangeOrVec::Vec(Arc::new(
                     section.uncompressed_data().ok()?.to_vec(),
                 ))),
@@ -1730,6 +2949,22 @@ where
     }
 }
 
+/// Decompresses the contents of an ELF section with `ELFCOMPRESS_ZSTD`
+/// compression. The data can consist of multiple zstd frames.
+fn decompress_zstd(mut compressed_data: &[u8], uncompressed_size: u64) -> Option<Vec<u8>> {
+    use std::io::Read;
+
+    let mut decompressed = Vec::with_capacity(usize::try_from(uncompressed_size).ok()?);
+    while !compressed_data.is_empty() {
+        let mut decoder = ruzstd::StreamingDecoder::new(&mut compressed_data).ok()?;
+        decoder.read_to_end(&mut decompressed).ok()?;
+    }
+    if decompressed.len() as u64 != uncompressed_size {
+        return None;
+    }
+    Some(decompressed)
+}
+
 // #[test]
 // fn test_my_jit() {
 //     let data = std::fs::read("/Users/mstange/Downloads/jitted-123175-0-fixed.so").unwrap();
@@ -1737,6 +2972,77 @@ where
 //     dbg!(jit_function_name(&file));
 // }
 
+/// Interprets `data` as a sequence of u64 values in the byte order of the
+/// recording, which may differ from the byte order of the converting machine.
+fn raw_data_u64(data: RawData, endian: Endianness) -> RawDataU64 {
+    match endian {
+        Endianness::LittleEndian => RawDataU64::from_raw_data::<byteorder::LittleEndian>(data),
+        Endianness::BigEndian => RawDataU64::from_raw_data::<byteorder::BigEndian>(data),
+    }
+}
+
+/// Converts an ELF build ID into a debug ID. Like for the ELF files themselves
+/// (see [`debug_id_for_object`]), the first bytes of the build ID are read as
+/// GUID fields in the byte order of the recorded machine, so that the debug ID
+/// matches the one computed from the binary by the symbolication code.
+fn debug_id_from_build_id(build_id: &[u8], endian: Endianness) -> DebugId {
+    DebugId::from_identifier(build_id, endian == Endianness::LittleEndian)
+}
+
+/// Returns the mode of the sampled instruction pointer. The callchain starts
+/// with a context marker for the mode of its first frame, which is the sampled
+/// instruction pointer. This marker is there even if the kernel couldn't
+/// record any frames, e.g. if the callchain only consists of context markers.
+fn ip_stack_mode(callchain: Option<RawDataU64>, cpu_mode_fallback: StackMode) -> StackMode {
+    let Some(callchain) = callchain else {
+        return cpu_mode_fallback;
+    };
+    (0..callchain.len())
+        .map(|i| callchain.get(i).unwrap())
+        .take_while(|address| *address >= PERF_CONTEXT_MAX)
+        .find_map(StackMode::from_context_frame)
+        .unwrap_or(cpu_mode_fallback)
+}
+
+/// Appends the user stack from the last branch records of `perf record
+/// --call-graph lbr`, if the stack doesn't have any user frames yet. In that
+/// mode, the kernel only records the kernel part of the callchain.
+///
+/// Each entry is a call which is still on the stack, innermost first, and its
+/// `from` address is the call instruction. That address is already inside the
+/// call instruction, so it doesn't need the return address adjustment.
+fn append_lbr_call_stack(
+    stack: &mut Vec<StackFrame>,
+    ip: Option<u64>,
+    ip_mode: StackMode,
+    lbr_call_stack: &[BranchEntry],
+) {
+    if lbr_call_stack.is_empty()
+        || stack
+            .iter()
+            .any(|frame| frame.stack_mode() == Some(StackMode::User))
+    {
+        return;
+    }
+    // If the sample is in the kernel, the user leaf isn't known; the stack
+    // starts with the call site in its caller.
+    if let (Some(ip), StackMode::User) = (ip, ip_mode) {
+        stack.push(StackFrame::InstructionPointer(ip, StackMode::User));
+    }
+    stack.extend(
+        lbr_call_stack
+            .iter()
+            .map(|entry| StackFrame::AdjustedReturnAddress(entry.from, StackMode::User)),
+    );
+}
+
+/// Whether the off-CPU or idle sample group overlaps the time range, if there is one.
+fn overlaps(time_range: Option<RawTimeRange>, group: &OffCpuSampleGroup) -> bool {
+    time_range.map_or(true, |time_range| {
+        time_range.overlaps(group.begin_timestamp, group.end_timestamp)
+    })
+}
+
 fn process_off_cpu_sample_group(
     off_cpu_sample: OffCpuSampleGroup,
     thread_handle: ThreadHandle,
@@ -1771,7 +3077,10 @@ fn process_off_cpu_sample_group(
     if sample_count > 1 {
         // Emit a "rest sample" with a CPU delta of zero covering the rest of the paused range.
         let cpu_delta = CpuDelta::from_nanos(0);
-        let weight = i32::try_from(sample_count - 1).unwrap_or(0) * off_cpu_weight_per_sample;
+        // The rest sample stands for the remaining sample_count - 1 samples.
+        let weight = i32::try_from(sample_count - 1)
+            .unwrap_or(i32::MAX)
+            .saturating_mul(off_cpu_weight_per_sample);
         let profile_timestamp = timestamp_converter.convert_time(end_timestamp);
         samples.add_sample(
             thread_handle,
@@ -1870,6 +3179,9 @@ struct KernelImageMapping {
     lib_handle: LibraryHandle,
     base_address: u64,
     end_address: u64,
+    /// Whether the profile contains a build ID for the kernel image, and it
+    /// matches the build ID of the running kernel's kallsyms.
+    has_recorded_matching_build_id: bool,
 }
 
 #[cfg(unix)]
@@ -1884,6 +3196,116 @@ fn path_from_unix_bytes(path_slice: &[u8]) -> Option<&Path> {
     Some(Path::new(std::str::from_utf8(path_slice).ok()?))
 }
 
+/// Returns the ELF build ID of `file`, if it is an object file with a build ID.
+fn elf_build_id_of_file(file: &std::fs::File) -> Option<Vec<u8>> {
+    let mmap = unsafe { memmap2::MmapOptions::new().map(file) }.ok()?;
+    let object = object::File::parse(&mmap[..]).ok()?;
+    Some(object.build_id().ok()??.to_owned())
+}
+
+/// Converts the path of a kernel module from an mmap record into the path for
+/// the profile. Paths which aren't valid UTF-8 are converted lossily, so that
+/// the module is still added.
+fn kernel_module_path(path_slice: &[u8]) -> String {
+    String::from_utf8_lossy(path_slice).into_owned()
+}
+
+/// Record a module whose file was skipped because its build ID didn't match
+/// the build ID in the profile, so that the reason for unresolved addresses
+/// in that module is visible in the profile itself.
+fn add_build_id_mismatch_marker(
+    profile: &mut Profile,
+    thread_handle: ThreadHandle,
+    timestamp: Timestamp,
+    path: &str,
+    expected_code_id: &CodeId,
+    found_code_id: Option<&CodeId>,
+) {
+    let path = profile.intern_string(path);
+    let expected = profile.intern_string(&expected_code_id.to_string());
+    let found = match found_code_id {
+        Some(found_code_id) => profile.intern_string(&found_code_id.to_string()),
+        None => profile.intern_string("<none>"),
+    };
+    profile.add_marker(
+        thread_handle,
+        MarkerTiming::Instant(timestamp),
+        BuildIdMismatchMarker {
+            path,
+            expected,
+            found,
+        },
+    );
+}
+
+struct BuildIdMismatchMarker {
+    path: StringHandle,
+    expected: StringHandle,
+    found: StringHandle,
+}
+
+impl StaticSchemaMarker for BuildIdMismatchMarker {
+    const UNIQUE_MARKER_TYPE_NAME: &'static str = "BuildIdMismatch";
+
+    fn schema() -> MarkerSchema {
+        MarkerSchema {
+            type_name: Self::UNIQUE_MARKER_TYPE_NAME.into(),
+            locations: vec![MarkerLocation::MarkerChart, MarkerLocation::MarkerTable],
+            chart_label: Some("{marker.data.path}".into()),
+            tooltip_label: Some("{marker.name} - {marker.data.path}".into()),
+            table_label: Some(
+                "{marker.name} - {marker.data.path} (expected {marker.data.expected}, found {marker.data.found})"
+                    .into(),
+            ),
+            fields: vec![
+                MarkerFieldSchema {
+                    key: "path".into(),
+                    label: "Path".into(),
+                    format: MarkerFieldFormat::FilePath,
+                    searchable: true,
+                },
+                MarkerFieldSchema {
+                    key: "expected".into(),
+                    label: "Expected build ID".into(),
+                    format: MarkerFieldFormat::String,
+                    searchable: true,
+                },
+                MarkerFieldSchema {
+                    key: "found".into(),
+                    label: "Found build ID".into(),
+                    format: MarkerFieldFormat::String,
+                    searchable: true,
+                },
+            ],
+            static_fields: vec![MarkerStaticField {
+                label: "Description".into(),
+                value: "Emitted when a module's file was skipped because its build ID didn't match. Addresses in this module can't be symbolicated.".into(),
+            }],
+        }
+    }
+
+    fn name(&self, profile: &mut Profile) -> StringHandle {
+        profile.intern_string("Build ID mismatch")
+    }
+
+    fn category(&self, _profile: &mut Profile) -> CategoryHandle {
+        CategoryHandle::OTHER
+    }
+
+    fn string_field_value(&self, field_index: u32) -> StringHandle {
+        match field_index {
+            0 => self.path,
+            1 => self.expected,
+            2 => self.found,
+            _ => unreachable!(),
+        }
+    }
+
+    fn number_field_value(&self, _field_index: u32) -> f64 {
+        unreachable!()
+    }
+}
+
 struct MmapMarker(StringHandle);
 
 impl StaticSchemaMarker for MmapMarker {
@@ -1921,3 +3343,576 @@ impl StaticSchemaMarker for MmapMarker {
         unreachable!()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use linux_perf_event_reader::CpuMode;
+    use object::write::{Object as WriteObject, StandardSection};
+    use object::{Architecture, BinaryFormat, Endianness as ObjectEndianness, SectionFlags};
+
+    use super::*;
+    use crate::linux_shared::ConvertRegsX86_64;
+
+    type TestUnwinder = framehop::x86_64::UnwinderX86_64<MmapRangeOrVec>;
+
+    /// A single cpu-clock event, sampled every millisecond.
+    fn test_interpretation() -> EventInterpretation {
+        EventInterpretation {
+            main_event_attr_index: 0,
+            main_event_name: "cpu-clock".to_string(),
+            sampling_is_time_based: Some(1_000_000),
+            off_cpu_indicator: None,
+            sched_switch_attr_index: None,
+            known_event_indices: HashMap::new(),
+            event_names: vec!["cpu-clock".to_string()],
+            attr_indices_by_event_id: HashMap::new(),
+            main_event_read_format_has_lost: false,
+        }
+    }
+
+    fn test_converter(
+        props: &ProfileCreationProps,
+        interpretation: EventInterpretation,
+    ) -> Converter<TestUnwinder> {
+        Converter::new(
+            props,
+            ReferenceTimestamp::from_millis_since_unix_epoch(0.0),
+            "test",
+            HashMap::new(),
+            None,
+            0,
+            Endianness::LittleEndian,
+            framehop::x86_64::CacheX86_64::new(),
+            vec![],
+            vec![],
+            interpretation,
+            None,
+            false,
+        )
+    }
+
+    /// A sample with a callchain but without registers or stack bytes.
+    fn test_sample(pid: i32, timestamp: u64, callchain: &[u8]) -> SampleRecord<'_> {
+        SampleRecord {
+            id: None,
+            addr: None,
+            stream_id: None,
+            raw: None,
+            ip: None,
+            timestamp: Some(timestamp),
+            pid: Some(pid),
+            tid: Some(pid),
+            cpu: Some(0),
+            period: None,
+            user_regs: None,
+            user_stack: None,
+            callchain: Some(raw_data_u64(
+                RawData::Single(callchain),
+                Endianness::LittleEndian,
+            )),
+            phys_addr: None,
+            data_page_size: None,
+            code_page_size: None,
+            intr_regs: None,
+            cpu_mode: CpuMode::User,
+        }
+    }
+
+    fn callchain_bytes(addresses: &[u64]) -> Vec<u8> {
+        addresses.iter().flat_map(|a| a.to_le_bytes()).collect()
+    }
+
+    /// The number of samples in each thread of the profile.
+    fn thread_sample_counts(profile: &Profile) -> Vec<u64> {
+        let json = serde_json::to_value(profile).unwrap();
+        json["threads"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|thread| thread["samples"]["length"].as_u64().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn build_id_path_overrides() {
+        let fixture = Path::new(env!("CARGO_MANIFEST_DIR")).join("../fixtures/other/ls-linux/ls");
+        let build_id = elf_build_id_of_file(&std::fs::File::open(&fixture).unwrap()).unwrap();
+        let other_build_id = vec![0x12; build_id.len()];
+
+        let mut props = ProfileCreationProps::for_tests();
+        props.build_id_path_overrides = vec![
+            (build_id.clone(), fixture.clone()),
+            (other_build_id.clone(), fixture.clone()),
+        ];
+        let mut converter = test_converter(&props, test_interpretation());
+        let mapped_path = b"/nonexistent/stripped/ls";
+        for (i, build_id) in [&build_id, &other_build_id].into_iter().enumerate() {
+            converter.add_module_to_process(
+                1,
+                mapped_path,
+                0,
+                0x10000000 * (i as u64 + 1),
+                0x1000,
+                Some(build_id),
+                None,
+                0,
+            );
+        }
+
+        // The override with the matching build ID is used, and named after the
+        // mapped file.
+        let module = &converter.modules_by_path[fixture.to_str().unwrap()];
+        assert_eq!(module.debug_name, "ls");
+
+        // The other override is rejected because its build ID doesn't match,
+        // so the mapping falls back to the mapped path.
+        assert!(converter
+            .modules_by_path
+            .contains_key("/nonexistent/stripped/ls"));
+        assert!(converter
+            .report
+            .warnings()
+            .contains(&ConversionWarning::BuildIdMismatch {
+                path: fixture.to_string_lossy().into(),
+                expected: ElfBuildId::from_bytes(&other_build_id).to_string(),
+                found: Some(ElfBuildId::from_bytes(&build_id).to_string()),
+            }));
+    }
+
+    #[test]
+    fn sampling_interval_override() {
+        let props = ProfileCreationProps::for_tests();
+        let mut converter = test_converter(&props, test_interpretation());
+        converter.set_sampling_interval(SamplingInterval::from_nanos(2_500_000));
+        let profile = converter.finish();
+
+        let json = serde_json::to_value(profile).unwrap();
+        assert_eq!(json["meta"]["interval"], 2.5);
+    }
+
+    #[test]
+    fn product_name_replaces_delayed_product_name() {
+        let props = ProfileCreationProps::for_tests();
+        let mut converter = test_converter(&props, test_interpretation());
+        converter.set_delayed_product_name_generator(|name| format!("{name} on host"));
+        converter.set_product_name("my product");
+        let record = CommOrExecRecord {
+            pid: 1,
+            tid: 1,
+            name: RawData::Single(b"app"),
+            is_execve: false,
+        };
+        converter.handle_comm(record, Some(1_000_000));
+        let profile = converter.finish();
+
+        let json = serde_json::to_value(profile).unwrap();
+        assert_eq!(json["meta"]["product"], "my product");
+    }
+
+    #[test]
+    fn module_list_includes_missing_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let module_list_path = dir.path().join("modules.csv");
+        let mut props = ProfileCreationProps::for_tests();
+        props.module_list_path = Some(module_list_path.clone());
+        let mut converter = test_converter(&props, test_interpretation());
+        let build_id = [0x12; 20];
+        converter.add_module_to_process(
+            1,
+            b"/nonexistent/libfoo.so",
+            0,
+            0x10000000,
+            0x1000,
+            Some(&build_id),
+            None,
+            0,
+        );
+
+        // The debug ID is derived from the build ID in the recording.
+        let module_infos: Vec<_> = converter.module_infos().collect();
+        assert_eq!(module_infos.len(), 1);
+        assert_eq!(module_infos[0].debug_name, "libfoo.so");
+        assert_eq!(
+            module_infos[0].debug_id,
+            debug_id_from_build_id(&build_id, Endianness::LittleEndian)
+        );
+
+        converter.finish();
+        assert_eq!(
+            std::fs::read_to_string(&module_list_path).unwrap(),
+            format!(
+                "debug_name,debug_id,code_id,avma_start,avma_end,base_avma,bias,protection,\
+                 unwind_sections,control_flow_protection,path\n\
+                 libfoo.so,{},{},0x10000000,0x10001000,0x10000000,0x10000000,,none,,/nonexistent/libfoo.so\n",
+                debug_id_from_build_id(&build_id, Endianness::LittleEndian).breakpad(),
+                "12".repeat(20),
+            )
+        );
+    }
+
+    /// The common data of a context switch record for the thread `tid` in the
+    /// process with the same pid.
+    fn switch_common_data(tid: i32, cpu: u32, timestamp: u64) -> CommonData {
+        CommonData {
+            pid: Some(tid),
+            tid: Some(tid),
+            timestamp: Some(timestamp),
+            id: None,
+            stream_id: None,
+            cpu: Some(cpu),
+        }
+    }
+
+    /// Returns the (time, count) samples of the "On-CPU threads" counter.
+    fn on_cpu_counter_samples(profile: Profile) -> Vec<(f64, f64)> {
+        let json = serde_json::to_value(profile).unwrap();
+        let counter = json["counters"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|counter| counter["name"] == "On-CPU threads")
+            .unwrap();
+        let samples = &counter["samples"];
+        let times = samples["time"].as_array().unwrap();
+        let counts = samples["count"].as_array().unwrap();
+        times
+            .iter()
+            .zip(counts)
+            .map(|(time, count)| (time.as_f64().unwrap(), count.as_f64().unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn on_cpu_thread_counter() {
+        let mut props = ProfileCreationProps::for_tests();
+        props.cpu_usage_counter = true;
+        let mut converter = test_converter(&props, test_interpretation());
+        let switch_in = ContextSwitchRecord::In {
+            prev_pid: None,
+            prev_tid: None,
+        };
+        let switch_out = ContextSwitchRecord::Out {
+            next_pid: None,
+            next_tid: None,
+            preempted: TaskWasPreempted::No,
+        };
+        for (e, tid, cpu, timestamp) in [
+            (switch_in, 1, 0, 1_000_000),
+            (switch_in, 2, 1, 2_000_000),
+            (switch_out, 1, 0, 3_000_000),
+            // Thread 3 was already running when the recording started, so its
+            // switch-out doesn't change the count.
+            (switch_out, 3, 2, 4_000_000),
+        ] {
+            converter.handle_context_switch(e, switch_common_data(tid, cpu, timestamp));
+        }
+
+        assert_eq!(
+            on_cpu_counter_samples(converter.finish()),
+            [(1.0, 1.0), (2.0, 1.0), (3.0, -1.0)]
+        );
+    }
+
+    #[test]
+    fn cpu_wide_context_switches() {
+        let mut props = ProfileCreationProps::for_tests();
+        props.cpu_usage_counter = true;
+        let mut converter = test_converter(&props, test_interpretation());
+        let switch_out_to = |next_tid| ContextSwitchRecord::Out {
+            next_pid: Some(next_tid),
+            next_tid: Some(next_tid),
+            preempted: TaskWasPreempted::No,
+        };
+        let switch_in_from = |prev_tid| ContextSwitchRecord::In {
+            prev_pid: Some(prev_tid),
+            prev_tid: Some(prev_tid),
+        };
+
+        // Thread 1 is switched out for thread 2. The switch-out record switches
+        // thread 2 in, so its switch-in record is a no-op.
+        converter.handle_context_switch(switch_out_to(2), switch_common_data(1, 0, 1_000_000));
+        converter.handle_context_switch(switch_in_from(1), switch_common_data(2, 0, 1_000_000));
+        assert_eq!(converter.cpu_wide_switch_current_tids[&0], 2);
+
+        // The switch-out record of thread 2 was lost, but the switch-in record
+        // of thread 1 switches thread 2 out.
+        converter.handle_context_switch(switch_in_from(2), switch_common_data(1, 0, 2_000_000));
+        assert_eq!(converter.cpu_wide_switch_current_tids[&0], 1);
+
+        // Switching to the idle thread only switches thread 1 out.
+        converter.handle_context_switch(switch_out_to(0), switch_common_data(1, 0, 3_000_000));
+
+        assert_eq!(
+            on_cpu_counter_samples(converter.finish()),
+            [(1.0, 1.0), (2.0, -1.0), (2.0, 1.0), (3.0, -1.0)]
+        );
+    }
+
+    #[test]
+    fn name_replacements() {
+        let mut props = ProfileCreationProps::for_tests();
+        props.name_replacements = vec![
+            ("/home/alice".to_string(), "~".to_string()),
+            ("secret".to_string(), "***".to_string()),
+        ];
+        let mut converter = test_converter(&props, test_interpretation());
+        for (tid, name) in [(1, &b"/home/alice/app"[..]), (2, &b"secret-worker"[..])] {
+            let record = CommOrExecRecord {
+                pid: 1,
+                tid,
+                name: RawData::Single(name),
+                is_execve: false,
+            };
+            converter.handle_comm(record, Some(1_000_000));
+        }
+        let profile = converter.finish();
+
+        let json = serde_json::to_value(profile).unwrap();
+        let names: Vec<_> = json["threads"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|thread| {
+                (
+                    thread["processName"].as_str().unwrap(),
+                    thread["name"].as_str().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(names, [("~/app", "~/app"), ("~/app", "***-worker")]);
+    }
+
+    #[test]
+    fn duplicate_samples_need_the_same_stack() {
+        let props = ProfileCreationProps::for_tests();
+        let mut converter = test_converter(&props, test_interpretation());
+        let stack_a = callchain_bytes(&[0x1000, 0x2000]);
+        let stack_b = callchain_bytes(&[0x1100, 0x2000]);
+        let extra_fields = SampleExtraFields::default();
+        for (timestamp, stack) in [
+            (1_000_000, &stack_a),
+            // Same timestamp, event and stack: a duplicate.
+            (1_000_000, &stack_a),
+            // Same timestamp, different stack: kept.
+            (1_000_000, &stack_b),
+            (2_000_000, &stack_b),
+        ] {
+            converter.handle_main_event_sample::<ConvertRegsX86_64>(
+                &test_sample(100, timestamp, stack),
+                0,
+                &extra_fields,
+            );
+        }
+        let (profile, report) = converter.finish_with_report();
+        assert_eq!(report.duplicate_sample_count(), 1);
+        assert_eq!(thread_sample_counts(&profile), [3]);
+    }
+
+    #[test]
+    fn invalid_munmap_ranges_are_skipped() {
+        let props = ProfileCreationProps::for_tests();
+        let mut converter = test_converter(&props, test_interpretation());
+        // A length which wraps around, an empty range and an unaligned address.
+        converter.handle_munmap(100, 0xffff_ffff_ffff_0000, 0x20000, 1_000_000);
+        converter.handle_munmap(100, 0x10000, 0, 1_000_000);
+        converter.handle_munmap(100, 0x10001, 0x1000, 1_000_000);
+        converter.handle_munmap(100, 0x10000, 0x1000, 1_000_000);
+        let (_profile, report) = converter.finish_with_report();
+        assert_eq!(report.skipped_record_count(), 3);
+    }
+
+    /// Turns each byte of trace data into a sample at that many milliseconds.
+    struct ByteSampleDecoder;
+
+    impl AuxDecoder for ByteSampleDecoder {
+        fn decode(&mut self, chunk: &AuxTraceChunk, samples: &mut Vec<DecodedAuxSample>) {
+            samples.extend(chunk.data.iter().map(|byte| DecodedAuxSample {
+                timestamp: u64::from(*byte) * 1_000_000,
+                pid: 100,
+                tid: 100,
+                frames: vec![(0x1000 + u64::from(*byte), StackMode::User)],
+            }));
+        }
+    }
+
+    #[test]
+    fn decode_auxtrace_record() {
+        let props = ProfileCreationProps::for_tests();
+        let mut converter = test_converter(&props, test_interpretation());
+        let mut record = vec![];
+        record.extend_from_slice(&3u64.to_le_bytes()); // size
+        record.extend_from_slice(&0u64.to_le_bytes()); // offset
+        record.extend_from_slice(&0u64.to_le_bytes()); // reference
+        record.extend_from_slice(&0u32.to_le_bytes()); // idx
+        record.extend_from_slice(&100u32.to_le_bytes()); // tid
+        record.extend_from_slice(&u32::MAX.to_le_bytes()); // cpu
+        record.extend_from_slice(&0u32.to_le_bytes()); // reserved
+        record.extend_from_slice(&[1, 2, 3]);
+
+        // Without a decoder for the trace type, the data is ignored.
+        converter.handle_auxtrace(&record);
+        converter.aux_decoder = Some(Box::new(ByteSampleDecoder));
+        converter.handle_auxtrace(&record);
+        // Trace data which is shorter than announced is skipped.
+        converter.handle_auxtrace(&record[..record.len() - 1]);
+        let (profile, report) = converter.finish_with_report();
+        assert_eq!(thread_sample_counts(&profile), [3]);
+        assert_eq!(report.skipped_record_count(), 1);
+    }
+
+    /// Wraps `data` into a zstd frame with a single uncompressed ("raw") block.
+    fn zstd_frame_with_raw_block(data: &[u8]) -> Vec<u8> {
+        assert!(data.len() < 256);
+        let mut frame = vec![];
+        frame.extend_from_slice(&0xFD2FB528u32.to_le_bytes());
+        // Frame header descriptor: Single_Segment_flag, 1-byte Frame_Content_Size.
+        frame.push(0x20);
+        frame.push(data.len() as u8);
+        // Block header: Last_Block, Block_Type = Raw_Block, Block_Size.
+        let block_header = 1 | ((data.len() as u32) << 3);
+        frame.extend_from_slice(&block_header.to_le_bytes()[..3]);
+        frame.extend_from_slice(data);
+        frame
+    }
+
+    #[test]
+    fn ip_stack_mode_from_context_markers() {
+        use linux_perf_event_reader::constants::{PERF_CONTEXT_KERNEL, PERF_CONTEXT_USER};
+
+        let callchain_bytes = |addresses: &[u64]| -> Vec<u8> {
+            addresses.iter().flat_map(|a| a.to_le_bytes()).collect()
+        };
+        let mode_for = |addresses: &[u64]| {
+            let bytes = callchain_bytes(addresses);
+            let callchain = raw_data_u64(RawData::Single(&bytes), Endianness::LittleEndian);
+            ip_stack_mode(Some(callchain), StackMode::User)
+        };
+
+        // Only context markers, no frames.
+        assert_eq!(
+            mode_for(&[PERF_CONTEXT_KERNEL, PERF_CONTEXT_USER]),
+            StackMode::Kernel
+        );
+        assert_eq!(mode_for(&[PERF_CONTEXT_USER]), StackMode::User);
+        // Context markers after the first frame don't apply to the ip.
+        assert_eq!(
+            mode_for(&[0x1000, PERF_CONTEXT_KERNEL, 0xffff_ffff_8100_0000]),
+            StackMode::User
+        );
+        assert_eq!(mode_for(&[]), StackMode::User);
+        assert_eq!(ip_stack_mode(None, StackMode::Kernel), StackMode::Kernel);
+    }
+
+    #[test]
+    fn big_endian_recording() {
+        let addresses = [0x1000, 0x7fff_1234_5678, u64::MAX - 1];
+        let bytes: Vec<u8> = addresses.iter().flat_map(|a| a.to_be_bytes()).collect();
+        let values = raw_data_u64(RawData::Single(&bytes), Endianness::BigEndian);
+        let values: Vec<u64> = (0..values.len()).map(|i| values.get(i).unwrap()).collect();
+        assert_eq!(values, addresses);
+
+        let build_id: Vec<u8> = (1..=20).collect();
+        let big_endian_id = debug_id_from_build_id(&build_id, Endianness::BigEndian);
+        assert_eq!(big_endian_id.uuid().as_bytes(), &build_id[..16]);
+        let little_endian_id = debug_id_from_build_id(&build_id, Endianness::LittleEndian);
+        assert_eq!(
+            little_endian_id.breakpad().to_string(),
+            "0403020106050807090A0B0C0D0E0F100"
+        );
+    }
+
+    #[test]
+    fn lbr_call_stack() {
+        let lbr_call_stack = [
+            BranchEntry::new(0x2010, 0x3000, 0, false),
+            BranchEntry::new(0x1020, 0x2000, 0, false),
+        ];
+
+        // A user sample: the leaf, then the call sites.
+        let mut stack = vec![];
+        append_lbr_call_stack(&mut stack, Some(0x3004), StackMode::User, &lbr_call_stack);
+        assert_eq!(
+            stack,
+            [
+                StackFrame::InstructionPointer(0x3004, StackMode::User),
+                StackFrame::AdjustedReturnAddress(0x2010, StackMode::User),
+                StackFrame::AdjustedReturnAddress(0x1020, StackMode::User),
+            ]
+        );
+
+        // A kernel sample: the user stack follows the kernel callchain.
+        let kernel_frame = StackFrame::InstructionPointer(0xffff_ffff_8100_0000, StackMode::Kernel);
+        let mut stack = vec![kernel_frame];
+        append_lbr_call_stack(
+            &mut stack,
+            Some(0xffff_ffff_8100_0000),
+            StackMode::Kernel,
+            &lbr_call_stack,
+        );
+        assert_eq!(
+            stack,
+            [
+                kernel_frame,
+                StackFrame::AdjustedReturnAddress(0x2010, StackMode::User),
+                StackFrame::AdjustedReturnAddress(0x1020, StackMode::User),
+            ]
+        );
+
+        // Frame pointer or DWARF stacks take precedence.
+        let mut stack = vec![StackFrame::InstructionPointer(0x3004, StackMode::User)];
+        append_lbr_call_stack(&mut stack, Some(0x3004), StackMode::User, &lbr_call_stack);
+        assert_eq!(stack.len(), 1);
+    }
+
+    #[test]
+    fn non_utf8_kernel_module_path() {
+        let path_slice = b"/lib/modules/6.1.0/extra/\xffmodule.ko";
+        assert_eq!(
+            kernel_module_path(path_slice),
+            "/lib/modules/6.1.0/extra/\u{fffd}module.ko"
+        );
+        assert_eq!(
+            DsoKey::detect(path_slice, CpuMode::Kernel),
+            Some(DsoKey::KernelModule {
+                name: "[\u{fffd}module]".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn zstd_compressed_eh_frame() {
+        let eh_frame: Vec<u8> = (0..64).collect();
+
+        let mut section_data = vec![];
+        // Elf64_Chdr
+        section_data.extend_from_slice(&object::elf::ELFCOMPRESS_ZSTD.to_le_bytes());
+        section_data.extend_from_slice(&0u32.to_le_bytes());
+        section_data.extend_from_slice(&(eh_frame.len() as u64).to_le_bytes());
+        section_data.extend_from_slice(&8u64.to_le_bytes());
+        section_data.extend(zstd_frame_with_raw_block(&eh_frame));
+
+        let mut obj = WriteObject::new(
+            BinaryFormat::Elf,
+            Architecture::X86_64,
+            ObjectEndianness::Little,
+        );
+        let text = obj.section_id(StandardSection::Text);
+        obj.append_section_data(text, &[0xc3], 1);
+        let section = obj.add_section(
+            vec![],
+            b".eh_frame".to_vec(),
+            object::SectionKind::ReadOnlyData,
+        );
+        obj.append_section_data(section, &section_data, 8);
+        obj.section_mut(section).flags = SectionFlags::Elf {
+            sh_flags: u64::from(object::elf::SHF_ALLOC | object::elf::SHF_COMPRESSED),
+        };
+        let bytes = obj.write().unwrap();
+
+        let file = object::File::parse(&bytes[..]).unwrap();
+        let section_info = Converter::<
+            framehop::x86_64::UnwinderX86_64<MmapRangeOrVec>,
+        >::module_section_info_with_object(None, &file);
+        assert_eq!(section_info.eh_frame.as_deref(), Some(&eh_frame[..]));
+    }
+}
@@ -11,6 +11,10 @@ pub enum KnownEvent {
     MmapExit,
     MprotectEnter,
     PageFault,
+    SchedWaking,
+    SchedWakeup,
+    RawSyscallEnter,
+    RawSyscallExit,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -32,6 +36,12 @@ pub struct EventInterpretation {
     pub sched_switch_attr_index: Option<usize>,
     pub known_event_indices: HashMap<usize, KnownEvent>,
     pub event_names: Vec<String>,
+    /// The attr index for each event ID, for resolving the IDs in
+    /// `PERF_FORMAT_ID` counter values.
+    pub attr_indices_by_event_id: HashMap<u64, usize>,
+    /// Whether the main event's attr has `PERF_FORMAT_LOST`, which isn't in
+    /// the parsed attr, so the importers have to set this from the raw attr.
+    pub main_event_read_format_has_lost: bool,
 }
 
 impl EventInterpretation {
@@ -79,6 +89,10 @@ impl EventInterpretation {
             ("syscalls:sys_enter_mprotect", KnownEvent::MprotectEnter),
             ("syscalls:sys_enter_mmap", KnownEvent::MmapEnter),
             ("syscalls:sys_exit_mmap", KnownEvent::MmapExit),
+            ("sched:sched_waking", KnownEvent::SchedWaking),
+            ("sched:sched_wakeup", KnownEvent::SchedWakeup),
+            ("raw_syscalls:sys_enter", KnownEvent::RawSyscallEnter),
+            ("raw_syscalls:sys_exit", KnownEvent::RawSyscallExit),
         ];
 
         for (event_name, event) in known_events {
@@ -101,6 +115,17 @@ impl EventInterpretation {
             })
             .collect();
 
+        let attr_indices_by_event_id = attrs
+            .iter()
+            .enumerate()
+            .flat_map(|(attr_index, attr_desc)| {
+                attr_desc
+                    .event_ids
+                    .iter()
+                    .map(move |event_id| (*event_id, attr_index))
+            })
+            .collect();
+
         Self {
             main_event_attr_index,
             main_event_name,
@@ -109,6 +134,8 @@ impl EventInterpretation {
             sched_switch_attr_index,
             known_event_indices,
             event_names,
+            attr_indices_by_event_id,
+            main_event_read_format_has_lost: false,
         }
     }
 }
@@ -1,3 +1,6 @@
+use std::io::Write;
+use std::path::Path;
+
 use object::{Object, ObjectSection, ObjectSegment, ObjectSymbol, SymbolKind};
 
 use super::object_rewriter;
@@ -61,6 +64,9 @@ pub fn jit_function_name<'data>(obj: &object::File<'data>) -> Option<&'data str>
 /// and mmap record are correct for the file offset and address 0x40.
 /// We could also choose to keep the program header, but then we would need to adjust a
 /// lot more: the mmap record, the symbol addresses, and the debug info.
+///
+/// If the fixed file already exists from an earlier conversion, it is reused as long
+/// as it has the expected contents.
 pub fn correct_bad_perf_jit_so_file(
     file: &std::fs::File,
     path: &str,
@@ -100,10 +106,56 @@ pub fn correct_bad_perf_jit_so_file(
     let mut fixed_path = path.strip_suffix(".so").unwrap().to_string();
     fixed_path.push_str("-fixed.so");
 
-    std::fs::write(&fixed_path, fixed_data).ok()?;
+    write_file_if_changed(Path::new(&fixed_path), &fixed_data).ok()?;
 
     // Open the fixed file for reading, and return it.
     let fixed_file = std::fs::File::open(&fixed_path).ok()?;
 
     Some((fixed_file, fixed_path))
 }
+
+/// Writes `data` to `path`, unless the file at `path` already has these contents.
+///
+/// The data is written to a temporary file in the same directory which is then
+/// renamed, so that concurrent conversions of the same profile never see a
+/// partially written file.
+fn write_file_if_changed(path: &Path, data: &[u8]) -> std::io::Result<()> {
+    if std::fs::read(path).is_ok_and(|existing_data| existing_data == data) {
+        return Ok(());
+    }
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let mut temp_file = tempfile::NamedTempFile::new_in(dir)?;
+    temp_file.write_all(data)?;
+    temp_file.persist(path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, SystemTime};
+
+    use super::*;
+
+    #[test]
+    fn reuse_existing_fixed_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("jitted-123-4-fixed.so");
+        write_file_if_changed(&path, b"fixed").unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"fixed");
+
+        let old_time = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+        let file = std::fs::File::options().write(true).open(&path).unwrap();
+        file.set_modified(old_time).unwrap();
+        drop(file);
+        write_file_if_changed(&path, b"fixed").unwrap();
+        let modified = std::fs::metadata(&path).unwrap().modified().unwrap();
+        assert_eq!(modified, old_time);
+
+        write_file_if_changed(&path, b"different").unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"different");
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+}
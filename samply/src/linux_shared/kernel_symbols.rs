@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::fmt::Debug;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
@@ -19,6 +20,9 @@ pub enum KernelSymbolsError {
     #[error("Could not read /proc/kallsyms: {0}")]
     CouldNotReadProcKallsyms(#[source] std::io::Error),
 
+    #[error("Could not read kallsyms file {0:?}: {1}")]
+    CouldNotReadKallsymsFile(PathBuf, #[source] std::io::Error),
+
     #[error("Did not find a _text symbol in the kernel symbol list")]
     NoTextSymbol,
 
@@ -28,9 +32,15 @@ pub enum KernelSymbolsError {
 
 #[derive(Debug, Clone)]
 pub struct KernelSymbols {
-    pub build_id: Vec<u8>,
+    /// The build ID of the kernel image. This is None if the symbols were read
+    /// from a kallsyms file that was captured without the kernel notes; in that
+    /// case we assume that the symbols belong to the kernel in the profile.
+    pub build_id: Option<Vec<u8>>,
     pub base_avma: u64,
     pub symbol_table: Arc<SymbolTable>,
+    /// The symbols of loaded kernel modules, keyed by module name, as absolute
+    /// addresses. Module names are normalized with [`normalize_kernel_module_name`].
+    pub module_symbols: HashMap<String, Vec<(u64, String)>>,
 }
 
 impl KernelSymbols {
@@ -42,14 +52,78 @@ impl KernelSymbols {
             .to_owned();
         let kallsyms = std::fs::read("/proc/kallsyms")
             .map_err(KernelSymbolsError::CouldNotReadProcKallsyms)?;
-        let (base_avma, symbol_table) = parse_kallsyms(&kallsyms)?;
+        Self::new_from_kallsyms_data(&kallsyms, Some(build_id))
+    }
+
+    /// Create the kernel symbols from a copy of /proc/kallsyms which was saved
+    /// at recording time, for example with `sudo cat /proc/kallsyms > kallsyms`.
+    pub fn new_from_kallsyms_file(path: &Path) -> Result<Self, KernelSymbolsError> {
+        let kallsyms = std::fs::read(path)
+            .map_err(|e| KernelSymbolsError::CouldNotReadKallsymsFile(path.to_owned(), e))?;
+        Self::new_from_kallsyms_data(&kallsyms, None)
+    }
+
+    fn new_from_kallsyms_data(
+        kallsyms: &[u8],
+        build_id: Option<Vec<u8>>,
+    ) -> Result<Self, KernelSymbolsError> {
+        let (base_avma, symbol_table) = parse_kallsyms(kallsyms)?;
         let symbol_table = Arc::new(symbol_table);
+        let module_symbols = parse_kallsyms_module_symbols(kallsyms);
         Ok(KernelSymbols {
             build_id,
             base_avma,
             symbol_table,
+            module_symbols,
         })
     }
+
+    /// Returns whether these symbols can be used for the kernel image with the
+    /// given build ID.
+    pub fn matches_build_id(&self, build_id: Option<&[u8]>) -> bool {
+        match (self.build_id.as_deref(), build_id) {
+            (Some(own_build_id), Some(build_id)) => own_build_id == build_id,
+            (Some(_), None) => false,
+            (None, _) => true,
+        }
+    }
+
+    /// Create a symbol table for the kernel module with the given name, relative
+    /// to the module's base address.
+    ///
+    /// `module_name` can be given with or without brackets, e.g. "[snd-seq-device]".
+    pub fn symbol_table_for_module(
+        &self,
+        module_name: &str,
+        module_base_avma: u64,
+    ) -> Option<SymbolTable> {
+        let symbols = self
+            .module_symbols
+            .get(&normalize_kernel_module_name(module_name))?;
+        let symbols = symbols
+            .iter()
+            .filter_map(|(absolute_addr, name)| {
+                let address = absolute_addr.checked_sub(module_base_avma)?;
+                Some(Symbol {
+                    address: u32::try_from(address).ok()?,
+                    size: None,
+                    name: name.clone(),
+                })
+            })
+            .collect();
+        Some(SymbolTable::new(symbols))
+    }
+}
+
+/// Normalizes a kernel module name so that the names from kallsyms and the names
+/// from mmap records / file names can be compared. The kernel treats dashes and
+/// underscores in module names as equivalent.
+///
+/// "[snd-seq-device]" -> "snd_seq_device"
+pub fn normalize_kernel_module_name(name: &str) -> String {
+    let name = name.strip_prefix('[').unwrap_or(name);
+    let name = name.strip_suffix(']').unwrap_or(name);
+    name.replace('-', "_")
 }
 
 pub fn build_id_from_notes_section_data(section_data: &[u8]) -> Option<&[u8]> {
@@ -99,11 +173,63 @@ impl<'a> Iterator for KallSymIter<'a> {
     }
 }
 
+/// Splits the name part of a kallsyms line into the symbol name and the module
+/// name, if present.
+///
+/// "tls_get_info_size\t[tls]" -> ("tls_get_info_size", Some("tls"))
+fn split_kallsyms_module_name(name: &[u8]) -> (&[u8], Option<&[u8]>) {
+    let Some(whitespace_pos) = name.iter().position(|b| b.is_ascii_whitespace()) else {
+        return (name, None);
+    };
+    let (symbol_name, rest) = name.split_at(whitespace_pos);
+    let module_start = rest.iter().position(|b| !b.is_ascii_whitespace());
+    let rest = &rest[module_start.unwrap_or(rest.len())..];
+    match rest.strip_prefix(b"[").and_then(|r| r.strip_suffix(b"]")) {
+        Some(module_name) => (symbol_name, Some(module_name)),
+        None => (symbol_name, None),
+    }
+}
+
+/// Returns whether a kallsyms "[module]" suffix names a pseudo-module, i.e. code
+/// which lives in the kernel address space but doesn't belong to a loadable
+/// kernel module, such as JIT-compiled BPF programs or ftrace trampolines.
+fn is_kallsyms_pseudo_module(module_name: &[u8]) -> bool {
+    matches!(module_name, b"bpf" | b"kprobes" | b"ftrace")
+        || module_name.starts_with(b"__builtin__")
+}
+
+/// Collect the symbols of kernel modules, i.e. of all kallsyms lines which have
+/// a "[module]" suffix. Pseudo-modules like "[bpf]" are left to the kernel
+/// symbol table.
+pub fn parse_kallsyms_module_symbols(data: &[u8]) -> HashMap<String, Vec<(u64, String)>> {
+    let mut module_symbols: HashMap<String, Vec<(u64, String)>> = HashMap::new();
+    for (absolute_addr, name) in KallSymIter::new(data) {
+        let (symbol_name, Some(module_name)) = split_kallsyms_module_name(name) else {
+            continue;
+        };
+        if is_kallsyms_pseudo_module(module_name) {
+            continue;
+        }
+        let module_name = normalize_kernel_module_name(&String::from_utf8_lossy(module_name));
+        module_symbols.entry(module_name).or_default().push((
+            absolute_addr,
+            String::from_utf8_lossy(symbol_name).to_string(),
+        ));
+    }
+    module_symbols
+}
+
 pub fn parse_kallsyms(data: &[u8]) -> Result<(u64, SymbolTable), KernelSymbolsError> {
     let mut symbols = Vec::new();
 
     let mut text_addr = None;
     for (absolute_addr, symbol_name) in KallSymIter::new(data) {
+        if let (_, Some(module_name)) = split_kallsyms_module_name(symbol_name) {
+            if !is_kallsyms_pseudo_module(module_name) {
+                // Module symbols are handled by parse_kallsyms_module_symbols.
+                continue;
+            }
+        }
         match (text_addr, symbol_name) {
             (None, b"_text") => {
                 text_addr = Some(absolute_addr);
@@ -158,10 +284,28 @@ fn hex_str<T: std::ops::Shl<T, Output = T> + std::ops::BitOr<T, Output = T> + Fr
     Ok((remaining, res))
 }
 
+/// Reads the build ID from a kernel module file. Compressed modules, e.g.
+/// `ext4.ko.zst`, are decompressed first. If `path` is an uncompressed `.ko`
+/// path which doesn't exist, the compressed variants of it are tried.
 pub fn kernel_module_build_id(path: &Path, binary_lookup_dirs: &[PathBuf]) -> Option<Vec<u8>> {
-    let file = open_file_with_fallback(path, binary_lookup_dirs).ok()?.0;
+    let mut candidates = vec![path.to_owned()];
+    if path.extension().is_some_and(|ext| ext == "ko") {
+        for ext in wholesym::COMPRESSED_KERNEL_MODULE_EXTENSIONS {
+            candidates.push(path.with_extension(ext));
+        }
+    }
+    let (file, path) = candidates
+        .iter()
+        .find_map(|candidate| open_file_with_fallback(candidate, binary_lookup_dirs).ok())?;
     let mmap = unsafe { memmap2::MmapOptions::new().map(&file) }.ok()?;
-    let obj = object::File::parse(&mmap[..]).ok()?;
+    let decompressed;
+    let data = if wholesym::is_compressed_kernel_module_path(&path) {
+        decompressed = wholesym::decompress_kernel_module(&mmap)?;
+        &decompressed[..]
+    } else {
+        &mmap[..]
+    };
+    let obj = object::File::parse(data).ok()?;
     match obj.build_id() {
         Ok(Some(build_id)) => Some(build_id.to_owned()),
         _ => None,
@@ -173,7 +317,7 @@ mod test {
     use debugid::CodeId;
 
     use super::build_id_from_notes_section_data;
-    use crate::linux_shared::kernel_symbols::parse_kallsyms;
+    use crate::linux_shared::kernel_symbols::{parse_kallsyms, KernelSymbols};
 
     #[test]
     fn test() {
@@ -182,6 +326,27 @@ mod test {
         assert_eq!(code_id.as_str(), "984b766f1cb5699c3b1b77b592983c22e9d197ad");
     }
 
+    #[test]
+    fn compressed_kernel_module() {
+        use std::io::Write;
+
+        let fixture =
+            std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("../fixtures/other/ls-linux/ls");
+        let data = std::fs::read(&fixture).unwrap();
+        let expected_build_id = super::kernel_module_build_id(&fixture, &[]).unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(&data).unwrap();
+        std::fs::write(dir.path().join("ls.ko.gz"), encoder.finish().unwrap()).unwrap();
+
+        // The recorded path may be the uncompressed one.
+        for name in ["ls.ko.gz", "ls.ko"] {
+            let build_id = super::kernel_module_build_id(&dir.path().join(name), &[]);
+            assert_eq!(build_id.as_ref(), Some(&expected_build_id), "{name}");
+        }
+    }
+
     #[test]
     fn test2() {
         let kallsyms = br#"ffff8000081e0000 T _text
@@ -343,4 +508,45 @@ ffff80000b5c5744 t bpf_prog_654d7024997e7811   [bpf]"#;
             "tegra_clk_periph_fixed_is_enabled"
         );
     }
+
+    #[test]
+    fn test_module_symbols() {
+        let kallsyms = b"ffffffffa7e00000 T _text
+ffffffffa7e00040 T secondary_startup_64
+ffffffffc0a01000 t tls_get_info_size\t[tls]
+ffffffffc0a01020 t tls_update\t[tls]
+ffffffffc0b02000 t snd_seq_device_load_drivers\t[snd_seq_device]
+ffffffffc0c03000 t bpf_prog_6deef7357e7b4530\t[bpf]";
+        let kernel_symbols = KernelSymbols::new_from_kallsyms_data(kallsyms, None).unwrap();
+        assert_eq!(kernel_symbols.base_avma, 0xffffffffa7e00000);
+        assert!(kernel_symbols.matches_build_id(Some(b"1234")));
+        assert_eq!(
+            &kernel_symbols.symbol_table.lookup(0x41).unwrap().name,
+            "secondary_startup_64"
+        );
+
+        let tls = kernel_symbols
+            .symbol_table_for_module("[tls]", 0xffffffffc0a01000)
+            .unwrap();
+        assert_eq!(&tls.lookup(0x21).unwrap().name, "tls_update");
+        let snd = kernel_symbols
+            .symbol_table_for_module("[snd-seq-device]", 0xffffffffc0b02000)
+            .unwrap();
+        assert_eq!(
+            &snd.lookup(0x4).unwrap().name,
+            "snd_seq_device_load_drivers"
+        );
+        assert!(kernel_symbols
+            .symbol_table_for_module("[raid10]", 0)
+            .is_none());
+
+        // BPF programs aren't a real module; they stay in the kernel table.
+        assert_eq!(
+            &kernel_symbols.symbol_table.lookup(0x18e03004).unwrap().name,
+            "bpf_prog_6deef7357e7b4530\t[bpf]"
+        );
+        assert!(kernel_symbols
+            .symbol_table_for_module("[bpf]", 0xffffffffc0c03000)
+            .is_none());
+    }
 }
@@ -0,0 +1,196 @@
+/// A decoded `PERF_SAMPLE_DATA_SRC` value, i.e. `union perf_mem_data_src`
+/// from `perf_event.h`.
+///
+/// ```c
+/// union perf_mem_data_src {
+///     __u64 val;
+///     struct {
+///         __u64   mem_op:5,       /* type of opcode */
+///                 mem_lvl:14,     /* memory hierarchy level */
+///                 mem_snoop:5,    /* snoop mode */
+///                 mem_lock:2,     /* lock instr */
+///                 mem_dtlb:7,     /* tlb access */
+///                 mem_lvl_num:4,  /* memory hierarchy level number */
+///                 mem_remote:1,   /* remote */
+///                 mem_snoopx:2,   /* snoop mode, ext */
+///                 mem_blk:3,      /* access blocked */
+///                 mem_hops:3,     /* hop level */
+///                 mem_rsvd:18;
+///     };
+/// };
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemDataSource(pub u64);
+
+const MEM_OP_LOAD: u64 = 0x02;
+const MEM_OP_STORE: u64 = 0x04;
+const MEM_OP_PFETCH: u64 = 0x08;
+const MEM_OP_EXEC: u64 = 0x10;
+
+const MEM_LVL_HIT: u64 = 0x02;
+const MEM_LVL_MISS: u64 = 0x04;
+const MEM_LVL_NAMES: [(u64, &str); 11] = [
+    (0x08, "L1"),
+    (0x10, "LFB"),
+    (0x20, "L2"),
+    (0x40, "L3"),
+    (0x80, "Local RAM"),
+    (0x100, "Remote RAM (1 hop)"),
+    (0x200, "Remote RAM (2 hops)"),
+    (0x400, "Remote cache (1 hop)"),
+    (0x800, "Remote cache (2 hops)"),
+    (0x1000, "I/O"),
+    (0x2000, "Uncached"),
+];
+
+const MEM_SNOOP_NONE: u64 = 0x02;
+const MEM_SNOOP_HIT: u64 = 0x04;
+const MEM_SNOOP_MISS: u64 = 0x08;
+const MEM_SNOOP_HITM: u64 = 0x10;
+
+const MEM_LOCK_LOCKED: u64 = 0x02;
+
+const MEM_TLB_HIT: u64 = 0x02;
+const MEM_TLB_MISS: u64 = 0x04;
+const MEM_TLB_NAMES: [(u64, &str); 4] = [
+    (0x08, "L1"),
+    (0x10, "L2"),
+    (0x20, "Hardware walker"),
+    (0x40, "OS fault handler"),
+];
+
+impl MemDataSource {
+    fn bits(&self, shift: u32, width: u32) -> u64 {
+        (self.0 >> shift) & ((1 << width) - 1)
+    }
+
+    /// The type of the memory access, e.g. "Load" or "Store".
+    pub fn op(&self) -> &'static str {
+        let op = self.bits(0, 5);
+        let locked = self.bits(24, 2) & MEM_LOCK_LOCKED != 0;
+        if op & MEM_OP_LOAD != 0 {
+            if locked {
+                "Locked load"
+            } else {
+                "Load"
+            }
+        } else if op & MEM_OP_STORE != 0 {
+            if locked {
+                "Locked store"
+            } else {
+                "Store"
+            }
+        } else if op & MEM_OP_PFETCH != 0 {
+            "Prefetch"
+        } else if op & MEM_OP_EXEC != 0 {
+            "Exec"
+        } else {
+            "N/A"
+        }
+    }
+
+    /// The level of the memory hierarchy that served the access, e.g.
+    /// "L1 hit" or "L3 miss".
+    pub fn level(&self) -> String {
+        let lvl = self.bits(5, 14);
+        let lvl_num = self.bits(33, 4);
+        let remote = self.bits(37, 1) != 0;
+
+        // Newer kernels describe the level with mem_lvl_num + mem_remote,
+        // and only use mem_lvl for the hit / miss information.
+        let name = match lvl_num {
+            0x1 => "L1",
+            0x2 => "L2",
+            0x3 => "L3",
+            0x4 => "L4",
+            0xb => "Any cache",
+            0xc => "LFB",
+            0xd => "RAM",
+            0xe => "PMEM",
+            _ => MEM_LVL_NAMES
+                .iter()
+                .find(|(mask, _)| lvl & mask != 0)
+                .map_or("", |(_, name)| name),
+        };
+        let name = match (name, remote && lvl_num != 0 && lvl_num != 0xf) {
+            ("", _) => return "N/A".to_string(),
+            (name, true) => format!("Remote {name}"),
+            (name, false) => name.to_string(),
+        };
+        if lvl & MEM_LVL_HIT != 0 {
+            format!("{name} hit")
+        } else if lvl & MEM_LVL_MISS != 0 {
+            format!("{name} miss")
+        } else {
+            name
+        }
+    }
+
+    /// The TLB access, e.g. "L1 hit" or "L2 or Hardware walker miss".
+    pub fn tlb(&self) -> String {
+        let dtlb = self.bits(26, 7);
+        let names: Vec<&str> = MEM_TLB_NAMES
+            .iter()
+            .filter(|(mask, _)| dtlb & mask != 0)
+            .map(|(_, name)| *name)
+            .collect();
+        if names.is_empty() {
+            return "N/A".to_string();
+        }
+        let names = names.join(" or ");
+        if dtlb & MEM_TLB_HIT != 0 {
+            format!("{names} hit")
+        } else if dtlb & MEM_TLB_MISS != 0 {
+            format!("{names} miss")
+        } else {
+            names
+        }
+    }
+
+    /// The snoop result, e.g. "Hit modified".
+    pub fn snoop(&self) -> &'static str {
+        let snoop = self.bits(19, 5);
+        if snoop & MEM_SNOOP_HITM != 0 {
+            "Hit modified"
+        } else if snoop & MEM_SNOOP_HIT != 0 {
+            "Hit"
+        } else if snoop & MEM_SNOOP_MISS != 0 {
+            "Miss"
+        } else if snoop & MEM_SNOOP_NONE != 0 {
+            "None"
+        } else {
+            "N/A"
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn decode_data_src() {
+        // Load, L1 hit, no snoop, L1 TLB hit
+        let l1_load = MemDataSource(
+            MEM_OP_LOAD
+                | ((0x08 | MEM_LVL_HIT) << 5)
+                | (MEM_SNOOP_NONE << 19)
+                | ((0x08 | MEM_TLB_HIT) << 26),
+        );
+        assert_eq!(l1_load.op(), "Load");
+        assert_eq!(l1_load.level(), "L1 hit");
+        assert_eq!(l1_load.snoop(), "None");
+        assert_eq!(l1_load.tlb(), "L1 hit");
+
+        // Locked store which went to remote RAM, described with mem_lvl_num.
+        let remote_ram_store = MemDataSource(
+            MEM_OP_STORE | (MEM_LVL_MISS << 5) | (MEM_LOCK_LOCKED << 24) | (0xd << 33) | (1 << 37),
+        );
+        assert_eq!(remote_ram_store.op(), "Locked store");
+        assert_eq!(remote_ram_store.level(), "Remote RAM miss");
+        assert_eq!(remote_ram_store.tlb(), "N/A");
+        assert_eq!(remote_ram_store.snoop(), "N/A");
+
+        assert_eq!(MemDataSource(0).level(), "N/A");
+    }
+}
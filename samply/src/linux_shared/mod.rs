@@ -1,23 +1,39 @@
+mod aux_trace;
 mod avma_range;
+mod branch_stack;
+mod conversion_report;
 mod convert_regs;
 mod converter;
 mod event_interpretation;
 mod injected_jit_object;
 mod kernel_symbols;
+mod mem_data_src;
 mod mmap_range_or_vec;
+mod module_info;
 mod object_rewriter;
+mod on_cpu_counter;
 mod pe_mappings;
+mod perf_stat_intervals;
 mod process;
 mod process_threads;
 mod processes;
+mod raw_syscall;
 mod rss_stat;
+mod sample_extra_fields;
+mod sched_wakeup;
 mod svma_file_range;
+mod syscall_tracepoint;
+mod text_poke;
 mod thread;
+mod time_range;
+mod unwind_sections;
 #[allow(unused)]
 pub mod vdso;
 
+pub use aux_trace::{new_aux_decoder, AuxTraceChunk};
 pub use convert_regs::{ConvertRegs, ConvertRegsAarch64, ConvertRegsX86_64};
 pub use converter::Converter;
 #[allow(unused)]
 pub use event_interpretation::{EventInterpretation, KnownEvent, OffCpuIndicator};
 pub use mmap_range_or_vec::MmapRangeOrVec;
+pub use sample_extra_fields::{attr_read_format_has_lost, SampleExtraFields};
@@ -0,0 +1,360 @@
+use std::borrow::Cow;
+use std::io::Write;
+use std::ops::Range;
+
+use debugid::DebugId;
+use object::elf;
+use object::read::elf::{ElfFile, FileHeader, SectionHeader};
+use object::{Architecture, Object, ReadRef};
+use wholesym::CodeId;
+
+use super::unwind_sections::ModuleUnwindSections;
+
+/// What the converter determined about a module when it was added to a process.
+///
+/// This allows other tools to translate addresses in the same way as the
+/// converter, without having to recompute the base address.
+#[derive(Debug, Clone)]
+pub struct ModuleInfo {
+    /// The path of the file that was used for this module.
+    pub path: String,
+    /// The code ID of the file, e.g. the ELF build ID.
+    pub code_id: Option<CodeId>,
+    /// The debug name of the library in the profile.
+    pub debug_name: String,
+    /// The debug ID of the library in the profile. If the file couldn't be
+    /// read, this is derived from the build ID in the perf.data file, or nil if
+    /// there was none. Together with `debug_name`, this is what symbols are
+    /// looked up by.
+    pub debug_id: DebugId,
+    /// The address range of the mapping, in the process's address space.
+    pub avma_range: Range<u64>,
+    /// The address at which the module's relative addresses start, i.e. the
+    /// address for relative address zero.
+    pub base_avma: u64,
+    /// The SVMA (stated virtual memory address) which corresponds to
+    /// `base_avma`, i.e. the SVMA for relative address zero.
+    pub base_svma: u64,
+    /// The unwind information that is used for this module.
+    pub unwind_sections: ModuleUnwindSections,
+    /// The protection bits of the mapping, if the mmap record had them.
+    pub protection: Option<MappingProtection>,
+    /// The control-flow protection features which the file was built for.
+    /// Empty if the file couldn't be read.
+    pub control_flow_protection: ControlFlowProtection,
+}
+
+/// The hardware control-flow protection features which an ELF binary was
+/// built for, from the `GNU_PROPERTY_*_FEATURE_1_AND` property in its
+/// `.note.gnu.property` section.
+///
+/// With CET shadow stacks, the kernel can also record the return addresses
+/// from the shadow stack, and with IBT / BTI, indirect branch targets start
+/// with a landing pad instruction, which affects unwinding heuristics.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ControlFlowProtection {
+    /// x86 Indirect Branch Tracking: indirect branch targets start with `endbr`.
+    pub ibt: bool,
+    /// x86 CET shadow stacks: return addresses are also pushed to a shadow stack.
+    pub shadow_stack: bool,
+    /// AArch64 Branch Target Identification: indirect branch targets start with `bti`.
+    pub bti: bool,
+    /// AArch64 pointer authentication of return addresses.
+    pub pac: bool,
+}
+
+impl ControlFlowProtection {
+    /// Reads the features from the `.note.gnu.property` section. Returns no
+    /// features for non-ELF files and for files without the property.
+    pub fn from_object<'data, R: ReadRef<'data>>(file: &object::File<'data, R>) -> Self {
+        match file {
+            object::File::Elf32(elf) => Self::from_elf(elf),
+            object::File::Elf64(elf) => Self::from_elf(elf),
+            _ => Self::default(),
+        }
+    }
+
+    fn from_elf<'data, Elf: FileHeader, R: ReadRef<'data>>(elf: &ElfFile<'data, Elf, R>) -> Self {
+        let endian = elf.endian();
+        let architecture = elf.architecture();
+        let mut result = Self::default();
+        for section in elf.elf_section_table().iter() {
+            let Ok(Some(mut notes)) = section.notes(endian, elf.data()) else {
+                continue;
+            };
+            while let Ok(Some(note)) = notes.next() {
+                let Some(mut properties) = note.gnu_properties(endian) else {
+                    continue;
+                };
+                while let Ok(Some(property)) = properties.next() {
+                    let Ok(features) = property.data_u32(endian) else {
+                        continue;
+                    };
+                    match (architecture, property.pr_type()) {
+                        (
+                            Architecture::X86_64 | Architecture::X86_64_X32 | Architecture::I386,
+                            elf::GNU_PROPERTY_X86_FEATURE_1_AND,
+                        ) => {
+                            result.ibt = features & elf::GNU_PROPERTY_X86_FEATURE_1_IBT != 0;
+                            result.shadow_stack =
+                                features & elf::GNU_PROPERTY_X86_FEATURE_1_SHSTK != 0;
+                        }
+                        (Architecture::Aarch64, elf::GNU_PROPERTY_AARCH64_FEATURE_1_AND) => {
+                            result.bti = features & elf::GNU_PROPERTY_AARCH64_FEATURE_1_BTI != 0;
+                            result.pac = features & elf::GNU_PROPERTY_AARCH64_FEATURE_1_PAC != 0;
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+        result
+    }
+}
+
+/// Formats the features as a `+`-separated list, e.g. `ibt+shstk`, or as an
+/// empty string if there are none.
+impl std::fmt::Display for ControlFlowProtection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let features = [
+            (self.ibt, "ibt"),
+            (self.shadow_stack, "shstk"),
+            (self.bti, "bti"),
+            (self.pac, "pac"),
+        ];
+        let names: Vec<&str> = features
+            .into_iter()
+            .filter_map(|(enabled, name)| enabled.then_some(name))
+            .collect();
+        f.write_str(&names.join("+"))
+    }
+}
+
+/// The protection bits of a mapping, i.e. the `prot` argument of `mmap`, as
+/// recorded in `PERF_RECORD_MMAP2` records.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MappingProtection(pub u32);
+
+impl MappingProtection {
+    pub const READ: u32 = 0x1;
+    pub const WRITE: u32 = 0x2;
+    pub const EXEC: u32 = 0x4;
+
+    pub fn is_executable(self) -> bool {
+        self.0 & Self::EXEC != 0
+    }
+
+    pub fn is_writable(self) -> bool {
+        self.0 & Self::WRITE != 0
+    }
+
+    /// Whether the mapping is both writable and executable. Regular libraries
+    /// map their code read-only, so this is usually code which is generated at
+    /// runtime by a JIT which doesn't tell us about its functions via jitdump.
+    pub fn is_likely_jit(self) -> bool {
+        self.is_writable() && self.is_executable()
+    }
+}
+
+/// Formats the protection like `ls -l` does, e.g. `r-x`.
+impl std::fmt::Display for MappingProtection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let flag = |bit, c| if self.0 & bit != 0 { c } else { '-' };
+        write!(
+            f,
+            "{}{}{}",
+            flag(Self::READ, 'r'),
+            flag(Self::WRITE, 'w'),
+            flag(Self::EXEC, 'x')
+        )
+    }
+}
+
+impl ModuleInfo {
+    /// The difference between AVMAs and SVMAs in this module.
+    pub fn bias(&self) -> u64 {
+        self.base_avma.wrapping_sub(self.base_svma)
+    }
+}
+
+/// Writes the debug name, debug ID, code ID, address range, base address,
+/// bias, mapping protection, unwind sections, control-flow protection and
+/// path of each module as CSV, sorted by path. The IDs are what symbols for the module can be
+/// fetched by, and an AVMA in the range translates to the SVMA `avma - bias`.
+/// Addresses are in hex. If a file was mapped more than once, the addresses
+/// are those of its last mapping. Fields are quoted as needed, see
+/// [`csv_field`].
+pub fn write_module_list_csv<'a>(
+    modules: impl Iterator<Item = &'a ModuleInfo>,
+    mut writer: impl Write,
+) -> std::io::Result<()> {
+    let mut modules: Vec<&ModuleInfo> = modules.collect();
+    modules.sort_by(|a, b| a.path.cmp(&b.path));
+    writeln!(
+        writer,
+        "debug_name,debug_id,code_id,avma_start,avma_end,base_avma,bias,protection,\
+         unwind_sections,control_flow_protection,path"
+    )?;
+    for module in modules {
+        let code_id = module
+            .code_id
+            .as_ref()
+            .map(ToString::to_string)
+            .unwrap_or_default();
+        let protection = module
+            .protection
+            .as_ref()
+            .map(ToString::to_string)
+            .unwrap_or_default();
+        writeln!(
+            writer,
+            "{},{},{},{:#x},{:#x},{:#x},{:#x},{},{},{},{}",
+            csv_field(&module.debug_name),
+            module.debug_id.breakpad(),
+            code_id,
+            module.avma_range.start,
+            module.avma_range.end,
+            module.base_avma,
+            module.bias(),
+            protection,
+            module.unwind_sections,
+            module.control_flow_protection,
+            csv_field(&module.path)
+        )?;
+    }
+    writer.flush()
+}
+
+/// Quotes a CSV field as described in RFC 4180 if it contains a comma, a
+/// double quote or a line break. Double quotes in the field are doubled.
+fn csv_field(field: &str) -> Cow<'_, str> {
+    if field.contains([',', '"', '\r', '\n']) {
+        Cow::Owned(format!("\"{}\"", field.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(field)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn address_translation() {
+        let module_info = ModuleInfo {
+            path: "/usr/lib/libexample.so".to_string(),
+            code_id: None,
+            debug_name: "libexample.so".to_string(),
+            debug_id: DebugId::nil(),
+            avma_range: 0x7f0000001000..0x7f0000005000,
+            base_avma: 0x7f0000000000,
+            base_svma: 0x200000,
+            unwind_sections: ModuleUnwindSections::EhFrameHdrAndEhFrame,
+            protection: None,
+            control_flow_protection: ControlFlowProtection::default(),
+        };
+        assert_eq!(module_info.bias(), 0x7effffe00000);
+    }
+
+    #[test]
+    fn control_flow_protection() {
+        use object::write::Object as WriteObject;
+        use object::{BinaryFormat, Endianness};
+
+        let elf_with_features = |architecture, property, features| {
+            let mut obj = WriteObject::new(BinaryFormat::Elf, architecture, Endianness::Little);
+            obj.add_elf_gnu_property_u32(property, features);
+            obj.write().unwrap()
+        };
+
+        let bytes = elf_with_features(
+            Architecture::X86_64,
+            elf::GNU_PROPERTY_X86_FEATURE_1_AND,
+            elf::GNU_PROPERTY_X86_FEATURE_1_IBT | elf::GNU_PROPERTY_X86_FEATURE_1_SHSTK,
+        );
+        let file = object::File::parse(&bytes[..]).unwrap();
+        let protection = ControlFlowProtection::from_object(&file);
+        assert_eq!(
+            protection,
+            ControlFlowProtection {
+                ibt: true,
+                shadow_stack: true,
+                ..Default::default()
+            }
+        );
+        assert_eq!(protection.to_string(), "ibt+shstk");
+
+        let bytes = elf_with_features(
+            Architecture::Aarch64,
+            elf::GNU_PROPERTY_AARCH64_FEATURE_1_AND,
+            elf::GNU_PROPERTY_AARCH64_FEATURE_1_BTI,
+        );
+        let file = object::File::parse(&bytes[..]).unwrap();
+        assert_eq!(
+            ControlFlowProtection::from_object(&file),
+            ControlFlowProtection {
+                bti: true,
+                ..Default::default()
+            }
+        );
+
+        let bytes = WriteObject::new(BinaryFormat::Elf, Architecture::X86_64, Endianness::Little)
+            .write()
+            .unwrap();
+        let file = object::File::parse(&bytes[..]).unwrap();
+        let protection = ControlFlowProtection::from_object(&file);
+        assert_eq!(protection, ControlFlowProtection::default());
+        assert_eq!(protection.to_string(), "");
+    }
+
+    #[test]
+    fn module_list_csv_quoting() {
+        let module_info = |path: &str| ModuleInfo {
+            path: path.to_string(),
+            code_id: None,
+            debug_name: path.rsplit('/').next().unwrap().to_string(),
+            debug_id: DebugId::nil(),
+            avma_range: 0x1000..0x2000,
+            base_avma: 0x1000,
+            base_svma: 0,
+            unwind_sections: ModuleUnwindSections::None,
+            protection: None,
+            control_flow_protection: ControlFlowProtection::default(),
+        };
+        let modules = [
+            module_info("/opt/app/lib\"quoted\".so"),
+            module_info("/opt/app/lib,comma.so"),
+            module_info("/usr/lib/libc.so.6"),
+        ];
+        let mut csv = Vec::new();
+        write_module_list_csv(modules.iter(), &mut csv).unwrap();
+        let nil = DebugId::nil().breakpad().to_string();
+        let addresses = "0x1000,0x2000,0x1000,0x1000";
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            format!(
+                "debug_name,debug_id,code_id,avma_start,avma_end,base_avma,bias,protection,\
+                 unwind_sections,control_flow_protection,path\n\
+                 \"lib\"\"quoted\"\".so\",{nil},,{addresses},,none,,\"/opt/app/lib\"\"quoted\"\".so\"\n\
+                 \"lib,comma.so\",{nil},,{addresses},,none,,\"/opt/app/lib,comma.so\"\n\
+                 libc.so.6,{nil},,{addresses},,none,,/usr/lib/libc.so.6\n"
+            )
+        );
+    }
+
+    #[test]
+    fn mapping_protection() {
+        let code = MappingProtection(MappingProtection::READ | MappingProtection::EXEC);
+        assert!(code.is_executable());
+        assert!(!code.is_likely_jit());
+        let jit = MappingProtection(
+            MappingProtection::READ | MappingProtection::WRITE | MappingProtection::EXEC,
+        );
+        assert!(jit.is_likely_jit());
+        let data = MappingProtection(MappingProtection::READ | MappingProtection::WRITE);
+        assert!(!data.is_executable());
+        assert!(!data.is_likely_jit());
+        assert_eq!(code.to_string(), "r-x");
+        assert_eq!(data.to_string(), "rw-");
+    }
+}
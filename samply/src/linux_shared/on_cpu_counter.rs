@@ -0,0 +1,43 @@
+use std::collections::HashSet;
+
+use fxprof_processed_profile::{CounterHandle, ProcessHandle, Profile, Timestamp};
+
+/// Tracks how many threads are running on a CPU at any given time, based on
+/// context switch records, and records this number as a profile counter.
+///
+/// This gives an overview of how busy the system was, across all threads.
+pub struct OnCpuThreadCounter {
+    counter: CounterHandle,
+    on_cpu_tids: HashSet<i32>,
+}
+
+impl OnCpuThreadCounter {
+    pub fn new(process: ProcessHandle, profile: &mut Profile) -> Self {
+        let counter = profile.add_counter(
+            process,
+            "On-CPU threads",
+            "CPU",
+            "Number of threads which are running on a CPU",
+        );
+        Self {
+            counter,
+            on_cpu_tids: HashSet::new(),
+        }
+    }
+
+    pub fn notify_switch_in(&mut self, tid: i32, timestamp: Timestamp, profile: &mut Profile) {
+        if self.on_cpu_tids.insert(tid) {
+            profile.add_counter_sample(self.counter, timestamp, 1.0, 1);
+        }
+    }
+
+    /// Called for switch-outs and for thread exits. A switch-out of a thread
+    /// which we haven't seen switching in is ignored, so that threads which
+    /// were already running when the recording started don't make the count
+    /// negative.
+    pub fn notify_switch_out(&mut self, tid: i32, timestamp: Timestamp, profile: &mut Profile) {
+        if self.on_cpu_tids.remove(&tid) {
+            profile.add_counter_sample(self.counter, timestamp, -1.0, 1);
+        }
+    }
+}
@@ -23,13 +23,12 @@
 //! which allows binary correlation and unwinding to work.
 
 use std::collections::BTreeMap;
+use std::io::Read;
 use std::path::{Path, PathBuf};
 
 use memmap2::Mmap;
-use object::pe::{ImageNtHeaders32, ImageNtHeaders64};
-use object::read::pe::{ImageNtHeaders, ImageOptionalHeader, PeFile};
-use object::FileKind;
-use wholesym::{CodeId, PeCodeId};
+use wholesym::samply_symbols::pe_code_id_for_binary;
+use wholesym::CodeId;
 
 use super::avma_range::AvmaRange;
 
@@ -40,37 +39,124 @@ pub struct SuspectedPeMapping {
     pub avma_range: AvmaRange,
 }
 
+#[derive(Debug, Clone)]
+struct SuspectedPeMappingEntry {
+    mapping: SuspectedPeMapping,
+    /// The value of `PeMappings::use_counter` when this entry was last inserted or found.
+    last_used: u64,
+}
+
+/// The maximum number of suspected PE mappings which are kept. When a new one is
+/// found beyond this limit, the least recently used one is evicted. This bounds
+/// the memory use for long Wine sessions which load and unload many DLLs.
+const MAX_SUSPECTED_PE_MAPPINGS: usize = 1024;
+
+/// The file extensions of PE files which are checked by default. Wine maps
+/// control panel applets, screen savers and drivers just like exes and dlls.
+const DEFAULT_PE_EXTENSIONS: &[&str] = &["exe", "dll", "cpl", "scr", "drv", "ocx"];
+
 pub struct PeMappings {
-    /// Mapping of start address to potential mapped PE binaries.
-    /// The key is equal to the start field of the value.
-    suspected_pe_mappings: BTreeMap<u64, SuspectedPeMapping>,
+    /// Mapping of (pid, start address) to potential mapped PE binaries.
+    /// The start address is equal to the start field of the mapping.
+    suspected_pe_mappings: BTreeMap<(i32, u64), SuspectedPeMappingEntry>,
+    /// Incremented on every insertion and lookup, for the LRU eviction.
+    use_counter: u64,
+    /// Lowercase file extensions, without the leading dot.
+    extensions: Vec<String>,
+    /// Whether to check files with other extensions for the `MZ` magic.
+    sniff_magic: bool,
 }
 
 impl PeMappings {
-    pub fn new() -> Self {
+    pub fn new(extra_extensions: &[String], sniff_magic: bool) -> Self {
+        let extensions = DEFAULT_PE_EXTENSIONS
+            .iter()
+            .map(|ext| ext.to_string())
+            .chain(
+                extra_extensions
+                    .iter()
+                    .map(|ext| ext.trim_start_matches('.').to_ascii_lowercase()),
+            )
+            .collect();
         Self {
             suspected_pe_mappings: BTreeMap::new(),
+            use_counter: 0,
+            extensions,
+            sniff_magic,
         }
     }
 
-    pub fn find_mapping(&self, avma_range: &AvmaRange) -> Option<&SuspectedPeMapping> {
-        let (_, mapping) = self
+    fn has_pe_extension(&self, path_slice: &[u8]) -> bool {
+        let filename = match path_slice.iter().rposition(|b| *b == b'/') {
+            Some(pos) => &path_slice[pos + 1..],
+            None => path_slice,
+        };
+        let Some(dot_pos) = filename.iter().rposition(|b| *b == b'.') else {
+            return false;
+        };
+        let extension = &filename[dot_pos + 1..];
+        self.extensions
+            .iter()
+            .any(|ext| ext.as_bytes().eq_ignore_ascii_case(extension))
+    }
+
+    pub fn find_mapping(
+        &mut self,
+        pid: i32,
+        avma_range: &AvmaRange,
+    ) -> Option<&SuspectedPeMapping> {
+        let (_, entry) = self
             .suspected_pe_mappings
-            .range(..=avma_range.start())
+            .range_mut((pid, 0)..=(pid, avma_range.start()))
             .next_back()?;
-        if !avma_range.encompasses(&mapping.avma_range) {
+        if !avma_range.encompasses(&entry.mapping.avma_range) {
             return None;
         }
-        Some(mapping)
+        self.use_counter += 1;
+        entry.last_used = self.use_counter;
+        Some(&entry.mapping)
+    }
+
+    /// Forgets the suspected PE mappings of process `pid` which overlap the
+    /// unmapped range.
+    pub fn handle_munmap(&mut self, pid: i32, avma_range: &AvmaRange) {
+        self.suspected_pe_mappings
+            .retain(|(mapping_pid, _), entry| {
+                *mapping_pid != pid || !entry.mapping.avma_range.overlaps(avma_range)
+            });
+    }
+
+    fn insert(&mut self, pid: i32, mapping: SuspectedPeMapping) {
+        // A new PE header mapping which overlaps an older suspected image means
+        // that the older image has been unmapped.
+        self.handle_munmap(pid, &mapping.avma_range);
+        if self.suspected_pe_mappings.len() >= MAX_SUSPECTED_PE_MAPPINGS {
+            let least_recently_used = self
+                .suspected_pe_mappings
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| *key);
+            if let Some(key) = least_recently_used {
+                self.suspected_pe_mappings.remove(&key);
+            }
+        }
+        self.use_counter += 1;
+        self.suspected_pe_mappings.insert(
+            (pid, mapping.avma_range.start()),
+            SuspectedPeMappingEntry {
+                mapping,
+                last_used: self.use_counter,
+            },
+        );
     }
 
-    pub fn check_mmap(&mut self, path_slice: &[u8], mapping_start_avma: u64) {
+    pub fn check_mmap(&mut self, pid: i32, path_slice: &[u8], mapping_start_avma: u64) {
         // Do a quick extension check first, to avoid end up trying to parse every mmapped file.
-        let filename_is_pe = path_slice.ends_with(b".exe")
-            || path_slice.ends_with(b".dll")
-            || path_slice.ends_with(b".EXE")
-            || path_slice.ends_with(b".DLL");
-        if !filename_is_pe {
+        // If magic sniffing is enabled, we check the first two bytes of files with other
+        // extensions instead, which is still much cheaper than parsing them.
+        let has_pe_extension = self.has_pe_extension(path_slice);
+        let may_be_pe = has_pe_extension || (self.sniff_magic && path_slice.starts_with(b"/"));
+        if !may_be_pe {
             return;
         }
 
@@ -79,6 +165,10 @@ impl PeMappings {
         };
         let path = Path::new(path);
 
+        if !has_pe_extension && !starts_with_mz_magic(path) {
+            return;
+        }
+
         // There are a few assumptions here:
         // - The SizeOfImage field in the PE header is defined to be a multiple of SectionAlignment.
         //   SectionAlignment is usually the page size. When it's not the page size, additional
@@ -87,41 +177,114 @@ impl PeMappings {
         //   here.
         // - VirtualAddress of the sections are defined to be adjacent after page-alignment. This
         //   means that we can treat the image as a contiguous region.
-        if let Some((size, code_id)) = get_pe_mapping_size_and_codeid(path) {
-            let mapping = SuspectedPeMapping {
-                path: path.to_owned(),
-                code_id,
-                avma_range: AvmaRange::with_start_size(mapping_start_avma, size),
-            };
-            self.suspected_pe_mappings
-                .insert(mapping_start_avma, mapping);
+        let Some((size, code_id)) = get_pe_mapping_size_and_codeid(path) else {
+            return;
+        };
+        if mapping_start_avma.checked_add(size).is_none() {
+            return;
         }
+        let mapping = SuspectedPeMapping {
+            path: path.to_owned(),
+            code_id,
+            avma_range: AvmaRange::with_start_size(mapping_start_avma, size),
+        };
+        self.insert(pid, mapping);
     }
 }
 
-fn get_pe_mapping_size_and_codeid(path: &Path) -> Option<(u64, CodeId)> {
-    fn inner<T: ImageNtHeaders>(data: &[u8]) -> Option<(u64, CodeId)> {
-        let file = PeFile::<T>::parse(data).ok()?;
-        let image_size = file.nt_headers().optional_header().size_of_image();
-        let timestamp = file
-            .nt_headers()
-            .file_header()
-            .time_date_stamp
-            .get(object::LittleEndian);
-        let code_id = CodeId::PeCodeId(PeCodeId {
-            timestamp,
-            image_size,
-        });
-
-        Some((image_size as u64, code_id))
-    }
+fn starts_with_mz_magic(path: &Path) -> bool {
+    let Ok(mut file) = std::fs::File::open(path) else {
+        return false;
+    };
+    let mut magic = [0; 2];
+    file.read_exact(&mut magic).is_ok() && &magic == b"MZ"
+}
 
+fn get_pe_mapping_size_and_codeid(path: &Path) -> Option<(u64, CodeId)> {
     let file = std::fs::File::open(path).ok()?;
     let mmap = unsafe { Mmap::map(&file).ok()? };
+    let code_id = pe_code_id_for_binary(&mmap)?;
+    if code_id.image_size == 0 {
+        return None;
+    }
+    Some((code_id.image_size as u64, CodeId::PeCodeId(code_id)))
+}
+
+#[cfg(test)]
+mod test {
+    use wholesym::PeCodeId;
+
+    use super::*;
+
+    #[test]
+    fn pe_extensions() {
+        let pe_mappings = PeMappings::new(&[".node".to_string()], false);
+        assert!(pe_mappings.has_pe_extension(b"/home/user/.wine/drive_c/game.exe"));
+        assert!(pe_mappings.has_pe_extension(b"/windows/system32/DESK.CPL"));
+        assert!(pe_mappings.has_pe_extension(b"/app/addon.node"));
+        assert!(!pe_mappings.has_pe_extension(b"/usr/lib/libc.so.6"));
+        assert!(!pe_mappings.has_pe_extension(b"/opt/dll.d/plugin"));
+    }
+
+    fn suspected_mapping(start: u64, size: u64) -> SuspectedPeMapping {
+        SuspectedPeMapping {
+            path: PathBuf::from(format!("/drive_c/{start:x}.dll")),
+            code_id: CodeId::PeCodeId(PeCodeId {
+                timestamp: 0,
+                image_size: size as u32,
+            }),
+            avma_range: AvmaRange::with_start_size(start, size),
+        }
+    }
+
+    #[test]
+    fn unmapped_and_replaced_mappings_are_removed() {
+        let mut pe_mappings = PeMappings::new(&[], false);
+        pe_mappings.insert(1, suspected_mapping(0x10000, 0x4000));
+        pe_mappings.insert(1, suspected_mapping(0x20000, 0x4000));
+        let image = AvmaRange::with_start_size(0x10000, 0x4000);
+        assert!(pe_mappings.find_mapping(1, &image).is_some());
+
+        pe_mappings.handle_munmap(1, &AvmaRange::with_start_size(0x11000, 0x1000));
+        assert!(pe_mappings.find_mapping(1, &image).is_none());
+        assert_eq!(pe_mappings.suspected_pe_mappings.len(), 1);
+
+        // A new image at an overlapping address replaces the old one.
+        pe_mappings.insert(1, suspected_mapping(0x1f000, 0x2000));
+        assert_eq!(pe_mappings.suspected_pe_mappings.len(), 1);
+    }
+
+    #[test]
+    fn mappings_are_per_process() {
+        let mut pe_mappings = PeMappings::new(&[], false);
+        pe_mappings.insert(1, suspected_mapping(0x10000, 0x4000));
+        pe_mappings.insert(2, suspected_mapping(0x10000, 0x4000));
+        let image = AvmaRange::with_start_size(0x10000, 0x4000);
+        assert!(pe_mappings.find_mapping(3, &image).is_none());
+
+        // An unmap in one process doesn't affect the other process.
+        pe_mappings.handle_munmap(1, &image);
+        assert!(pe_mappings.find_mapping(1, &image).is_none());
+        assert!(pe_mappings.find_mapping(2, &image).is_some());
+    }
+
+    #[test]
+    fn least_recently_used_mapping_is_evicted() {
+        let mut pe_mappings = PeMappings::new(&[], false);
+        for i in 0..MAX_SUSPECTED_PE_MAPPINGS as u64 {
+            pe_mappings.insert(1, suspected_mapping(0x10000 * (i + 1), 0x1000));
+        }
+        let first = AvmaRange::with_start_size(0x10000, 0x1000);
+        assert!(pe_mappings.find_mapping(1, &first).is_some());
 
-    match FileKind::parse(&mmap[..]).ok()? {
-        FileKind::Pe32 => inner::<ImageNtHeaders32>(&mmap),
-        FileKind::Pe64 => inner::<ImageNtHeaders64>(&mmap),
-        _ => None,
+        pe_mappings.insert(1, suspected_mapping(0x1_0000_0000, 0x1000));
+        assert_eq!(
+            pe_mappings.suspected_pe_mappings.len(),
+            MAX_SUSPECTED_PE_MAPPINGS
+        );
+        // The first mapping was used recently, so the second one was evicted.
+        assert!(pe_mappings.find_mapping(1, &first).is_some());
+        let second = AvmaRange::with_start_size(0x20000, 0x1000);
+        assert!(pe_mappings.find_mapping(1, &second).is_none());
     }
 }
@@ -0,0 +1,149 @@
+use std::time::Duration;
+
+/// The readings of one event from the output of `perf stat -I`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IntervalCounterSeries {
+    /// The event name, e.g. "cycles" or "cache-misses".
+    pub event: String,
+    /// The unit of the counts, e.g. "msec" for `task-clock`. Empty for plain counts.
+    pub unit: String,
+    /// The time at the end of each interval, relative to the start of `perf stat`,
+    /// and the count during that interval.
+    pub readings: Vec<(Duration, f64)>,
+}
+
+/// Parses the output of `perf stat -I <ms>` into one series per event.
+///
+/// Both the human-readable output and the CSV output (`-x,`) are understood.
+/// Lines which don't look like counter readings, such as headers, comments
+/// and `<not counted>` events, are skipped. Per-CPU output (`-A`) is not
+/// supported; these lines are skipped as well.
+pub fn parse_perf_stat_intervals(text: &str) -> Vec<IntervalCounterSeries> {
+    let mut series: Vec<IntervalCounterSeries> = Vec::new();
+    for line in text.lines() {
+        let Some((time, count, unit, event)) =
+            parse_csv_line(line).or_else(|| parse_human_readable_line(line))
+        else {
+            continue;
+        };
+        let Ok(time) = Duration::try_from_secs_f64(time) else {
+            continue;
+        };
+        let reading = (time, count);
+        match series.iter_mut().find(|s| s.event == event) {
+            Some(s) => s.readings.push(reading),
+            None => series.push(IntervalCounterSeries {
+                event: event.to_string(),
+                unit: unit.to_string(),
+                readings: vec![reading],
+            }),
+        }
+    }
+    series
+}
+
+/// `1.001056219,1234567,,cycles,1000123456,100.00,,`
+fn parse_csv_line(line: &str) -> Option<(f64, f64, &str, &str)> {
+    let mut fields = line.trim().split(',');
+    let time = fields.next()?.trim().parse().ok()?;
+    let count = fields.next()?.trim().parse().ok()?;
+    let unit = fields.next()?.trim();
+    let event = fields.next()?.trim();
+    if event.is_empty() {
+        return None;
+    }
+    Some((time, count, unit, event))
+}
+
+/// `     1.001234567          1,234,567      cycles       #    1.234 GHz`
+///
+/// Events which were multiplexed have the percentage of the time during which
+/// they were counted at the end, e.g. `1,234,567  cycles  (50.12%)`.
+fn parse_human_readable_line(line: &str) -> Option<(f64, f64, &str, &str)> {
+    let line = match line.split_once('#') {
+        Some((before_comment, _)) => before_comment,
+        None => line,
+    };
+    let line = line.trim_end();
+    let line = match line.rsplit_once(char::is_whitespace) {
+        Some((before, last)) if last.starts_with('(') && last.ends_with("%)") => before,
+        _ => line,
+    };
+    let mut tokens = line.split_whitespace();
+    let time = tokens.next()?.parse().ok()?;
+    let count = tokens.next()?.replace(',', "").parse().ok()?;
+    let (unit, event) = match (tokens.next()?, tokens.next()) {
+        (event, None) => ("", event),
+        (unit, Some(event)) => (unit, event),
+    };
+    Some((time, count, unit, event))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn csv_output() {
+        let text = "\
+# started on Mon Oct  6 10:00:00 2025
+
+     1.001056219,1234567,,cycles,1000123456,100.00,,
+     1.001056219,987654,,instructions,1000123456,100.00,0.80,insn per cycle
+     2.002110321,2345678,,cycles,1000100000,100.00,,
+     2.002110321,<not counted>,,instructions,0,100.00,,
+";
+        let series = parse_perf_stat_intervals(text);
+        assert_eq!(series.len(), 2);
+        assert_eq!(series[0].event, "cycles");
+        assert_eq!(
+            series[0].readings,
+            vec![
+                (Duration::from_secs_f64(1.001056219), 1234567.0),
+                (Duration::from_secs_f64(2.002110321), 2345678.0)
+            ]
+        );
+        assert_eq!(series[1].event, "instructions");
+        assert_eq!(series[1].readings.len(), 1);
+    }
+
+    #[test]
+    fn human_readable_output() {
+        let text = "\
+#           time             counts unit events
+     1.001234567           1,000.52 msec task-clock                #    1.000 CPUs utilized
+     1.001234567          1,234,567      cycles                    #    1.234 GHz
+     2.002345678          2,000,000      cycles
+     3.003456789          2,500,000      cycles                    (50.12%)
+     4.004567890          3,000,000      cycles                    #    1.500 GHz  (49.88%)
+";
+        let series = parse_perf_stat_intervals(text);
+        assert_eq!(series.len(), 2);
+        assert_eq!(series[0].event, "task-clock");
+        assert_eq!(series[0].unit, "msec");
+        assert_eq!(series[0].readings[0].1, 1000.52);
+        assert_eq!(series[1].event, "cycles");
+        assert_eq!(series[1].unit, "");
+        assert_eq!(
+            series[1]
+                .readings
+                .iter()
+                .map(|(_, count)| *count)
+                .collect::<Vec<_>>(),
+            [1234567.0, 2000000.0, 2500000.0, 3000000.0]
+        );
+    }
+
+    #[test]
+    fn skips_lines_with_invalid_times() {
+        let text = "\
+-1.000000000,1000,,cycles,1000000000,100.00,,
+inf,2000,,cycles,1000000000,100.00,,
+1e30,3000,,cycles,1000000000,100.00,,
+1.000000000,4000,,cycles,1000000000,100.00,,
+";
+        let series = parse_perf_stat_intervals(text);
+        assert_eq!(series.len(), 1);
+        assert_eq!(series[0].readings, vec![(Duration::from_secs(1), 4000.0)]);
+    }
+}
@@ -1,3 +1,5 @@
+use std::collections::BTreeMap;
+use std::ops::Range;
 use std::path::{Path, PathBuf};
 
 use framehop::Unwinder;
@@ -12,7 +14,9 @@ use crate::shared::jit_category_manager::JitCategoryManager;
 use crate::shared::jit_function_add_marker::JitFunctionAddMarker;
 use crate::shared::jit_function_recycler::JitFunctionRecycler;
 use crate::shared::jitdump_manager::JitDumpManager;
-use crate::shared::lib_mappings::{LibMappingAdd, LibMappingInfo, LibMappingOp, LibMappingOpQueue};
+use crate::shared::lib_mappings::{
+    LibMappingAdd, LibMappingInfo, LibMappingOp, LibMappingOpQueue, LibMappingRemoveRange,
+};
 use crate::shared::marker_file::get_markers;
 use crate::shared::perf_map::try_load_perf_map;
 use crate::shared::process_sample_data::{MarkerSpanOnThread, ProcessSampleData};
@@ -24,6 +28,8 @@ use crate::shared::unresolved_samples::UnresolvedSamples;
 pub struct Process<U> {
     pub profile_process: ProcessHandle,
     pub unwinder: U,
+    /// The address ranges of the modules in `unwinder`, as start -> end.
+    unwinder_module_ranges: BTreeMap<u64, u64>,
     pub jitdump_manager: JitDumpManager,
     pub lib_mapping_ops: LibMappingOpQueue,
     pub name: Option<String>,
@@ -42,6 +48,7 @@ pub struct Process<U> {
 
 pub struct ProcessForkData<U> {
     unwinder: U,
+    unwinder_module_ranges: BTreeMap<u64, u64>,
     lib_mapping_ops: LibMappingOpQueue,
 }
 
@@ -58,11 +65,14 @@ where
         name: Option<String>,
         thread_recycler: Option<ThreadRecycler>,
         jit_function_recycler: Option<JitFunctionRecycler>,
+        suffix_reused_tids: bool,
         unlink_aux_files: bool,
+        spill_samples_threshold: Option<usize>,
     ) -> Self {
         Self {
             profile_process: process_handle,
             unwinder: U::default(),
+            unwinder_module_ranges: BTreeMap::new(),
             jitdump_manager: JitDumpManager::new(unlink_aux_files),
             lib_mapping_ops: Default::default(),
             name: name.clone(),
@@ -74,8 +84,9 @@ where
                 main_thread_label_frame,
                 name,
                 thread_recycler,
+                suffix_reused_tids,
             ),
-            unresolved_samples: Default::default(),
+            unresolved_samples: UnresolvedSamples::with_spill_threshold(spill_samples_threshold),
             jit_app_cache_mapping_ops: LibMappingOpQueue::default(),
             jit_function_recycler,
             marker_file_paths: Vec::new(),
@@ -91,6 +102,7 @@ where
     pub fn clone_fork_data(&self) -> ProcessForkData<U> {
         ProcessForkData {
             unwinder: self.unwinder.clone(),
+            unwinder_module_ranges: self.unwinder_module_ranges.clone(),
             lib_mapping_ops: self.lib_mapping_ops.clone(),
         }
     }
@@ -98,6 +110,7 @@ where
     /// Called on the child process that was created by the fork.
     pub fn adopt_fork_data_from_parent(&mut self, fork_data: ProcessForkData<U>) {
         self.unwinder = fork_data.unwinder;
+        self.unwinder_module_ranges = fork_data.unwinder_module_ranges;
         self.lib_mapping_ops = fork_data.lib_mapping_ops;
     }
 
@@ -193,12 +206,15 @@ where
         profile: &mut Profile,
         jit_category_manager: &mut JitCategoryManager,
         timestamp_converter: &TimestampConverter,
+        aux_file_lookup_dirs: &[PathBuf],
     ) -> (ProcessSampleData, Option<(String, ProcessRecyclingData)>) {
         self.unwinder = U::default();
+        self.unwinder_module_ranges.clear();
 
         let perf_map_mappings = if !self.unresolved_samples.is_empty() {
             try_load_perf_map(
                 self.pid as u32,
+                aux_file_lookup_dirs,
                 profile,
                 jit_category_manager,
                 self.jit_function_recycler.as_mut(),
@@ -266,6 +282,25 @@ where
         (process_sample_data, process_recycling_data)
     }
 
+    /// Returns the sample data of this process so far, for a snapshot of the
+    /// profile. Unlike [`Process::finish`], this doesn't load the perf map,
+    /// jitdump and marker files, because they may still be written to; JIT
+    /// frames from these files are only resolved in the final profile.
+    pub fn snapshot_sample_data(&mut self) -> ProcessSampleData {
+        let jitdump_ops = if self.jit_app_cache_mapping_ops.is_empty() {
+            Vec::new()
+        } else {
+            vec![self.jit_app_cache_mapping_ops.clone()]
+        };
+        ProcessSampleData::new(
+            self.unresolved_samples.snapshot(),
+            self.lib_mapping_ops.clone(),
+            jitdump_ops,
+            None,
+            Vec::new(),
+        )
+    }
+
     #[allow(clippy::too_many_arguments)]
     pub fn add_regular_lib_mapping(
         &mut self,
@@ -275,13 +310,56 @@ where
         relative_address_at_start: u32,
         info: LibMappingInfo,
     ) {
-        self.lib_mapping_ops.push(
+        self.lib_mapping_ops.push_add_ending_overlapping(
             timestamp,
-            LibMappingOp::Add(LibMappingAdd {
+            LibMappingAdd {
                 start_avma: start_address,
                 end_avma: end_address,
                 relative_address_at_start,
                 info,
+            },
+        );
+    }
+
+    /// Adds a module to the unwinder. Modules which overlap with `avma_range`
+    /// are removed first, because their address range has been reused.
+    pub fn add_unwinder_module(&mut self, avma_range: Range<u64>, module: U::Module) {
+        self.remove_unwinder_modules_in_range(avma_range.start, avma_range.end);
+        self.unwinder.add_module(module);
+        self.unwinder_module_ranges
+            .insert(avma_range.start, avma_range.end);
+    }
+
+    fn remove_unwinder_modules_in_range(&mut self, start_address: u64, end_address: u64) {
+        // The tracked modules don't overlap, so their end addresses are sorted too.
+        let overlapping_starts: Vec<u64> = self
+            .unwinder_module_ranges
+            .range(..end_address)
+            .rev()
+            .take_while(|(_start, end)| **end > start_address)
+            .map(|(start, _end)| *start)
+            .collect();
+        for start in overlapping_starts {
+            self.unwinder.remove_module(start);
+            self.unwinder_module_ranges.remove(&start);
+        }
+    }
+
+    /// Ends the lib mappings in `start_address..end_address` at `timestamp`,
+    /// e.g. because the range was unmapped. Modules in the range are also
+    /// removed from the unwinder.
+    pub fn remove_lib_mapping_range(
+        &mut self,
+        timestamp: u64,
+        start_address: u64,
+        end_address: u64,
+    ) {
+        self.remove_unwinder_modules_in_range(start_address, end_address);
+        self.lib_mapping_ops.push(
+            timestamp,
+            LibMappingOp::RemoveRange(LibMappingRemoveRange {
+                start_avma: start_address,
+                end_avma: end_address,
             }),
         );
     }
@@ -13,7 +13,14 @@ pub struct ProcessThreads {
     pub profile_process: ProcessHandle,
     pub main_thread: Thread,
     pub threads_by_tid: FastHashMap<i32, Thread>,
+    /// The "swapper/N" threads of the idle task, keyed by CPU. Only used in
+    /// the process for pid 0.
+    pub idle_threads_by_cpu: FastHashMap<u32, Thread>,
     pub thread_recycler: Option<ThreadRecycler>,
+
+    /// Some() if threads whose tid was used by an earlier thread of this process
+    /// should get a generation suffix in their name.
+    pub tid_generations: Option<TidGenerations>,
 }
 
 impl ProcessThreads {
@@ -24,13 +31,21 @@ impl ProcessThreads {
         main_thread_label_frame: FrameInfo,
         name: Option<String>,
         thread_recycler: Option<ThreadRecycler>,
+        suffix_reused_tids: bool,
     ) -> Self {
+        let tid_generations = if suffix_reused_tids {
+            Some(TidGenerations::default())
+        } else {
+            None
+        };
         Self {
             pid,
             profile_process: process_handle,
             main_thread: Thread::new(main_thread_handle, main_thread_label_frame, name),
             threads_by_tid: Default::default(),
+            idle_threads_by_cpu: Default::default(),
             thread_recycler,
+            tid_generations,
         }
     }
 
@@ -78,11 +93,17 @@ impl ProcessThreads {
 
                 let thread_handle =
                     profile.add_thread(self.profile_process, tid as u32, start_time, false);
-                if let Some(name) = &name {
-                    profile.set_thread_name(thread_handle, name);
+                if let Some(tid_generations) = self.tid_generations.as_mut() {
+                    tid_generations.notify_new_thread(tid);
+                }
+                let display_name = name
+                    .as_deref()
+                    .map(|name| thread_display_name(self.tid_generations.as_ref(), tid, name));
+                if let Some(display_name) = &display_name {
+                    profile.set_thread_name(thread_handle, display_name);
                 }
                 let thread_label_frame =
-                    make_thread_label_frame(profile, name.as_deref(), self.pid, tid);
+                    make_thread_label_frame(profile, display_name.as_deref(), self.pid, tid);
                 let thread = Thread::new(thread_handle, thread_label_frame, name);
                 entry.insert(thread)
             }
@@ -136,9 +157,14 @@ impl ProcessThreads {
                         }
                     }
                 } else {
+                    let display_name =
+                        thread_display_name(self.tid_generations.as_ref(), tid, &name);
                     let thread_label_frame =
-                        make_thread_label_frame(profile, Some(&name), self.pid, tid);
+                        make_thread_label_frame(profile, Some(&display_name), self.pid, tid);
                     thread.rename_without_recycling(name, thread_label_frame, profile);
+                    if self.tid_generations.is_some() {
+                        profile.set_thread_name(thread.profile_thread, &display_name);
+                    }
                 }
             }
         }
@@ -171,6 +197,9 @@ impl ProcessThreads {
             return &mut self.main_thread;
         }
         self.threads_by_tid.entry(tid).or_insert_with(|| {
+            if let Some(tid_generations) = self.tid_generations.as_mut() {
+                tid_generations.notify_new_thread(tid);
+            }
             let profile_thread = profile.add_thread(
                 self.profile_process,
                 tid as u32,
@@ -182,13 +211,33 @@ impl ProcessThreads {
                 profile_thread,
                 context_switch_data: Default::default(),
                 last_sample_timestamp: None,
+                last_sample_attr_index_and_stack: None,
                 off_cpu_stack: None,
+                current_syscall: None,
                 name: None,
                 thread_label_frame,
             }
         })
     }
 
+    /// Returns the idle thread of the given CPU. The idle task (pid 0) has one
+    /// thread per CPU, which all have tid 0, so they can't be told apart by tid.
+    pub fn get_idle_thread_for_cpu(&mut self, cpu: u32, profile: &mut Profile) -> &mut Thread {
+        self.idle_threads_by_cpu.entry(cpu).or_insert_with(|| {
+            profile.set_process_name(self.profile_process, "swapper");
+            let profile_thread = profile.add_thread(
+                self.profile_process,
+                0,
+                Timestamp::from_millis_since_reference(0.0),
+                false,
+            );
+            let name = format!("swapper/{cpu}");
+            profile.set_thread_name(profile_thread, &name);
+            let thread_label_frame = make_thread_label_frame(profile, Some(&name), self.pid, 0);
+            Thread::new(profile_thread, thread_label_frame, Some(name))
+        })
+    }
+
     pub fn remove_non_main_thread(&mut self, tid: i32, time: Timestamp, profile: &mut Profile) {
         let Some(mut thread) = self.threads_by_tid.remove(&tid) else {
             return;
@@ -221,3 +270,86 @@ pub fn make_thread_label_frame(
         flags: FrameFlags::empty(),
     }
 }
+
+/// Counts how many threads of a process have used each tid, so that threads
+/// with a reused tid can be told apart by name.
+#[derive(Debug, Default)]
+pub struct TidGenerations(FastHashMap<i32, u32>);
+
+impl TidGenerations {
+    /// Called whenever a new thread with this tid is created.
+    pub fn notify_new_thread(&mut self, tid: i32) {
+        *self.0.entry(tid).or_default() += 1;
+    }
+
+    /// Returns the generation of the current thread with this tid, starting at 1.
+    pub fn generation(&self, tid: i32) -> u32 {
+        self.0.get(&tid).copied().unwrap_or(1)
+    }
+}
+
+/// Returns the thread name with a " (#N)" suffix if the thread's tid was used
+/// by N - 1 earlier threads.
+fn thread_display_name(tid_generations: Option<&TidGenerations>, tid: i32, name: &str) -> String {
+    match tid_generations.map(|g| g.generation(tid)) {
+        Some(generation) if generation > 1 => format!("{name} (#{generation})"),
+        _ => name.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use fxprof_processed_profile::{ReferenceTimestamp, SamplingInterval};
+
+    use super::*;
+
+    #[test]
+    fn idle_threads_are_per_cpu() {
+        let mut profile = Profile::new(
+            "",
+            ReferenceTimestamp::from_millis_since_unix_epoch(0.0),
+            SamplingInterval::from_millis(1),
+        );
+        let start = Timestamp::from_millis_since_reference(0.0);
+        let process = profile.add_process("<0>", 0, start);
+        let main_thread = profile.add_thread(process, 0, start, true);
+        let label = make_thread_label_frame(&mut profile, None, 0, 0);
+        let mut threads = ProcessThreads::new(0, process, main_thread, label, None, None, false);
+
+        let cpu0 = threads
+            .get_idle_thread_for_cpu(0, &mut profile)
+            .profile_thread;
+        let cpu1 = threads.get_idle_thread_for_cpu(1, &mut profile);
+        assert_eq!(cpu1.name.as_deref(), Some("swapper/1"));
+        let cpu1 = cpu1.profile_thread;
+        assert_ne!(cpu0, cpu1);
+        assert_ne!(cpu0, main_thread);
+        assert_eq!(
+            threads
+                .get_idle_thread_for_cpu(0, &mut profile)
+                .profile_thread,
+            cpu0
+        );
+    }
+
+    #[test]
+    fn reused_tids_get_generation_suffix() {
+        let mut tid_generations = TidGenerations::default();
+        tid_generations.notify_new_thread(12);
+        assert_eq!(
+            thread_display_name(Some(&tid_generations), 12, "worker"),
+            "worker"
+        );
+        tid_generations.notify_new_thread(12);
+        tid_generations.notify_new_thread(12);
+        assert_eq!(
+            thread_display_name(Some(&tid_generations), 12, "worker"),
+            "worker (#3)"
+        );
+        assert_eq!(
+            thread_display_name(Some(&tid_generations), 13, "worker"),
+            "worker"
+        );
+        assert_eq!(thread_display_name(None, 12, "worker"), "worker");
+    }
+}
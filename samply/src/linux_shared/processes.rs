@@ -1,5 +1,6 @@
 use std::collections::hash_map::Entry;
 use std::collections::HashMap;
+use std::path::PathBuf;
 
 use framehop::Unwinder;
 use fxprof_processed_profile::{CategoryColor, Profile, Timestamp};
@@ -26,15 +27,38 @@ where
     /// The sample data for all removed processes.
     process_sample_datas: Vec<ProcessSampleData>,
 
+    /// Whether threads whose tid was used by an earlier thread of the same
+    /// process should get a generation suffix in their name.
+    suffix_reused_tids: bool,
+
     /// Whether aux files (like jitdump) should be unlinked on open
     unlink_aux_data: bool,
+
+    /// Passed to [`UnresolvedSamples::with_spill_threshold`] for each process.
+    ///
+    /// [`UnresolvedSamples::with_spill_threshold`]: crate::shared::unresolved_samples::UnresolvedSamples::with_spill_threshold
+    spill_samples_threshold: Option<usize>,
+
+    /// Additional directories to look for perf map files in.
+    aux_file_lookup_dirs: Vec<PathBuf>,
+
+    /// Whether threads whose names only differ in a trailing number are reused
+    /// for each other.
+    reuse_numbered_threads: bool,
 }
 
 impl<U> Processes<U>
 where
     U: Unwinder + Default,
 {
-    pub fn new(allow_reuse: bool, unlink_aux_data: bool) -> Self {
+    pub fn new(
+        allow_reuse: bool,
+        suffix_reused_tids: bool,
+        unlink_aux_data: bool,
+        spill_samples_threshold: Option<usize>,
+        aux_file_lookup_dirs: Vec<PathBuf>,
+        reuse_numbered_threads: bool,
+    ) -> Self {
         let process_recycler = if allow_reuse {
             Some(ProcessRecycler::new())
         } else {
@@ -44,7 +68,11 @@ where
             processes_by_pid: HashMap::new(),
             process_recycler,
             process_sample_datas: Vec::new(),
+            suffix_reused_tids,
             unlink_aux_data,
+            spill_samples_threshold,
+            aux_file_lookup_dirs,
+            reuse_numbered_threads,
         }
     }
 
@@ -77,7 +105,9 @@ where
                             name,
                             Some(thread_recycler),
                             Some(jit_function_recycler),
+                            self.suffix_reused_tids,
                             self.unlink_aux_data,
+                            self.spill_samples_threshold,
                         );
                         return entry.insert(process);
                     }
@@ -98,7 +128,9 @@ where
                     make_thread_label_frame(profile, name.as_deref(), pid, pid);
                 let (thread_recycler, jit_function_recycler) = if self.process_recycler.is_some() {
                     (
-                        Some(ThreadRecycler::new()),
+                        Some(ThreadRecycler::new_merging_numbered_names(
+                            self.reuse_numbered_threads,
+                        )),
                         Some(JitFunctionRecycler::default()),
                     )
                 } else {
@@ -112,7 +144,9 @@ where
                     name,
                     thread_recycler,
                     jit_function_recycler,
+                    self.suffix_reused_tids,
                     self.unlink_aux_data,
+                    self.spill_samples_threshold,
                 );
                 entry.insert(process)
             }
@@ -149,7 +183,9 @@ where
             let main_thread_label_frame = make_thread_label_frame(profile, None, pid, pid);
             let (thread_recycler, jit_function_recycler) = if self.process_recycler.is_some() {
                 (
-                    Some(ThreadRecycler::new()),
+                    Some(ThreadRecycler::new_merging_numbered_names(
+                        self.reuse_numbered_threads,
+                    )),
                     Some(JitFunctionRecycler::default()),
                 )
             } else {
@@ -163,7 +199,9 @@ where
                 None, // no name
                 thread_recycler,
                 jit_function_recycler,
+                self.suffix_reused_tids,
                 self.unlink_aux_data,
+                self.spill_samples_threshold,
             )
         })
     }
@@ -182,8 +220,12 @@ where
 
         process.notify_dead(time, profile);
 
-        let (process_sample_data, process_recycling_data) =
-            process.finish(profile, jit_category_manager, timestamp_converter);
+        let (process_sample_data, process_recycling_data) = process.finish(
+            profile,
+            jit_category_manager,
+            timestamp_converter,
+            &self.aux_file_lookup_dirs,
+        );
         if !process_sample_data.is_empty() {
             self.process_sample_datas.push(process_sample_data);
         }
@@ -230,6 +272,33 @@ where
         }
     }
 
+    /// Flushes the samples of all processes so far into `profile`, which is
+    /// a copy of the profile that is being built. The processes are kept alive
+    /// so that the conversion can continue.
+    pub fn snapshot(&mut self, profile: &mut Profile, unresolved_stacks: &UnresolvedStacks) {
+        let process_sample_datas: Vec<ProcessSampleData> = self
+            .process_sample_datas
+            .iter_mut()
+            .map(ProcessSampleData::snapshot)
+            .chain(
+                self.processes_by_pid
+                    .values_mut()
+                    .map(Process::snapshot_sample_data),
+            )
+            .filter(|process_sample_data| !process_sample_data.is_empty())
+            .collect();
+
+        let user_category = profile.add_category("User", CategoryColor::Yellow).into();
+        let kernel_category = profile.add_category("Kernel", CategoryColor::Orange).into();
+        ProcessSampleData::flush_all_samples_to_profile(
+            process_sample_datas,
+            profile,
+            user_category,
+            kernel_category,
+            unresolved_stacks,
+        );
+    }
+
     pub fn finish(
         mut self,
         profile: &mut Profile,
@@ -239,8 +308,12 @@ where
     ) {
         // Gather the ProcessSampleData from any processes which are still alive at the end of profiling.
         for process in self.processes_by_pid.into_values() {
-            let (process_sample_data, _process_recycling_data) =
-                process.finish(profile, jit_category_manager, timestamp_converter);
+            let (process_sample_data, _process_recycling_data) = process.finish(
+                profile,
+                jit_category_manager,
+                timestamp_converter,
+                &self.aux_file_lookup_dirs,
+            );
             if !process_sample_data.is_empty() {
                 self.process_sample_datas.push(process_sample_data);
             }
@@ -248,15 +321,12 @@ where
 
         let user_category = profile.add_category("User", CategoryColor::Yellow).into();
         let kernel_category = profile.add_category("Kernel", CategoryColor::Orange).into();
-        let mut stack_frame_scratch_buf = Vec::new();
-        for process_sample_data in self.process_sample_datas {
-            process_sample_data.flush_samples_to_profile(
-                profile,
-                user_category,
-                kernel_category,
-                &mut stack_frame_scratch_buf,
-                unresolved_stacks,
-            );
-        }
+        ProcessSampleData::flush_all_samples_to_profile(
+            self.process_sample_datas,
+            profile,
+            user_category,
+            kernel_category,
+            unresolved_stacks,
+        );
     }
 }
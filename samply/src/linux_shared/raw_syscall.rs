@@ -0,0 +1,189 @@
+use byteorder::ByteOrder;
+use linux_perf_data::{linux_perf_event_reader, Endianness};
+use linux_perf_event_reader::RawData;
+
+/// The syscall number from the payload of the `raw_syscalls:sys_enter` and
+/// `raw_syscalls:sys_exit` tracepoints. Both tracepoints start with the same
+/// fields.
+///
+/// ```
+/// # cat /sys/kernel/debug/tracing/events/raw_syscalls/sys_enter/format
+/// name: sys_enter
+/// ID: 22
+/// format:
+///         field:unsigned short common_type;       offset:0;       size:2; signed:0;
+///         field:unsigned char common_flags;       offset:2;       size:1; signed:0;
+///         field:unsigned char common_preempt_count;       offset:3;       size:1; signed:0;
+///         field:int common_pid;   offset:4;       size:4; signed:1;
+///
+///         field:long id;  offset:8;       size:8; signed:1;
+///         field:unsigned long args[6];    offset:16;      size:48;        signed:0;
+///
+/// print fmt: "NR %ld (%lx, %lx, %lx, %lx, %lx, %lx)", REC->id, ...
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RawSyscall {
+    /// The syscall number. This is architecture-specific.
+    pub id: i64,
+}
+
+impl RawSyscall {
+    pub fn parse(data: RawData, endian: Endianness) -> Result<Self, std::io::Error> {
+        match endian {
+            Endianness::LittleEndian => Self::parse_impl::<byteorder::LittleEndian>(data),
+            Endianness::BigEndian => Self::parse_impl::<byteorder::BigEndian>(data),
+        }
+    }
+
+    pub fn parse_impl<O: ByteOrder>(mut data: RawData) -> Result<Self, std::io::Error> {
+        let _common_type = data.read_u16::<O>()?;
+        let _common_flags = data.read_u8()?;
+        let _common_preempt_count = data.read_u8()?;
+        let _common_pid = data.read_i32::<O>()?;
+        let id = data.read_u64::<O>()? as i64;
+        Ok(Self { id })
+    }
+}
+
+/// The names of x86_64 syscalls which commonly block. Other syscalls are
+/// shown with their number.
+pub fn syscall_name_x86_64(id: i64) -> Option<&'static str> {
+    let name = match id {
+        0 => "read",
+        1 => "write",
+        2 => "open",
+        3 => "close",
+        7 => "poll",
+        9 => "mmap",
+        10 => "mprotect",
+        11 => "munmap",
+        16 => "ioctl",
+        17 => "pread64",
+        18 => "pwrite64",
+        19 => "readv",
+        20 => "writev",
+        23 => "select",
+        24 => "sched_yield",
+        28 => "madvise",
+        34 => "pause",
+        35 => "nanosleep",
+        40 => "sendfile",
+        42 => "connect",
+        43 => "accept",
+        44 => "sendto",
+        45 => "recvfrom",
+        46 => "sendmsg",
+        47 => "recvmsg",
+        56 => "clone",
+        59 => "execve",
+        61 => "wait4",
+        65 => "semop",
+        69 => "msgsnd",
+        70 => "msgrcv",
+        72 => "fcntl",
+        73 => "flock",
+        74 => "fsync",
+        75 => "fdatasync",
+        162 => "sync",
+        202 => "futex",
+        208 => "io_getevents",
+        209 => "io_submit",
+        230 => "clock_nanosleep",
+        232 => "epoll_wait",
+        247 => "waitid",
+        257 => "openat",
+        270 => "pselect6",
+        271 => "ppoll",
+        277 => "sync_file_range",
+        281 => "epoll_pwait",
+        288 => "accept4",
+        295 => "preadv",
+        296 => "pwritev",
+        299 => "recvmmsg",
+        306 => "syncfs",
+        307 => "sendmmsg",
+        426 => "io_uring_enter",
+        441 => "epoll_pwait2",
+        449 => "futex_waitv",
+        _ => return None,
+    };
+    Some(name)
+}
+
+/// The names of aarch64 syscalls which commonly block. aarch64 uses the
+/// generic syscall table.
+pub fn syscall_name_aarch64(id: i64) -> Option<&'static str> {
+    let name = match id {
+        2 => "io_submit",
+        4 => "io_getevents",
+        22 => "epoll_pwait",
+        25 => "fcntl",
+        29 => "ioctl",
+        32 => "flock",
+        56 => "openat",
+        57 => "close",
+        63 => "read",
+        64 => "write",
+        65 => "readv",
+        66 => "writev",
+        67 => "pread64",
+        68 => "pwrite64",
+        69 => "preadv",
+        70 => "pwritev",
+        71 => "sendfile",
+        72 => "pselect6",
+        73 => "ppoll",
+        81 => "sync",
+        82 => "fsync",
+        83 => "fdatasync",
+        84 => "sync_file_range",
+        95 => "waitid",
+        98 => "futex",
+        101 => "nanosleep",
+        115 => "clock_nanosleep",
+        124 => "sched_yield",
+        188 => "msgrcv",
+        189 => "msgsnd",
+        193 => "semop",
+        202 => "accept",
+        203 => "connect",
+        206 => "sendto",
+        207 => "recvfrom",
+        211 => "sendmsg",
+        212 => "recvmsg",
+        215 => "munmap",
+        220 => "clone",
+        221 => "execve",
+        222 => "mmap",
+        226 => "mprotect",
+        233 => "madvise",
+        242 => "accept4",
+        243 => "recvmmsg",
+        260 => "wait4",
+        267 => "syncfs",
+        269 => "sendmmsg",
+        426 => "io_uring_enter",
+        441 => "epoll_pwait2",
+        449 => "futex_waitv",
+        _ => return None,
+    };
+    Some(name)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_sys_enter() {
+        let mut data = vec![0x16, 0, 0, 0];
+        data.extend_from_slice(&1234i32.to_le_bytes());
+        data.extend_from_slice(&202i64.to_le_bytes());
+        data.extend_from_slice(&[0; 48]);
+        let syscall = RawSyscall::parse(RawData::Single(&data), Endianness::LittleEndian).unwrap();
+        assert_eq!(syscall.id, 202);
+        assert_eq!(syscall_name_x86_64(syscall.id), Some("futex"));
+        assert_eq!(syscall_name_aarch64(98), Some("futex"));
+        assert_eq!(syscall_name_x86_64(-1), None);
+    }
+}
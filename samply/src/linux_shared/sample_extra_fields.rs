@@ -0,0 +1,512 @@
+use byteorder::ByteOrder;
+use linux_perf_data::{linux_perf_event_reader, Endianness};
+use linux_perf_event_reader::{
+    BranchSampleFormat, RawData, RawEventRecord, ReadFormat, RecordType, SampleFormat,
+};
+
+use super::branch_stack::{BranchEntry, BranchStack};
+use super::mem_data_src::MemDataSource;
+
+/// `PERF_FORMAT_LOST`, which perf sets on Linux 6.0 and newer. [`ReadFormat`]
+/// doesn't know this flag and drops it, so it has to be read from the raw
+/// `perf_event_attr`.
+const PERF_FORMAT_LOST: u64 = 1 << 4;
+
+/// Whether the raw `perf_event_attr` in `attr_bytes` has `PERF_FORMAT_LOST` in
+/// its `read_format`, i.e. whether each read value is followed by a count of
+/// lost samples.
+pub fn attr_read_format_has_lost(attr_bytes: &[u8], endian: Endianness) -> bool {
+    // The read_format field is at offset 32, after type, size, config,
+    // sample_period and sample_type.
+    let Some(read_format_bytes) = attr_bytes.get(32..40) else {
+        return false;
+    };
+    let read_format = match endian {
+        Endianness::LittleEndian => byteorder::LittleEndian::read_u64(read_format_bytes),
+        Endianness::BigEndian => byteorder::BigEndian::read_u64(read_format_bytes),
+    };
+    read_format & PERF_FORMAT_LOST != 0
+}
+
+/// Sample fields which are not exposed by `SampleRecord`, so we read them
+/// from the raw sample record ourselves.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SampleExtraFields {
+    /// The value from `PERF_SAMPLE_WEIGHT`, or the `var1_dw` value from
+    /// `PERF_SAMPLE_WEIGHT_STRUCT`. For memory sampling events this is
+    /// usually the access latency in cycles.
+    pub weight: Option<u64>,
+    /// The value from `PERF_SAMPLE_DATA_SRC`, describing where in the memory
+    /// hierarchy a memory access was served.
+    pub data_src: Option<MemDataSource>,
+    /// The counter values from `PERF_SAMPLE_READ`. With `PERF_FORMAT_GROUP`,
+    /// this has the values of all events in the sampled event's group.
+    pub read_values: Option<SampleReadValues>,
+    /// The last branch records from `PERF_SAMPLE_BRANCH_STACK`.
+    pub branch_stack: Option<BranchStack>,
+}
+
+/// The counter values of a `PERF_SAMPLE_READ` sample, i.e. a `struct read_format`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SampleReadValues {
+    /// The time the event was enabled, from `PERF_FORMAT_TOTAL_TIME_ENABLED`.
+    pub time_enabled: Option<u64>,
+    /// The time the event was actually counting, from
+    /// `PERF_FORMAT_TOTAL_TIME_RUNNING`. This is less than `time_enabled` if
+    /// the event was multiplexed with other events.
+    pub time_running: Option<u64>,
+    /// One value per event, with the group leader first.
+    pub values: Vec<SampleReadValue>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SampleReadValue {
+    /// The raw counter value since the event was enabled.
+    pub value: u64,
+    /// The event ID from `PERF_FORMAT_ID`, which identifies the event's attr.
+    pub id: Option<u64>,
+    /// The number of lost samples from `PERF_FORMAT_LOST`.
+    pub lost: Option<u64>,
+}
+
+impl SampleReadValues {
+    /// Extrapolates a counter value to the whole enabled time, the same way
+    /// `perf stat` does for multiplexed events. Returns the raw value if the
+    /// times weren't recorded.
+    pub fn scaled(&self, value: u64) -> u64 {
+        match (self.time_enabled, self.time_running) {
+            (Some(enabled), Some(running)) if running != 0 && running < enabled => {
+                let scaled = u128::from(value) * u128::from(enabled) / u128::from(running);
+                u64::try_from(scaled).unwrap_or(u64::MAX)
+            }
+            _ => value,
+        }
+    }
+}
+
+impl SampleExtraFields {
+    /// Returns `None` if the record is not a sample record, or if it was
+    /// malformed. `read_format_has_lost` says whether the event's attr has
+    /// `PERF_FORMAT_LOST`, see [`attr_read_format_has_lost`].
+    pub fn parse(record: &RawEventRecord, read_format_has_lost: bool) -> Option<Self> {
+        if record.record_type != RecordType::SAMPLE {
+            return None;
+        }
+        let result = match record.parse_info.endian {
+            Endianness::LittleEndian => Self::parse_impl::<byteorder::LittleEndian>(
+                record.data,
+                &record.parse_info,
+                read_format_has_lost,
+            ),
+            Endianness::BigEndian => Self::parse_impl::<byteorder::BigEndian>(
+                record.data,
+                &record.parse_info,
+                read_format_has_lost,
+            ),
+        };
+        result.ok()
+    }
+
+    /// Walks the sample record in the same order as the kernel writes it, see
+    /// the comment for `PERF_RECORD_SAMPLE` in `perf_event.h`.
+    fn parse_impl<T: ByteOrder>(
+        mut cur: RawData,
+        parse_info: &linux_perf_event_reader::RecordParseInfo,
+        read_format_has_lost: bool,
+    ) -> Result<Self, std::io::Error> {
+        let sample_format = parse_info.sample_format;
+        let read_format = parse_info.read_format;
+        if !sample_format.intersects(
+            SampleFormat::READ
+                | SampleFormat::BRANCH_STACK
+                | SampleFormat::WEIGHT
+                | SampleFormat::WEIGHT_STRUCT
+                | SampleFormat::DATA_SRC,
+        ) {
+            return Ok(Self::default());
+        }
+
+        let fixed_size_fields = [
+            SampleFormat::IDENTIFIER,
+            SampleFormat::IP,
+            SampleFormat::TID,
+            SampleFormat::TIME,
+            SampleFormat::ADDR,
+            SampleFormat::ID,
+            SampleFormat::STREAM_ID,
+            SampleFormat::CPU,
+            SampleFormat::PERIOD,
+        ];
+        for field in fixed_size_fields {
+            if sample_format.contains(field) {
+                cur.skip(8)?;
+            }
+        }
+
+        let read_values = if sample_format.contains(SampleFormat::READ) {
+            Some(Self::parse_read_format::<T>(
+                &mut cur,
+                read_format,
+                read_format_has_lost,
+            )?)
+        } else {
+            None
+        };
+
+        if sample_format.contains(SampleFormat::CALLCHAIN) {
+            let callchain_length = cur.read_u64::<T>()?;
+            let callchain_size = usize::try_from(callchain_length)
+                .ok()
+                .and_then(|length| length.checked_mul(8))
+                .ok_or_else(|| {
+                    std::io::Error::new(
+                        std::io::ErrorKind::InvalidData,
+                        "callchain length overflows",
+                    )
+                })?;
+            cur.skip(callchain_size)?;
+        }
+
+        if sample_format.contains(SampleFormat::RAW) {
+            let size = cur.read_u32::<T>()?;
+            cur.skip(size as usize)?;
+        }
+
+        let branch_stack = if sample_format.contains(SampleFormat::BRANCH_STACK) {
+            let branch_sample_format = parse_info.branch_sample_format;
+            let nr = cur.read_u64::<T>()?;
+            if branch_sample_format.contains(BranchSampleFormat::HW_INDEX) {
+                cur.skip(8)?;
+            }
+            let is_big_endian = parse_info.endian == Endianness::BigEndian;
+            let mut entries = Vec::with_capacity((nr as usize).min(cur.len() / 24));
+            for _ in 0..nr {
+                let from = cur.read_u64::<T>()?;
+                let to = cur.read_u64::<T>()?;
+                let flags = cur.read_u64::<T>()?;
+                entries.push(BranchEntry::new(from, to, flags, is_big_endian));
+            }
+            Some(BranchStack {
+                is_call_stack: branch_sample_format.contains(BranchSampleFormat::CALL_STACK),
+                entries,
+            })
+        } else {
+            None
+        };
+
+        if sample_format.contains(SampleFormat::REGS_USER) {
+            let regs_abi = cur.read_u64::<T>()?;
+            if regs_abi != 0 {
+                cur.skip(parse_info.user_regs_count as usize * 8)?;
+            }
+        }
+
+        if sample_format.contains(SampleFormat::STACK_USER) {
+            let stack_size = cur.read_u64::<T>()?;
+            cur.skip(stack_size as usize)?;
+            if stack_size != 0 {
+                let _dynamic_size = cur.read_u64::<T>()?;
+            }
+        }
+
+        let weight = if sample_format.contains(SampleFormat::WEIGHT_STRUCT) {
+            // union perf_sample_weight { u64 full; struct { u32 var1_dw; u16 var2_w; u16 var3_w; } }
+            let var1_dw = cur.read_u32::<T>()?;
+            cur.skip(4)?;
+            Some(u64::from(var1_dw))
+        } else if sample_format.contains(SampleFormat::WEIGHT) {
+            Some(cur.read_u64::<T>()?)
+        } else {
+            None
+        };
+
+        let data_src = if sample_format.contains(SampleFormat::DATA_SRC) {
+            Some(MemDataSource(cur.read_u64::<T>()?))
+        } else {
+            None
+        };
+
+        Ok(Self {
+            weight,
+            data_src,
+            read_values,
+            branch_stack,
+        })
+    }
+
+    /// Reads a `struct read_format`, see the comment for [`ReadFormat`]. In the
+    /// group format, the times come before the values; otherwise after. With
+    /// `PERF_FORMAT_LOST`, each value ends with a lost sample count.
+    fn parse_read_format<T: ByteOrder>(
+        cur: &mut RawData,
+        read_format: ReadFormat,
+        has_lost: bool,
+    ) -> Result<SampleReadValues, std::io::Error> {
+        let mut read_values = SampleReadValues::default();
+        if read_format.contains(ReadFormat::GROUP) {
+            let nr = cur.read_u64::<T>()?;
+            if read_format.contains(ReadFormat::TOTAL_TIME_ENABLED) {
+                read_values.time_enabled = Some(cur.read_u64::<T>()?);
+            }
+            if read_format.contains(ReadFormat::TOTAL_TIME_RUNNING) {
+                read_values.time_running = Some(cur.read_u64::<T>()?);
+            }
+            for _ in 0..nr {
+                let value = cur.read_u64::<T>()?;
+                let id = if read_format.contains(ReadFormat::ID) {
+                    Some(cur.read_u64::<T>()?)
+                } else {
+                    None
+                };
+                let lost = if has_lost {
+                    Some(cur.read_u64::<T>()?)
+                } else {
+                    None
+                };
+                read_values.values.push(SampleReadValue { value, id, lost });
+            }
+        } else {
+            let value = cur.read_u64::<T>()?;
+            if read_format.contains(ReadFormat::TOTAL_TIME_ENABLED) {
+                read_values.time_enabled = Some(cur.read_u64::<T>()?);
+            }
+            if read_format.contains(ReadFormat::TOTAL_TIME_RUNNING) {
+                read_values.time_running = Some(cur.read_u64::<T>()?);
+            }
+            let id = if read_format.contains(ReadFormat::ID) {
+                Some(cur.read_u64::<T>()?)
+            } else {
+                None
+            };
+            let lost = if has_lost {
+                Some(cur.read_u64::<T>()?)
+            } else {
+                None
+            };
+            read_values.values.push(SampleReadValue { value, id, lost });
+        }
+        Ok(read_values)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use linux_perf_event_reader::{Endianness, PerfEventAttr, RecordParseInfo};
+
+    use super::*;
+
+    fn parse_info(sample_format: SampleFormat) -> RecordParseInfo {
+        parse_info_with_read_format(sample_format, ReadFormat::empty())
+    }
+
+    fn parse_info_with_read_format(
+        sample_format: SampleFormat,
+        read_format: ReadFormat,
+    ) -> RecordParseInfo {
+        let mut attr_bytes = [0u8; 128];
+        // type = PERF_TYPE_HARDWARE, size = 128
+        attr_bytes[4..8].copy_from_slice(&128u32.to_le_bytes());
+        attr_bytes[24..32].copy_from_slice(&sample_format.bits().to_le_bytes());
+        attr_bytes[32..40].copy_from_slice(&read_format.bits().to_le_bytes());
+        let (attr, _) =
+            PerfEventAttr::parse::<_, byteorder::LittleEndian>(&attr_bytes[..]).unwrap();
+        RecordParseInfo::new(&attr, Endianness::LittleEndian)
+    }
+
+    #[test]
+    fn weight_after_callchain() {
+        let parse_info =
+            parse_info(SampleFormat::IP | SampleFormat::CALLCHAIN | SampleFormat::WEIGHT);
+        let mut data = vec![];
+        data.extend_from_slice(&0x1000u64.to_le_bytes());
+        data.extend_from_slice(&2u64.to_le_bytes());
+        data.extend_from_slice(&0x2000u64.to_le_bytes());
+        data.extend_from_slice(&0x3000u64.to_le_bytes());
+        data.extend_from_slice(&250u64.to_le_bytes());
+        let record =
+            RawEventRecord::new(RecordType::SAMPLE, 0, RawData::from(&data[..]), parse_info);
+        let fields = SampleExtraFields::parse(&record, false).unwrap();
+        assert_eq!(fields.weight, Some(250));
+
+        let parse_info = self::parse_info(SampleFormat::IP | SampleFormat::CALLCHAIN);
+        let record =
+            RawEventRecord::new(RecordType::SAMPLE, 0, RawData::from(&data[..]), parse_info);
+        assert_eq!(
+            SampleExtraFields::parse(&record, false).unwrap().weight,
+            None
+        );
+
+        let parse_info = self::parse_info(
+            SampleFormat::IP
+                | SampleFormat::CALLCHAIN
+                | SampleFormat::WEIGHT
+                | SampleFormat::DATA_SRC,
+        );
+        data.extend_from_slice(&0x1_4202u64.to_le_bytes());
+        let record =
+            RawEventRecord::new(RecordType::SAMPLE, 0, RawData::from(&data[..]), parse_info);
+        let fields = SampleExtraFields::parse(&record, false).unwrap();
+        assert_eq!(fields.weight, Some(250));
+        assert_eq!(fields.data_src, Some(MemDataSource(0x1_4202)));
+
+        // A corrupt callchain length makes the record malformed.
+        let parse_info =
+            self::parse_info(SampleFormat::IP | SampleFormat::CALLCHAIN | SampleFormat::WEIGHT);
+        data[8..16].copy_from_slice(&(u64::MAX / 4).to_le_bytes());
+        let record =
+            RawEventRecord::new(RecordType::SAMPLE, 0, RawData::from(&data[..]), parse_info);
+        assert_eq!(SampleExtraFields::parse(&record, false), None);
+    }
+
+    #[test]
+    fn branch_stack() {
+        let parse_info =
+            parse_info(SampleFormat::IP | SampleFormat::BRANCH_STACK | SampleFormat::WEIGHT);
+        let mut data = vec![];
+        data.extend_from_slice(&0x1000u64.to_le_bytes());
+        data.extend_from_slice(&2u64.to_le_bytes()); // nr
+        for (from, to, flags) in [(0x2010u64, 0x3000u64, 1u64), (0x1020, 0x2000, 0)] {
+            data.extend_from_slice(&from.to_le_bytes());
+            data.extend_from_slice(&to.to_le_bytes());
+            data.extend_from_slice(&flags.to_le_bytes());
+        }
+        data.extend_from_slice(&250u64.to_le_bytes());
+        let record =
+            RawEventRecord::new(RecordType::SAMPLE, 0, RawData::from(&data[..]), parse_info);
+        let fields = SampleExtraFields::parse(&record, false).unwrap();
+        assert_eq!(fields.weight, Some(250));
+        let branch_stack = fields.branch_stack.unwrap();
+        assert!(!branch_stack.is_call_stack);
+        assert_eq!(
+            branch_stack.entries,
+            [
+                BranchEntry::new(0x2010, 0x3000, 1, false),
+                BranchEntry::new(0x1020, 0x2000, 0, false),
+            ]
+        );
+        assert!(branch_stack.entries[0].mispredicted);
+    }
+
+    #[test]
+    fn group_read_values() {
+        let parse_info = parse_info_with_read_format(
+            SampleFormat::IP | SampleFormat::READ | SampleFormat::WEIGHT,
+            ReadFormat::GROUP
+                | ReadFormat::ID
+                | ReadFormat::TOTAL_TIME_ENABLED
+                | ReadFormat::TOTAL_TIME_RUNNING,
+        );
+        let mut data = vec![];
+        data.extend_from_slice(&0x1000u64.to_le_bytes());
+        data.extend_from_slice(&2u64.to_le_bytes()); // nr
+        data.extend_from_slice(&4000u64.to_le_bytes()); // time_enabled
+        data.extend_from_slice(&1000u64.to_le_bytes()); // time_running
+        data.extend_from_slice(&20000u64.to_le_bytes());
+        data.extend_from_slice(&7u64.to_le_bytes());
+        data.extend_from_slice(&31234u64.to_le_bytes());
+        data.extend_from_slice(&8u64.to_le_bytes());
+        data.extend_from_slice(&250u64.to_le_bytes());
+        let record =
+            RawEventRecord::new(RecordType::SAMPLE, 0, RawData::from(&data[..]), parse_info);
+        let fields = SampleExtraFields::parse(&record, false).unwrap();
+        assert_eq!(fields.weight, Some(250));
+        let read_values = fields.read_values.unwrap();
+        assert_eq!(
+            read_values.values,
+            [
+                SampleReadValue {
+                    value: 20000,
+                    id: Some(7),
+                    lost: None,
+                },
+                SampleReadValue {
+                    value: 31234,
+                    id: Some(8),
+                    lost: None,
+                },
+            ]
+        );
+        assert_eq!(read_values.scaled(31234), 124936);
+
+        let parse_info = parse_info_with_read_format(
+            SampleFormat::IP | SampleFormat::READ,
+            ReadFormat::TOTAL_TIME_ENABLED | ReadFormat::ID,
+        );
+        let mut data = vec![];
+        data.extend_from_slice(&0x1000u64.to_le_bytes());
+        data.extend_from_slice(&500u64.to_le_bytes()); // value
+        data.extend_from_slice(&4000u64.to_le_bytes()); // time_enabled
+        data.extend_from_slice(&7u64.to_le_bytes()); // id
+        let record =
+            RawEventRecord::new(RecordType::SAMPLE, 0, RawData::from(&data[..]), parse_info);
+        let read_values = SampleExtraFields::parse(&record, false)
+            .unwrap()
+            .read_values
+            .unwrap();
+        assert_eq!(read_values.time_enabled, Some(4000));
+        assert_eq!(read_values.scaled(500), 500);
+        assert_eq!(
+            read_values.values,
+            [SampleReadValue {
+                value: 500,
+                id: Some(7),
+                lost: None,
+            }]
+        );
+    }
+
+    #[test]
+    fn group_read_values_with_lost_counts() {
+        // PERF_FORMAT_LOST is only in the raw attr; ReadFormat drops it.
+        let mut attr_bytes = [0u8; 128];
+        let read_format = ReadFormat::GROUP | ReadFormat::ID;
+        attr_bytes[32..40].copy_from_slice(&(read_format.bits() | PERF_FORMAT_LOST).to_le_bytes());
+        assert!(attr_read_format_has_lost(
+            &attr_bytes,
+            Endianness::LittleEndian
+        ));
+        assert!(!attr_read_format_has_lost(
+            &attr_bytes,
+            Endianness::BigEndian
+        ));
+
+        let parse_info = parse_info_with_read_format(
+            SampleFormat::IP | SampleFormat::READ | SampleFormat::CALLCHAIN | SampleFormat::WEIGHT,
+            read_format,
+        );
+        let mut data = vec![];
+        data.extend_from_slice(&0x1000u64.to_le_bytes());
+        data.extend_from_slice(&2u64.to_le_bytes()); // nr
+        for (value, id, lost) in [(20000u64, 7u64, 3u64), (31234, 8, 0)] {
+            data.extend_from_slice(&value.to_le_bytes());
+            data.extend_from_slice(&id.to_le_bytes());
+            data.extend_from_slice(&lost.to_le_bytes());
+        }
+        data.extend_from_slice(&1u64.to_le_bytes()); // callchain length
+        data.extend_from_slice(&0x2000u64.to_le_bytes());
+        data.extend_from_slice(&250u64.to_le_bytes());
+        let record =
+            RawEventRecord::new(RecordType::SAMPLE, 0, RawData::from(&data[..]), parse_info);
+        let fields = SampleExtraFields::parse(&record, true).unwrap();
+        assert_eq!(fields.weight, Some(250));
+        assert_eq!(
+            fields.read_values.unwrap().values,
+            [
+                SampleReadValue {
+                    value: 20000,
+                    id: Some(7),
+                    lost: Some(3),
+                },
+                SampleReadValue {
+                    value: 31234,
+                    id: Some(8),
+                    lost: Some(0),
+                },
+            ]
+        );
+
+        // Without the flag, the lost counts shift all later fields.
+        let fields = SampleExtraFields::parse(&record, false);
+        assert_ne!(fields.and_then(|fields| fields.weight), Some(250));
+    }
+}
@@ -0,0 +1,102 @@
+use std::fmt::Debug;
+
+use byteorder::ByteOrder;
+use linux_perf_data::{linux_perf_event_reader, Endianness};
+use linux_perf_event_reader::RawData;
+
+/// The payload of the `sched:sched_waking` and `sched:sched_wakeup` tracepoints.
+/// Both tracepoints share the same format.
+///
+/// ```
+/// # cat /sys/kernel/debug/tracing/events/sched/sched_waking/format
+/// name: sched_waking
+/// ID: 318
+/// format:
+///         field:unsigned short common_type;       offset:0;       size:2; signed:0;
+///         field:unsigned char common_flags;       offset:2;       size:1; signed:0;
+///         field:unsigned char common_preempt_count;       offset:3;       size:1; signed:0;
+///         field:int common_pid;   offset:4;       size:4; signed:1;
+///
+///         field:char comm[16];    offset:8;       size:16;        signed:0;
+///         field:pid_t pid;        offset:24;      size:4; signed:1;
+///         field:int prio; offset:28;      size:4; signed:1;
+///         field:int target_cpu;   offset:32;      size:4; signed:1;
+///
+/// print fmt: "comm=%s pid=%d prio=%d target_cpu=%03d", REC->comm, REC->pid, REC->prio, REC->target_cpu
+/// ```
+#[allow(dead_code)]
+#[derive(Debug, Clone)]
+pub struct SchedWakeup {
+    pub common_type: u16,
+    pub common_flags: u8,
+    pub common_preempt_count: u8,
+    /// The tid of the waking thread.
+    pub common_pid: i32,
+    /// The name of the thread that is being woken up.
+    pub comm: String,
+    /// The tid of the thread that is being woken up.
+    pub pid: i32,
+    pub prio: i32,
+    pub target_cpu: i32,
+}
+
+impl SchedWakeup {
+    pub fn parse(data: RawData, endian: Endianness) -> Result<Self, std::io::Error> {
+        match endian {
+            Endianness::LittleEndian => Self::parse_impl::<byteorder::LittleEndian>(data),
+            Endianness::BigEndian => Self::parse_impl::<byteorder::BigEndian>(data),
+        }
+    }
+
+    pub fn parse_impl<O: ByteOrder>(mut data: RawData) -> Result<Self, std::io::Error> {
+        let common_type = data.read_u16::<O>()?;
+        let common_flags = data.read_u8()?;
+        let common_preempt_count = data.read_u8()?;
+        let common_pid = data.read_i32::<O>()?;
+        let mut comm = [0; 16];
+        data.read_exact(&mut comm)?;
+        let comm_len = comm.iter().position(|b| *b == 0).unwrap_or(comm.len());
+        let comm = String::from_utf8_lossy(&comm[..comm_len]).into_owned();
+        let pid = data.read_i32::<O>()?;
+        let prio = data.read_i32::<O>()?;
+        let target_cpu = data.read_i32::<O>()?;
+        Ok(SchedWakeup {
+            common_type,
+            common_flags,
+            common_preempt_count,
+            common_pid,
+            comm,
+            pid,
+            prio,
+            target_cpu,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_sched_waking() {
+        let mut bytes = vec![];
+        bytes.extend_from_slice(&318u16.to_le_bytes());
+        bytes.extend_from_slice(&[0x1, 0x0]);
+        bytes.extend_from_slice(&1234i32.to_le_bytes());
+        bytes.extend_from_slice(b"worker-thread\0\0\0");
+        bytes.extend_from_slice(&5678i32.to_le_bytes());
+        bytes.extend_from_slice(&120i32.to_le_bytes());
+        bytes.extend_from_slice(&3i32.to_le_bytes());
+
+        let wakeup = SchedWakeup::parse(RawData::Single(&bytes), Endianness::LittleEndian).unwrap();
+        assert_eq!(wakeup.common_pid, 1234);
+        assert_eq!(wakeup.comm, "worker-thread");
+        assert_eq!(wakeup.pid, 5678);
+        assert_eq!(wakeup.prio, 120);
+        assert_eq!(wakeup.target_cpu, 3);
+
+        assert!(
+            SchedWakeup::parse(RawData::Single(&bytes[..20]), Endianness::LittleEndian).is_err()
+        );
+    }
+}
@@ -1,6 +1,6 @@
 use std::fmt::Debug;
 
-use object::{Object, ObjectSection, ObjectSegment, SectionKind};
+use object::{Object, ObjectSection, ObjectSegment, SectionKind, SegmentFlags};
 
 // A file range in an object file, such as a segment or a section,
 // for which we know the corresponding Stated Virtual Memory Address (SVMA).
@@ -9,16 +9,24 @@ pub struct SvmaFileRange {
     pub svma: u64,
     pub file_offset: u64,
     pub size: u64,
+    /// Whether this range is mapped as executable, i.e. whether it's a
+    /// `PF_X` segment or a text section.
+    pub is_executable: bool,
 }
 
 impl SvmaFileRange {
     pub fn from_segment<'data, S: ObjectSegment<'data>>(segment: S) -> Self {
         let svma = segment.address();
         let (file_offset, size) = segment.file_range();
+        let is_executable = matches!(
+            segment.flags(),
+            SegmentFlags::Elf { p_flags } if p_flags & object::elf::PF_X != 0
+        );
         SvmaFileRange {
             svma,
             file_offset,
             size,
+            is_executable,
         }
     }
 
@@ -29,6 +37,7 @@ impl SvmaFileRange {
             svma,
             file_offset,
             size,
+            is_executable: section.kind() == SectionKind::Text,
         })
     }
 
@@ -47,6 +56,10 @@ impl SvmaFileRange {
         let other_file_range_end = other_file_offset + other_file_size;
         other_file_offset <= self.file_offset && self_file_range_end <= other_file_range_end
     }
+
+    pub fn contains_file_offset(&self, file_offset: u64) -> bool {
+        self.file_offset <= file_offset && file_offset < self.file_offset + self.size
+    }
 }
 
 impl Debug for SvmaFileRange {
@@ -55,6 +68,7 @@ impl Debug for SvmaFileRange {
             .field("svma", &format!("{:#x}", &self.svma))
             .field("file_offset", &format!("{:#x}", &self.file_offset))
             .field("size", &format!("{:#x}", &self.size))
+            .field("is_executable", &self.is_executable)
             .finish()
     }
 }
@@ -125,6 +139,27 @@ impl Debug for SvmaFileRange {
 /// mapping. We also tried a solution where we just check for overlap between the segment
 /// and the mapping, but this sometimes got the wrong segment, because the mapping is
 /// larger than the segment due to alignment, and can extend into other segments.
+///
+/// If neither containment check finds a segment, e.g. because the mapping starts in the
+/// middle of one segment and ends in the middle of the next one, we fall back to the
+/// segment which contains the first mapped byte, or, if the mapping starts in a gap
+/// between segments (e.g. in the alignment padding before a segment), the first segment
+/// which starts inside the mapping.
+///
+/// The mappings we get here are executable mappings, so executable segments are
+/// checked first. This matters for position-independent executables where the
+/// executable segment shares a file page with the read-only segment before it, as
+/// is the default with lld:
+///
+/// ```plain
+/// File offset:  0x0 |--R--||--RX--|
+/// SVMA:         0x0 |--R--|            |--RX--|
+/// AVMA:  base + 0x0 |--R--|            |--RX--|
+/// ```
+///
+/// The mapping for the RX segment starts at file offset 0 because mappings are
+/// page-aligned, so it also encompasses the R segment in file offset space, even
+/// though the R segment is mapped one page lower.
 pub fn compute_vma_bias<'data, O: Object<'data>>(
     file: &O,
     mapping_start_file_offset: u64,
@@ -159,14 +194,16 @@ fn compute_vma_bias_impl(
     mapping_avma: u64,
     mapping_size: u64,
 ) -> Option<u64> {
-    // Find a contribution which either fully contains the mapping, or which is fully contained by the mapping.
-    // Linux perf simply always uses the .text section as the reference contribution.
-    let ref_contribution = if let Some(contribution) = contributions.iter().find(|contribution| {
-        contribution.encompasses_file_range(mapping_file_offset, mapping_size)
-            || contribution.is_encompassed_by_file_range(mapping_file_offset, mapping_size)
-    }) {
-        contribution
-    } else {
+    let (executable_contributions, other_contributions): (Vec<_>, Vec<_>) = contributions
+        .iter()
+        .cloned()
+        .partition(|contribution| contribution.is_executable);
+    let Some(ref_contribution) =
+        find_reference_contribution(&executable_contributions, mapping_file_offset, mapping_size)
+            .or_else(|| {
+                find_reference_contribution(&other_contributions, mapping_file_offset, mapping_size)
+            })
+    else {
         println!(
             "Could not find segment or section overlapping the file offset range 0x{:x}..0x{:x}",
             mapping_file_offset,
@@ -187,6 +224,35 @@ fn compute_vma_bias_impl(
     Some(bias)
 }
 
+fn find_reference_contribution(
+    contributions: &[SvmaFileRange],
+    mapping_file_offset: u64,
+    mapping_size: u64,
+) -> Option<&SvmaFileRange> {
+    // Find a contribution which either fully contains the mapping, or which is fully contained by the mapping.
+    // Linux perf simply always uses the .text section as the reference contribution.
+    contributions
+        .iter()
+        .find(|contribution| {
+            contribution.encompasses_file_range(mapping_file_offset, mapping_size)
+                || contribution.is_encompassed_by_file_range(mapping_file_offset, mapping_size)
+        })
+        .or_else(|| {
+            contributions
+                .iter()
+                .find(|contribution| contribution.contains_file_offset(mapping_file_offset))
+        })
+        .or_else(|| {
+            contributions
+                .iter()
+                .filter(|contribution| {
+                    mapping_file_offset <= contribution.file_offset
+                        && contribution.file_offset < mapping_file_offset + mapping_size
+                })
+                .min_by_key(|contribution| contribution.file_offset)
+        })
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -199,21 +265,25 @@ mod test {
                 svma: 0x0,
                 file_offset: 0x0,
                 size: 0x14bd0bc,
+                is_executable: false,
             },
             SvmaFileRange {
                 svma: 0x14be0c0,
                 file_offset: 0x14bd0c0,
                 size: 0xf5bf60,
+                is_executable: true,
             },
             SvmaFileRange {
                 svma: 0x241b020,
                 file_offset: 0x2419020,
                 size: 0x08e920,
+                is_executable: false,
             },
             SvmaFileRange {
                 svma: 0x24aa940,
                 file_offset: 0x24a7940,
                 size: 0x002d48,
+                is_executable: false,
             },
         ];
         assert_eq!(
@@ -231,21 +301,25 @@ mod test {
                 svma: 0x0,
                 file_offset: 0x0,
                 size: 0x3c8ed8,
+                is_executable: false,
             },
             SvmaFileRange {
                 svma: 0x03ca000,
                 file_offset: 0x3c9000,
                 size: 0xfec770,
+                is_executable: true,
             },
             SvmaFileRange {
                 svma: 0x13b7770,
                 file_offset: 0x13b5770,
                 size: 0x0528d0,
+                is_executable: false,
             },
             SvmaFileRange {
                 svma: 0x140c000,
                 file_offset: 0x1409000,
                 size: 0x0118f0,
+                is_executable: false,
             },
         ];
         assert_eq!(
@@ -253,4 +327,89 @@ mod test {
             Some(0x55d15ee29000)
         );
     }
+
+    #[test]
+    fn test_compute_base_avma_impl_partial_overlap() {
+        let segments = &[
+            SvmaFileRange {
+                svma: 0x0,
+                file_offset: 0x0,
+                size: 0x1000,
+                is_executable: false,
+            },
+            SvmaFileRange {
+                svma: 0x201000,
+                file_offset: 0x1000,
+                size: 0x5000,
+                is_executable: true,
+            },
+            SvmaFileRange {
+                svma: 0x407000,
+                file_offset: 0x6000,
+                size: 0x1000,
+                is_executable: false,
+            },
+        ];
+        // The mapping starts in the middle of the second segment and ends in the
+        // middle of the third one, so neither segment contains the other.
+        assert_eq!(
+            compute_vma_bias_impl(segments, 0x3000, 0x7f0000003000, 0x3800),
+            Some(0x7effffe00000)
+        );
+        // The mapping starts in the alignment padding before a segment.
+        let padded_segments = &[SvmaFileRange {
+            svma: 0x201100,
+            file_offset: 0x1100,
+            size: 0x5000,
+            is_executable: true,
+        }];
+        assert_eq!(
+            compute_vma_bias_impl(padded_segments, 0x1000, 0x7f0000001000, 0x2000),
+            Some(0x7effffe00000)
+        );
+    }
+
+    #[test]
+    fn test_compute_base_avma_impl_pie_with_shared_file_page() {
+        // A PIE linked by lld, with the R, RX and RW segments packed into
+        // consecutive file pages, mapped at a random base.
+        let pie_segments = &[
+            SvmaFileRange {
+                svma: 0x0,
+                file_offset: 0x0,
+                size: 0x5b4,
+                is_executable: false,
+            },
+            SvmaFileRange {
+                svma: 0x15c0,
+                file_offset: 0x5c0,
+                size: 0x1a0,
+                is_executable: true,
+            },
+            SvmaFileRange {
+                svma: 0x2760,
+                file_offset: 0x760,
+                size: 0x1f0,
+                is_executable: false,
+            },
+        ];
+        // The RX mapping starts at file offset 0, which is also the start of the
+        // R segment.
+        assert_eq!(
+            compute_vma_bias_impl(pie_segments, 0x0, 0x5612a3e01000, 0x1000),
+            Some(0x5612a3e00000)
+        );
+        // Without the executable flag, the R segment would be picked.
+        let non_executable_segments: Vec<_> = pie_segments
+            .iter()
+            .map(|segment| SvmaFileRange {
+                is_executable: false,
+                ..segment.clone()
+            })
+            .collect();
+        assert_eq!(
+            compute_vma_bias_impl(&non_executable_segments, 0x0, 0x5612a3e01000, 0x1000),
+            Some(0x5612a3e01000)
+        );
+    }
 }
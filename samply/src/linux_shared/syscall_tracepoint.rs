@@ -0,0 +1,250 @@
+use std::fmt::Write;
+
+use byteorder::ByteOrder;
+use linux_perf_data::{linux_perf_event_reader, Endianness};
+use linux_perf_event_reader::RawData;
+
+/// How a syscall argument is displayed in the marker.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ArgFormat {
+    /// A signed integer, e.g. a byte count.
+    Int,
+    /// A file descriptor. `AT_FDCWD` is shown by name.
+    Fd,
+    /// Flags or other bit masks.
+    Hex,
+    /// File modes.
+    Octal,
+    /// A pointer into user memory. The tracepoint only has the address, not the
+    /// pointed-to data, so for example file names can't be shown.
+    Pointer,
+}
+
+use ArgFormat::*;
+
+/// The arguments of the `syscalls:sys_enter_<name>` tracepoints which we decode,
+/// in the order of the tracepoint fields. The `syscalls:sys_exit_<name>`
+/// tracepoints of the same syscalls are decoded too; they only have the return value.
+///
+/// ```
+/// # cat /sys/kernel/debug/tracing/events/syscalls/sys_enter_openat/format
+/// name: sys_enter_openat
+/// ID: 638
+/// format:
+///         field:unsigned short common_type;       offset:0;       size:2; signed:0;
+///         field:unsigned char common_flags;       offset:2;       size:1; signed:0;
+///         field:unsigned char common_preempt_count;       offset:3;       size:1; signed:0;
+///         field:int common_pid;   offset:4;       size:4; signed:1;
+///
+///         field:int __syscall_nr; offset:8;       size:4; signed:1;
+///         field:int dfd;  offset:16;      size:8; signed:0;
+///         field:const char * filename;    offset:24;      size:8; signed:0;
+///         field:int flags;        offset:32;      size:8; signed:0;
+///         field:umode_t mode;     offset:40;      size:8; signed:0;
+/// ```
+const SYSCALL_LAYOUTS: &[(&str, &[(&str, ArgFormat)])] = &[
+    (
+        "openat",
+        &[
+            ("dfd", Fd),
+            ("filename", Pointer),
+            ("flags", Hex),
+            ("mode", Octal),
+        ],
+    ),
+    (
+        "open",
+        &[("filename", Pointer), ("flags", Hex), ("mode", Octal)],
+    ),
+    ("close", &[("fd", Fd)]),
+    ("read", &[("fd", Fd), ("buf", Pointer), ("count", Int)]),
+    ("write", &[("fd", Fd), ("buf", Pointer), ("count", Int)]),
+    (
+        "pread64",
+        &[("fd", Fd), ("buf", Pointer), ("count", Int), ("pos", Int)],
+    ),
+    (
+        "pwrite64",
+        &[("fd", Fd), ("buf", Pointer), ("count", Int), ("pos", Int)],
+    ),
+    ("lseek", &[("fd", Fd), ("offset", Int), ("whence", Int)]),
+    ("munmap", &[("addr", Pointer), ("len", Hex)]),
+    ("fsync", &[("fd", Fd)]),
+    ("fdatasync", &[("fd", Fd)]),
+    ("ioctl", &[("fd", Fd), ("cmd", Hex), ("arg", Hex)]),
+    ("dup", &[("fildes", Fd)]),
+    ("dup2", &[("oldfd", Fd), ("newfd", Fd)]),
+    ("dup3", &[("oldfd", Fd), ("newfd", Fd), ("flags", Hex)]),
+    ("pipe2", &[("fildes", Pointer), ("flags", Hex)]),
+    ("eventfd2", &[("count", Int), ("flags", Hex)]),
+    (
+        "socket",
+        &[("family", Int), ("type", Hex), ("protocol", Int)],
+    ),
+    (
+        "connect",
+        &[("fd", Fd), ("uservaddr", Pointer), ("addrlen", Int)],
+    ),
+    (
+        "accept4",
+        &[
+            ("fd", Fd),
+            ("upeer_sockaddr", Pointer),
+            ("upeer_addrlen", Pointer),
+            ("flags", Hex),
+        ],
+    ),
+];
+
+const AT_FDCWD: i32 = -100;
+
+/// Formats the arguments of a syscall tracepoint, e.g. `fd=3` for
+/// `syscalls:sys_enter_close`, or `ret=3` for `syscalls:sys_exit_openat`.
+/// Returns `None` for tracepoints which aren't in [`SYSCALL_LAYOUTS`].
+pub fn format_syscall_tracepoint(
+    event_name: &str,
+    data: RawData,
+    endian: Endianness,
+) -> Option<String> {
+    match endian {
+        Endianness::LittleEndian => {
+            format_syscall_tracepoint_impl::<byteorder::LittleEndian>(event_name, data)
+        }
+        Endianness::BigEndian => {
+            format_syscall_tracepoint_impl::<byteorder::BigEndian>(event_name, data)
+        }
+    }
+}
+
+/// Returns the address and length of the unmapped range for a
+/// `syscalls:sys_enter_munmap` tracepoint.
+pub fn parse_munmap_tracepoint(
+    event_name: &str,
+    mut data: RawData,
+    endian: Endianness,
+) -> Option<(u64, u64)> {
+    if event_name != "syscalls:sys_enter_munmap" {
+        return None;
+    }
+    data.skip(16).ok()?;
+    match endian {
+        Endianness::LittleEndian => Some((
+            data.read_u64::<byteorder::LittleEndian>().ok()?,
+            data.read_u64::<byteorder::LittleEndian>().ok()?,
+        )),
+        Endianness::BigEndian => Some((
+            data.read_u64::<byteorder::BigEndian>().ok()?,
+            data.read_u64::<byteorder::BigEndian>().ok()?,
+        )),
+    }
+}
+
+fn format_syscall_tracepoint_impl<O: ByteOrder>(
+    event_name: &str,
+    mut data: RawData,
+) -> Option<String> {
+    let syscall = event_name.strip_prefix("syscalls:sys_")?;
+    let (is_enter, syscall) = match syscall.split_once('_')? {
+        ("enter", syscall) => (true, syscall),
+        ("exit", syscall) => (false, syscall),
+        _ => return None,
+    };
+    let (_, args) = SYSCALL_LAYOUTS.iter().find(|(name, _)| *name == syscall)?;
+
+    // Skip the common fields and __syscall_nr, which is padded to 8 bytes.
+    data.skip(16).ok()?;
+    if !is_enter {
+        let ret = data.read_u64::<O>().ok()? as i64;
+        return Some(format!("ret={ret}"));
+    }
+
+    let mut s = String::new();
+    for (i, (arg_name, format)) in args.iter().enumerate() {
+        let value = data.read_u64::<O>().ok()?;
+        if i != 0 {
+            s.push_str(", ");
+        }
+        match format {
+            Int => write!(s, "{arg_name}={}", value as i64),
+            Fd if value as i32 == AT_FDCWD => write!(s, "{arg_name}=AT_FDCWD"),
+            Fd => write!(s, "{arg_name}={}", value as i32),
+            Hex | Pointer => write!(s, "{arg_name}={value:#x}"),
+            Octal => write!(s, "{arg_name}={value:#o}"),
+        }
+        .unwrap();
+    }
+    Some(s)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn tracepoint_data(args: &[u64]) -> Vec<u8> {
+        let mut data = vec![0x7e, 0x02, 0, 0];
+        data.extend_from_slice(&1234i32.to_le_bytes());
+        data.extend_from_slice(&257i32.to_le_bytes());
+        data.extend_from_slice(&[0; 4]);
+        for arg in args {
+            data.extend_from_slice(&arg.to_le_bytes());
+        }
+        data
+    }
+
+    #[test]
+    fn openat() {
+        let data = tracepoint_data(&[(-100i64) as u64, 0x7ffd1234, 0x80000, 0o644]);
+        assert_eq!(
+            format_syscall_tracepoint(
+                "syscalls:sys_enter_openat",
+                RawData::Single(&data),
+                Endianness::LittleEndian
+            )
+            .as_deref(),
+            Some("dfd=AT_FDCWD, filename=0x7ffd1234, flags=0x80000, mode=0o644")
+        );
+
+        let data = tracepoint_data(&[3]);
+        assert_eq!(
+            format_syscall_tracepoint(
+                "syscalls:sys_exit_openat",
+                RawData::Single(&data),
+                Endianness::LittleEndian
+            )
+            .as_deref(),
+            Some("ret=3")
+        );
+    }
+
+    #[test]
+    fn munmap() {
+        let data = tracepoint_data(&[0x7f00_0000_0000, 0x5000]);
+        assert_eq!(
+            parse_munmap_tracepoint(
+                "syscalls:sys_enter_munmap",
+                RawData::Single(&data),
+                Endianness::LittleEndian
+            ),
+            Some((0x7f00_0000_0000, 0x5000))
+        );
+        assert_eq!(
+            parse_munmap_tracepoint(
+                "syscalls:sys_enter_close",
+                RawData::Single(&data),
+                Endianness::LittleEndian
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn unknown_tracepoints() {
+        let data = tracepoint_data(&[1, 2, 3]);
+        for name in ["syscalls:sys_enter_futex", "sched:sched_switch", "cycles"] {
+            assert_eq!(
+                format_syscall_tracepoint(name, RawData::Single(&data), Endianness::LittleEndian),
+                None
+            );
+        }
+    }
+}
@@ -0,0 +1,179 @@
+use byteorder::ByteOrder;
+use fxprof_processed_profile::{
+    CategoryHandle, MarkerFieldFormat, MarkerFieldSchema, MarkerLocation, MarkerSchema,
+    MarkerStaticField, Profile, StaticSchemaMarker, StringHandle,
+};
+use linux_perf_data::{linux_perf_event_reader, Endianness};
+use linux_perf_event_reader::RawData;
+
+/// A modification of kernel code, from a `PERF_RECORD_TEXT_POKE` record.
+///
+/// The kernel emits these records when it patches its own code at runtime,
+/// e.g. for static keys, ftrace, kprobes and live patches. The bytes in the
+/// vmlinux image are only correct for the time before the first poke.
+///
+/// ```c
+/// struct {
+///     struct perf_event_header header;
+///     u64 addr;
+///     u16 old_len;
+///     u16 new_len;
+///     u8 bytes[];
+///     struct sample_id sample_id;
+/// };
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextPoke {
+    /// The kernel address of the first modified byte.
+    pub address: u64,
+    pub old_bytes: Vec<u8>,
+    pub new_bytes: Vec<u8>,
+}
+
+impl TextPoke {
+    pub fn parse(data: RawData, endian: Endianness) -> Result<Self, std::io::Error> {
+        match endian {
+            Endianness::LittleEndian => Self::parse_impl::<byteorder::LittleEndian>(data),
+            Endianness::BigEndian => Self::parse_impl::<byteorder::BigEndian>(data),
+        }
+    }
+
+    fn parse_impl<O: ByteOrder>(mut data: RawData) -> Result<Self, std::io::Error> {
+        let address = data.read_u64::<O>()?;
+        let old_len = data.read_u16::<O>()?;
+        let new_len = data.read_u16::<O>()?;
+        let mut old_bytes = vec![0; usize::from(old_len)];
+        data.read_exact(&mut old_bytes)?;
+        let mut new_bytes = vec![0; usize::from(new_len)];
+        data.read_exact(&mut new_bytes)?;
+        Ok(Self {
+            address,
+            old_bytes,
+            new_bytes,
+        })
+    }
+}
+
+/// A marker for a [`TextPoke`], on the thread which modified the code.
+///
+/// The old and new bytes are included so that the kernel code at the time of
+/// a sample can be reconstructed from the vmlinux image, e.g. for an assembly
+/// view.
+#[derive(Debug, Clone)]
+pub struct TextPokeMarker {
+    /// The kernel address, formatted as hex. Kernel addresses don't fit into
+    /// the f64 of a number field.
+    pub address: StringHandle,
+    pub old_bytes: StringHandle,
+    pub new_bytes: StringHandle,
+}
+
+impl TextPokeMarker {
+    pub fn new(poke: &TextPoke, profile: &mut Profile) -> Self {
+        Self {
+            address: profile.intern_string(&format!("{:#x}", poke.address)),
+            old_bytes: profile.intern_string(&hex_bytes(&poke.old_bytes)),
+            new_bytes: profile.intern_string(&hex_bytes(&poke.new_bytes)),
+        }
+    }
+}
+
+fn hex_bytes(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+impl StaticSchemaMarker for TextPokeMarker {
+    const UNIQUE_MARKER_TYPE_NAME: &'static str = "TextPoke";
+
+    fn schema() -> MarkerSchema {
+        MarkerSchema {
+            type_name: Self::UNIQUE_MARKER_TYPE_NAME.into(),
+            locations: vec![MarkerLocation::MarkerChart, MarkerLocation::MarkerTable],
+            chart_label: Some("{marker.data.address}".into()),
+            tooltip_label: Some("{marker.name} - {marker.data.address}".into()),
+            table_label: Some(
+                "{marker.name} - {marker.data.address}: {marker.data.oldBytes} -> {marker.data.newBytes}"
+                    .into(),
+            ),
+            fields: vec![
+                MarkerFieldSchema {
+                    key: "address".into(),
+                    label: "Address".into(),
+                    format: MarkerFieldFormat::String,
+                    searchable: true,
+                },
+                MarkerFieldSchema {
+                    key: "oldBytes".into(),
+                    label: "Old bytes".into(),
+                    format: MarkerFieldFormat::String,
+                    searchable: false,
+                },
+                MarkerFieldSchema {
+                    key: "newBytes".into(),
+                    label: "New bytes".into(),
+                    format: MarkerFieldFormat::String,
+                    searchable: false,
+                },
+            ],
+            static_fields: vec![MarkerStaticField {
+                label: "Description".into(),
+                value: "Emitted when the kernel modified its own code, e.g. for static keys, ftrace or live patches.".into(),
+            }],
+        }
+    }
+
+    fn name(&self, profile: &mut Profile) -> StringHandle {
+        profile.intern_string("Text poke")
+    }
+
+    fn category(&self, _profile: &mut Profile) -> CategoryHandle {
+        CategoryHandle::OTHER
+    }
+
+    fn string_field_value(&self, field_index: u32) -> StringHandle {
+        match field_index {
+            0 => self.address,
+            1 => self.old_bytes,
+            2 => self.new_bytes,
+            _ => unreachable!(),
+        }
+    }
+
+    fn number_field_value(&self, _field_index: u32) -> f64 {
+        unreachable!()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_into_marker() {
+        let mut data = Vec::new();
+        data.extend_from_slice(&0xffff_ffff_8100_1002u64.to_le_bytes());
+        data.extend_from_slice(&5u16.to_le_bytes());
+        data.extend_from_slice(&5u16.to_le_bytes());
+        data.extend_from_slice(&[0x0f, 0x1f, 0x44, 0x00, 0x00]); // nopl
+        data.extend_from_slice(&[0xe9, 0x10, 0x20, 0x30, 0x40]); // jmp
+        data.extend_from_slice(&[0; 6]); // padding
+        let poke = TextPoke::parse(RawData::Single(&data), Endianness::LittleEndian).unwrap();
+        assert_eq!(poke.address, 0xffff_ffff_8100_1002);
+        assert_eq!(poke.old_bytes, [0x0f, 0x1f, 0x44, 0x00, 0x00]);
+        assert_eq!(poke.new_bytes, [0xe9, 0x10, 0x20, 0x30, 0x40]);
+
+        let mut profile = Profile::new(
+            "test",
+            fxprof_processed_profile::ReferenceTimestamp::from_millis_since_unix_epoch(0.0),
+            fxprof_processed_profile::SamplingInterval::from_millis(1),
+        );
+        let marker = TextPokeMarker::new(&poke, &mut profile);
+        assert_eq!(marker.address, profile.intern_string("0xffffffff81001002"));
+        assert_eq!(marker.old_bytes, profile.intern_string("0f 1f 44 00 00"));
+        assert_eq!(marker.new_bytes, profile.intern_string("e9 10 20 30 40"));
+    }
+}
@@ -10,11 +10,17 @@ pub struct Thread {
     pub profile_thread: ThreadHandle,
     pub context_switch_data: ThreadContextSwitchData,
     pub last_sample_timestamp: Option<u64>,
+    /// The attr index and the stack of the last sample, used together with
+    /// `last_sample_timestamp` to detect duplicate samples.
+    pub last_sample_attr_index_and_stack: Option<(usize, UnresolvedStackHandle)>,
 
     /// Some() between sched_switch and the next context switch IN
     ///
     /// Refers to a stack in the containing Process's UnresolvedSamples stack table.
     pub off_cpu_stack: Option<UnresolvedStackHandle>,
+    /// The number of the syscall the thread is in, between raw_syscalls:sys_enter
+    /// and raw_syscalls:sys_exit. Only tracked if off-CPU syscall labels are enabled.
+    pub current_syscall: Option<i64>,
     pub name: Option<String>,
     pub thread_label_frame: FrameInfo,
 }
@@ -29,7 +35,9 @@ impl Thread {
             profile_thread: thread_handle,
             context_switch_data: Default::default(),
             last_sample_timestamp: None,
+            last_sample_attr_index_and_stack: None,
             off_cpu_stack: None,
+            current_syscall: None,
             name,
             thread_label_frame,
         }
@@ -0,0 +1,67 @@
+use std::time::Duration;
+
+/// A range of raw perf timestamps, i.e. nanoseconds in the clock that the
+/// recording used. Events outside of this range are not added to the profile.
+///
+/// The start is inclusive and the end is exclusive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RawTimeRange {
+    start: u64,
+    end: u64,
+}
+
+impl RawTimeRange {
+    pub fn new(start: u64, end: u64) -> Self {
+        Self { start, end }
+    }
+
+    /// Creates a range from offsets relative to `reference_raw`, e.g. from
+    /// the `--time-range` command line argument. An end of `Duration::MAX`
+    /// means that the range is open-ended.
+    pub fn from_relative(reference_raw: u64, start: Duration, end: Duration) -> Self {
+        let offset = |duration: Duration| {
+            let nanos = u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX);
+            reference_raw.saturating_add(nanos)
+        };
+        Self::new(offset(start), offset(end))
+    }
+
+    pub fn contains(&self, timestamp: u64) -> bool {
+        self.start <= timestamp && timestamp < self.end
+    }
+
+    /// Whether the span from `begin` to `end` (inclusive) has any overlap with this range.
+    pub fn overlaps(&self, begin: u64, end: u64) -> bool {
+        begin < self.end && self.start <= end
+    }
+
+    /// Moves `timestamp` into the range, e.g. for the start time of a thread
+    /// which was created before the range.
+    pub fn clamp(&self, timestamp: u64) -> u64 {
+        timestamp.clamp(self.start, self.end.max(self.start))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn relative_range() {
+        let range = RawTimeRange::from_relative(
+            1_000_000,
+            Duration::from_millis(1),
+            Duration::from_millis(3),
+        );
+        assert!(!range.contains(1_999_999));
+        assert!(range.contains(2_000_000));
+        assert!(!range.contains(4_000_000));
+        assert!(range.overlaps(500_000, 2_500_000));
+        assert!(!range.overlaps(500_000, 1_999_999));
+        assert_eq!(range.clamp(0), 2_000_000);
+        assert_eq!(range.clamp(u64::MAX), 4_000_000);
+
+        let open_ended = RawTimeRange::from_relative(1_000_000, Duration::ZERO, Duration::MAX);
+        assert!(open_ended.contains(u64::MAX - 1));
+    }
+}
@@ -0,0 +1,75 @@
+use framehop::ExplicitModuleSectionInfo;
+
+/// Which unwind information is used for a module.
+///
+/// framehop doesn't expose the choice it makes internally, so this mirrors its
+/// selection logic based on the sections that we hand to it. This is useful for
+/// finding out why unwinding doesn't work for a particular module.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModuleUnwindSections {
+    /// `.eh_frame_hdr` and `.eh_frame`.
+    EhFrameHdrAndEhFrame,
+    /// `.eh_frame` without an `.eh_frame_hdr` index.
+    EhFrame,
+    /// `.debug_frame`.
+    DebugFrame,
+    /// No unwind information. Unwinding falls back to frame pointers.
+    None,
+}
+
+impl ModuleUnwindSections {
+    pub fn for_section_info<D>(section_info: &ExplicitModuleSectionInfo<D>) -> Self {
+        match (
+            &section_info.eh_frame,
+            &section_info.eh_frame_hdr,
+            &section_info.debug_frame,
+        ) {
+            (Some(_), Some(_), _) => ModuleUnwindSections::EhFrameHdrAndEhFrame,
+            (Some(_), None, _) => ModuleUnwindSections::EhFrame,
+            (None, _, Some(_)) => ModuleUnwindSections::DebugFrame,
+            (None, _, None) => ModuleUnwindSections::None,
+        }
+    }
+}
+
+/// Formats the sections as a `+`-separated list, e.g. `eh_frame_hdr+eh_frame`,
+/// or as `none`.
+impl std::fmt::Display for ModuleUnwindSections {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ModuleUnwindSections::EhFrameHdrAndEhFrame => "eh_frame_hdr+eh_frame",
+            ModuleUnwindSections::EhFrame => "eh_frame",
+            ModuleUnwindSections::DebugFrame => "debug_frame",
+            ModuleUnwindSections::None => "none",
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn eh_frame_hdr_takes_precedence() {
+        let mut section_info = ExplicitModuleSectionInfo::<Vec<u8>> {
+            eh_frame: Some(vec![0]),
+            debug_frame: Some(vec![0]),
+            ..Default::default()
+        };
+        assert_eq!(
+            ModuleUnwindSections::for_section_info(&section_info),
+            ModuleUnwindSections::EhFrame
+        );
+        section_info.eh_frame_hdr = Some(vec![0]);
+        assert_eq!(
+            ModuleUnwindSections::for_section_info(&section_info),
+            ModuleUnwindSections::EhFrameHdrAndEhFrame
+        );
+        section_info.eh_frame = None;
+        assert_eq!(
+            ModuleUnwindSections::for_section_info(&section_info),
+            ModuleUnwindSections::DebugFrame
+        );
+        assert_eq!(ModuleUnwindSections::DebugFrame.to_string(), "debug_frame");
+    }
+}
@@ -1,3 +1,5 @@
+use std::path::{Path, PathBuf};
+
 use object::Object;
 use once_cell::sync::OnceCell;
 use wholesym::{CodeId, ElfBuildId};
@@ -70,3 +72,46 @@ impl VdsoObject {
         self.build_id
     }
 }
+
+/// Returns the path of a saved VDSO image with the given build ID, if one can be
+/// found in a perf build-id cache.
+///
+/// `perf record` stores the VDSO image of the profiled processes in its build-id
+/// cache, usually at `~/.debug/[vdso]/<build-id>/vdso`, with a symlink from
+/// `~/.debug/.build-id/<xx>/<rest>`. `perf archive` packs the same layout, so we
+/// also check for a `.debug` directory in each of the `extra_dirs`.
+pub fn find_vdso_image_in_build_id_cache(
+    build_id: &[u8],
+    extra_dirs: &[PathBuf],
+) -> Option<PathBuf> {
+    let build_id = ElfBuildId::from_bytes(build_id).to_string();
+    if build_id.len() <= 2 {
+        return None;
+    }
+    let (two_chars, rest) = build_id.split_at(2);
+
+    let mut cache_roots = Vec::new();
+    if let Some(perf_buildid_dir) = std::env::var_os("PERF_BUILDID_DIR") {
+        cache_roots.push(PathBuf::from(perf_buildid_dir));
+    }
+    if let Some(home) = std::env::var_os("HOME") {
+        cache_roots.push(Path::new(&home).join(".debug"));
+    }
+    cache_roots.extend(extra_dirs.iter().map(|dir| dir.join(".debug")));
+
+    cache_roots.into_iter().find_map(|root| {
+        [
+            root.join("[vdso]").join(&build_id).join("vdso"),
+            root.join(".build-id")
+                .join(two_chars)
+                .join(rest)
+                .join("vdso"),
+            root.join(".build-id")
+                .join(two_chars)
+                .join(rest)
+                .join("elf"),
+        ]
+        .into_iter()
+        .find(|path| path.is_file())
+    })
+}
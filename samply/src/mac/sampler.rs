@@ -110,7 +110,10 @@ impl Sampler {
         let mut stack_scratch_buffer = Vec::new();
         let mut live_tasks = vec![root_task];
         let mut unwinder_cache = Default::default();
-        let mut unresolved_stacks = UnresolvedStacks::default();
+        let mut unresolved_stacks = UnresolvedStacks::new(
+            self.profile_creation_props.fold_leaf_recursion,
+            &mut profile,
+        );
         let mut last_sleep_overshoot = 0;
         let mut stop_profiling = false;
 
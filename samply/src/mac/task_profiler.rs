@@ -222,7 +222,9 @@ impl TaskProfiler {
                 );
                 let (thread_recycler, jit_function_recycler) = match process_recycler {
                     Some(_) => (
-                        Some(ThreadRecycler::new()),
+                        Some(ThreadRecycler::new_merging_numbered_names(
+                            profile_creation_props.reuse_numbered_threads,
+                        )),
                         Some(JitFunctionRecycler::default()),
                     ),
                     None => (None, None),
@@ -655,7 +657,7 @@ impl TaskProfiler {
         profile: &mut Profile,
     ) -> (ProcessSampleData, Option<(String, ProcessRecyclingData)>) {
         let perf_map_mappings = if !self.unresolved_samples.is_empty() {
-            try_load_perf_map(self.pid, profile, jit_category_manager, None)
+            try_load_perf_map(self.pid, &[], profile, jit_category_manager, None)
         } else {
             None
         };
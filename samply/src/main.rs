@@ -16,11 +16,11 @@ mod shared;
 
 use std::ffi::OsStr;
 use std::fs::File;
-use std::io::BufReader;
+use std::io::{BufReader, Read};
 use std::net::IpAddr;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use clap::{Args, Parser, Subcommand, ValueEnum};
 #[cfg(any(target_os = "android", target_os = "linux"))]
@@ -33,11 +33,14 @@ pub use mac::{kernel_error, thread_act, thread_info};
 use profile_json_preparse::parse_libinfo_map_from_profile_file;
 use server::{start_server_main, PortSelection, ServerProps};
 use shared::included_processes::IncludedProcesses;
+use shared::profile_diff::{diff_function_sample_counts, FunctionSampleCounts, LibSymbols};
 use shared::recording_props::{
     CoreClrProfileProps, ProcessLaunchProps, ProfileCreationProps, RecordingMode, RecordingProps,
 };
 use shared::save_profile::save_profile_to_file;
 use shared::symbol_props::SymbolProps;
+use wholesym::debugid::DebugId;
+use wholesym::SymbolNameOverrideKey;
 #[cfg(target_os = "windows")]
 use windows::profiler;
 
@@ -64,6 +67,9 @@ EXAMPLES:
 
     # Import perf.data files from Linux perf:
     samply import perf.data
+
+    # Compare the functions' sample counts in two perf.data files:
+    samply diff before.perf.data after.perf.data
 "#
 )]
 struct Opt {
@@ -80,13 +86,17 @@ enum Action {
         target_os = "windows"
     ))]
     /// Record a profile and display it.
-    Record(RecordArgs),
+    Record(Box<RecordArgs>),
 
     /// Load a profile from a file and display it.
-    Load(LoadArgs),
+    Load(Box<LoadArgs>),
 
     /// Import a perf.data file and display the profile.
-    Import(ImportArgs),
+    Import(Box<ImportArgs>),
+
+    /// Compare the per-function sample counts of two perf.data files, and
+    /// print the functions which got hotter or colder.
+    Diff(Box<DiffArgs>),
 
     #[cfg(target_os = "windows")]
     #[clap(hide = true)]
@@ -112,7 +122,8 @@ struct LoadArgs {
 
 #[derive(Debug, Args)]
 struct ImportArgs {
-    /// Path to the profile file that should be imported.
+    /// Path to the profile file that should be imported. Use `-` to read a
+    /// perf.data stream from stdin, e.g. from `perf record -o -`.
     file: PathBuf,
 
     /// Optional extra paths to ETL files for user sessions.
@@ -135,7 +146,8 @@ struct ImportArgs {
     #[command(flatten)]
     symbol_args: SymbolArgs,
 
-    /// Additional directories to use for looking up jitdump and marker files.
+    /// Additional directories to use for looking up jitdump, marker and perf map
+    /// (perf-<pid>.map) files.
     #[arg(long)]
     aux_file_dir: Vec<PathBuf>,
 
@@ -156,12 +168,69 @@ struct ImportArgs {
     coreclr: Vec<CoreClrArgs>,
 
     /// Time range of recording to include in profile. Format is "start-stop" or "start+duration" with each part optional, e.g. "5s", "5s-", "-10s", "1s-10s" or "1s+9s".
-    #[cfg(target_os = "windows")]
     #[arg(long, value_parser=parse_time_range)]
     time_range: Option<(std::time::Duration, std::time::Duration)>,
+
+    /// Output of `perf stat -I <ms>` (plain or with `-x,`), recorded alongside the
+    /// perf.data file. Each event becomes a counter track. The interval times are
+    /// assumed to start with the first sample.
+    #[arg(long, value_name = "FILE")]
+    perf_stat_intervals: Option<PathBuf>,
+
+    /// Write an edge profile to this CSV file: how often each branch in the
+    /// samples' branch stacks (e.g. from `perf record -b` or
+    /// `--call-graph lbr`) was taken, and how often it was mispredicted.
+    #[arg(long, value_name = "FILE")]
+    branch_edges: Option<PathBuf>,
+
+    /// Write the IDs, addresses and properties of every module in the profile
+    /// to this CSV file, e.g. the debug ID, the base address and the SVMA bias,
+    /// the unwind sections which were used ("eh_frame_hdr+eh_frame") and the
+    /// control-flow protection ("ibt+shstk"). This also lists modules whose
+    /// files couldn't be found, so that their symbols can be fetched
+    /// separately.
+    #[arg(long, value_name = "FILE")]
+    module_list: Option<PathBuf>,
+
+    /// Declare this sampling interval in the profile, e.g. "2ms", instead of the
+    /// one derived from the recording. Use this if the samples in the perf.data
+    /// file were resampled or filtered. It doesn't change which samples are
+    /// imported.
+    #[arg(long, value_name = "DURATION", value_parser = humantime::parse_duration)]
+    sampling_interval: Option<std::time::Duration>,
+
+    /// When importing a perf.data stream from stdin, save a snapshot of the
+    /// profile so far to the output file at this interval, e.g. "10s", so that
+    /// it can be looked at while perf is still recording.
+    #[arg(long, value_parser = humantime::parse_duration)]
+    snapshot_interval: Option<Duration>,
+}
+
+#[derive(Debug, Args)]
+struct DiffArgs {
+    /// Path to the perf.data file of the baseline run.
+    before: PathBuf,
+
+    /// Path to the perf.data file of the run which is compared to the baseline.
+    after: PathBuf,
+
+    /// How many of the functions which got hotter, and of the functions which
+    /// got colder, to print.
+    #[arg(long, default_value = "20")]
+    limit: usize,
+
+    #[command(flatten)]
+    profile_creation_args: ProfileCreationArgs,
+
+    #[command(flatten)]
+    symbol_args: SymbolArgs,
+
+    /// Additional directories to use for looking up jitdump, marker and perf map
+    /// (perf-<pid>.map) files.
+    #[arg(long)]
+    aux_file_dir: Vec<PathBuf>,
 }
 
-#[allow(unused)]
 fn parse_time_range(
     arg: &str,
 ) -> Result<(std::time::Duration, std::time::Duration), humantime::DurationError> {
@@ -316,6 +385,11 @@ struct SymbolArgs {
     #[arg(long)]
     windows_symbol_cache: Option<PathBuf>,
 
+    /// Request lowercase file names and identifiers from Windows and Breakpad
+    /// symbol servers, for symbol stores which normalize their paths to lowercase
+    #[arg(long)]
+    lowercase_symbol_server_paths: bool,
+
     /// Additional URLs of symbol servers serving Breakpad .sym files
     #[arg(long)]
     breakpad_symbol_server: Vec<String>,
@@ -331,6 +405,74 @@ struct SymbolArgs {
     /// Extra directory containing symbol files, with the directory structure used by simpleperf's scripts
     #[arg(long)]
     simpleperf_binary_cache: Option<PathBuf>,
+
+    /// Extra directory containing separate debug files in a .build-id/xx/rest.debug
+    /// tree, like /usr/lib/debug. Checked before /usr/lib/debug.
+    #[arg(long)]
+    build_id_debug_root: Vec<PathBuf>,
+
+    /// Overrides the directory used by `perf buildid-cache`, whose binaries are
+    /// checked by build ID. Defaults to $PERF_BUILDID_DIR or ~/.debug, like perf.
+    #[arg(long)]
+    perf_buildid_cache: Option<PathBuf>,
+
+    /// Extra directory containing DWARF package files (.dwp) for binaries built
+    /// with -gsplit-dwarf, named <binaryname>.dwp. By default, only the .dwp file
+    /// next to the binary is used.
+    #[arg(long)]
+    dwp_dir: Vec<PathBuf>,
+
+    /// Authenticate with a symbol server, in the form URL_PREFIX=ENV_VAR. Requests
+    /// to URLs under URL_PREFIX, i.e. with the same scheme, host and port and a
+    /// path starting with its path, get an "Authorization: Bearer" header with
+    /// the token from the environment variable ENV_VAR.
+    #[arg(long, value_name = "URL_PREFIX=ENV_VAR")]
+    symbol_server_token_env: Vec<String>,
+
+    /// Replace the name of a symbol, in the form DEBUG_ID:SYMBOL=NAME, e.g. for a
+    /// function whose name demangles badly. DEBUG_ID is the library's breakpad ID
+    /// and SYMBOL is either the symbol's relative address in hex (0x1234) or its
+    /// mangled name.
+    #[arg(long, value_name = "DEBUG_ID:SYMBOL=NAME", value_parser = parse_symbol_name_override)]
+    symbol_name_override: Vec<(DebugId, SymbolNameOverrideKey, String)>,
+}
+
+fn parse_name_replacement(arg: &str) -> Result<(String, String), String> {
+    match arg.split_once('=') {
+        Some((from, to)) if !from.is_empty() => Ok((from.to_string(), to.to_string())),
+        _ => Err("expected FROM=TO with a non-empty FROM".to_string()),
+    }
+}
+
+fn parse_build_id_path(arg: &str) -> Result<(Vec<u8>, PathBuf), String> {
+    let (build_id, path) = arg.split_once('=').ok_or("expected BUILD_ID=PATH")?;
+    if build_id.is_empty() || build_id.len() % 2 != 0 {
+        return Err(format!("invalid build ID {build_id}"));
+    }
+    let build_id = (0..build_id.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(build_id.get(i..i + 2)?, 16).ok())
+        .collect::<Option<Vec<u8>>>()
+        .ok_or_else(|| format!("invalid build ID {build_id}"))?;
+    Ok((build_id, PathBuf::from(path)))
+}
+
+fn parse_symbol_name_override(
+    arg: &str,
+) -> Result<(DebugId, SymbolNameOverrideKey, String), String> {
+    let (debug_id, symbol_and_name) = arg.split_once(':').ok_or("expected DEBUG_ID:SYMBOL=NAME")?;
+    let (symbol, name) = symbol_and_name
+        .split_once('=')
+        .ok_or("expected DEBUG_ID:SYMBOL=NAME")?;
+    let debug_id =
+        DebugId::from_breakpad(debug_id).map_err(|_| format!("invalid debug ID {debug_id:?}"))?;
+    let key = match symbol.strip_prefix("0x") {
+        Some(hex) => SymbolNameOverrideKey::Address(
+            u32::from_str_radix(hex, 16).map_err(|_| format!("invalid address {symbol:?}"))?,
+        ),
+        None => SymbolNameOverrideKey::MangledName(symbol.to_string()),
+    };
+    Ok((debug_id, key, name.to_string()))
 }
 
 #[derive(Debug, Args, Clone)]
@@ -356,10 +498,42 @@ pub struct ProfileCreationArgs {
     #[arg(long)]
     reuse_threads: bool,
 
+    /// When merging threads with --reuse-threads, ignore a trailing number in
+    /// thread names, so that for example the threads "worker-0" and "worker-1"
+    /// of a thread pool are merged.
+    #[arg(long, requires = "reuse_threads")]
+    reuse_numbered_threads: bool,
+
+    /// Keep threads with a reused tid separate, but add a generation suffix like
+    /// "(#2)" to their names. Only respected on Linux.
+    #[arg(long, conflicts_with = "reuse_threads")]
+    suffix_reused_tids: bool,
+
+    /// Leave out threads which have neither samples nor markers, for example
+    /// short-lived helper threads that never ran while the profiler was sampling.
+    /// Only respected on Linux.
+    #[arg(long)]
+    prune_idle_threads: bool,
+
     /// Fold repeated frames at the base of the stack.
     #[arg(long)]
     fold_recursive_prefix: bool,
 
+    /// Fold recursion at the leaf end of the stack into a single frame, below a
+    /// label with the approximate recursion depth. This reduces the number of
+    /// distinct stacks for deeply recursive code.
+    #[arg(long)]
+    fold_leaf_recursion: bool,
+
+    /// Drop all kernel frames from sample stacks, for application-only analysis.
+    #[arg(long)]
+    no_kernel_frames: bool,
+
+    /// Don't add a marker frame at the root end of stacks on which unwinding
+    /// failed, just stop the stack at the last frame that could be unwound.
+    #[arg(long)]
+    no_truncated_stack_marker: bool,
+
     /// If a process produces jitdump or marker files, unlink them after
     /// opening. This ensures that the files will not be left in /tmp,
     /// but it will also be impossible to look at JIT disassembly, and line
@@ -371,6 +545,11 @@ pub struct ProfileCreationArgs {
     #[arg(long)]
     per_cpu_threads: bool,
 
+    /// Add a counter track with the number of threads which are running on a
+    /// CPU, based on context switch events. Linux only.
+    #[arg(long)]
+    cpu_usage_counter: bool,
+
     /// Include up to <INCLUDE_ARGS> command line arguments in the process name.
     /// This can help differentiate processes if the same executable is used
     /// for different types of programs. And in --reuse-threads mode it
@@ -386,6 +565,95 @@ pub struct ProfileCreationArgs {
     #[arg(long)]
     unstable_presymbolicate: bool,
 
+    /// Path to the debug vmlinux image of the profiled kernel, used for kernel
+    /// symbols. The placeholder "{linux_version}" is replaced with the kernel
+    /// release string. Defaults to /usr/lib/debug/boot/vmlinux-{linux_version}.
+    #[arg(long, value_name = "PATH")]
+    vmlinux: Option<String>,
+
+    /// Path to a copy of /proc/kallsyms which was saved when the profile was
+    /// recorded. Used for kernel symbols instead of the running kernel's symbols,
+    /// for example when importing a perf.data file from another machine.
+    #[arg(long, value_name = "PATH")]
+    kallsyms: Option<PathBuf>,
+
+    /// Additional file extension of PE images mapped by Wine, e.g. "node".
+    /// exe, dll, cpl, scr, drv and ocx files are always checked. Can be
+    /// specified multiple times.
+    #[arg(long, value_name = "EXT")]
+    pe_extension: Vec<String>,
+
+    /// Check files with other extensions for the PE "MZ" magic if they're
+    /// mapped at file offset 0. This is slower, but finds PE images with
+    /// unusual extensions under Wine.
+    #[arg(long)]
+    sniff_pe_magic: bool,
+
+    /// The file name of a dynamic linker, e.g. "ld-linux-x86-64.so.2". Frames in
+    /// the dynamic linker are put into a separate "Dynamic linker" category.
+    /// A trailing "*" matches any suffix. Can be specified multiple times, and
+    /// replaces the list of common dynamic linker names. Only respected on Linux.
+    #[arg(long, value_name = "NAME")]
+    dynamic_linker_name: Vec<String>,
+
+    /// The weight of each off-CPU sample. By default, off-CPU samples have a
+    /// weight of 1 if the sampling is time-based and 0 if it is based on an
+    /// event count. Set this to 1 to see idle threads in event-count profiles.
+    /// Must not be negative.
+    #[arg(long, value_name = "WEIGHT", value_parser = clap::value_parser!(i32).range(0..))]
+    off_cpu_weight: Option<i32>,
+
+    /// Show the syscall that a thread was blocked in as the leaf frame of its
+    /// off-CPU samples. This needs a perf.data file which was recorded with
+    /// the raw_syscalls:sys_enter and raw_syscalls:sys_exit tracepoints.
+    #[arg(long)]
+    off_cpu_syscalls: bool,
+
+    /// Don't create off-CPU samples for the time that threads spend blocked,
+    /// so that the profile only contains on-CPU samples. This makes profiles of
+    /// mostly-blocked workloads much smaller. CPU usage is still tracked.
+    #[arg(long)]
+    no_off_cpu_samples: bool,
+
+    /// Add a marker with the raw instruction pointer of each sample, e.g. to
+    /// map samples to exact instructions for assembly-level analysis. This
+    /// makes profiles a lot bigger. Only respected on Linux.
+    #[arg(long)]
+    sample_ip_markers: bool,
+
+    /// Show the samples of the idle task (pid 0) as one "swapper/N" thread per
+    /// CPU, instead of dropping them. Useful for system-wide recordings.
+    #[arg(long)]
+    idle_threads: bool,
+
+    /// When importing a perf.data file, hold back this many records at a time
+    /// and process them in timestamp order. This fixes up records from different
+    /// CPUs which were written out of order, at the cost of memory. At most
+    /// 10000000 records.
+    #[arg(long, value_name = "RECORDS", value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(..=10_000_000))]
+    reorder_window: Option<usize>,
+
+    /// Limit memory use when converting very large profiles: once a process has
+    /// this many samples buffered in memory, move them to a temporary file.
+    /// This makes the conversion slower.
+    #[arg(long, value_name = "SAMPLES")]
+    spill_samples_after: Option<usize>,
+
+    /// Replace FROM with TO in thread and process names, e.g. to redact file
+    /// paths or secrets from command lines before sharing a profile. Can be
+    /// specified multiple times; the replacements are applied in order. Only
+    /// respected on Linux.
+    #[arg(long, value_name = "FROM=TO", value_parser = parse_name_replacement)]
+    replace_in_names: Vec<(String, String)>,
+
+    /// Use the file at PATH for the binary with the ELF build ID BUILD_ID, given
+    /// in hex, instead of the path from the recording. This can point to an
+    /// unstripped copy of a system library, for example. The file is only used
+    /// if its build ID matches. Can be specified multiple times. Only respected
+    /// on Linux.
+    #[arg(long, value_name = "BUILD_ID=PATH", value_parser = parse_build_id_path)]
+    build_id_path: Vec<(Vec<u8>, PathBuf)>,
+
     /// Emit markers for any unknown ETW events that are encountered.
     #[cfg(target_os = "windows")]
     #[arg(long)]
@@ -436,14 +704,18 @@ fn main() {
         }
 
         Action::Import(import_args) => {
-            let input_file = match File::open(&import_args.file) {
-                Ok(file) => file,
-                Err(err) => {
-                    eprintln!("Could not open file {:?}: {}", import_args.file, err);
-                    std::process::exit(1)
-                }
-            };
-            convert_file_to_profile(&input_file, &import_args);
+            if import_args.file.as_os_str() == "-" {
+                convert_perf_pipe_to_profile(std::io::stdin().lock(), &import_args);
+            } else {
+                let input_file = match File::open(&import_args.file) {
+                    Ok(file) => file,
+                    Err(err) => {
+                        eprintln!("Could not open file {:?}: {}", import_args.file, err);
+                        std::process::exit(1)
+                    }
+                };
+                convert_file_to_profile(&input_file, &import_args);
+            }
             if let Some(server_props) = import_args.server_props() {
                 let profile_filename = &import_args.output;
                 let libinfo_map = profile_json_preparse::parse_libinfo_map_from_profile_file(
@@ -460,6 +732,10 @@ fn main() {
             }
         }
 
+        Action::Diff(diff_args) => {
+            diff_perf_data_files(&diff_args);
+        }
+
         #[cfg(any(
             target_os = "android",
             target_os = "macos",
@@ -531,25 +807,16 @@ impl ImportArgs {
         let filename = self.file.file_name().unwrap_or(self.file.as_os_str());
         let fallback_profile_name = filename.to_string_lossy().into();
         ProfileCreationProps {
-            profile_name: self.profile_creation_args.profile_name.clone(),
-            fallback_profile_name,
-            main_thread_only: self.profile_creation_args.main_thread_only,
-            reuse_threads: self.profile_creation_args.reuse_threads,
-            fold_recursive_prefix: self.profile_creation_args.fold_recursive_prefix,
-            unlink_aux_files: self.profile_creation_args.unlink_aux_files,
-            create_per_cpu_threads: self.profile_creation_args.per_cpu_threads,
-            arg_count_to_include_in_process_name: self.profile_creation_args.include_args,
             override_arch: self.override_arch.clone(),
-            unstable_presymbolicate: self.profile_creation_args.unstable_presymbolicate,
             coreclr: to_coreclr_profile_props(&self.coreclr),
-            #[cfg(target_os = "windows")]
-            unknown_event_markers: self.profile_creation_args.unknown_event_markers,
-            #[cfg(not(target_os = "windows"))]
-            unknown_event_markers: false,
-            #[cfg(target_os = "windows")]
             time_range: self.time_range,
-            #[cfg(not(target_os = "windows"))]
-            time_range: None,
+            perf_stat_intervals_path: self.perf_stat_intervals.clone(),
+            branch_edges_path: self.branch_edges.clone(),
+            module_list_path: self.module_list.clone(),
+            sampling_interval_override: self.sampling_interval,
+            ..self
+                .profile_creation_args
+                .profile_creation_props(fallback_profile_name)
         }
     }
 
@@ -654,22 +921,61 @@ impl RecordArgs {
             }
         };
         ProfileCreationProps {
-            profile_name: self.profile_creation_args.profile_name.clone(),
+            coreclr: to_coreclr_profile_props(&self.coreclr),
+            ..self
+                .profile_creation_args
+                .profile_creation_props(fallback_profile_name)
+        }
+    }
+}
+
+impl ProfileCreationArgs {
+    /// The profile creation properties for these arguments. The properties
+    /// which only some subcommands have arguments for are left at their
+    /// defaults.
+    fn profile_creation_props(&self, fallback_profile_name: String) -> ProfileCreationProps {
+        ProfileCreationProps {
+            profile_name: self.profile_name.clone(),
             fallback_profile_name,
-            main_thread_only: self.profile_creation_args.main_thread_only,
-            reuse_threads: self.profile_creation_args.reuse_threads,
-            fold_recursive_prefix: self.profile_creation_args.fold_recursive_prefix,
-            unlink_aux_files: self.profile_creation_args.unlink_aux_files,
-            create_per_cpu_threads: self.profile_creation_args.per_cpu_threads,
-            arg_count_to_include_in_process_name: self.profile_creation_args.include_args,
+            main_thread_only: self.main_thread_only,
+            reuse_threads: self.reuse_threads,
+            reuse_numbered_threads: self.reuse_numbered_threads,
+            suffix_reused_tids: self.suffix_reused_tids,
+            prune_idle_threads: self.prune_idle_threads,
+            fold_recursive_prefix: self.fold_recursive_prefix,
+            fold_leaf_recursion: self.fold_leaf_recursion,
+            include_kernel_frames: !self.no_kernel_frames,
+            include_truncated_stack_marker: !self.no_truncated_stack_marker,
+            unlink_aux_files: self.unlink_aux_files,
+            create_per_cpu_threads: self.per_cpu_threads,
+            arg_count_to_include_in_process_name: self.include_args,
             override_arch: None,
-            unstable_presymbolicate: self.profile_creation_args.unstable_presymbolicate,
-            coreclr: to_coreclr_profile_props(&self.coreclr),
+            unstable_presymbolicate: self.unstable_presymbolicate,
+            coreclr: CoreClrProfileProps::default(),
             #[cfg(target_os = "windows")]
-            unknown_event_markers: self.profile_creation_args.unknown_event_markers,
+            unknown_event_markers: self.unknown_event_markers,
             #[cfg(not(target_os = "windows"))]
             unknown_event_markers: false,
             time_range: None,
+            vmlinux_debug_path: self.vmlinux.clone(),
+            kallsyms_path: self.kallsyms.clone(),
+            extra_pe_extensions: self.pe_extension.clone(),
+            sniff_pe_magic: self.sniff_pe_magic,
+            dynamic_linker_names: self.dynamic_linker_name.clone(),
+            off_cpu_weight: self.off_cpu_weight,
+            reorder_window: self.reorder_window,
+            cpu_usage_counter: self.cpu_usage_counter,
+            off_cpu_syscalls: self.off_cpu_syscalls,
+            include_off_cpu_samples: !self.no_off_cpu_samples,
+            sample_ip_markers: self.sample_ip_markers,
+            idle_threads: self.idle_threads,
+            spill_samples_threshold: self.spill_samples_after,
+            name_replacements: self.replace_in_names.clone(),
+            build_id_path_overrides: self.build_id_path.clone(),
+            perf_stat_intervals_path: None,
+            branch_edges_path: None,
+            module_list_path: None,
+            sampling_interval_override: None,
         }
     }
 }
@@ -715,10 +1021,16 @@ impl SymbolArgs {
             symbol_dir: self.symbol_dir.clone(),
             windows_symbol_server: self.windows_symbol_server.clone(),
             windows_symbol_cache: self.windows_symbol_cache.clone(),
+            lowercase_symbol_server_paths: self.lowercase_symbol_server_paths,
             breakpad_symbol_server: self.breakpad_symbol_server.clone(),
             breakpad_symbol_dir: self.breakpad_symbol_dir.clone(),
             breakpad_symbol_cache: self.breakpad_symbol_cache.clone(),
             simpleperf_binary_cache: self.simpleperf_binary_cache.clone(),
+            build_id_debug_root: self.build_id_debug_root.clone(),
+            perf_buildid_cache: self.perf_buildid_cache.clone(),
+            dwp_dir: self.dwp_dir.clone(),
+            symbol_server_token_env: self.symbol_server_token_env.clone(),
+            symbol_name_overrides: self.symbol_name_override.clone(),
         }
     }
 }
@@ -790,21 +1102,36 @@ fn convert_etl_file_to_profile(_input_file: &File, import_args: &ImportArgs) {
 }
 
 fn convert_perf_data_file_to_profile(input_file: &File, import_args: &ImportArgs) {
-    let path = import_args
-        .file
+    let profile = import_perf_data_file(
+        &import_args.file,
+        input_file,
+        import_args.profile_creation_props(),
+        import_args.symbol_props().symbol_dir,
+        import_args.aux_file_dir.clone(),
+    );
+    save_profile_to_file(&profile, &import_args.output).expect("Couldn't write JSON");
+}
+
+/// Converts the perf.data file at `file_path`, or exits if it can't be parsed.
+/// The file's directory is searched for binaries and aux files, too.
+fn import_perf_data_file(
+    file_path: &Path,
+    input_file: &File,
+    profile_creation_props: ProfileCreationProps,
+    mut binary_lookup_dirs: Vec<PathBuf>,
+    mut aux_file_lookup_dirs: Vec<PathBuf>,
+) -> fxprof_processed_profile::Profile {
+    let path = file_path
         .canonicalize()
         .expect("Couldn't form absolute path");
     let file_meta = input_file.metadata().ok();
     let file_mod_time = file_meta.and_then(|metadata| metadata.modified().ok());
-    let profile_creation_props = import_args.profile_creation_props();
-    let mut binary_lookup_dirs = import_args.symbol_props().symbol_dir.clone();
-    let mut aux_file_lookup_dirs = import_args.aux_file_dir.clone();
     if let Some(parent_dir) = path.parent() {
         binary_lookup_dirs.push(parent_dir.into());
         aux_file_lookup_dirs.push(parent_dir.into());
     }
     let reader = BufReader::new(input_file);
-    let profile = match import::perf::convert(
+    match import::perf::convert(
         reader,
         file_mod_time,
         binary_lookup_dirs,
@@ -816,10 +1143,119 @@ fn convert_perf_data_file_to_profile(input_file: &File, import_args: &ImportArgs
             eprintln!("Error importing perf.data file: {:?}", error);
             std::process::exit(1);
         }
+    }
+}
+
+fn diff_perf_data_files(diff_args: &DiffArgs) {
+    let import = |file_path: &Path| {
+        let input_file = match File::open(file_path) {
+            Ok(file) => file,
+            Err(err) => {
+                eprintln!("Could not open file {:?}: {}", file_path, err);
+                std::process::exit(1)
+            }
+        };
+        let filename = file_path.file_name().unwrap_or(file_path.as_os_str());
+        let profile_creation_props = diff_args
+            .profile_creation_args
+            .profile_creation_props(filename.to_string_lossy().into());
+        import_perf_data_file(
+            file_path,
+            &input_file,
+            profile_creation_props,
+            diff_args.symbol_args.symbol_props().symbol_dir,
+            diff_args.aux_file_dir.clone(),
+        )
+    };
+    let before = import(&diff_args.before);
+    let after = import(&diff_args.after);
+
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let mut symbol_manager = server::create_symbol_manager(diff_args.symbol_args.symbol_props());
+    let mut function_sample_counts = |profile| {
+        let symbols = rt.block_on(LibSymbols::lookup_profile_addresses(
+            profile,
+            &mut symbol_manager,
+        ));
+        FunctionSampleCounts::from_profile(profile, &symbols)
+    };
+    let mut diff = diff_function_sample_counts(
+        &function_sample_counts(&before),
+        &function_sample_counts(&after),
+    );
+    diff.regressions.truncate(diff_args.limit);
+    diff.improvements.truncate(diff_args.limit);
+    print!("{diff}");
+}
+
+fn convert_perf_pipe_to_profile(input: impl Read, import_args: &ImportArgs) {
+    let profile_creation_props = import_args.profile_creation_props();
+    let binary_lookup_dirs = import_args.symbol_props().symbol_dir.clone();
+    let aux_file_lookup_dirs = import_args.aux_file_dir.clone();
+    let observer = PipeImportObserver {
+        last_report: Instant::now(),
+        snapshot_interval: import_args.snapshot_interval,
+        last_snapshot: Instant::now(),
+        output: &import_args.output,
+    };
+    let profile = match import::perf_pipe::convert_pipe(
+        BufReader::new(input),
+        binary_lookup_dirs,
+        aux_file_lookup_dirs,
+        profile_creation_props,
+        observer,
+    ) {
+        Ok(profile) => profile,
+        Err(error) => {
+            eprintln!("Error importing perf.data stream: {:?}", error);
+            std::process::exit(1);
+        }
     };
     save_profile_to_file(&profile, &import_args.output).expect("Couldn't write JSON");
 }
 
+/// Reports the progress of a perf.data stream import, and saves snapshots of
+/// the profile if `--snapshot-interval` was given.
+struct PipeImportObserver<'a> {
+    last_report: Instant,
+    snapshot_interval: Option<Duration>,
+    last_snapshot: Instant,
+    output: &'a Path,
+}
+
+impl import::perf_pipe::PipeConversionObserver for PipeImportObserver<'_> {
+    fn on_progress(&mut self, progress: &import::perf_pipe::PipeConversionProgress) {
+        if self.last_report.elapsed() >= Duration::from_secs(1) {
+            eprintln!("Converted {} samples so far", progress.sample_count);
+            self.last_report = Instant::now();
+        }
+    }
+
+    fn wants_snapshot(&mut self, _progress: &import::perf_pipe::PipeConversionProgress) -> bool {
+        self.snapshot_interval
+            .is_some_and(|interval| self.last_snapshot.elapsed() >= interval)
+    }
+
+    fn on_snapshot(&mut self, profile: fxprof_processed_profile::Profile) {
+        // Write to a hidden file next to the output first, so that the output
+        // file is never seen half-written.
+        let mut temp_file_name = OsStr::new(".").to_owned();
+        temp_file_name.push(
+            self.output
+                .file_name()
+                .unwrap_or(OsStr::new("profile.json")),
+        );
+        let temp_path = self.output.with_file_name(temp_file_name);
+        let result = save_profile_to_file(&profile, &temp_path)
+            .and_then(|()| std::fs::rename(&temp_path, self.output));
+        match result {
+            Ok(()) => eprintln!("Saved a snapshot of the profile to {:?}", self.output),
+            Err(err) => eprintln!("Couldn't save a snapshot of the profile: {err}"),
+        }
+        self.last_snapshot = Instant::now();
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -854,4 +1290,116 @@ mod test {
         let opt_res = Opt::try_parse_from(["samply", "record", "-p", "1234", "rustup"]);
         assert!(opt_res.is_err());
     }
+
+    #[test]
+    fn verify_cli_symbol_name_override() {
+        let opt = Opt::parse_from([
+            "samply",
+            "load",
+            "profile.json",
+            "--symbol-name-override",
+            "AA152DEB2D9B76084AFE8E6BAB9A2A1F0:0x1a2b=parse",
+            "--symbol-name-override",
+            "AA152DEB2D9B76084AFE8E6BAB9A2A1F0:_ZN3foo3barE=foo::bar",
+        ]);
+        let Action::Load(load_args) = opt.action else {
+            panic!("expected the load subcommand");
+        };
+        let debug_id = DebugId::from_breakpad("AA152DEB2D9B76084AFE8E6BAB9A2A1F0").unwrap();
+        assert_eq!(
+            load_args.symbol_props().symbol_name_overrides,
+            [
+                (
+                    debug_id,
+                    SymbolNameOverrideKey::Address(0x1a2b),
+                    "parse".to_string()
+                ),
+                (
+                    debug_id,
+                    SymbolNameOverrideKey::MangledName("_ZN3foo3barE".to_string()),
+                    "foo::bar".to_string()
+                ),
+            ]
+        );
+
+        for invalid in [
+            "parse",
+            "AA152DEB:0x1a2b=parse",
+            "AA152DEB2D9B76084AFE8E6BAB9A2A1F0:0xzz=parse",
+        ] {
+            let opt_res = Opt::try_parse_from([
+                "samply",
+                "load",
+                "profile.json",
+                "--symbol-name-override",
+                invalid,
+            ]);
+            assert!(opt_res.is_err(), "{invalid} should be rejected");
+        }
+    }
+
+    #[test]
+    fn verify_cli_build_id_path() {
+        let opt = Opt::parse_from([
+            "samply",
+            "import",
+            "perf.data",
+            "--build-id-path",
+            "63260a3e6e46db57=/home/user/libfoo.so",
+        ]);
+        let Action::Import(import_args) = opt.action else {
+            panic!("expected the import subcommand");
+        };
+        assert_eq!(
+            import_args.profile_creation_props().build_id_path_overrides,
+            [(
+                vec![0x63, 0x26, 0x0a, 0x3e, 0x6e, 0x46, 0xdb, 0x57],
+                PathBuf::from("/home/user/libfoo.so")
+            )]
+        );
+
+        for invalid in [
+            "/home/user/libfoo.so",
+            "=/libfoo.so",
+            "632=/libfoo.so",
+            "zz=/libfoo.so",
+        ] {
+            let opt_res =
+                Opt::try_parse_from(["samply", "import", "perf.data", "--build-id-path", invalid]);
+            assert!(opt_res.is_err(), "{invalid} should be rejected");
+        }
+    }
+
+    #[test]
+    fn verify_cli_off_cpu_weight() {
+        let opt = Opt::parse_from(["samply", "import", "perf.data", "--off-cpu-weight", "3"]);
+        let Action::Import(import_args) = opt.action else {
+            panic!("expected the import subcommand");
+        };
+        assert_eq!(import_args.profile_creation_props().off_cpu_weight, Some(3));
+
+        let opt_res = Opt::try_parse_from(["samply", "import", "perf.data", "--off-cpu-weight=-1"]);
+        assert!(opt_res.is_err(), "negative weights should be rejected");
+    }
+
+    #[test]
+    fn verify_cli_reorder_window() {
+        let opt = Opt::parse_from(["samply", "import", "perf.data", "--reorder-window", "1000"]);
+        let Action::Import(import_args) = opt.action else {
+            panic!("expected the import subcommand");
+        };
+        assert_eq!(
+            import_args.profile_creation_props().reorder_window,
+            Some(1000)
+        );
+
+        let opt_res = Opt::try_parse_from([
+            "samply",
+            "import",
+            "perf.data",
+            "--reorder-window",
+            "18446744073709551615",
+        ]);
+        assert!(opt_res.is_err(), "huge windows should be rejected");
+    }
 }
@@ -65,6 +65,19 @@ impl PortSelection {
     }
 }
 
+/// Creates a symbol manager which finds symbols in the places given by
+/// `symbol_props`, and applies its symbol name overrides.
+pub fn create_symbol_manager(symbol_props: SymbolProps) -> SymbolManager {
+    let symbol_name_overrides = symbol_props
+        .symbol_name_overrides
+        .iter()
+        .map(|(debug_id, key, name)| ((*debug_id, key.clone()), name.clone()))
+        .collect();
+    let mut symbol_manager = SymbolManager::with_config(create_symbol_manager_config(symbol_props));
+    symbol_manager.set_symbol_name_overrides(symbol_name_overrides);
+    symbol_manager
+}
+
 fn create_symbol_manager_config(symbol_props: SymbolProps) -> SymbolManagerConfig {
     let _config_dir = AppDirs::new(Some(SAMPLY_NAME), true).map(|dirs| dirs.config_dir);
     let cache_base_dir = AppDirs::new(Some(SAMPLY_NAME), false).map(|dirs| dirs.cache_dir);
@@ -112,6 +125,8 @@ fn create_symbol_manager_config(symbol_props: SymbolProps) -> SymbolManagerConfi
         }
     }
 
+    config = config.lowercase_symbol_server_paths(symbol_props.lowercase_symbol_server_paths);
+
     if let Some(binary_cache) = symbol_props.simpleperf_binary_cache {
         config = config.simpleperf_binary_cache_dir(binary_cache);
     }
@@ -120,6 +135,39 @@ fn create_symbol_manager_config(symbol_props: SymbolProps) -> SymbolManagerConfi
         config = config.extra_symbols_directory(dir);
     }
 
+    for dir in symbol_props.build_id_debug_root {
+        config = config.build_id_debug_root(dir);
+    }
+
+    for dir in symbol_props.dwp_dir {
+        config = config.dwp_dir(dir);
+    }
+
+    let perf_buildid_cache_dir =
+        symbol_props
+            .perf_buildid_cache
+            .or_else(|| match std::env::var_os("PERF_BUILDID_DIR") {
+                Some(dir) => Some(dir.into()),
+                None => Some(PathBuf::from(std::env::var_os("HOME")?).join(".debug")),
+            });
+    if let Some(dir) = perf_buildid_cache_dir {
+        config = config.perf_buildid_cache_dir(dir);
+    }
+
+    for entry in symbol_props.symbol_server_token_env {
+        match entry.rsplit_once('=') {
+            Some((url_prefix, env_var_name)) => {
+                if std::env::var_os(env_var_name).is_none() {
+                    eprintln!("Warning: The environment variable {env_var_name} for the symbol server token is not set.");
+                }
+                config = config.server_bearer_token_from_env(url_prefix, env_var_name);
+            }
+            None => {
+                eprintln!("Warning: Ignoring --symbol-server-token-env {entry}, expected URL_PREFIX=ENV_VAR.");
+            }
+        }
+    }
+
     config
 }
 
@@ -163,8 +211,7 @@ async fn start_server(
 
     let template_values = Arc::new(template_values);
 
-    let config = create_symbol_manager_config(symbol_props);
-    let mut symbol_manager = SymbolManager::with_config(config);
+    let mut symbol_manager = create_symbol_manager(symbol_props);
     if server_props.verbose {
         symbol_manager.set_observer(Some(Arc::new(VerboseSymbolManagerObserver::new())));
     }
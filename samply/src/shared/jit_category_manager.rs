@@ -105,7 +105,6 @@ impl JitCategoryManager {
         }
     }
 
-    #[allow(dead_code)]
     pub fn default_category(&mut self, profile: &mut Profile) -> CategoryHandle {
         self.generic_jit_category.get(profile)
     }
@@ -233,8 +232,9 @@ impl JitCategoryManager {
     }
 }
 
+/// A category which is only added to the profile once it's used.
 #[derive(Debug, Clone)]
-struct LazilyCreatedCategory {
+pub struct LazilyCreatedCategory {
     name: &'static str,
     color: CategoryColor,
     handle: Option<CategoryHandle>,
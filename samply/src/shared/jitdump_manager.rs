@@ -1,10 +1,13 @@
+use std::io::{Cursor, Read, Seek, SeekFrom};
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 use fxprof_processed_profile::{
-    LibraryHandle, MarkerTiming, Profile, Symbol, SymbolTable, ThreadHandle,
+    LibraryHandle, MarkerTiming, Profile, SourceLine, Symbol, SymbolTable, ThreadHandle,
+};
+use linux_perf_data::jitdump::{
+    JitCodeDebugInfoRecord, JitDumpReader, JitDumpRecord, JitDumpRecordType,
 };
-use linux_perf_data::jitdump::{JitDumpReader, JitDumpRecord, JitDumpRecordType};
 
 use super::jit_category_manager::JitCategoryManager;
 use super::jit_function_add_marker::JitFunctionAddMarker;
@@ -15,11 +18,20 @@ use super::lib_mappings::{
 use super::timestamp_converter::TimestampConverter;
 use super::utils::open_file_with_fallback;
 
+/// How many calls to [`JitDumpManager::process_pending_records`] are skipped
+/// between two checks for new jitdump records.
+///
+/// Checking means reading from every jitdump file, and `process_pending_records`
+/// is called for almost every sample. The JIT functions are added to the
+/// profile with their timestamps, so it doesn't matter if we see them late.
+const JITDUMP_POLL_INTERVAL: u32 = 100;
+
 #[derive(Debug)]
 pub struct JitDumpManager {
     pending_jitdump_paths: Vec<(ThreadHandle, PathBuf, Vec<PathBuf>)>,
     processors: Vec<SingleJitDumpProcessor>,
     unlink_after_open: bool,
+    calls_since_last_poll: u32,
 }
 
 impl JitDumpManager {
@@ -28,6 +40,7 @@ impl JitDumpManager {
             pending_jitdump_paths: Vec::new(),
             processors: Vec::new(),
             unlink_after_open,
+            calls_since_last_poll: 0,
         }
     }
 
@@ -39,9 +52,30 @@ impl JitDumpManager {
     ) {
         self.pending_jitdump_paths
             .push((thread, path.into(), lookup_dirs));
+        // Open the new file on the next call, before it can be deleted.
+        self.calls_since_last_poll = JITDUMP_POLL_INTERVAL;
     }
 
+    /// Reads the new records from all jitdump files. This only does something
+    /// on every [`JITDUMP_POLL_INTERVAL`]th call, or if a jitdump path was
+    /// added since the last check. [`finish`](Self::finish) reads all the
+    /// remaining records.
     pub fn process_pending_records(
+        &mut self,
+        jit_category_manager: &mut JitCategoryManager,
+        profile: &mut Profile,
+        recycler: Option<&mut JitFunctionRecycler>,
+        timestamp_converter: &TimestampConverter,
+    ) {
+        self.calls_since_last_poll += 1;
+        if self.calls_since_last_poll < JITDUMP_POLL_INTERVAL {
+            return;
+        }
+        self.calls_since_last_poll = 0;
+        self.poll(jit_category_manager, profile, recycler, timestamp_converter);
+    }
+
+    fn poll(
         &mut self,
         jit_category_manager: &mut JitCategoryManager,
         profile: &mut Profile,
@@ -54,8 +88,8 @@ impl JitDumpManager {
                     path: &Path,
                     lookup_dirs: &[PathBuf],
                     unlink_after_open: bool,
-                ) -> Option<(JitDumpReader<std::fs::File>, PathBuf)> {
-                    let (file, path) = open_file_with_fallback(path, lookup_dirs).ok()?;
+                ) -> Option<(JitDumpReader<JitDumpFile>, PathBuf)> {
+                    let (file, path) = JitDumpFile::open(path, lookup_dirs)?;
                     let reader = JitDumpReader::new(file).ok()?;
                     if unlink_after_open {
                         std::fs::remove_file(&path).ok()?;
@@ -94,7 +128,7 @@ impl JitDumpManager {
         recycler: Option<&mut JitFunctionRecycler>,
         timestamp_converter: &TimestampConverter,
     ) -> Vec<LibMappingOpQueue> {
-        self.process_pending_records(jit_category_manager, profile, recycler, timestamp_converter);
+        self.poll(jit_category_manager, profile, recycler, timestamp_converter);
         self.processors
             .into_iter()
             .map(|processor| processor.finish(profile))
@@ -102,15 +136,94 @@ impl JitDumpManager {
     }
 }
 
+/// Returns whether a file name looks like a jitdump file, i.e. `jit-<pid>.dump`,
+/// optionally compressed as `jit-<pid>.dump.zst` or `jit-<pid>.dump.gz`.
+pub fn is_jitdump_file_name(file_name: &str) -> bool {
+    let uncompressed_name = file_name
+        .strip_suffix(".zst")
+        .or_else(|| file_name.strip_suffix(".gz"))
+        .unwrap_or(file_name);
+    uncompressed_name.starts_with("jit-") && uncompressed_name.ends_with(".dump")
+}
+
+/// The source of a jitdump's bytes.
+#[derive(Debug)]
+enum JitDumpFile {
+    /// An uncompressed jitdump file. It may still be growing while we read it.
+    File(std::fs::File),
+    /// The decompressed contents of a `.dump.zst` or `.dump.gz` file.
+    Decompressed(Cursor<Vec<u8>>),
+}
+
+impl JitDumpFile {
+    /// Opens the jitdump file at `path`, or in one of the `lookup_dirs`. If no
+    /// uncompressed file is found, looks for a compressed file with a `.zst` or
+    /// `.gz` suffix.
+    fn open(path: &Path, lookup_dirs: &[PathBuf]) -> Option<(Self, PathBuf)> {
+        let mut candidates = vec![path.to_owned()];
+        if path.extension().is_some_and(|ext| ext == "dump") {
+            for suffix in ["zst", "gz"] {
+                let mut compressed_path = path.as_os_str().to_owned();
+                compressed_path.push(".");
+                compressed_path.push(suffix);
+                candidates.push(compressed_path.into());
+            }
+        }
+        candidates.into_iter().find_map(|candidate| {
+            let (file, path) = open_file_with_fallback(&candidate, lookup_dirs).ok()?;
+            let jitdump_file = match path.extension().and_then(|ext| ext.to_str()) {
+                Some("zst") => {
+                    let mut decoder = ruzstd::StreamingDecoder::new(file).ok()?;
+                    let mut data = Vec::new();
+                    decoder.read_to_end(&mut data).ok()?;
+                    JitDumpFile::Decompressed(Cursor::new(data))
+                }
+                Some("gz") => {
+                    let mut data = Vec::new();
+                    flate2::read::GzDecoder::new(file)
+                        .read_to_end(&mut data)
+                        .ok()?;
+                    JitDumpFile::Decompressed(Cursor::new(data))
+                }
+                _ => JitDumpFile::File(file),
+            };
+            Some((jitdump_file, path))
+        })
+    }
+}
+
+impl Read for JitDumpFile {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            JitDumpFile::File(file) => file.read(buf),
+            JitDumpFile::Decompressed(cursor) => cursor.read(buf),
+        }
+    }
+}
+
+impl Seek for JitDumpFile {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        match self {
+            JitDumpFile::File(file) => file.seek(pos),
+            JitDumpFile::Decompressed(cursor) => cursor.seek(pos),
+        }
+    }
+}
+
 #[derive(Debug)]
 struct SingleJitDumpProcessor {
     /// Some() until a JIT_CODE_CLOSE record is encountered.
-    reader: Option<JitDumpReader<std::fs::File>>,
+    reader: Option<JitDumpReader<JitDumpFile>>,
     lib_handle: LibraryHandle,
     lib_mapping_ops: LibMappingOpQueue,
     symbols: Vec<Symbol>,
+    source_lines: Vec<SourceLine>,
     thread_handle: ThreadHandle,
 
+    /// The line table from the last JIT_CODE_DEBUG_INFO record. This record
+    /// comes right before the JIT_CODE_LOAD record for the same code.
+    pending_debug_info: Option<JitCodeLineTable>,
+
     /// The relative_address of the next JIT function.
     ///
     /// We define the relative address space for Jitdump files as follows:
@@ -123,7 +236,7 @@ struct SingleJitDumpProcessor {
 
 impl SingleJitDumpProcessor {
     pub fn new(
-        reader: JitDumpReader<std::fs::File>,
+        reader: JitDumpReader<JitDumpFile>,
         lib_handle: LibraryHandle,
         thread_handle: ThreadHandle,
     ) -> Self {
@@ -132,7 +245,9 @@ impl SingleJitDumpProcessor {
             lib_handle,
             lib_mapping_ops: Default::default(),
             symbols: Default::default(),
+            source_lines: Default::default(),
             thread_handle,
+            pending_debug_info: None,
             cumulative_address: 0,
         }
     }
@@ -151,14 +266,13 @@ impl SingleJitDumpProcessor {
             match next_record_header.record_type {
                 JitDumpRecordType::JIT_CODE_LOAD
                 | JitDumpRecordType::JIT_CODE_MOVE
+                | JitDumpRecordType::JIT_CODE_DEBUG_INFO
                 | JitDumpRecordType::JIT_CODE_UNWINDING_INFO
                 | JitDumpRecordType::JIT_CODE_CLOSE => {
                     // These are interesting.
                 }
                 _ => {
-                    // We skip other records. We especially want to skip JIT_CODE_DEBUG_INFO
-                    // records because they can be big and we don't need to read them from
-                    // the file.
+                    // We skip other records.
                     if let Ok(true) = reader.skip_next_record() {
                         continue;
                     } else {
@@ -185,6 +299,15 @@ impl SingleJitDumpProcessor {
                         size: Some(code_size),
                         name: symbol_name.to_owned(),
                     });
+                    if let Some(line_table) = self.pending_debug_info.take() {
+                        if line_table.code_addr == start_avma {
+                            self.source_lines.extend(line_table.source_lines(
+                                start_avma,
+                                code_size,
+                                relative_address_at_start,
+                            ));
+                        }
+                    }
 
                     let timestamp = timestamp_converter.convert_time(raw_jitdump_record.timestamp);
                     let symbol_name_handle = profile.intern_string(symbol_name);
@@ -230,6 +353,9 @@ impl SingleJitDumpProcessor {
                     );
                     // TODO: Remove from + add to unwinder
                 }
+                Ok(JitDumpRecord::CodeDebugInfo(record)) => {
+                    self.pending_debug_info = Some(JitCodeLineTable::new(&record));
+                }
                 Ok(JitDumpRecord::CodeUnwindingInfo(_unwinding_info)) => {
                     // TODO: Queue up, and add to unwinder on next CodeLoad
                 }
@@ -250,7 +376,10 @@ impl SingleJitDumpProcessor {
             return;
         }
 
-        let symbol_table = SymbolTable::new(std::mem::take(&mut self.symbols));
+        let symbol_table = SymbolTable::with_source_lines(
+            std::mem::take(&mut self.symbols),
+            std::mem::take(&mut self.source_lines),
+        );
         profile.set_lib_symbol_table(self.lib_handle, Arc::new(symbol_table));
         self.reader = None;
     }
@@ -260,3 +389,124 @@ impl SingleJitDumpProcessor {
         self.lib_mapping_ops
     }
 }
+
+/// The line table of a JIT function, from a JIT_CODE_DEBUG_INFO record.
+#[derive(Debug, Clone)]
+struct JitCodeLineTable {
+    code_addr: u64,
+    /// (avma, file path, line), sorted by avma. Each entry covers the code up
+    /// to the next entry, or up to the end of the function.
+    entries: Vec<(u64, String, u32)>,
+}
+
+impl JitCodeLineTable {
+    fn new(record: &JitCodeDebugInfoRecord) -> Self {
+        let entries = record
+            .entries
+            .iter()
+            .map(|entry| {
+                let file_path = String::from_utf8_lossy(&entry.file_path.as_slice()).into_owned();
+                (entry.code_addr, file_path, entry.line)
+            })
+            .collect();
+        Self {
+            code_addr: record.code_addr,
+            entries,
+        }
+    }
+
+    /// Converts the entries to source lines in the jitdump's relative address
+    /// space, for a function whose code is at `start_avma` and which is at
+    /// `relative_address_at_start` in that address space.
+    fn source_lines(
+        self,
+        start_avma: u64,
+        code_size: u32,
+        relative_address_at_start: u32,
+    ) -> Vec<SourceLine> {
+        let entries: Vec<(u32, String, u32)> = self
+            .entries
+            .into_iter()
+            .filter_map(|(avma, file_path, line)| {
+                let offset = u32::try_from(avma.checked_sub(start_avma)?).ok()?;
+                (offset < code_size).then_some((offset, file_path, line))
+            })
+            .collect();
+        let ends: Vec<u32> = entries
+            .iter()
+            .skip(1)
+            .map(|(offset, _, _)| *offset)
+            .chain(std::iter::once(code_size))
+            .collect();
+        entries
+            .into_iter()
+            .zip(ends)
+            .map(|((offset, file_path, line), end)| SourceLine {
+                address: relative_address_at_start + offset,
+                size: end.saturating_sub(offset),
+                file_path,
+                line,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Write;
+
+    use super::*;
+
+    #[test]
+    fn jitdump_file_names() {
+        assert!(is_jitdump_file_name("jit-1234.dump"));
+        assert!(is_jitdump_file_name("jit-1234.dump.zst"));
+        assert!(is_jitdump_file_name("jit-1234.dump.gz"));
+        assert!(!is_jitdump_file_name("jit-1234.zst"));
+        assert!(!is_jitdump_file_name("marker-1234.txt"));
+    }
+
+    #[test]
+    fn jit_code_line_table() {
+        let line_table = JitCodeLineTable {
+            code_addr: 0x7000,
+            entries: vec![
+                (0x6ff0, "before.js".to_string(), 1),
+                (0x7000, "app.js".to_string(), 10),
+                (0x7008, "app.js".to_string(), 12),
+                (0x7020, "after.js".to_string(), 1),
+            ],
+        };
+        let source_line = |address, size, file_path: &str, line| SourceLine {
+            address,
+            size,
+            file_path: file_path.to_string(),
+            line,
+        };
+        assert_eq!(
+            line_table.source_lines(0x7000, 0x20, 0x100),
+            [
+                source_line(0x100, 0x8, "app.js", 10),
+                source_line(0x108, 0x18, "app.js", 12),
+            ]
+        );
+    }
+
+    #[test]
+    fn open_compressed_jitdump() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut encoder = flate2::write::GzEncoder::new(
+            std::fs::File::create(dir.path().join("jit-1234.dump.gz")).unwrap(),
+            flate2::Compression::default(),
+        );
+        encoder.write_all(b"JiTD contents").unwrap();
+        encoder.finish().unwrap();
+
+        // The mmap record refers to the uncompressed file, which doesn't exist.
+        let (mut file, path) = JitDumpFile::open(&dir.path().join("jit-1234.dump"), &[]).unwrap();
+        assert_eq!(path, dir.path().join("jit-1234.dump.gz"));
+        let mut contents = Vec::new();
+        file.read_to_end(&mut contents).unwrap();
+        assert_eq!(contents, b"JiTD contents");
+    }
+}
@@ -42,6 +42,18 @@ impl LibMappingInfo {
         }
     }
 
+    pub fn new_lib_with_optional_category(
+        lib_handle: LibraryHandle,
+        category: Option<CategoryPairHandle>,
+    ) -> Self {
+        Self {
+            lib_handle,
+            category,
+            js_frame: None,
+            art_info: None,
+        }
+    }
+
     pub fn new_jit_function(
         lib_handle: LibraryHandle,
         category: CategoryPairHandle,
@@ -139,6 +151,22 @@ impl LibMappingOpQueue {
         self.0.push((timestamp, op));
     }
 
+    /// Adds a mapping from `timestamp` on, and ends the parts of earlier
+    /// mappings which overlap with it at the same time. This is used when an
+    /// address range is reused by a different file, e.g. after a plugin was
+    /// unloaded and another library was loaded in its place. Without the removal,
+    /// an earlier mapping which only partially overlaps would be dropped entirely.
+    pub fn push_add_ending_overlapping(&mut self, timestamp: u64, op: LibMappingAdd) {
+        self.push(
+            timestamp,
+            LibMappingOp::RemoveRange(LibMappingRemoveRange {
+                start_avma: op.start_avma,
+                end_avma: op.end_avma,
+            }),
+        );
+        self.push(timestamp, LibMappingOp::Add(op));
+    }
+
     pub fn is_empty(&self) -> bool {
         self.0.is_empty()
     }
@@ -167,6 +195,9 @@ pub enum LibMappingOp {
     Move(LibMappingMove),
     #[allow(unused)]
     Remove(LibMappingRemove),
+    /// Removes an address range, e.g. because it was unmapped. Mappings which
+    /// overlap the range partially are shortened or split.
+    RemoveRange(LibMappingRemoveRange),
     Clear,
 }
 
@@ -196,6 +227,9 @@ impl LibMappingOp {
             LibMappingOp::Remove(op) => {
                 lib_mappings.remove_mapping(op.start_avma);
             }
+            LibMappingOp::RemoveRange(op) => {
+                lib_mappings.remove_range(op.start_avma, op.end_avma);
+            }
             LibMappingOp::Clear => {
                 lib_mappings.clear();
             }
@@ -223,3 +257,64 @@ pub struct LibMappingMove {
 pub struct LibMappingRemove {
     pub start_avma: u64,
 }
+
+#[derive(Debug, Clone)]
+pub struct LibMappingRemoveRange {
+    pub start_avma: u64,
+    pub end_avma: u64,
+}
+
+#[cfg(test)]
+mod test {
+    use debugid::DebugId;
+    use fxprof_processed_profile::{LibraryInfo, Profile, ReferenceTimestamp, SamplingInterval};
+
+    use super::*;
+
+    #[test]
+    fn reused_address_range() {
+        let mut profile = Profile::new(
+            "",
+            ReferenceTimestamp::from_millis_since_unix_epoch(0.0),
+            SamplingInterval::from_millis(1),
+        );
+        let mut add_lib = |name: &str| {
+            profile.add_lib(LibraryInfo {
+                name: name.to_string(),
+                debug_name: name.to_string(),
+                path: format!("/plugins/{name}"),
+                debug_path: format!("/plugins/{name}"),
+                debug_id: DebugId::nil(),
+                code_id: None,
+                arch: None,
+                symbol_table: None,
+            })
+        };
+        let (plugin_a, plugin_b) = (add_lib("a.so"), add_lib("b.so"));
+        let add = |start_avma, end_avma, lib_handle| LibMappingAdd {
+            start_avma,
+            end_avma,
+            relative_address_at_start: 0,
+            info: LibMappingInfo::new_lib(lib_handle),
+        };
+
+        // b.so is loaded over the second half of a.so's range.
+        let mut ops = LibMappingOpQueue::default();
+        ops.push_add_ending_overlapping(10, add(0x1000, 0x3000, plugin_a));
+        ops.push_add_ending_overlapping(20, add(0x2000, 0x4000, plugin_b));
+        let mut mappings = LibMappingsHierarchy::new(ops);
+        let lookup = |mappings: &LibMappingsHierarchy, address| {
+            mappings
+                .convert_address(address)
+                .map(|(rva, info)| (rva, info.lib_handle))
+        };
+
+        mappings.process_ops(15);
+        assert_eq!(lookup(&mappings, 0x2500), Some((0x1500, plugin_a)));
+
+        mappings.process_ops(25);
+        assert_eq!(lookup(&mappings, 0x1500), Some((0x500, plugin_a)));
+        assert_eq!(lookup(&mappings, 0x2500), Some((0x500, plugin_b)));
+        assert_eq!(lookup(&mappings, 0x3500), Some((0x1500, plugin_b)));
+    }
+}
@@ -7,10 +7,12 @@ pub mod jit_function_recycler;
 pub mod jitdump_manager;
 pub mod lib_mappings;
 pub mod marker_file;
+pub mod native_lib_category_manager;
 pub mod per_cpu;
 pub mod perf_map;
 pub mod process_name;
 pub mod process_sample_data;
+pub mod profile_diff;
 pub mod recording_props;
 pub mod recycling;
 pub mod save_profile;
@@ -0,0 +1,104 @@
+use fxprof_processed_profile::{CategoryColor, CategoryPairHandle, Profile};
+
+use super::jit_category_manager::LazilyCreatedCategory;
+
+/// Assigns categories to frames in regular (non-JIT) libraries, based on the
+/// file name of the library.
+///
+/// At the moment this only knows about the dynamic linker, whose frames show
+/// up during lazy symbol binding and are usually noise.
+#[derive(Debug, Clone)]
+pub struct NativeLibCategoryManager {
+    dynamic_linker_names: Vec<String>,
+    dynamic_linker_category: LazilyCreatedCategory,
+}
+
+impl NativeLibCategoryManager {
+    /// The file names of common dynamic linkers. A trailing `*` matches any suffix.
+    pub const DEFAULT_DYNAMIC_LINKER_NAMES: &'static [&'static str] = &[
+        "ld-linux*",
+        "ld-musl-*",
+        "ld-2.*",
+        "ld.so*",
+        "linker",
+        "linker64",
+    ];
+
+    /// Create a new manager. If `dynamic_linker_names` is empty, the
+    /// [`DEFAULT_DYNAMIC_LINKER_NAMES`](Self::DEFAULT_DYNAMIC_LINKER_NAMES) are used.
+    pub fn new(dynamic_linker_names: &[String]) -> Self {
+        let dynamic_linker_names = if dynamic_linker_names.is_empty() {
+            Self::DEFAULT_DYNAMIC_LINKER_NAMES
+                .iter()
+                .map(|name| name.to_string())
+                .collect()
+        } else {
+            dynamic_linker_names.to_vec()
+        };
+        Self {
+            dynamic_linker_names,
+            dynamic_linker_category: LazilyCreatedCategory::new(
+                "Dynamic linker",
+                CategoryColor::LightBlue,
+            ),
+        }
+    }
+
+    /// Get the category for frames in the library with the file name `lib_name`,
+    /// or `None` if the library should use the default category.
+    ///
+    /// The category is only created in the profile once a matching library
+    /// is encountered.
+    pub fn category_for_lib(
+        &mut self,
+        lib_name: &str,
+        profile: &mut Profile,
+    ) -> Option<CategoryPairHandle> {
+        if self
+            .dynamic_linker_names
+            .iter()
+            .any(|pattern| name_matches(pattern, lib_name))
+        {
+            return Some(self.dynamic_linker_category.get(profile).into());
+        }
+        None
+    }
+}
+
+fn name_matches(pattern: &str, name: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => name.starts_with(prefix),
+        None => name == pattern,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use fxprof_processed_profile::{ReferenceTimestamp, SamplingInterval};
+
+    use super::*;
+
+    #[test]
+    fn test_dynamic_linker_names() {
+        let mut profile = Profile::new(
+            "",
+            ReferenceTimestamp::from_millis_since_unix_epoch(0.0),
+            SamplingInterval::from_millis(1),
+        );
+        let mut manager = NativeLibCategoryManager::new(&[]);
+        let ld = manager.category_for_lib("ld-linux-x86-64.so.2", &mut profile);
+        assert!(ld.is_some());
+        assert_eq!(manager.category_for_lib("ld-2.31.so", &mut profile), ld);
+        assert_eq!(manager.category_for_lib("libc.so.6", &mut profile), None);
+        assert_eq!(manager.category_for_lib("ldconfig", &mut profile), None);
+
+        let mut manager = NativeLibCategoryManager::new(&["my-loader.so".to_string()]);
+        assert!(manager
+            .category_for_lib("my-loader.so", &mut profile)
+            .is_some());
+        assert_eq!(
+            manager.category_for_lib("ld-linux-x86-64.so.2", &mut profile),
+            None
+        );
+    }
+}
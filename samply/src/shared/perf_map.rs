@@ -1,3 +1,4 @@
+use std::path::PathBuf;
 use std::sync::Arc;
 
 use debugid::DebugId;
@@ -22,17 +23,29 @@ fn process_perf_map_line(line: &str) -> Option<(u64, u64, &str)> {
 
 /// Tries to load a perf mapping file that could have been generated by the process during
 /// execution.
+///
+/// The file is looked for in `lookup_dirs` first, e.g. if the map was copied
+/// along with a recording from a different machine, and then in `/tmp`, where
+/// runtimes write it. The lookup dirs come first because a map in `/tmp` on the
+/// converting machine may belong to an unrelated process with the same pid. Any runtime which writes perf maps is supported; for
+/// example, CPython 3.12+ run with `-X perf` writes `py::` entries for its
+/// trampolines, which adds the Python functions to the native stacks.
 pub fn try_load_perf_map(
     pid: u32,
+    lookup_dirs: &[PathBuf],
     profile: &mut Profile,
     jit_category_manager: &mut JitCategoryManager,
     mut recycler: Option<&mut JitFunctionRecycler>,
 ) -> Option<LibMappings<LibMappingInfo>> {
     let name = format!("perf-{}.map", pid);
-    let path = format!("/tmp/{name}");
-    let Ok(content) = std::fs::read_to_string(&path) else {
-        return None;
-    };
+    let tmp_dir = PathBuf::from("/tmp");
+    let (mut file, path) = lookup_dirs.iter().chain([&tmp_dir]).find_map(|dir| {
+        let path = dir.join(&name);
+        Some((std::fs::File::open(&path).ok()?, path))
+    })?;
+    let mut content = String::new();
+    std::io::Read::read_to_string(&mut file, &mut content).ok()?;
+    let path = path.to_string_lossy().into_owned();
 
     // Read the map file and set everything up so that absolute addresses
     // in JIT code get symbolicated to the right function name.
@@ -112,3 +125,77 @@ pub fn try_load_perf_map(
 
     Some(mappings)
 }
+
+#[cfg(test)]
+mod test {
+    use fxprof_processed_profile::{ReferenceTimestamp, SamplingInterval};
+
+    use super::*;
+
+    #[test]
+    fn perf_map_in_lookup_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        // Use a pid which can't exist, so that there's no such file in /tmp.
+        let pid = u32::MAX;
+        std::fs::write(
+            dir.path().join(format!("perf-{pid}.map")),
+            "7f0000001000 40 py::fib:/home/user/fib.py\n7f0000001040 20 py::main:/home/user/fib.py\n",
+        )
+        .unwrap();
+
+        let mut profile = Profile::new(
+            "test",
+            ReferenceTimestamp::from_millis_since_unix_epoch(0.0),
+            SamplingInterval::from_millis(1),
+        );
+        let mut jit_category_manager = JitCategoryManager::new();
+        assert!(
+            try_load_perf_map(pid, &[], &mut profile, &mut jit_category_manager, None).is_none()
+        );
+
+        let mappings = try_load_perf_map(
+            pid,
+            &[dir.path().to_owned()],
+            &mut profile,
+            &mut jit_category_manager,
+            None,
+        )
+        .unwrap();
+        let (relative_address, _) = mappings.convert_address(0x7f0000001048).unwrap();
+        assert_eq!(relative_address, 0x48);
+        assert!(mappings.convert_address(0x7f0000001060).is_none());
+    }
+
+    #[test]
+    fn perf_map_in_lookup_dir_takes_precedence() {
+        let dir = tempfile::tempdir().unwrap();
+        // A pid which can't exist, so that no process writes this file in /tmp.
+        let pid = u32::MAX - 1;
+        let name = format!("perf-{pid}.map");
+        let tmp_path = PathBuf::from("/tmp").join(&name);
+        std::fs::write(&tmp_path, "7f0000002000 40 unrelated_function\n").unwrap();
+        std::fs::write(
+            dir.path().join(&name),
+            "7f0000001000 40 py::fib:/home/user/fib.py\n",
+        )
+        .unwrap();
+
+        let mut profile = Profile::new(
+            "test",
+            ReferenceTimestamp::from_millis_since_unix_epoch(0.0),
+            SamplingInterval::from_millis(1),
+        );
+        let mut jit_category_manager = JitCategoryManager::new();
+        let mappings = try_load_perf_map(
+            pid,
+            &[dir.path().to_owned()],
+            &mut profile,
+            &mut jit_category_manager,
+            None,
+        );
+        std::fs::remove_file(&tmp_path).unwrap();
+        let mappings = mappings.unwrap();
+        assert!(mappings.convert_address(0x7f0000001010).is_some());
+        assert!(mappings.convert_address(0x7f0000002010).is_none());
+    }
+}
@@ -28,7 +28,7 @@ pub enum RssStatMember {
     ResidentSharedMemoryPages,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub struct ProcessSampleData {
     unresolved_samples: UnresolvedSamples,
     regular_lib_mapping_op_queue: LibMappingOpQueue,
@@ -58,6 +58,39 @@ impl ProcessSampleData {
         self.unresolved_samples.is_empty()
     }
 
+    /// Returns a copy of this data which can be flushed into a snapshot of the
+    /// profile, while `self` is kept for the final profile.
+    pub fn snapshot(&mut self) -> ProcessSampleData {
+        ProcessSampleData {
+            unresolved_samples: self.unresolved_samples.snapshot(),
+            regular_lib_mapping_op_queue: self.regular_lib_mapping_op_queue.clone(),
+            jitdump_lib_mapping_op_queues: self.jitdump_lib_mapping_op_queues.clone(),
+            perf_map_mappings: self.perf_map_mappings.clone(),
+            marker_spans: self.marker_spans.clone(),
+        }
+    }
+
+    /// Flushes the samples of all processes into the profile. This is the last
+    /// step of every importer, once all libraries and JIT symbols are known.
+    pub fn flush_all_samples_to_profile(
+        process_sample_datas: impl IntoIterator<Item = ProcessSampleData>,
+        profile: &mut Profile,
+        user_category: CategoryPairHandle,
+        kernel_category: CategoryPairHandle,
+        stacks: &UnresolvedStacks,
+    ) {
+        let mut stack_frame_scratch_buf = Vec::new();
+        for process_sample_data in process_sample_datas {
+            process_sample_data.flush_samples_to_profile(
+                profile,
+                user_category,
+                kernel_category,
+                &mut stack_frame_scratch_buf,
+                stacks,
+            );
+        }
+    }
+
     #[allow(clippy::too_many_arguments)]
     pub fn flush_samples_to_profile(
         self,
@@ -234,6 +267,154 @@ impl StaticSchemaMarker for OtherEventMarker {
     }
 }
 
+/// A marker for a syscall tracepoint whose arguments we know how to decode,
+/// e.g. `syscalls:sys_enter_openat`.
+#[derive(Debug, Clone)]
+pub struct SyscallTracepointMarker {
+    pub name: StringHandle,
+    pub args: StringHandle,
+}
+
+impl StaticSchemaMarker for SyscallTracepointMarker {
+    const UNIQUE_MARKER_TYPE_NAME: &'static str = "SyscallTracepoint";
+
+    fn schema() -> MarkerSchema {
+        MarkerSchema {
+            type_name: Self::UNIQUE_MARKER_TYPE_NAME.into(),
+            locations: vec![MarkerLocation::MarkerChart, MarkerLocation::MarkerTable],
+            chart_label: Some("{marker.data.args}".into()),
+            tooltip_label: Some("{marker.name}({marker.data.args})".into()),
+            table_label: Some("{marker.data.args}".into()),
+            fields: vec![MarkerFieldSchema {
+                key: "args".into(),
+                label: "Arguments".into(),
+                format: MarkerFieldFormat::String,
+                searchable: true,
+            }],
+            static_fields: vec![MarkerStaticField {
+                label: "Description".into(),
+                value: "Emitted for syscall tracepoints, e.g. syscalls:sys_enter_openat.".into(),
+            }],
+        }
+    }
+
+    fn name(&self, _profile: &mut Profile) -> StringHandle {
+        self.name
+    }
+
+    fn category(&self, _profile: &mut Profile) -> CategoryHandle {
+        CategoryHandle::OTHER
+    }
+
+    fn string_field_value(&self, _field_index: u32) -> StringHandle {
+        self.args
+    }
+
+    fn number_field_value(&self, _field_index: u32) -> f64 {
+        unreachable!()
+    }
+}
+
+/// A marker with the counter values of a sample whose event has
+/// `PERF_SAMPLE_READ`, e.g. the instructions count for each cycles sample
+/// when sampling an event group.
+#[derive(Debug, Clone)]
+pub struct CounterValuesMarker {
+    pub name: StringHandle,
+    /// The values of all events in the group, e.g. `instructions=1234`.
+    pub values: StringHandle,
+}
+
+impl StaticSchemaMarker for CounterValuesMarker {
+    const UNIQUE_MARKER_TYPE_NAME: &'static str = "CounterValues";
+
+    fn schema() -> MarkerSchema {
+        MarkerSchema {
+            type_name: Self::UNIQUE_MARKER_TYPE_NAME.into(),
+            locations: vec![MarkerLocation::MarkerTable],
+            chart_label: None,
+            tooltip_label: Some("{marker.name} - {marker.data.values}".into()),
+            table_label: Some("{marker.data.values}".into()),
+            fields: vec![MarkerFieldSchema {
+                key: "values".into(),
+                label: "Counter values".into(),
+                format: MarkerFieldFormat::String,
+                searchable: true,
+            }],
+            static_fields: vec![MarkerStaticField {
+                label: "Description".into(),
+                value: "The counter values read with each sample, scaled for multiplexing.".into(),
+            }],
+        }
+    }
+
+    fn name(&self, _profile: &mut Profile) -> StringHandle {
+        self.name
+    }
+
+    fn category(&self, _profile: &mut Profile) -> CategoryHandle {
+        CategoryHandle::OTHER
+    }
+
+    fn string_field_value(&self, _field_index: u32) -> StringHandle {
+        self.values
+    }
+
+    fn number_field_value(&self, _field_index: u32) -> f64 {
+        unreachable!()
+    }
+}
+
+/// A marker with the raw instruction pointer of a sample, for assembly-level
+/// analysis.
+#[derive(Debug, Clone)]
+pub struct SampleIpMarker {
+    pub name: StringHandle,
+    /// The absolute address, formatted as hex. Kernel addresses don't fit
+    /// into the f64 of a number field.
+    pub ip: StringHandle,
+}
+
+impl StaticSchemaMarker for SampleIpMarker {
+    const UNIQUE_MARKER_TYPE_NAME: &'static str = "SampleIp";
+
+    fn schema() -> MarkerSchema {
+        MarkerSchema {
+            type_name: Self::UNIQUE_MARKER_TYPE_NAME.into(),
+            locations: vec![MarkerLocation::MarkerTable],
+            chart_label: None,
+            tooltip_label: Some("{marker.name} - {marker.data.ip}".into()),
+            table_label: Some("{marker.data.ip}".into()),
+            fields: vec![MarkerFieldSchema {
+                key: "ip".into(),
+                label: "Instruction pointer".into(),
+                format: MarkerFieldFormat::String,
+                searchable: true,
+            }],
+            static_fields: vec![MarkerStaticField {
+                label: "Description".into(),
+                value: "Emitted for each sample if --sample-ip-markers is used.".into(),
+            }],
+        }
+    }
+
+    fn name(&self, _profile: &mut Profile) -> StringHandle {
+        self.name
+    }
+
+    fn category(&self, _profile: &mut Profile) -> CategoryHandle {
+        CategoryHandle::OTHER
+    }
+
+    fn string_field_value(&self, _field_index: u32) -> StringHandle {
+        self.ip
+    }
+
+    fn number_field_value(&self, _field_index: u32) -> f64 {
+        unreachable!()
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct UserTimingMarker(pub StringHandle);
 
@@ -359,6 +540,151 @@ impl StaticSchemaMarker for SchedSwitchMarkerOnThreadTrack {
     }
 }
 
+/// Emitted for samples with `PERF_SAMPLE_DATA_SRC`, e.g. from `perf mem record`.
+#[derive(Debug, Clone)]
+pub struct MemoryAccessMarker {
+    /// The type of the access, e.g. "Load".
+    pub op: StringHandle,
+    /// The memory hierarchy level which served the access, e.g. "L3 hit".
+    pub level: StringHandle,
+    pub tlb: StringHandle,
+    pub snoop: StringHandle,
+}
+
+impl StaticSchemaMarker for MemoryAccessMarker {
+    const UNIQUE_MARKER_TYPE_NAME: &'static str = "MemoryAccess";
+
+    fn schema() -> MarkerSchema {
+        MarkerSchema {
+            type_name: Self::UNIQUE_MARKER_TYPE_NAME.into(),
+            locations: vec![MarkerLocation::MarkerChart, MarkerLocation::MarkerTable],
+            chart_label: Some("{marker.data.level}".into()),
+            tooltip_label: Some("{marker.name} - {marker.data.level}".into()),
+            table_label: Some(
+                "{marker.name} - {marker.data.level} (TLB: {marker.data.tlb})".into(),
+            ),
+            fields: vec![
+                MarkerFieldSchema {
+                    key: "level".into(),
+                    label: "Memory level".into(),
+                    format: MarkerFieldFormat::String,
+                    searchable: true,
+                },
+                MarkerFieldSchema {
+                    key: "tlb".into(),
+                    label: "TLB".into(),
+                    format: MarkerFieldFormat::String,
+                    searchable: true,
+                },
+                MarkerFieldSchema {
+                    key: "snoop".into(),
+                    label: "Snoop".into(),
+                    format: MarkerFieldFormat::String,
+                    searchable: false,
+                },
+            ],
+            static_fields: vec![MarkerStaticField {
+                label: "Description".into(),
+                value: "Emitted for sampled memory accesses.".into(),
+            }],
+        }
+    }
+
+    fn name(&self, _profile: &mut Profile) -> StringHandle {
+        self.op
+    }
+
+    fn category(&self, _profile: &mut Profile) -> CategoryHandle {
+        CategoryHandle::OTHER
+    }
+
+    fn string_field_value(&self, field_index: u32) -> StringHandle {
+        match field_index {
+            0 => self.level,
+            1 => self.tlb,
+            2 => self.snoop,
+            _ => unreachable!(),
+        }
+    }
+
+    fn number_field_value(&self, _field_index: u32) -> f64 {
+        unreachable!()
+    }
+}
+
+/// Emitted on the waking thread for `sched:sched_waking` / `sched:sched_wakeup` samples.
+#[derive(Debug, Clone)]
+pub struct SchedWakeupMarker {
+    /// The name of the tracepoint, e.g. "sched_waking".
+    pub name: StringHandle,
+    /// The name of the thread that is being woken up.
+    pub wakee_name: StringHandle,
+    pub wakee_tid: i32,
+    pub target_cpu: i32,
+}
+
+impl StaticSchemaMarker for SchedWakeupMarker {
+    const UNIQUE_MARKER_TYPE_NAME: &'static str = "sched_wakeup";
+
+    fn schema() -> MarkerSchema {
+        MarkerSchema {
+            type_name: Self::UNIQUE_MARKER_TYPE_NAME.into(),
+            locations: vec![MarkerLocation::MarkerChart, MarkerLocation::MarkerTable],
+            chart_label: Some("{marker.data.wakee}".into()),
+            tooltip_label: Some(
+                "{marker.name} - {marker.data.wakee} ({marker.data.wakeeTid})".into(),
+            ),
+            table_label: Some(
+                "{marker.name} - {marker.data.wakee} ({marker.data.wakeeTid})".into(),
+            ),
+            fields: vec![
+                MarkerFieldSchema {
+                    key: "wakee".into(),
+                    label: "Woken thread".into(),
+                    format: MarkerFieldFormat::String,
+                    searchable: true,
+                },
+                MarkerFieldSchema {
+                    key: "wakeeTid".into(),
+                    label: "Woken TID".into(),
+                    format: MarkerFieldFormat::Integer,
+                    searchable: true,
+                },
+                MarkerFieldSchema {
+                    key: "targetCpu".into(),
+                    label: "Target CPU".into(),
+                    format: MarkerFieldFormat::Integer,
+                    searchable: false,
+                },
+            ],
+            static_fields: vec![MarkerStaticField {
+                label: "Description".into(),
+                value: "Emitted when this thread wakes up another thread.".into(),
+            }],
+        }
+    }
+
+    fn name(&self, _profile: &mut Profile) -> StringHandle {
+        self.name
+    }
+
+    fn category(&self, _profile: &mut Profile) -> CategoryHandle {
+        CategoryHandle::OTHER
+    }
+
+    fn string_field_value(&self, _field_index: u32) -> StringHandle {
+        self.wakee_name
+    }
+
+    fn number_field_value(&self, field_index: u32) -> f64 {
+        match field_index {
+            1 => self.wakee_tid.into(),
+            2 => self.target_cpu.into(),
+            _ => unreachable!(),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct SimpleMarker(pub StringHandle);
 
@@ -0,0 +1,387 @@
+//! Comparing the per-function sample counts of two profiles, e.g. of a
+//! baseline run and a regressed run of the same program.
+//!
+//! Functions are matched by their symbolicated name, so the two profiles can
+//! come from different builds, and addresses don't need to match. Library
+//! addresses which weren't symbolicated during conversion are looked up with
+//! [`LibSymbols::lookup_profile_addresses`] first.
+
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt;
+
+use debugid::DebugId;
+use fxprof_processed_profile::{Profile, SampleFrame};
+use wholesym::{LookupAddress, SymbolManager};
+
+use super::symbol_precog::wholesym_library_info;
+
+/// Function names for library addresses which weren't symbolicated during
+/// conversion, keyed by the library's debug name and debug ID.
+#[derive(Debug, Clone, Default)]
+pub struct LibSymbols<'a> {
+    libs: HashMap<(&'a str, DebugId), HashMap<u32, String>>,
+}
+
+impl<'a> LibSymbols<'a> {
+    /// Looks up the function names of all unsymbolicated library addresses in
+    /// `profile`. Addresses whose library has no symbols are skipped.
+    pub async fn lookup_profile_addresses(
+        profile: &'a Profile,
+        symbol_manager: &mut SymbolManager,
+    ) -> LibSymbols<'a> {
+        let mut libs = HashMap::new();
+        for (lib, rvas) in profile.lib_used_rva_iter() {
+            symbol_manager.add_known_library(wholesym_library_info(lib));
+            let Ok(symbol_map) = symbol_manager
+                .load_symbol_map(&lib.debug_name, lib.debug_id)
+                .await
+            else {
+                continue;
+            };
+            let mut names = HashMap::new();
+            for &rva in rvas {
+                if let Some(info) = symbol_map.lookup(LookupAddress::Relative(rva)).await {
+                    names.insert(rva, info.symbol.name);
+                }
+            }
+            libs.insert((lib.debug_name.as_str(), lib.debug_id), names);
+        }
+        LibSymbols { libs }
+    }
+
+    /// Adds the function name for an address in a library.
+    #[cfg(test)]
+    pub fn add(&mut self, debug_name: &'a str, debug_id: DebugId, rva: u32, name: String) {
+        self.libs
+            .entry((debug_name, debug_id))
+            .or_default()
+            .insert(rva, name);
+    }
+
+    /// Returns the name which `frame` is matched by. Addresses without a
+    /// symbol are named like in the profiler, but with the library name
+    /// appended so that they don't match addresses in other libraries.
+    fn function_name(&self, frame: SampleFrame<'a>) -> Cow<'a, str> {
+        match frame {
+            SampleFrame::Function(name) => Cow::Borrowed(name),
+            SampleFrame::AddressInLib(lib, rva) => {
+                match self
+                    .libs
+                    .get(&(lib.debug_name.as_str(), lib.debug_id))
+                    .and_then(|names| names.get(&rva))
+                {
+                    Some(name) => Cow::Owned(name.clone()),
+                    None => Cow::Owned(format!("0x{rva:x} ({})", lib.name)),
+                }
+            }
+            SampleFrame::UnknownAddress(address) => Cow::Owned(format!("0x{address:x}")),
+        }
+    }
+}
+
+/// The sample counts of a single function.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FunctionCounts {
+    /// The weight of the samples in which this function was the leaf function.
+    pub self_count: i64,
+    /// The weight of the samples in which this function was anywhere on the
+    /// stack. Recursive calls are only counted once per sample.
+    pub total_count: i64,
+}
+
+/// The sample counts of all functions of one profile, by function name.
+#[derive(Debug, Clone, Default)]
+pub struct FunctionSampleCounts {
+    functions: HashMap<String, FunctionCounts>,
+}
+
+impl FunctionSampleCounts {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Counts the samples of all threads in `profile`. `symbols` supplies the
+    /// names of library addresses which weren't symbolicated during conversion.
+    pub fn from_profile(profile: &Profile, symbols: &LibSymbols) -> Self {
+        let mut counts = Self::new();
+        let mut names = Vec::new();
+        profile.for_each_sample_stack(|frames, weight| {
+            names.clear();
+            names.extend(frames.iter().map(|frame| symbols.function_name(*frame)));
+            let stack: Vec<&str> = names.iter().map(|name| name.as_ref()).collect();
+            counts.add_sample(&stack, weight.into());
+        });
+        counts
+    }
+
+    /// Adds a sample whose stack is already resolved to function names,
+    /// ordered from the root to the leaf.
+    pub fn add_sample(&mut self, stack: &[&str], weight: i64) {
+        for (i, name) in stack.iter().enumerate() {
+            if stack[..i].contains(name) {
+                continue;
+            }
+            let counts = self.functions.entry(name.to_string()).or_default();
+            counts.total_count += weight;
+            if i == stack.len() - 1 {
+                counts.self_count += weight;
+            }
+        }
+    }
+
+    pub fn get(&self, name: &str) -> FunctionCounts {
+        self.functions.get(name).copied().unwrap_or_default()
+    }
+}
+
+/// How the sample counts of a function changed between two profiles.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionDelta {
+    pub name: String,
+    pub before: FunctionCounts,
+    pub after: FunctionCounts,
+}
+
+impl FunctionDelta {
+    pub fn self_delta(&self) -> i64 {
+        self.after.self_count - self.before.self_count
+    }
+
+    pub fn total_delta(&self) -> i64 {
+        self.after.total_count - self.before.total_count
+    }
+}
+
+/// The functions whose sample counts changed between two profiles.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProfileDiff {
+    /// The functions which got hotter, with the largest increase in self
+    /// samples first. Functions with the same self delta are ordered by their
+    /// increase in total samples.
+    pub regressions: Vec<FunctionDelta>,
+    /// The functions which got colder, with the largest decrease first.
+    pub improvements: Vec<FunctionDelta>,
+}
+
+/// Prints the regressions and the improvements as a table, with the change in
+/// self and total samples of each function, and its sample counts before and
+/// after.
+impl fmt::Display for ProfileDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sections = [
+            ("Functions which got hotter", &self.regressions),
+            ("Functions which got colder", &self.improvements),
+        ];
+        for (title, deltas) in sections {
+            writeln!(f, "{title}:")?;
+            if deltas.is_empty() {
+                writeln!(f, "  (none)")?;
+                continue;
+            }
+            writeln!(
+                f,
+                "  {:>8} {:>8} {:>17}  function",
+                "self", "total", "self before/after"
+            )?;
+            for delta in deltas {
+                writeln!(
+                    f,
+                    "  {:>+8} {:>+8} {:>17}  {}",
+                    delta.self_delta(),
+                    delta.total_delta(),
+                    format!("{}/{}", delta.before.self_count, delta.after.self_count),
+                    delta.name
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Compares the sample counts of each function. The counts are not
+/// normalized, so both profiles should cover the same amount of work.
+pub fn diff_function_sample_counts(
+    before: &FunctionSampleCounts,
+    after: &FunctionSampleCounts,
+) -> ProfileDiff {
+    let mut names: Vec<&String> = before.functions.keys().collect();
+    names.extend(
+        after
+            .functions
+            .keys()
+            .filter(|name| !before.functions.contains_key(*name)),
+    );
+
+    let key = |delta: &FunctionDelta| (delta.self_delta(), delta.total_delta());
+    let mut diff = ProfileDiff::default();
+    for name in names {
+        let delta = FunctionDelta {
+            name: name.clone(),
+            before: before.get(name),
+            after: after.get(name),
+        };
+        match key(&delta).cmp(&(0, 0)) {
+            Ordering::Greater => diff.regressions.push(delta),
+            Ordering::Less => diff.improvements.push(delta),
+            Ordering::Equal => {}
+        }
+    }
+    diff.regressions
+        .sort_by(|a, b| key(b).cmp(&key(a)).then_with(|| a.name.cmp(&b.name)));
+    diff.improvements
+        .sort_by(|a, b| key(a).cmp(&key(b)).then_with(|| a.name.cmp(&b.name)));
+    diff
+}
+
+#[cfg(test)]
+mod test {
+    use fxprof_processed_profile::{
+        CategoryColor, CpuDelta, Frame, FrameFlags, FrameInfo, LibraryInfo, ReferenceTimestamp,
+        SamplingInterval, Timestamp,
+    };
+
+    use super::*;
+
+    #[test]
+    fn counts_recursion_once() {
+        let mut counts = FunctionSampleCounts::new();
+        counts.add_sample(&["main", "parse", "parse", "alloc"], 2);
+        counts.add_sample(&["main", "parse"], 1);
+        assert_eq!(
+            counts.get("parse"),
+            FunctionCounts {
+                self_count: 1,
+                total_count: 3
+            }
+        );
+        assert_eq!(
+            counts.get("alloc"),
+            FunctionCounts {
+                self_count: 2,
+                total_count: 2
+            }
+        );
+        assert_eq!(counts.get("missing"), FunctionCounts::default());
+    }
+
+    #[test]
+    fn sorted_regressions_and_improvements() {
+        let mut before = FunctionSampleCounts::new();
+        before.add_sample(&["main", "parse"], 10);
+        before.add_sample(&["main", "render"], 10);
+        before.add_sample(&["main", "layout"], 5);
+        let mut after = FunctionSampleCounts::new();
+        after.add_sample(&["main", "parse"], 30);
+        after.add_sample(&["main", "render"], 8);
+        after.add_sample(&["main", "compress"], 5);
+
+        let diff = diff_function_sample_counts(&before, &after);
+        let regressions: Vec<_> = diff
+            .regressions
+            .iter()
+            .map(|d| (d.name.as_str(), d.self_delta(), d.total_delta()))
+            .collect();
+        assert_eq!(
+            regressions,
+            [("parse", 20, 20), ("compress", 5, 5), ("main", 0, 18)]
+        );
+        let improvements: Vec<_> = diff
+            .improvements
+            .iter()
+            .map(|d| (d.name.as_str(), d.self_delta()))
+            .collect();
+        assert_eq!(improvements, [("layout", -5), ("render", -2)]);
+    }
+
+    #[test]
+    fn print_diff() {
+        let mut before = FunctionSampleCounts::new();
+        before.add_sample(&["main", "render"], 10);
+        let mut after = FunctionSampleCounts::new();
+        after.add_sample(&["main", "parse"], 3);
+        after.add_sample(&["main", "render"], 8);
+
+        let diff = diff_function_sample_counts(&before, &after);
+        assert_eq!(
+            diff.to_string(),
+            "\
+Functions which got hotter:
+      self    total self before/after  function
+        +3       +3               0/3  parse
+        +0       +1               0/0  main
+Functions which got colder:
+      self    total self before/after  function
+        -2       -2              10/8  render
+"
+        );
+        assert_eq!(
+            ProfileDiff::default().to_string(),
+            "Functions which got hotter:\n  (none)\nFunctions which got colder:\n  (none)\n"
+        );
+    }
+
+    #[test]
+    fn matches_functions_by_symbolicated_name() {
+        let lib = LibraryInfo {
+            name: "app".to_string(),
+            debug_name: "app".to_string(),
+            path: "/usr/bin/app".to_string(),
+            debug_path: "/usr/bin/app".to_string(),
+            debug_id: DebugId::nil(),
+            code_id: None,
+            arch: None,
+            symbol_table: None,
+        };
+        let profile_with_sample = |address| {
+            let mut profile = Profile::new(
+                "",
+                ReferenceTimestamp::from_millis_since_unix_epoch(0.0),
+                SamplingInterval::from_millis(1),
+            );
+            let process =
+                profile.add_process("app", 1, Timestamp::from_millis_since_reference(0.0));
+            let thread = profile.add_thread(
+                process,
+                1,
+                Timestamp::from_millis_since_reference(0.0),
+                true,
+            );
+            let lib = profile.add_lib(lib.clone());
+            let category = profile.add_category("Regular", CategoryColor::Blue);
+            let frame = FrameInfo {
+                frame: Frame::RelativeAddressFromInstructionPointer(lib, address),
+                category_pair: category.into(),
+                flags: FrameFlags::empty(),
+            };
+            profile.add_sample(
+                thread,
+                Timestamp::from_millis_since_reference(1.0),
+                std::iter::once(frame),
+                CpuDelta::ZERO,
+                1,
+            );
+            profile
+        };
+
+        // The function moved between the two builds.
+        let before = profile_with_sample(0x1000);
+        let after = profile_with_sample(0x2000);
+        let mut symbols = LibSymbols::default();
+        symbols.add("app", DebugId::nil(), 0x1000, "hot_function".to_string());
+        symbols.add("app", DebugId::nil(), 0x2000, "hot_function".to_string());
+        let diff = diff_function_sample_counts(
+            &FunctionSampleCounts::from_profile(&before, &symbols),
+            &FunctionSampleCounts::from_profile(&after, &symbols),
+        );
+        assert_eq!(diff, ProfileDiff::default());
+
+        let diff = diff_function_sample_counts(
+            &FunctionSampleCounts::from_profile(&before, &symbols),
+            &FunctionSampleCounts::from_profile(&after, &LibSymbols::default()),
+        );
+        assert_eq!(diff.regressions[0].name, "0x2000 (app)");
+        assert_eq!(diff.improvements[0].name, "hot_function");
+    }
+}
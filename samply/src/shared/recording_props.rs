@@ -73,8 +73,25 @@ pub struct ProfileCreationProps {
     pub main_thread_only: bool,
     /// Merge non-overlapping threads of the same name.
     pub reuse_threads: bool,
+    /// When reusing threads, treat thread names which only differ in a
+    /// trailing number as the same name.
+    pub reuse_numbered_threads: bool,
+    /// Keep threads with a reused tid separate, but suffix their names with
+    /// a generation number, e.g. "worker (#2)".
+    #[allow(dead_code)]
+    pub suffix_reused_tids: bool,
+    /// Leave out threads which have neither samples nor markers.
+    #[allow(dead_code)]
+    pub prune_idle_threads: bool,
     /// Fold repeated frames at the base of the stack.
     pub fold_recursive_prefix: bool,
+    /// Fold recursion at the leaf end of stacks into a single frame with a
+    /// recursion depth label.
+    pub fold_leaf_recursion: bool,
+    /// Include kernel frames in sample stacks.
+    pub include_kernel_frames: bool,
+    /// End stacks on which unwinding failed with a truncation marker frame.
+    pub include_truncated_stack_marker: bool,
     /// Unlink jitdump/marker files
     pub unlink_aux_files: bool,
     /// Create a separate thread for each CPU.
@@ -93,11 +110,70 @@ pub struct ProfileCreationProps {
     #[allow(dead_code)]
     pub unknown_event_markers: bool,
     /// Time range to include, relative to start of recording.
-    #[allow(dead_code)]
     pub time_range: Option<(std::time::Duration, std::time::Duration)>,
+    /// Explicit path to the kernel debug image (vmlinux). May contain the
+    /// placeholder `{linux_version}`. Overrides the guessed path.
+    pub vmlinux_debug_path: Option<String>,
+    /// A copy of /proc/kallsyms which was saved at recording time. Used for
+    /// kernel symbols instead of the kallsyms of the running kernel.
+    pub kallsyms_path: Option<PathBuf>,
+    /// File extensions, in addition to the built-in ones like exe and dll,
+    /// of files which may be PE images mapped by Wine.
+    pub extra_pe_extensions: Vec<String>,
+    /// Check mapped files with unknown extensions for the PE `MZ` magic.
+    pub sniff_pe_magic: bool,
+    /// The weight of each off-CPU sample. If None, off-CPU samples have a
+    /// weight of 1 for time-based sampling and 0 otherwise. Never negative.
+    pub off_cpu_weight: Option<i32>,
+    /// If set, records from a perf.data file are passed through a window of
+    /// this many records which restores timestamp order across CPU buffers.
+    pub reorder_window: Option<usize>,
+    /// File names of dynamic linkers, whose frames get their own category.
+    /// A trailing `*` matches any suffix. If empty, common names are used.
+    pub dynamic_linker_names: Vec<String>,
+    /// Add a counter with the number of on-CPU threads, based on context
+    /// switch records.
+    pub cpu_usage_counter: bool,
+    /// Append the syscall which a thread was blocked in as a leaf frame of
+    /// its off-CPU stacks. Needs the raw_syscalls:sys_enter tracepoint.
+    pub off_cpu_syscalls: bool,
+    /// Create samples for the time that threads spend off-CPU, based on
+    /// context switch records.
+    pub include_off_cpu_samples: bool,
+    /// Add a marker with the raw instruction pointer of each sample.
+    pub sample_ip_markers: bool,
+    /// Keep the samples of the idle task (pid 0) in one "swapper/N" thread per
+    /// CPU, instead of dropping them.
+    pub idle_threads: bool,
+    /// The output of `perf stat -I`, whose counter readings are added as
+    /// counter tracks. Only used when importing perf.data files.
+    pub perf_stat_intervals_path: Option<PathBuf>,
+    /// Write how often each branch in the samples' branch stacks was taken,
+    /// and how often it was mispredicted, to this CSV file. Only used when
+    /// importing perf.data files.
+    pub branch_edges_path: Option<PathBuf>,
+    /// Write the IDs, addresses and properties of every module which was
+    /// mapped into a profiled process to this CSV file, including modules
+    /// whose file couldn't be found. Only used when importing perf.data files.
+    pub module_list_path: Option<PathBuf>,
+    /// The sampling interval to declare in the profile, instead of the one
+    /// derived from the recording, e.g. because the samples were resampled.
+    /// Only used when importing perf.data files.
+    pub sampling_interval_override: Option<Duration>,
+    /// Move the unresolved samples of a process to a temporary file whenever
+    /// this many have been collected in memory, to limit memory use.
+    pub spill_samples_threshold: Option<usize>,
+    /// Substrings which are replaced in thread and process names before they're
+    /// stored in the profile, as (from, to) pairs, e.g. to redact file paths.
+    pub name_replacements: Vec<(String, String)>,
+    /// Files to use for the modules with the given ELF build IDs, instead of
+    /// the mapped paths, e.g. unstripped copies of system libraries. A file is
+    /// only used if its build ID matches.
+    pub build_id_path_overrides: Vec<(Vec<u8>, PathBuf)>,
 }
 
 impl ProfileCreationProps {
+    #[cfg(any(target_os = "macos", target_os = "windows"))]
     pub fn profile_name(&self) -> &str {
         self.profile_name
             .as_deref()
@@ -105,6 +181,54 @@ impl ProfileCreationProps {
     }
 }
 
+#[cfg(test)]
+impl ProfileCreationProps {
+    /// Properties for tests, which don't depend on the machine running them.
+    pub fn for_tests() -> Self {
+        Self {
+            profile_name: Some("test".to_string()),
+            fallback_profile_name: "test".to_string(),
+            main_thread_only: false,
+            reuse_threads: false,
+            reuse_numbered_threads: false,
+            suffix_reused_tids: false,
+            prune_idle_threads: false,
+            fold_recursive_prefix: false,
+            fold_leaf_recursion: false,
+            include_kernel_frames: true,
+            include_truncated_stack_marker: false,
+            unlink_aux_files: false,
+            create_per_cpu_threads: false,
+            arg_count_to_include_in_process_name: 0,
+            override_arch: None,
+            unstable_presymbolicate: false,
+            coreclr: Default::default(),
+            unknown_event_markers: false,
+            time_range: None,
+            vmlinux_debug_path: None,
+            // Don't use the kernel symbols of the machine that runs the tests.
+            kallsyms_path: Some(PathBuf::from("/nonexistent/kallsyms")),
+            extra_pe_extensions: vec![],
+            sniff_pe_magic: false,
+            off_cpu_weight: None,
+            reorder_window: None,
+            dynamic_linker_names: vec![],
+            cpu_usage_counter: false,
+            off_cpu_syscalls: false,
+            include_off_cpu_samples: true,
+            sample_ip_markers: false,
+            idle_threads: false,
+            perf_stat_intervals_path: None,
+            branch_edges_path: None,
+            module_list_path: None,
+            sampling_interval_override: None,
+            spill_samples_threshold: None,
+            name_replacements: vec![],
+            build_id_path_overrides: vec![],
+        }
+    }
+}
+
 /// Properties which are meaningful for launching and recording a fresh process.
 #[derive(Debug, Clone)]
 pub struct ProcessLaunchProps {
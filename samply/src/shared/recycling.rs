@@ -36,28 +36,94 @@ impl Ord for ProcessRecyclingData {
 pub type ProcessRecycler = RecyclerByName<ProcessRecyclingData>;
 pub type ThreadRecycler = RecyclerByName<(ThreadHandle, FrameInfo)>;
 
-pub struct RecyclerByName<T: Ord>(FastHashMap<String, BinaryHeap<Reverse<T>>>);
+pub struct RecyclerByName<T: Ord> {
+    pools: FastHashMap<String, BinaryHeap<Reverse<T>>>,
+    /// Whether names which only differ in a trailing number share a pool.
+    merge_numbered_names: bool,
+}
 
 impl<T: Ord> RecyclerByName<T> {
     pub fn new() -> Self {
-        Self(FastHashMap::default())
+        Self::new_merging_numbered_names(false)
+    }
+
+    /// Creates a recycler which, if `merge_numbered_names` is true, treats names
+    /// which only differ in a trailing number as the same name, e.g. "worker-0"
+    /// and "worker-1" from a thread pool.
+    pub fn new_merging_numbered_names(merge_numbered_names: bool) -> Self {
+        Self {
+            pools: FastHashMap::default(),
+            merge_numbered_names,
+        }
+    }
+
+    fn pool_key<'a>(&self, name: &'a str) -> &'a str {
+        if self.merge_numbered_names {
+            strip_trailing_number(name)
+        } else {
+            name
+        }
     }
 
     pub fn add_to_pool(&mut self, name: &str, value: T) {
-        self.0
-            .entry(name.to_string())
+        let key = self.pool_key(name);
+        self.pools
+            .entry(key.to_string())
             .or_default()
             .push(Reverse(value));
     }
 
     pub fn recycle_by_name(&mut self, name: &str) -> Option<T> {
-        let heap = self.0.get_mut(name)?;
+        let key = self.pool_key(name);
+        let heap = self.pools.get_mut(key)?;
         let process: Reverse<T> = heap
             .pop()
             .expect("We only have non-empty BinaryHeaps in this HashMap");
         if heap.is_empty() {
-            self.0.remove(name);
+            self.pools.remove(key);
         }
         Some(process.0)
     }
 }
+
+/// Removes a trailing number from `name`, along with any separators before it,
+/// e.g. "worker-12" becomes "worker". Names which are only a number are
+/// returned unchanged.
+fn strip_trailing_number(name: &str) -> &str {
+    let without_digits = name.trim_end_matches(|c: char| c.is_ascii_digit());
+    if without_digits.len() == name.len() {
+        return name;
+    }
+    let without_separator = without_digits.trim_end_matches(['-', '_', ' ', '#', '.', ':']);
+    if without_separator.is_empty() {
+        return name;
+    }
+    without_separator
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn numbered_names() {
+        assert_eq!(strip_trailing_number("worker-12"), "worker");
+        assert_eq!(
+            strip_trailing_number("tokio-runtime-worker"),
+            "tokio-runtime-worker"
+        );
+        assert_eq!(strip_trailing_number("pool3"), "pool");
+        assert_eq!(strip_trailing_number("v8 thread #4"), "v8 thread");
+        assert_eq!(strip_trailing_number("1234"), "1234");
+
+        let mut recycler = RecyclerByName::new_merging_numbered_names(true);
+        recycler.add_to_pool("worker-0", 1);
+        assert_eq!(recycler.recycle_by_name("worker-1"), Some(1));
+        assert_eq!(recycler.recycle_by_name("worker-1"), None);
+
+        let mut recycler = RecyclerByName::new();
+        recycler.add_to_pool("worker-0", 1);
+        assert_eq!(recycler.recycle_by_name("worker-1"), None);
+        assert_eq!(recycler.recycle_by_name("worker-0"), Some(1));
+    }
+}
@@ -4,7 +4,7 @@ use std::io::BufWriter;
 use std::path::Path;
 
 use flate2::{Compression, GzBuilder};
-use fxprof_processed_profile::Profile;
+use fxprof_processed_profile::{profile_to_json_writer, Profile};
 
 // Level two has an acceptable trade-off between how long compression
 // takes and how much data it saves on the profile JSONs I tested with.
@@ -26,9 +26,9 @@ pub fn save_profile_to_file(profile: &Profile, output_path: &Path) -> std::io::R
         let builder = GzBuilder::new().filename(name_without_gz.as_bytes());
         let gz = builder.write(writer, Compression::new(GZIP_COMPRESSION_LEVEL));
         let gz = BufWriter::new(gz);
-        serde_json::to_writer(gz, &profile)?;
+        profile_to_json_writer(profile, gz)?;
     } else {
-        serde_json::to_writer(writer, &profile)?;
+        profile_to_json_writer(profile, writer)?;
     }
     Ok(())
 }
@@ -1,6 +1,6 @@
 use std::collections::VecDeque;
 
-use fxprof_processed_profile::{CategoryPairHandle, Frame, FrameFlags, FrameInfo};
+use fxprof_processed_profile::{CategoryPairHandle, Frame, FrameFlags, FrameInfo, StringHandle};
 
 use super::jit_category_manager::{JsFrame, JsName};
 use super::lib_mappings::{AndroidArtInfo, LibMappingsHierarchy};
@@ -13,10 +13,13 @@ pub struct StackConverter {
     libart_frame_buffer: VecDeque<SecondPassFrameInfo>,
 }
 
-struct FirstPassFrameInfo {
-    mode: StackMode,
-    lookup_address: u64,
-    from_ip: bool,
+enum FirstPassFrameInfo {
+    Address {
+        mode: StackMode,
+        lookup_address: u64,
+        from_ip: bool,
+    },
+    Label(StringHandle, StackMode),
 }
 
 #[derive(Debug)]
@@ -63,8 +66,11 @@ impl<I: Iterator<Item = StackFrame>> Iterator for FirstPassIter<I> {
                 StackFrame::ReturnAddress(addr, mode) => (mode, addr.saturating_sub(1), false),
                 StackFrame::AdjustedReturnAddress(addr, mode) => (mode, addr, false),
                 StackFrame::TruncatedStackMarker => continue,
+                StackFrame::Label(name, mode) => {
+                    return Some(FirstPassFrameInfo::Label(name, mode))
+                }
             };
-            return Some(FirstPassFrameInfo {
+            return Some(FirstPassFrameInfo::Address {
                 mode,
                 lookup_address,
                 from_ip,
@@ -81,11 +87,25 @@ impl<'a, I: Iterator<Item = FirstPassFrameInfo>> Iterator for SecondPassIter<'a,
     }
 
     fn next(&mut self) -> Option<Self::Item> {
-        let FirstPassFrameInfo {
-            mode,
-            lookup_address,
-            from_ip,
-        } = self.inner.next()?;
+        let (mode, lookup_address, from_ip) = match self.inner.next()? {
+            FirstPassFrameInfo::Address {
+                mode,
+                lookup_address,
+                from_ip,
+            } => (mode, lookup_address, from_ip),
+            FirstPassFrameInfo::Label(name, mode) => {
+                let category = match mode {
+                    StackMode::User => self.user_category,
+                    StackMode::Kernel => self.kernel_category,
+                };
+                return Some(SecondPassFrameInfo {
+                    location: Frame::Label(name),
+                    category,
+                    js_frame: None,
+                    art_info: None,
+                });
+            }
+        };
         let (location, category, js_frame, art_info) = match mode {
             StackMode::User => match self.lib_mappings.convert_address(lookup_address) {
                 Some((relative_lookup_address, info)) => {
@@ -160,6 +160,8 @@ struct PrecogLibrarySymbols {
     string_table: Option<Arc<StringTable>>,
 }
 
+/// The symbols for the library addresses in a profile, keyed by library, as
+/// stored in the `.syms.json` file next to the profile.
 pub struct PrecogSymbolInfo {
     string_table: Arc<StringTable>,
     data: Vec<PrecogLibrarySymbols>,
@@ -309,6 +311,14 @@ impl PrecogSymbolInfo {
         serde_json::from_reader(reader).expect("failed to parse sidecar syms.json")
     }
 
+    /// Writes the symbols to a sidecar JSON file, which can be loaded with
+    /// [`PrecogSymbolInfo::try_load`].
+    pub fn save(&self, path: &Path) {
+        let file = File::create(path).unwrap();
+        let writer = BufWriter::new(file);
+        to_writer(writer, self).expect("Couldn't write JSON for presymbolication");
+    }
+
     pub fn into_hash_map(
         self,
     ) -> HashMap<DebugId, Arc<dyn wholesym::samply_symbols::SymbolMapTrait + Send + Sync>> {
@@ -325,95 +335,175 @@ impl PrecogSymbolInfo {
     }
 }
 
-pub fn presymbolicate(profile: &fxprof_processed_profile::Profile, precog_output: &Path) {
-    let rt = tokio::runtime::Runtime::new().unwrap();
+/// Converts the library info from the profile into the info which wholesym
+/// needs to find the library's symbols.
+pub fn wholesym_library_info(lib: &fxprof_processed_profile::LibraryInfo) -> wholesym::LibraryInfo {
+    wholesym::LibraryInfo {
+        name: Some(lib.debug_name.clone()),
+        path: Some(lib.path.clone()),
+        debug_path: Some(lib.debug_path.clone()),
+        debug_id: Some(lib.debug_id),
+        arch: lib.arch.clone(),
+        debug_name: Some(lib.debug_name.clone()),
+        code_id: lib
+            .code_id
+            .as_ref()
+            .map(|id| wholesym::CodeId::from_str(id).expect("bad codeid")),
+    }
+}
 
+/// Looks up the symbols for all library addresses in `profile` which weren't
+/// symbolicated during conversion, using `symbol_manager`.
+///
+/// This is the symbolication pass of [`presymbolicate`] as a standalone step,
+/// so that an already-converted profile can be symbolicated later, for example
+/// once the symbols are available from a symbol server. Libraries whose
+/// symbols can't be found are skipped.
+///
+/// The profile itself is not modified, and no symbolicated profile is
+/// returned: the frames of a [`Profile`](fxprof_processed_profile::Profile)
+/// get their symbols when their samples are added, so existing frames can't be
+/// symbolicated afterwards. Instead, the symbols are returned as a
+/// [`PrecogSymbolInfo`], which can be saved next to the profile and is used by
+/// the server to answer symbol requests for it.
+pub async fn symbolicate_profile(
+    profile: &fxprof_processed_profile::Profile,
+    symbol_manager: &mut wholesym::SymbolManager,
+) -> PrecogSymbolInfo {
     let mut string_table = StringTable::new();
     let mut results = Vec::new();
 
-    let config = wholesym::SymbolManagerConfig::new()
-        .use_spotlight(true)
-        // .verbose(true)
-        .respect_nt_symbol_path(true);
-    let mut symbol_manager = wholesym::SymbolManager::with_config(config);
-
     for (lib, rvas) in profile.lib_used_rva_iter() {
         // Add the library to the symbol manager with all the info, so that load_symbol_map can find it later
-        symbol_manager.add_known_library(wholesym::LibraryInfo {
-            name: Some(lib.debug_name.clone()),
-            path: Some(lib.path.clone()),
-            debug_path: Some(lib.debug_path.clone()),
-            debug_id: Some(lib.debug_id),
-            arch: lib.arch.clone(),
-            debug_name: Some(lib.debug_name.clone()),
-            code_id: lib
-                .code_id
-                .as_ref()
-                .map(|id| wholesym::CodeId::from_str(id).expect("bad codeid")),
-        });
+        symbol_manager.add_known_library(wholesym_library_info(lib));
 
         //eprintln!("Library {} ({}) has {} rvas", lib.debug_name, lib.debug_id, rvas.len());
 
-        let result = rt.block_on(async {
-            let Ok(symbol_map) = symbol_manager
-                .load_symbol_map(&lib.debug_name, lib.debug_id)
+        let Ok(symbol_map) = symbol_manager
+            .load_symbol_map(&lib.debug_name, lib.debug_id)
+            .await
+        else {
+            //eprintln!("Couldn't load symbol map for {} at {} {} ({})", lib.debug_name, lib.path, lib.debug_path, lib.debug_id);
+            continue;
+        };
+
+        let mut symbol_table = Vec::new();
+        let mut symbol_table_map = HashMap::new();
+
+        let mut known_addresses = Vec::new();
+        for rva in rvas {
+            if let Some(addr_info) = symbol_map
+                .lookup(wholesym::LookupAddress::Relative(*rva))
                 .await
-            else {
-                //eprintln!("Couldn't load symbol map for {} at {} {} ({})", lib.debug_name, lib.path, lib.debug_path, lib.debug_id);
-                return None;
-            };
-
-            let mut symbol_table = Vec::new();
-            let mut symbol_table_map = HashMap::new();
-
-            let mut known_addresses = Vec::new();
-            for rva in rvas {
-                if let Some(addr_info) = symbol_map
-                    .lookup(wholesym::LookupAddress::Relative(*rva))
-                    .await
-                {
-                    let index = symbol_table_map
-                        .entry(addr_info.symbol.address)
-                        .or_insert_with(|| {
-                            let info = InternedSymbolInfo::new(&addr_info, &mut string_table);
-                            symbol_table.push(info);
-                            symbol_table.len() - 1
-                        });
-                    known_addresses.push((*rva, *index));
-                }
+            {
+                let index = symbol_table_map
+                    .entry(addr_info.symbol.address)
+                    .or_insert_with(|| {
+                        let info = InternedSymbolInfo::new(&addr_info, &mut string_table);
+                        symbol_table.push(info);
+                        symbol_table.len() - 1
+                    });
+                known_addresses.push((*rva, *index));
             }
+        }
 
-            Some(PrecogLibrarySymbols {
-                debug_name: lib.debug_name.clone(),
-                debug_id: lib.debug_id.to_string(),
-                code_id: lib
-                    .code_id
-                    .as_ref()
-                    .map(|id| id.to_string())
-                    .unwrap_or("".to_owned()),
-                symbol_table,
-                known_addresses,
-                string_table: None,
-            })
+        results.push(PrecogLibrarySymbols {
+            debug_name: lib.debug_name.clone(),
+            debug_id: lib.debug_id.to_string(),
+            code_id: lib
+                .code_id
+                .as_ref()
+                .map(|id| id.to_string())
+                .unwrap_or("".to_owned()),
+            symbol_table,
+            known_addresses,
+            string_table: None,
         });
+    }
 
-        if let Some(result) = result {
-            results.push(result);
-        }
+    let string_table = Arc::new(string_table);
+    for lib in &mut results {
+        lib.string_table = Some(string_table.clone());
     }
+    PrecogSymbolInfo {
+        string_table,
+        data: results,
+    }
+}
 
-    {
-        let string_table = Arc::new(string_table);
-        for lib in &mut results {
-            lib.string_table = Some(string_table.clone());
-        }
-        let info = PrecogSymbolInfo {
-            string_table,
-            data: results,
-        };
+pub fn presymbolicate(profile: &fxprof_processed_profile::Profile, precog_output: &Path) {
+    let rt = tokio::runtime::Runtime::new().unwrap();
 
-        let file = File::create(precog_output).unwrap();
-        let writer = BufWriter::new(file);
-        to_writer(writer, &info).expect("Couldn't write JSON for presymbolication");
+    let config = wholesym::SymbolManagerConfig::new()
+        .use_spotlight(true)
+        // .verbose(true)
+        .respect_nt_symbol_path(true);
+    let mut symbol_manager = wholesym::SymbolManager::with_config(config);
+
+    let info = rt.block_on(symbolicate_profile(profile, &mut symbol_manager));
+    info.save(precog_output);
+}
+
+#[cfg(test)]
+mod test {
+    use fxprof_processed_profile::{
+        CategoryColor, CpuDelta, Frame, FrameFlags, FrameInfo, LibraryInfo, Profile,
+        ReferenceTimestamp, SamplingInterval, Timestamp,
+    };
+
+    use super::*;
+
+    #[test]
+    fn symbolicate_converted_profile() {
+        let ls_path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("../fixtures/other/ls-linux/ls")
+            .to_string_lossy()
+            .into_owned();
+        let debug_id = DebugId::from_breakpad("3E0A2663466E57DBABF718F6A3562C6E0").unwrap();
+        let mut profile = Profile::new(
+            "",
+            ReferenceTimestamp::from_millis_since_unix_epoch(0.0),
+            SamplingInterval::from_millis(1),
+        );
+        let process = profile.add_process("ls", 1, Timestamp::from_millis_since_reference(0.0));
+        let thread = profile.add_thread(
+            process,
+            1,
+            Timestamp::from_millis_since_reference(0.0),
+            true,
+        );
+        let lib = profile.add_lib(LibraryInfo {
+            name: "ls".to_string(),
+            debug_name: "ls".to_string(),
+            path: ls_path.clone(),
+            debug_path: ls_path,
+            debug_id,
+            code_id: None,
+            arch: None,
+            symbol_table: None,
+        });
+        let category = profile.add_category("Regular", CategoryColor::Blue);
+        let frame = FrameInfo {
+            frame: Frame::RelativeAddressFromInstructionPointer(lib, 0xd6f4),
+            category_pair: category.into(),
+            flags: FrameFlags::empty(),
+        };
+        profile.add_sample(
+            thread,
+            Timestamp::from_millis_since_reference(1.0),
+            std::iter::once(frame),
+            CpuDelta::ZERO,
+            1,
+        );
+
+        let mut symbol_manager = wholesym::SymbolManager::with_config(Default::default());
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let info = rt.block_on(symbolicate_profile(&profile, &mut symbol_manager));
+        let symbol_map = &info.into_hash_map()[&debug_id];
+        let address_info = symbol_map
+            .lookup_sync(wholesym::LookupAddress::Relative(0xd6f4))
+            .unwrap();
+        assert_eq!(address_info.symbol.name, "gobble_file.constprop.0");
+        assert_eq!(address_info.symbol.address, 0xd5d4);
     }
 }
@@ -1,5 +1,8 @@
 use std::path::PathBuf;
 
+use wholesym::debugid::DebugId;
+use wholesym::SymbolNameOverrideKey;
+
 #[derive(Debug, Clone)]
 pub struct SymbolProps {
     /// Extra directories containing symbol files
@@ -8,6 +11,8 @@ pub struct SymbolProps {
     pub windows_symbol_server: Vec<String>,
     /// Overrides the default cache directory for Windows symbol files which were downloaded from a symbol server
     pub windows_symbol_cache: Option<PathBuf>,
+    /// Request lowercase file names and identifiers from Windows and Breakpad symbol servers
+    pub lowercase_symbol_server_paths: bool,
     /// Additional URLs of symbol servers serving Breakpad .sym files
     pub breakpad_symbol_server: Vec<String>,
     /// Additional local directories containing Breakpad .sym files
@@ -16,4 +21,15 @@ pub struct SymbolProps {
     pub breakpad_symbol_cache: Option<PathBuf>,
     /// Extra directory containing symbol files, with the directory structure used by simpleperf's scripts
     pub simpleperf_binary_cache: Option<PathBuf>,
+    /// Extra directories containing a .build-id tree of separate debug files
+    pub build_id_debug_root: Vec<PathBuf>,
+    /// The directory used by `perf buildid-cache`, usually ~/.debug
+    pub perf_buildid_cache: Option<PathBuf>,
+    /// Extra directories containing .dwp files for binaries built with split DWARF
+    pub dwp_dir: Vec<PathBuf>,
+    /// Bearer tokens for symbol servers, as `URL_PREFIX=ENV_VAR` pairs
+    pub symbol_server_token_env: Vec<String>,
+    /// Display names which replace the names of specific symbols, by the debug
+    /// ID of the library
+    pub symbol_name_overrides: Vec<(DebugId, SymbolNameOverrideKey, String)>,
 }
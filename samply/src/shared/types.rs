@@ -2,6 +2,7 @@ use std::collections::HashMap;
 use std::hash::BuildHasherDefault;
 
 use fxhash::FxHasher;
+use fxprof_processed_profile::StringHandle;
 use linux_perf_data::linux_perf_event_reader;
 use linux_perf_event_reader::constants::{
     PERF_CONTEXT_GUEST, PERF_CONTEXT_GUEST_KERNEL, PERF_CONTEXT_GUEST_USER, PERF_CONTEXT_KERNEL,
@@ -47,6 +48,9 @@ pub enum StackFrame {
     ReturnAddress(u64, StackMode),
     AdjustedReturnAddress(u64, StackMode),
     TruncatedStackMarker,
+    /// A synthetic frame with a fixed name, e.g. the syscall which an off-CPU
+    /// thread was blocked in. The mode determines the frame's category.
+    Label(StringHandle, StackMode),
 }
 
 impl StackFrame {
@@ -55,6 +59,7 @@ impl StackFrame {
             StackFrame::InstructionPointer(_, stack_mode) => Some(*stack_mode),
             StackFrame::ReturnAddress(_, stack_mode) => Some(*stack_mode),
             StackFrame::AdjustedReturnAddress(_, stack_mode) => Some(*stack_mode),
+            StackFrame::Label(_, stack_mode) => Some(*stack_mode),
             StackFrame::TruncatedStackMarker => None,
         }
     }
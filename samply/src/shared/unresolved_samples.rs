@@ -1,13 +1,22 @@
 use std::collections::hash_map::Entry;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
 
-use fxprof_processed_profile::{CpuDelta, FrameInfo, MarkerHandle, ThreadHandle, Timestamp};
+use fxprof_processed_profile::{
+    CpuDelta, FrameInfo, MarkerHandle, Profile, StringHandle, ThreadHandle, Timestamp,
+};
 
 use super::types::{FastHashMap, StackFrame, StackMode};
 
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Default)]
 pub struct UnresolvedSamples {
     samples_and_markers: Vec<UnresolvedSampleOrMarker>,
     prev_sample_info_per_thread: FastHashMap<ThreadHandle, PreviousSampleInfo>,
+    /// Once this many samples and markers are buffered in memory, they are
+    /// moved to `spill_file`.
+    spill_threshold: Option<usize>,
+    spill_file: Option<SpillFile>,
 }
 
 #[derive(Debug, Clone)]
@@ -17,12 +26,85 @@ struct PreviousSampleInfo {
 }
 
 impl UnresolvedSamples {
-    pub fn into_inner(self) -> Vec<UnresolvedSampleOrMarker> {
-        self.samples_and_markers
+    /// Create an empty sample table which moves its samples to a temporary
+    /// file on disk whenever `spill_threshold` samples and markers have
+    /// accumulated in memory. This bounds the memory use for huge profiles.
+    /// With `None`, everything is kept in memory.
+    pub fn with_spill_threshold(spill_threshold: Option<usize>) -> Self {
+        Self {
+            spill_threshold: spill_threshold.map(|threshold| threshold.max(1)),
+            ..Default::default()
+        }
+    }
+
+    /// Returns all samples and markers in the order in which they were added,
+    /// reading back the ones which were moved to disk.
+    pub fn into_inner(self) -> impl Iterator<Item = UnresolvedSampleOrMarker> {
+        let spilled = self.spill_file.map(|spill_file| {
+            spill_file
+                .into_records()
+                .expect("Couldn't read back the samples from the temporary file")
+        });
+        spilled
+            .into_iter()
+            .flatten()
+            .chain(self.samples_and_markers)
+    }
+
+    /// Returns an in-memory copy of all samples and markers added so far,
+    /// including the ones which were moved to disk. `self` is left unchanged
+    /// and can keep accumulating samples.
+    pub fn snapshot(&mut self) -> UnresolvedSamples {
+        let mut samples_and_markers = match &mut self.spill_file {
+            Some(spill_file) => spill_file
+                .read_records()
+                .expect("Couldn't read back the samples from the temporary file"),
+            None => Vec::new(),
+        };
+        samples_and_markers.extend(self.samples_and_markers.iter().cloned());
+        UnresolvedSamples {
+            samples_and_markers,
+            ..Default::default()
+        }
     }
 
     pub fn is_empty(&self) -> bool {
         self.samples_and_markers.is_empty()
+            && self
+                .spill_file
+                .as_ref()
+                .map_or(true, |spill_file| spill_file.record_count == 0)
+    }
+
+    /// Moves the buffered samples and markers to the spill file if there are
+    /// enough of them. Must be called before computing the index of a new entry.
+    fn spill_if_needed(&mut self) {
+        let Some(threshold) = self.spill_threshold else {
+            return;
+        };
+        if self.samples_and_markers.len() < threshold {
+            return;
+        }
+        let result = match &mut self.spill_file {
+            Some(spill_file) => spill_file.append(&self.samples_and_markers),
+            None => SpillFile::new().and_then(|spill_file| {
+                self.spill_file
+                    .insert(spill_file)
+                    .append(&self.samples_and_markers)
+            }),
+        };
+        if let Err(err) = result {
+            eprintln!("Could not move samples to a temporary file, keeping them in memory: {err}");
+            self.spill_threshold = None;
+            return;
+        }
+        self.samples_and_markers.clear();
+        // The indexes of the buffered samples are no longer valid. This means that
+        // the next zero-CPU sample on each thread starts a new sample rather than
+        // being merged into a spilled one.
+        for sample_info in self.prev_sample_info_per_thread.values_mut() {
+            sample_info.prev_sample_index_if_zero_cpu = None;
+        }
     }
 
     #[allow(clippy::too_many_arguments)]
@@ -36,6 +118,7 @@ impl UnresolvedSamples {
         weight: i32,
         extra_label_frame: Option<FrameInfo>,
     ) {
+        self.spill_if_needed();
         let sample_index = self.samples_and_markers.len();
         self.samples_and_markers.push(UnresolvedSampleOrMarker {
             thread_handle,
@@ -64,6 +147,7 @@ impl UnresolvedSamples {
         weight: i32,
         extra_label_frame: Option<FrameInfo>,
     ) {
+        self.spill_if_needed();
         match self.prev_sample_info_per_thread.entry(thread_handle) {
             Entry::Occupied(mut entry) => {
                 let sample_info = entry.get_mut();
@@ -121,6 +205,7 @@ impl UnresolvedSamples {
         stack: UnresolvedStackHandle,
         marker_handle: MarkerHandle,
     ) {
+        self.spill_if_needed();
         self.samples_and_markers.push(UnresolvedSampleOrMarker {
             thread_handle,
             timestamp,
@@ -154,6 +239,158 @@ pub struct SampleData {
     pub weight: i32,
 }
 
+/// A temporary file with samples and markers which were moved out of memory.
+/// Each entry is stored as a fixed-size record.
+#[derive(Debug)]
+struct SpillFile {
+    writer: BufWriter<File>,
+    record_count: usize,
+    tables: SpillTables,
+}
+
+/// Thread handles, label frames and marker handles can't be serialized, so
+/// the records refer to them by their index in these tables, which stay in
+/// memory. There are only a few distinct threads and label frames per process.
+#[derive(Debug, Default)]
+struct SpillTables {
+    threads: Vec<ThreadHandle>,
+    thread_indexes: FastHashMap<ThreadHandle, u32>,
+    label_frames: Vec<FrameInfo>,
+    label_frame_indexes: BTreeMap<FrameInfo, u32>,
+    markers: Vec<MarkerHandle>,
+}
+
+/// thread, stack, timestamp, timestamp_mono, label frame, kind, weight or
+/// marker index, CPU delta
+const SPILL_RECORD_SIZE: usize = 4 + 4 + 8 + 8 + 4 + 4 + 4 + 8;
+
+impl SpillFile {
+    fn new() -> std::io::Result<Self> {
+        Ok(Self {
+            writer: BufWriter::new(tempfile::tempfile()?),
+            record_count: 0,
+            tables: SpillTables::default(),
+        })
+    }
+
+    fn append(&mut self, entries: &[UnresolvedSampleOrMarker]) -> std::io::Result<()> {
+        for entry in entries {
+            let record = self.tables.encode(entry);
+            self.writer.write_all(&record)?;
+        }
+        self.writer.flush()?;
+        self.record_count += entries.len();
+        Ok(())
+    }
+
+    /// Reads all records which were appended so far, and leaves the file
+    /// positioned so that more records can be appended afterwards.
+    fn read_records(&mut self) -> std::io::Result<Vec<UnresolvedSampleOrMarker>> {
+        self.writer.flush()?;
+        let file = self.writer.get_mut();
+        file.rewind()?;
+        let mut reader = BufReader::new(&*file);
+        let mut records = Vec::with_capacity(self.record_count);
+        for _ in 0..self.record_count {
+            let mut record = [0; SPILL_RECORD_SIZE];
+            reader.read_exact(&mut record)?;
+            records.push(self.tables.decode(&record));
+        }
+        drop(reader);
+        file.seek(SeekFrom::End(0))?;
+        Ok(records)
+    }
+
+    /// Reads all records back, in the order in which they were appended.
+    fn into_records(self) -> std::io::Result<impl Iterator<Item = UnresolvedSampleOrMarker>> {
+        let Self {
+            writer,
+            record_count,
+            tables,
+        } = self;
+        let mut file = writer.into_inner().map_err(|err| err.into_error())?;
+        file.rewind()?;
+        let mut reader = BufReader::new(file);
+        Ok((0..record_count).map(move |_| {
+            let mut record = [0; SPILL_RECORD_SIZE];
+            reader
+                .read_exact(&mut record)
+                .expect("Couldn't read back a sample from the temporary file");
+            tables.decode(&record)
+        }))
+    }
+}
+
+impl SpillTables {
+    const NO_LABEL_FRAME: u32 = u32::MAX;
+    const KIND_SAMPLE: u32 = 0;
+    const KIND_MARKER: u32 = 1;
+
+    fn encode(&mut self, entry: &UnresolvedSampleOrMarker) -> [u8; SPILL_RECORD_SIZE] {
+        let thread_index = *self
+            .thread_indexes
+            .entry(entry.thread_handle)
+            .or_insert_with(|| {
+                self.threads.push(entry.thread_handle);
+                (self.threads.len() - 1) as u32
+            });
+        let label_frame_index = match &entry.extra_label_frame {
+            Some(frame) => match self.label_frame_indexes.get(frame) {
+                Some(index) => *index,
+                None => {
+                    let index = self.label_frames.len() as u32;
+                    self.label_frames.push(frame.clone());
+                    self.label_frame_indexes.insert(frame.clone(), index);
+                    index
+                }
+            },
+            None => Self::NO_LABEL_FRAME,
+        };
+        let (kind, value, cpu_delta) = match &entry.sample_or_marker {
+            SampleOrMarker::Sample(SampleData { cpu_delta, weight }) => {
+                (Self::KIND_SAMPLE, *weight as u32, cpu_delta.as_micros())
+            }
+            SampleOrMarker::MarkerHandle(marker_handle) => {
+                self.markers.push(*marker_handle);
+                (Self::KIND_MARKER, (self.markers.len() - 1) as u32, 0)
+            }
+        };
+
+        let mut record = [0; SPILL_RECORD_SIZE];
+        record[0..4].copy_from_slice(&thread_index.to_le_bytes());
+        record[4..8].copy_from_slice(&entry.stack.0.to_le_bytes());
+        record[8..16].copy_from_slice(&entry.timestamp.nanos_since_reference().to_le_bytes());
+        record[16..24].copy_from_slice(&entry.timestamp_mono.to_le_bytes());
+        record[24..28].copy_from_slice(&label_frame_index.to_le_bytes());
+        record[28..32].copy_from_slice(&kind.to_le_bytes());
+        record[32..36].copy_from_slice(&value.to_le_bytes());
+        record[36..44].copy_from_slice(&cpu_delta.to_le_bytes());
+        record
+    }
+
+    fn decode(&self, record: &[u8; SPILL_RECORD_SIZE]) -> UnresolvedSampleOrMarker {
+        let u32_at = |offset: usize| u32::from_le_bytes(record[offset..][..4].try_into().unwrap());
+        let u64_at = |offset: usize| u64::from_le_bytes(record[offset..][..8].try_into().unwrap());
+        let label_frame_index = u32_at(24);
+        let sample_or_marker = match u32_at(28) {
+            Self::KIND_MARKER => SampleOrMarker::MarkerHandle(self.markers[u32_at(32) as usize]),
+            _ => SampleOrMarker::Sample(SampleData {
+                weight: u32_at(32) as i32,
+                cpu_delta: CpuDelta::from_micros(u64_at(36)),
+            }),
+        };
+        UnresolvedSampleOrMarker {
+            thread_handle: self.threads[u32_at(0) as usize],
+            stack: UnresolvedStackHandle(u32_at(4)),
+            timestamp: Timestamp::from_nanos_since_reference(u64_at(8)),
+            timestamp_mono: u64_at(16),
+            extra_label_frame: (label_frame_index != Self::NO_LABEL_FRAME)
+                .then(|| self.label_frames[label_frame_index as usize].clone()),
+            sample_or_marker,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
 pub struct UnresolvedStackHandle(u32);
 
@@ -166,13 +403,34 @@ impl UnresolvedStackHandle {
 pub struct UnresolvedStacks {
     pub stacks: Vec<(UnresolvedStackHandle, StackFrame)>, // (prefix, frame)
     pub stack_lookup: FastHashMap<(UnresolvedStackHandle, StackFrame), UnresolvedStackHandle>, // (prefix, frame) -> stack index
+    leaf_recursion_folding: Option<LeafRecursionFolding>,
+    scratch_frames: Vec<StackFrame>,
 }
 
 impl UnresolvedStacks {
+    /// Create an empty stack table. If `fold_leaf_recursion` is true, `convert`
+    /// folds recursion at the leaf end of stacks, see [`LeafRecursionFolding`].
+    pub fn new(fold_leaf_recursion: bool, profile: &mut Profile) -> Self {
+        Self {
+            leaf_recursion_folding: fold_leaf_recursion.then(|| LeafRecursionFolding::new(profile)),
+            ..Default::default()
+        }
+    }
+
     /// Get the `UnresolvedStackHandle` for a stack. The stack must be ordered from
     /// caller-most to callee-most ("outside to inside").
     pub fn convert(&mut self, frames: impl Iterator<Item = StackFrame>) -> UnresolvedStackHandle {
-        self.convert_with_prefix(UnresolvedStackHandle::EMPTY, frames)
+        let Some(folding) = &self.leaf_recursion_folding else {
+            return self.convert_with_prefix(UnresolvedStackHandle::EMPTY, frames);
+        };
+        let mut scratch_frames = std::mem::take(&mut self.scratch_frames);
+        scratch_frames.clear();
+        scratch_frames.extend(frames);
+        folding.fold(&mut scratch_frames);
+        let stack =
+            self.convert_with_prefix(UnresolvedStackHandle::EMPTY, scratch_frames.drain(..));
+        self.scratch_frames = scratch_frames;
+        stack
     }
 
     pub fn convert_with_prefix(
@@ -220,3 +478,189 @@ impl UnresolvedStacks {
         }
     }
 }
+
+/// Folds stacks which only differ in the depth of the recursion at their leaf
+/// end, to keep the number of unique stacks small for recursion-heavy code.
+///
+/// The run of identical frames closest to the leaf is replaced with a single
+/// frame, below a label frame with the recursion depth. Depths are put into
+/// power-of-two buckets, so stacks with a recursion depth of 8 and 15 end up
+/// as the same stack. Every sample keeps its stack, so no weight is lost.
+#[derive(Debug, Clone)]
+pub struct LeafRecursionFolding {
+    /// The label for depths from `2^(i+1)` to `2^(i+2) - 1` at index `i`.
+    /// The last label is used for all deeper recursion.
+    depth_labels: Vec<StringHandle>,
+}
+
+impl LeafRecursionFolding {
+    const BUCKET_COUNT: usize = 16;
+
+    pub fn new(profile: &mut Profile) -> Self {
+        let depth_labels = (0..Self::BUCKET_COUNT)
+            .map(|i| {
+                let min_depth = 1usize << (i + 1);
+                let label = if i + 1 == Self::BUCKET_COUNT {
+                    format!("Recursion depth >= {min_depth}")
+                } else {
+                    format!("Recursion depth {min_depth}-{}", 2 * min_depth - 1)
+                };
+                profile.intern_string(&label)
+            })
+            .collect();
+        Self { depth_labels }
+    }
+
+    fn label_for_depth(&self, depth: usize) -> StringHandle {
+        let bucket = (depth.ilog2() as usize).saturating_sub(1);
+        self.depth_labels[bucket.min(Self::BUCKET_COUNT - 1)]
+    }
+
+    /// `frames` must be ordered from caller-most to callee-most.
+    fn fold(&self, frames: &mut Vec<StackFrame>) {
+        let len = frames.len();
+        if len < 2 {
+            return;
+        }
+        // The leaf frame is usually the instruction pointer inside the recursive
+        // function, which differs from the return addresses of the recursive calls.
+        // Look at the frames above it in that case.
+        let run_end = if frames[len - 1] == frames[len - 2] {
+            len
+        } else {
+            len - 1
+        };
+        let frame = frames[run_end - 1];
+        let run_start = frames[..run_end]
+            .iter()
+            .rposition(|f| *f != frame)
+            .map_or(0, |i| i + 1);
+        let depth = run_end - run_start;
+        if depth < 2 {
+            return;
+        }
+        let label = StackFrame::Label(self.label_for_depth(depth), StackMode::User);
+        frames.splice(run_start..run_end - 1, [label]);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use fxprof_processed_profile::{
+        CategoryHandle, Frame, FrameFlags, MarkerTiming, ReferenceTimestamp, SamplingInterval,
+    };
+
+    use super::*;
+    use crate::shared::process_sample_data::UserTimingMarker;
+
+    fn recursive_stack(depth: usize) -> Vec<StackFrame> {
+        let mut frames = vec![StackFrame::ReturnAddress(0x1000, StackMode::User)];
+        frames.extend(
+            std::iter::repeat(StackFrame::ReturnAddress(0x2000, StackMode::User)).take(depth),
+        );
+        frames.push(StackFrame::InstructionPointer(0x2010, StackMode::User));
+        frames
+    }
+
+    #[test]
+    fn leaf_recursion_folding() {
+        let mut profile = Profile::new(
+            "",
+            ReferenceTimestamp::from_millis_since_unix_epoch(0.0),
+            SamplingInterval::from_millis(1),
+        );
+        let mut stacks = UnresolvedStacks::new(true, &mut profile);
+        let mut convert = |depth| stacks.convert(recursive_stack(depth).into_iter());
+
+        // Depths in the same power-of-two bucket are folded into the same stack.
+        assert_eq!(convert(8), convert(15));
+        assert_ne!(convert(8), convert(16));
+        assert_ne!(convert(2), convert(4));
+        // Without recursion, the stack is left alone.
+        assert_ne!(convert(1), convert(2));
+
+        let folded = convert(5);
+        let mut frames = vec![];
+        stacks.convert_back(folded, &mut frames);
+        assert_eq!(frames.len(), 4);
+        assert!(matches!(frames[2], StackFrame::Label(_, StackMode::User)));
+
+        let mut unfolded_stacks = UnresolvedStacks::default();
+        assert_ne!(
+            unfolded_stacks.convert(recursive_stack(8).into_iter()),
+            unfolded_stacks.convert(recursive_stack(9).into_iter())
+        );
+    }
+
+    #[test]
+    fn spilled_samples_round_trip() {
+        let mut profile = Profile::new(
+            "",
+            ReferenceTimestamp::from_millis_since_unix_epoch(0.0),
+            SamplingInterval::from_millis(1),
+        );
+        let process = profile.add_process("p", 1, Timestamp::from_millis_since_reference(0.0));
+        let threads = [
+            profile.add_thread(
+                process,
+                1,
+                Timestamp::from_millis_since_reference(0.0),
+                true,
+            ),
+            profile.add_thread(
+                process,
+                2,
+                Timestamp::from_millis_since_reference(0.0),
+                false,
+            ),
+        ];
+        let label_frame = FrameInfo {
+            frame: Frame::Label(profile.intern_string("label")),
+            category_pair: CategoryHandle::OTHER.into(),
+            flags: FrameFlags::empty(),
+        };
+        let marker_name = profile.intern_string("marker");
+        let marker = profile.add_marker(
+            threads[1],
+            MarkerTiming::Instant(Timestamp::from_millis_since_reference(2.0)),
+            UserTimingMarker(marker_name),
+        );
+        let mut stacks = UnresolvedStacks::default();
+        let stack = stacks.convert(recursive_stack(3).into_iter());
+
+        let mut in_memory = UnresolvedSamples::default();
+        let mut spilled = UnresolvedSamples::with_spill_threshold(Some(2));
+        for samples in [&mut in_memory, &mut spilled] {
+            for i in 0..5u64 {
+                let thread = threads[i as usize % 2];
+                let timestamp = Timestamp::from_nanos_since_reference(i * 1000);
+                if i == 2 {
+                    samples.attach_stack_to_marker(thread, timestamp, i, stack, marker);
+                } else {
+                    let label_frame = (i == 3).then(|| label_frame.clone());
+                    let cpu_delta = CpuDelta::from_micros(i);
+                    samples.add_sample(thread, timestamp, i, stack, cpu_delta, -1, label_frame);
+                }
+            }
+        }
+        assert!(spilled.spill_file.as_ref().unwrap().record_count > 0);
+        assert!(!spilled.is_empty());
+
+        // A snapshot reads the spilled samples back without disturbing the file,
+        // so more samples can be spilled afterwards.
+        let snapshot: Vec<_> = spilled.snapshot().into_inner().collect();
+        assert_eq!(snapshot.len(), 5);
+        for samples in [&mut in_memory, &mut spilled] {
+            for i in 5..8u64 {
+                let timestamp = Timestamp::from_nanos_since_reference(i * 1000);
+                let cpu_delta = CpuDelta::from_micros(i);
+                samples.add_sample(threads[0], timestamp, i, stack, cpu_delta, 1, None);
+            }
+        }
+
+        let in_memory: Vec<_> = in_memory.into_inner().collect();
+        let spilled: Vec<_> = spilled.into_inner().collect();
+        assert_eq!(format!("{spilled:?}"), format!("{in_memory:?}"));
+        assert_eq!(format!("{snapshot:?}"), format!("{:?}", &in_memory[..5]));
+    }
+}
@@ -1,8 +1,10 @@
+use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 
 use debugid::CodeId;
 use fxprof_processed_profile::{LibraryHandle, LibraryInfo, Profile};
 use linux_perf_data::jitdump::JitDumpHeader;
+use object::Object;
 use wholesym::samply_symbols::debug_id_and_code_id_for_jitdump;
 
 pub fn open_file_with_fallback<P: AsRef<Path>>(
@@ -26,6 +28,109 @@ pub fn open_file_with_fallback<P: AsRef<Path>>(
     Err(e)
 }
 
+/// The kernel truncates comm names to 15 bytes (`TASK_COMM_LEN - 1`).
+const MAX_COMM_LEN: usize = 15;
+
+/// Finds a process's main executable from its comm name, for when the mapping
+/// doesn't have a usable path.
+///
+/// Executables are looked up in the extra directories and in the directories
+/// listed in `$PATH`, and only accepted if they are ELF files whose build ID
+/// matches. The build ID check makes sure that we don't pick up an unrelated
+/// binary which happens to have the same name. Results are cached, because
+/// the same executable is usually mapped into many processes, but the build ID
+/// of a cached file is checked again every time it's returned.
+#[derive(Debug, Default)]
+pub struct ExecutableFinder {
+    path_dirs: Option<Vec<PathBuf>>,
+    cache: HashMap<(String, Vec<u8>), Option<PathBuf>>,
+}
+
+impl ExecutableFinder {
+    pub fn find<P: AsRef<Path>>(
+        &mut self,
+        name: &str,
+        build_id: &[u8],
+        extra_dirs: &[P],
+    ) -> Option<(std::fs::File, PathBuf)> {
+        if name.is_empty() || name.contains('/') {
+            return None;
+        }
+        let path_dirs = self.path_dirs.get_or_insert_with(|| {
+            std::env::var_os("PATH")
+                .map(|paths| std::env::split_paths(&paths).collect())
+                .unwrap_or_default()
+        });
+        let path = self
+            .cache
+            .entry((name.to_owned(), build_id.to_owned()))
+            .or_insert_with(|| {
+                let dirs = extra_dirs
+                    .iter()
+                    .map(AsRef::as_ref)
+                    .chain(path_dirs.iter().map(PathBuf::as_path));
+                find_executable_with_build_id(name, build_id, dirs)
+            })
+            .clone()?;
+        let file = std::fs::File::open(&path).ok()?;
+        if !is_elf_file_with_build_id(&file, build_id) {
+            // The file was replaced since we found it.
+            self.cache.remove(&(name.to_owned(), build_id.to_owned()));
+            return None;
+        }
+        Some((file, path))
+    }
+}
+
+fn find_executable_with_build_id<'a>(
+    name: &str,
+    build_id: &[u8],
+    dirs: impl Iterator<Item = &'a Path>,
+) -> Option<PathBuf> {
+    for dir in dirs {
+        if name.len() < MAX_COMM_LEN {
+            let p = dir.join(name);
+            if is_elf_file_at_path_with_build_id(&p, build_id) {
+                return Some(p);
+            }
+            continue;
+        }
+
+        // The comm may have been truncated, so check all files whose name
+        // starts with it.
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            continue;
+        };
+        let found = entries
+            .filter_map(|entry| Some(entry.ok()?.path()))
+            .filter(|p| {
+                p.file_name()
+                    .and_then(|f| f.to_str())
+                    .is_some_and(|f| f.starts_with(name))
+            })
+            .find(|p| is_elf_file_at_path_with_build_id(p, build_id));
+        if found.is_some() {
+            return found;
+        }
+    }
+    None
+}
+
+fn is_elf_file_at_path_with_build_id(path: &Path, build_id: &[u8]) -> bool {
+    std::fs::File::open(path).is_ok_and(|file| is_elf_file_with_build_id(&file, build_id))
+}
+
+fn is_elf_file_with_build_id(file: &std::fs::File, build_id: &[u8]) -> bool {
+    let Ok(mmap) = (unsafe { memmap2::MmapOptions::new().map(file) }) else {
+        return false;
+    };
+    let Ok(object) = object::File::parse(&mmap[..]) else {
+        return false;
+    };
+    object.format() == object::BinaryFormat::Elf
+        && object.build_id().ok().flatten() == Some(build_id)
+}
+
 pub fn lib_handle_for_jitdump(
     path: &Path,
     header: &JitDumpHeader,
@@ -52,3 +157,64 @@ pub fn lib_handle_for_jitdump(
         symbol_table: None,
     })
 }
+
+#[cfg(test)]
+mod test {
+    use object::write::{Object as WriteObject, StandardSegment};
+    use object::{Architecture, BinaryFormat, Endianness, SectionKind};
+
+    use super::*;
+
+    fn elf_with_build_id(build_id: &[u8]) -> Vec<u8> {
+        let mut obj = WriteObject::new(BinaryFormat::Elf, Architecture::X86_64, Endianness::Little);
+        let mut note = vec![];
+        note.extend_from_slice(&4u32.to_le_bytes());
+        note.extend_from_slice(&(build_id.len() as u32).to_le_bytes());
+        note.extend_from_slice(&3u32.to_le_bytes()); // NT_GNU_BUILD_ID
+        note.extend_from_slice(b"GNU\0");
+        note.extend_from_slice(build_id);
+        let section = obj.add_section(
+            obj.segment_name(StandardSegment::Data).to_vec(),
+            b".note.gnu.build-id".to_vec(),
+            SectionKind::Note,
+        );
+        obj.append_section_data(section, &note, 4);
+        obj.write().unwrap()
+    }
+
+    #[test]
+    fn find_executable_by_truncated_comm() {
+        let dir = tempfile::tempdir().unwrap();
+        let build_id = [0x5a; 20];
+        std::fs::write(
+            dir.path().join("long-program-name"),
+            elf_with_build_id(&build_id),
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("long-program-nam2"),
+            elf_with_build_id(&[1; 20]),
+        )
+        .unwrap();
+        std::fs::write(dir.path().join("short"), elf_with_build_id(&build_id)).unwrap();
+        let dirs = [dir.path()];
+
+        let mut finder = ExecutableFinder::default();
+        // "long-program-name" is 17 bytes, so the comm is truncated to 15 bytes.
+        let (_, path) = finder.find("long-program-na", &build_id, &dirs).unwrap();
+        assert_eq!(path, dir.path().join("long-program-name"));
+        let (_, path) = finder.find("short", &build_id, &dirs).unwrap();
+        assert_eq!(path, dir.path().join("short"));
+        // Names shorter than 15 bytes are not truncated, so they need to match exactly.
+        assert!(finder.find("shor", &build_id, &dirs).is_none());
+        // The build ID has to match.
+        assert!(finder.find("short", &[0x5b; 20], &dirs).is_none());
+
+        // Results are cached, but a cached file whose build ID no longer
+        // matches is rejected.
+        std::fs::remove_file(dir.path().join("short")).unwrap();
+        std::fs::write(dir.path().join("short"), elf_with_build_id(&[2; 20])).unwrap();
+        assert!(finder.find("short", &build_id, &dirs).is_none());
+        assert!(finder.find("short", &[2; 20], &dirs).is_some());
+    }
+}
@@ -563,6 +563,9 @@ impl ProfileContext {
             allow_jit_function_recycling,
         );
 
+        let unresolved_stacks =
+            UnresolvedStacks::new(profile_creation_props.fold_leaf_recursion, &mut profile);
+
         let cpus = if profile_creation_props.create_per_cpu_threads {
             Some(Cpus::new(
                 Timestamp::from_nanos_since_reference(0),
@@ -578,7 +581,7 @@ impl ProfileContext {
             processes: Processes::new(),
             threads: Threads::new(),
             thread_handles: BTreeMap::new(),
-            unresolved_stacks: UnresolvedStacks::default(),
+            unresolved_stacks,
             process_recycler,
             gpu_thread_handle: None,
             included_processes,
@@ -771,7 +774,9 @@ impl ProfileContext {
             make_thread_label_frame(&mut self.profile, Some(&name), pid, pid);
         let (thread_recycler, jit_function_recycler) = if self.process_recycler.is_some() {
             (
-                Some(ThreadRecycler::new()),
+                Some(ThreadRecycler::new_merging_numbered_names(
+                    self.profile_creation_props.reuse_numbered_threads,
+                )),
                 Some(JitFunctionRecycler::default()),
             )
         } else {
@@ -840,7 +845,9 @@ impl ProfileContext {
             )
         } else if self.process_recycler.is_some() {
             (
-                Some(ThreadRecycler::new()),
+                Some(ThreadRecycler::new_merging_numbered_names(
+                    self.profile_creation_props.reuse_numbered_threads,
+                )),
                 Some(JitFunctionRecycler::default()),
             )
         } else {
@@ -2048,7 +2055,6 @@ impl ProfileContext {
         // (This is a rather weak justification. The better justification is that this is consistent with what
         // samply does on Linux and macOS, where the queued samples also want to respect JIT function names from
         // a /tmp/perf-1234.map file, and this file may not exist until the profiled process finishes.)
-        let mut stack_frame_scratch_buf = Vec::new();
         self.js_jit_lib
             .finish_and_set_symbol_table(&mut self.profile);
         self.coreclr_jit_lib
@@ -2060,15 +2066,13 @@ impl ProfileContext {
             .categories
             .get(KnownCategory::Kernel, &mut self.profile);
 
-        for process_sample_data in process_sample_datas {
-            process_sample_data.flush_samples_to_profile(
-                &mut self.profile,
-                user_category.into(),
-                kernel_category.into(),
-                &mut stack_frame_scratch_buf,
-                &self.unresolved_stacks,
-            )
-        }
+        ProcessSampleData::flush_all_samples_to_profile(
+            process_sample_datas,
+            &mut self.profile,
+            user_category.into(),
+            kernel_category.into(),
+            &self.unresolved_stacks,
+        );
 
         log::info!(
             "{} events, {} samples, {} stack-samples",
@@ -69,8 +69,14 @@ impl BreakpadSymbolDownloader {
         Arc::get_mut(&mut self.inner).unwrap().observer = observer;
     }
 
-    pub async fn get_file(&self, rel_path: &str) -> Option<PathBuf> {
-        self.inner.get_file(rel_path).await
+    /// Returns the path of the .sym file at `rel_path`, downloading it if it's not
+    /// found locally. Downloads which don't match `expected_sha256` are rejected.
+    pub async fn get_file(
+        &self,
+        rel_path: &str,
+        expected_sha256: Option<&[u8; 32]>,
+    ) -> Option<PathBuf> {
+        self.inner.get_file(rel_path, expected_sha256).await
     }
 
     pub async fn get_file_no_download(&self, rel_path: &str) -> Option<PathBuf> {
@@ -121,14 +127,18 @@ impl BreakpadSymbolDownloaderInner {
         None
     }
 
-    pub async fn get_file(&self, rel_path: &str) -> Option<PathBuf> {
+    pub async fn get_file(
+        &self,
+        rel_path: &str,
+        expected_sha256: Option<&[u8; 32]>,
+    ) -> Option<PathBuf> {
         if let Some(path) = self.get_file_no_download(rel_path).await {
             return Some(path);
         }
 
         for (server_base_url, cache_dir) in &self.breakpad_servers {
             if let Ok(path) = self
-                .get_bp_sym_file_from_server(rel_path, server_base_url, cache_dir)
+                .get_bp_sym_file_from_server(rel_path, server_base_url, cache_dir, expected_sha256)
                 .await
             {
                 return Some(path);
@@ -153,6 +163,7 @@ impl BreakpadSymbolDownloaderInner {
         rel_path: &str,
         server_base_url: &str,
         cache_dir: &Path,
+        expected_sha256: Option<&[u8; 32]>,
     ) -> Result<PathBuf, DownloadError> {
         let dest_path = cache_dir.join(rel_path);
         let server_base_url = server_base_url.trim_end_matches('/');
@@ -163,7 +174,7 @@ impl BreakpadSymbolDownloaderInner {
         let mut index_generator = BreakpadIndexParser::new();
         let mut consumer = |chunk: &[u8]| index_generator.consume(chunk);
         let outcome = download
-            .download_to_file(&dest_path, Some(&mut consumer))
+            .download_to_file(&dest_path, expected_sha256, Some(&mut consumer))
             .await?;
 
         match outcome {
@@ -1,7 +1,12 @@
 use std::collections::HashMap;
 use std::path::PathBuf;
+use std::sync::Arc;
 
+use debugid::DebugId;
 use symsrv::{parse_nt_symbol_path, NtSymbolPathEntry};
+use tokio::sync::Semaphore;
+
+use crate::{FileRequestRecorder, FileRequestReplay};
 
 /// The configuration of a [`SymbolManager`](crate::SymbolManager).
 ///
@@ -15,12 +20,44 @@ pub struct SymbolManagerConfig {
     pub(crate) breakpad_servers: Vec<(String, PathBuf)>,
     pub(crate) breakpad_symindex_cache_dir: Option<PathBuf>,
     pub(crate) windows_servers: Vec<(String, PathBuf)>,
+    pub(crate) lowercase_symbol_server_paths: bool,
     pub(crate) use_debuginfod: bool,
     pub(crate) use_spotlight: bool,
     pub(crate) debuginfod_cache_dir_if_not_installed: Option<PathBuf>,
     pub(crate) debuginfod_servers: Vec<(String, PathBuf)>,
     pub(crate) extra_symbol_directories: Vec<PathBuf>,
     pub(crate) simpleperf_binary_cache_directories: Vec<PathBuf>,
+    pub(crate) build_id_debug_roots: Vec<PathBuf>,
+    pub(crate) perf_buildid_cache_dirs: Vec<PathBuf>,
+    pub(crate) dyld_shared_cache_dirs: Vec<PathBuf>,
+    pub(crate) dwp_directories: Vec<PathBuf>,
+    pub(crate) max_concurrent_local_file_loads: Option<usize>,
+    pub(crate) max_concurrent_downloads: Option<usize>,
+    pub(crate) server_headers: Vec<ServerHeader>,
+    pub(crate) file_request_recorder: Option<Arc<FileRequestRecorder>>,
+    pub(crate) file_request_replay: Option<FileRequestReplay>,
+    /// Keyed by the lowercased debug name and the debug ID.
+    pub(crate) symbol_file_sha256s: HashMap<(String, DebugId), [u8; 32]>,
+}
+
+/// An HTTP header which is sent with every request to URLs under `url_prefix`,
+/// see [`SymbolManagerConfig::server_header`].
+#[derive(Clone)]
+pub(crate) struct ServerHeader {
+    pub(crate) url_prefix: String,
+    pub(crate) name: String,
+    pub(crate) value: String,
+}
+
+impl std::fmt::Debug for ServerHeader {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // The value is often a secret, so never print it.
+        f.debug_struct("ServerHeader")
+            .field("url_prefix", &self.url_prefix)
+            .field("name", &self.name)
+            .field("value", &"<redacted>")
+            .finish()
+    }
 }
 
 impl SymbolManagerConfig {
@@ -128,6 +165,18 @@ impl SymbolManagerConfig {
         self
     }
 
+    /// Whether to lowercase the file name and the identifier in the paths which
+    /// are requested from Windows and Breakpad symbol servers, and in the paths
+    /// of their local caches.
+    ///
+    /// By default, the paths use the file name as recorded and an upper-case
+    /// identifier. Symbol stores which normalize their paths to lowercase don't
+    /// find these on case-sensitive file systems.
+    pub fn lowercase_symbol_server_paths(mut self, lowercase: bool) -> Self {
+        self.lowercase_symbol_server_paths = lowercase;
+        self
+    }
+
     /// Whether debuginfod should be used, i.e. whether the `DEBUGINFOD_URLS` environment variable should be respected.
     ///
     /// At the moment this will only work if you specify a custom cache directory with `debuginfod_cache_dir_if_not_installed`.
@@ -184,4 +233,157 @@ impl SymbolManagerConfig {
         self.simpleperf_binary_cache_directories.push(dir.into());
         self
     }
+
+    /// Add a directory which contains separate debug files in a `.build-id`
+    /// directory tree, with the same layout as `/usr/lib/debug`. For an ELF
+    /// build ID `abcdef...`, we will check "<dir>/.build-id/ab/cdef....debug".
+    ///
+    /// Directories added here are checked in the order of the calls, before
+    /// the system-wide directory `/usr/lib/debug`.
+    pub fn build_id_debug_root(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.build_id_debug_roots.push(dir.into());
+        self
+    }
+
+    /// Add a directory which is used by `perf buildid-cache`, usually `~/.debug`
+    /// or the directory in the `PERF_BUILDID_DIR` environment variable.
+    ///
+    /// perf stores the binaries it saw during recording in this cache. For an
+    /// ELF build ID `abcdef...`, we will check "<dir>/.build-id/ab/cdef.../elf"
+    /// for the binary and "<dir>/.build-id/ab/cdef.../debug" for its separate
+    /// debug file.
+    pub fn perf_buildid_cache_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.perf_buildid_cache_dirs.push(dir.into());
+        self
+    }
+
+    /// Add a directory which contains a dyld shared cache that was extracted
+    /// from a device, for example from an iOS device or from an IPSW.
+    ///
+    /// The directory is expected to contain the main cache file, e.g.
+    /// `dyld_shared_cache_arm64e`, next to its subcache files, e.g.
+    /// `dyld_shared_cache_arm64e.01` and `dyld_shared_cache_arm64e.symbols`.
+    /// System library paths such as `/usr/lib/libobjc.A.dylib` are looked up in
+    /// the caches in these directories before the caches of the running system.
+    pub fn dyld_shared_cache_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.dyld_shared_cache_dirs.push(dir.into());
+        self
+    }
+
+    /// Add a directory which may contain DWARF package files (`.dwp`) for
+    /// binaries built with split DWARF (`-gsplit-dwarf`).
+    ///
+    /// By default, we only check "<binarypath>.dwp", next to the binary. For
+    /// each directory added here, we also check "<dir>/<binaryname>.dwp".
+    pub fn dwp_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.dwp_directories.push(dir.into());
+        self
+    }
+
+    /// Limit how many local files can be opened and mapped at the same time.
+    ///
+    /// Symbolicating a profile with thousands of libraries can otherwise run
+    /// into the limit for open file descriptors. Unlimited by default. The
+    /// limit is clamped to at least 1 and at most [`Semaphore::MAX_PERMITS`].
+    pub fn max_concurrent_local_file_loads(mut self, limit: usize) -> Self {
+        self.max_concurrent_local_file_loads = Some(limit.clamp(1, Semaphore::MAX_PERMITS));
+        self
+    }
+
+    /// Limit how many files can be fetched from symbol servers (including
+    /// debuginfod and source file URLs) at the same time. Unlimited by default.
+    /// The limit is clamped like in
+    /// [`max_concurrent_local_file_loads`](Self::max_concurrent_local_file_loads).
+    pub fn max_concurrent_downloads(mut self, limit: usize) -> Self {
+        self.max_concurrent_downloads = Some(limit.clamp(1, Semaphore::MAX_PERMITS));
+        self
+    }
+
+    /// Send an extra HTTP header with every request to a URL under `url_prefix`,
+    /// for example to authenticate with a private symbol server.
+    ///
+    /// A URL is under `url_prefix` if it has the same scheme, host and port,
+    /// and if its path starts with the path segments of `url_prefix`. So a
+    /// prefix of `https://symbols.example.com` doesn't match
+    /// `https://symbols.example.com.evil.org/`.
+    ///
+    /// This applies to all symbol servers, debuginfod servers and source file
+    /// downloads. Windows symbol servers with a matching header are queried by
+    /// wholesym instead of the `symsrv` crate, which can't send extra headers;
+    /// this doesn't support compressed (`.pd_`) files or `file.ptr` redirects.
+    /// Headers with an invalid name or value, and invalid URL prefixes, are
+    /// ignored. Header values are never included in log or debug output.
+    ///
+    /// This method can be called multiple times; all matching headers are sent.
+    pub fn server_header(
+        mut self,
+        url_prefix: impl Into<String>,
+        name: impl Into<String>,
+        value: impl Into<String>,
+    ) -> Self {
+        self.server_headers.push(ServerHeader {
+            url_prefix: url_prefix.into(),
+            name: name.into(),
+            value: value.into(),
+        });
+        self
+    }
+
+    /// Send an `Authorization: Bearer <token>` header with every request to a
+    /// URL which starts with `url_prefix`. See [`server_header`](Self::server_header).
+    pub fn server_bearer_token(self, url_prefix: impl Into<String>, token: &str) -> Self {
+        self.server_header(url_prefix, "Authorization", format!("Bearer {token}"))
+    }
+
+    /// Like [`server_bearer_token`](Self::server_bearer_token), but reads the
+    /// token from the environment variable `env_var_name`. If the variable is
+    /// not set, the configuration is left unchanged.
+    pub fn server_bearer_token_from_env(
+        self,
+        url_prefix: impl Into<String>,
+        env_var_name: &str,
+    ) -> Self {
+        match std::env::var(env_var_name) {
+            Ok(token) => self.server_bearer_token(url_prefix, &token),
+            Err(_) => self,
+        }
+    }
+
+    /// Pin the expected SHA-256 hash of the symbol file for the library with
+    /// the given debug name and debug ID.
+    ///
+    /// Breakpad `.sym` files and PDB files from symbol servers, and from the
+    /// local caches of these servers, are then only used if their contents
+    /// match the hash. A Breakpad symbol file download which doesn't match is
+    /// rejected before it is stored in the cache, and a PDB file from a
+    /// symbol server's cache which doesn't match is removed from the cache.
+    /// Symbol files of libraries without a pinned hash are not verified.
+    ///
+    /// The debug name is matched case-insensitively, because symbol server
+    /// paths may be lowercased.
+    pub fn symbol_file_sha256(
+        mut self,
+        debug_name: impl Into<String>,
+        debug_id: DebugId,
+        sha256: [u8; 32],
+    ) -> Self {
+        self.symbol_file_sha256s
+            .insert((debug_name.into().to_lowercase(), debug_id), sha256);
+        self
+    }
+
+    /// Record every file load, in order, with its outcome. See [`FileRequestRecorder`].
+    pub fn record_file_requests(mut self, recorder: Arc<FileRequestRecorder>) -> Self {
+        self.file_request_recorder = Some(recorder);
+        self
+    }
+
+    /// Serve all file loads from a recording, without accessing the file
+    /// system or any servers. Only the binaries which are passed in directly,
+    /// e.g. to [`SymbolManager::load_symbol_map_for_binary_at_path`](crate::SymbolManager::load_symbol_map_for_binary_at_path),
+    /// are still read from disk.
+    pub fn replay_file_requests(mut self, replay: FileRequestReplay) -> Self {
+        self.file_request_replay = Some(replay);
+        self
+    }
 }
@@ -163,7 +163,10 @@ impl ManualDebuginfodDownloader {
             .initiate_download(&url, self.observer.clone())
             .await
             .ok()?;
-        download.download_to_file(&dest_path, None).await.ok()?;
+        download
+            .download_to_file(&dest_path, None, None)
+            .await
+            .ok()?;
 
         Some(dest_path)
     }
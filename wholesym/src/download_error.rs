@@ -33,6 +33,11 @@ pub enum DownloadError {
     #[error("Error while writing the downloaded file to disk: {0}")]
     DiskWrite(std::io::Error),
 
+    /// The downloaded file did not match the pinned SHA-256 hash, see
+    /// [`SymbolManagerConfig::symbol_file_sha256`](crate::SymbolManagerConfig::symbol_file_sha256).
+    #[error("The downloaded file did not match the expected SHA-256 hash")]
+    Sha256Mismatch,
+
     /// Redirect-related error.
     #[error("Redirect-related error")]
     Redirect(Box<dyn std::error::Error + Send + Sync>),
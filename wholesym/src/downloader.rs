@@ -6,8 +6,10 @@ use std::time::{Duration, Instant};
 
 use futures_util::AsyncRead;
 use futures_util::AsyncReadExt as _;
+use reqwest::header::{HeaderName, HeaderValue};
 use tokio::io::AsyncWriteExt;
 
+use crate::config::ServerHeader;
 use crate::download::response_to_uncompressed_stream_with_progress;
 use crate::file_creation::{create_file_cleanly, CleanFileCreationError};
 use crate::DownloadError;
@@ -164,6 +166,49 @@ impl Drop for DownloadStatusReporter {
 
 pub struct Downloader {
     reqwest_client: Result<reqwest::Client, reqwest::Error>,
+    /// Extra headers, as (scope, name, value), which are sent with every
+    /// request to URLs in the scope.
+    server_headers: Vec<(ServerUrlScope, HeaderName, HeaderValue)>,
+}
+
+/// The URLs for which a server header is sent: URLs with the same scheme, host
+/// and port as the configured URL prefix, and whose path starts with the path
+/// segments of the prefix. Comparing parsed URLs makes sure that a header for
+/// `https://example.com` isn't sent to `https://example.com.evil.org`.
+#[derive(Debug, Clone)]
+struct ServerUrlScope {
+    scheme: String,
+    host: String,
+    port: Option<u16>,
+    path_segments: Vec<String>,
+}
+
+impl ServerUrlScope {
+    fn parse(url_prefix: &str) -> Option<Self> {
+        let url = reqwest::Url::parse(url_prefix).ok()?;
+        Some(Self {
+            scheme: url.scheme().to_owned(),
+            host: url.host_str()?.to_owned(),
+            port: url.port_or_known_default(),
+            path_segments: Self::path_segments(&url),
+        })
+    }
+
+    fn path_segments(url: &reqwest::Url) -> Vec<String> {
+        url.path_segments()
+            .into_iter()
+            .flatten()
+            .filter(|segment| !segment.is_empty())
+            .map(ToOwned::to_owned)
+            .collect()
+    }
+
+    fn contains(&self, url: &reqwest::Url) -> bool {
+        url.scheme() == self.scheme
+            && url.host_str() == Some(&self.host)
+            && url.port_or_known_default() == self.port
+            && Self::path_segments(url).starts_with(&self.path_segments)
+    }
 }
 
 impl Default for Downloader {
@@ -174,6 +219,10 @@ impl Default for Downloader {
 
 impl Downloader {
     pub fn new() -> Self {
+        Self::with_server_headers(&[])
+    }
+
+    pub fn with_server_headers(server_headers: &[ServerHeader]) -> Self {
         let builder = reqwest::Client::builder();
 
         // Turn off HTTP 2, in order to work around https://github.com/seanmonstar/reqwest/issues/1761 .
@@ -189,7 +238,31 @@ impl Downloader {
         // TODO: Add timeouts, user agent, maybe other settings
         let reqwest_client = builder.build();
 
-        Self { reqwest_client }
+        let server_headers = server_headers
+            .iter()
+            .filter_map(|header| {
+                let scope = ServerUrlScope::parse(&header.url_prefix)?;
+                let name = HeaderName::from_bytes(header.name.as_bytes()).ok()?;
+                let mut value = HeaderValue::from_str(&header.value).ok()?;
+                value.set_sensitive(true);
+                Some((scope, name, value))
+            })
+            .collect();
+
+        Self {
+            reqwest_client,
+            server_headers,
+        }
+    }
+
+    /// Whether any extra headers are sent with requests to `url`.
+    pub fn has_server_headers(&self, url: &str) -> bool {
+        let Ok(url) = reqwest::Url::parse(url) else {
+            return false;
+        };
+        self.server_headers
+            .iter()
+            .any(|(scope, _, _)| scope.contains(&url))
     }
 
     pub async fn initiate_download(
@@ -214,6 +287,15 @@ impl Downloader {
         // decompression for this reqwest client.
         let request_builder = request_builder.header("Accept-Encoding", "gzip");
 
+        let parsed_url = reqwest::Url::parse(url).ok();
+        let request_builder = self
+            .server_headers
+            .iter()
+            .filter(|(scope, _, _)| parsed_url.as_ref().is_some_and(|url| scope.contains(url)))
+            .fold(request_builder, |request_builder, (_, name, value)| {
+                request_builder.header(name.clone(), value.clone())
+            });
+
         // Send the request and wait for the headers.
         let response_result = request_builder.send().await;
 
@@ -291,9 +373,13 @@ pub enum FileDownloadOutcome {
 
 impl PendingDownload {
     #[allow(clippy::type_complexity)]
+    /// Downloads the file to `dest_path`. If `expected_sha256` is given, the
+    /// download fails with [`DownloadError::Sha256Mismatch`] if the contents
+    /// don't match, and no file is created at `dest_path`.
     pub async fn download_to_file(
         self,
         dest_path: &Path,
+        expected_sha256: Option<&[u8; 32]>,
         mut chunk_consumer: Option<&mut (dyn FnMut(&[u8]) + Send)>,
     ) -> Result<FileDownloadOutcome, DownloadError> {
         let PendingDownload {
@@ -326,6 +412,8 @@ impl PendingDownload {
                 let mut dest_file = tokio::fs::File::from_std(dest_file);
                 let mut buf = vec![0u8; 4096];
                 let mut uncompressed_size_in_bytes = 0;
+                let mut sha256_context =
+                    expected_sha256.map(|_| ring::digest::Context::new(&ring::digest::SHA256));
                 loop {
                     let count = stream
                         .read(&mut buf)
@@ -339,11 +427,21 @@ impl PendingDownload {
                         .write_all(&buf[..count])
                         .await
                         .map_err(DownloadError::DiskWrite)?;
+                    if let Some(sha256_context) = &mut sha256_context {
+                        sha256_context.update(&buf[..count]);
+                    }
                     if let Some(chunk_consumer) = &mut chunk_consumer {
                         chunk_consumer(&buf[..count]);
                     }
                 }
                 dest_file.flush().await.map_err(DownloadError::DiskWrite)?;
+                if let (Some(sha256_context), Some(expected_sha256)) =
+                    (sha256_context, expected_sha256)
+                {
+                    if sha256_context.finish().as_ref() != expected_sha256 {
+                        return Err(DownloadError::Sha256Mismatch);
+                    }
+                }
                 Ok((
                     FileDownloadOutcome::DidCreateNewFile,
                     uncompressed_size_in_bytes,
@@ -0,0 +1,133 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use bytes::Bytes;
+use samply_symbols::FileAndPathHelperResult;
+
+use crate::helper::WholesymFileContents;
+
+/// Records which files a [`SymbolManager`](crate::SymbolManager) tries to
+/// load, in order, together with the outcome of each attempt.
+///
+/// Install it with [`SymbolManagerConfig::record_file_requests`](crate::SymbolManagerConfig::record_file_requests).
+/// If the recorder was created with [`FileRequestRecorder::with_contents`],
+/// the recording can be replayed with
+/// [`SymbolManagerConfig::replay_file_requests`](crate::SymbolManagerConfig::replay_file_requests),
+/// which makes symbolication problems reproducible without the original
+/// files or network access.
+#[derive(Debug, Default)]
+pub struct FileRequestRecorder {
+    capture_contents: bool,
+    requests: Mutex<Vec<FileRequest>>,
+}
+
+/// A single call to load a file, see [`FileRequestRecorder`].
+#[derive(Debug, Clone)]
+pub struct FileRequest {
+    /// A description of the requested location, e.g. `LocalFile("/usr/bin/ls")`.
+    pub location: String,
+    /// What happened when the file was loaded.
+    pub outcome: FileRequestOutcome,
+}
+
+/// The outcome of a [`FileRequest`].
+#[derive(Debug, Clone)]
+pub enum FileRequestOutcome {
+    /// The file was loaded.
+    Found {
+        /// The size of the file, in bytes.
+        len: usize,
+        /// The file contents, if the recorder captures contents.
+        contents: Option<Bytes>,
+    },
+    /// The file could not be loaded.
+    NotFound {
+        /// The error message.
+        error: String,
+    },
+}
+
+impl FileRequestRecorder {
+    /// Create a recorder which records the requested locations and their outcomes.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a recorder which also keeps a copy of the contents of each
+    /// loaded file, so that the recording can be replayed.
+    pub fn with_contents() -> Self {
+        Self {
+            capture_contents: true,
+            requests: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// The requests that were recorded so far, in the order in which they were made.
+    pub fn requests(&self) -> Vec<FileRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    /// Create a replay from the requests that were recorded so far.
+    pub fn to_replay(&self) -> FileRequestReplay {
+        FileRequestReplay::from_requests(self.requests())
+    }
+
+    pub(crate) fn record(
+        &self,
+        location: String,
+        result: &FileAndPathHelperResult<WholesymFileContents>,
+    ) {
+        let outcome = match result {
+            Ok(contents) => FileRequestOutcome::Found {
+                len: contents.len(),
+                contents: self
+                    .capture_contents
+                    .then(|| Bytes::copy_from_slice(contents)),
+            },
+            Err(e) => FileRequestOutcome::NotFound {
+                error: e.to_string(),
+            },
+        };
+        self.requests
+            .lock()
+            .unwrap()
+            .push(FileRequest { location, outcome });
+    }
+}
+
+/// Serves file requests from a recording instead of from the file system or
+/// from symbol servers. See [`FileRequestRecorder`].
+///
+/// Locations which are not in the recording fail to load.
+#[derive(Debug, Clone, Default)]
+pub struct FileRequestReplay {
+    outcomes: Arc<HashMap<String, FileRequestOutcome>>,
+}
+
+impl FileRequestReplay {
+    /// Create a replay from recorded requests. If a location was requested
+    /// multiple times, the last outcome is used.
+    pub fn from_requests(requests: impl IntoIterator<Item = FileRequest>) -> Self {
+        let outcomes = requests
+            .into_iter()
+            .map(|request| (request.location, request.outcome))
+            .collect();
+        Self {
+            outcomes: Arc::new(outcomes),
+        }
+    }
+
+    pub(crate) fn load(&self, location: &str) -> FileAndPathHelperResult<WholesymFileContents> {
+        match self.outcomes.get(location) {
+            Some(FileRequestOutcome::Found {
+                contents: Some(contents),
+                ..
+            }) => Ok(WholesymFileContents::Bytes(contents.clone())),
+            Some(FileRequestOutcome::Found { contents: None, .. }) => {
+                Err(format!("The contents of {location} were not recorded").into())
+            }
+            Some(FileRequestOutcome::NotFound { error }) => Err(error.clone().into()),
+            None => Err(format!("{location} is not in the recording").into()),
+        }
+    }
+}
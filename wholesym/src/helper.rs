@@ -10,13 +10,15 @@ use samply_symbols::{
     CandidatePathInfo, CodeId, ElfBuildId, FileAndPathHelper, FileAndPathHelperResult,
     FileLocation, LibraryInfo, OptionallySendFuture, PeCodeId, SymbolMapTrait,
 };
-use symsrv::{SymsrvDownloader, SymsrvObserver};
+use symsrv::{CachePath, NtSymbolPathEntry, SymsrvDownloader, SymsrvObserver};
+use tokio::sync::Semaphore;
 use uuid::Uuid;
 
 use crate::breakpad::BreakpadSymbolDownloader;
 use crate::config::SymbolManagerConfig;
 use crate::debuginfod::DebuginfodDownloader;
 use crate::downloader::{Downloader, DownloaderObserver};
+use crate::kernel_module::{decompress_kernel_module, is_compressed_kernel_module_path};
 use crate::vdso::get_vdso_data;
 use crate::{DownloadError, SymbolManagerObserver};
 
@@ -57,6 +59,27 @@ pub enum WholesymFileLocation {
     DebuginfodExecutable(ElfBuildId),
     UrlForSourceFile(String),
     VdsoLoadedIntoThisProcess,
+    /// The DWARF package file for the binary at this path, see
+    /// [`SymbolManagerConfig::dwp_dir`].
+    DwpFileForBinary(PathBuf),
+    /// A binary whose contents were passed in by the caller, see
+    /// [`SymbolManager::load_symbol_map_for_binary_bytes`](crate::SymbolManager::load_symbol_map_for_binary_bytes).
+    /// The string is only used as a label; there is no file to load.
+    InMemoryBinary(String),
+}
+
+impl WholesymFileLocation {
+    /// Whether loading the file at this location may need to fetch it from a server.
+    fn may_need_download(&self) -> bool {
+        matches!(
+            self,
+            Self::SymsrvFile(..)
+                | Self::BreakpadSymbolServerFile(_)
+                | Self::DebuginfodDebugFile(_)
+                | Self::DebuginfodExecutable(_)
+                | Self::UrlForSourceFile(_)
+        )
+    }
 }
 
 impl FileLocation for WholesymFileLocation {
@@ -168,12 +191,10 @@ impl FileLocation for WholesymFileLocation {
         // only useful in combination with the debug info inside the binary
         // (the "skeleton units"); a DWP file by itself cannot be used to
         // look up symbols if the binary has been stripped of debug info.
+        // The helper checks next to the binary and in the configured dwp
+        // directories.
         match self {
-            Self::LocalFile(binary_path) => {
-                let mut dwp_path = binary_path.as_os_str().to_os_string();
-                dwp_path.push(".dwp");
-                Some(Self::LocalFile(dwp_path.into()))
-            }
+            Self::LocalFile(binary_path) => Some(Self::DwpFileForBinary(binary_path.clone())),
             _ => None,
         }
     }
@@ -196,10 +217,15 @@ impl FileReadOnlyHelper {
     ) -> FileAndPathHelperResult<WholesymFileContents> {
         match location {
             WholesymFileLocation::LocalFile(path) => {
-                let file = File::open(path)?;
-                Ok(WholesymFileContents::Mmap(unsafe {
-                    memmap2::MmapOptions::new().map(&file)?
-                }))
+                let file = File::open(&path)?;
+                let mmap = unsafe { memmap2::MmapOptions::new().map(&file)? };
+                file_contents_from_mmap(&path, mmap)
+            }
+            WholesymFileLocation::DwpFileForBinary(binary_path) => {
+                let path = sibling_dwp_path(&binary_path);
+                let file = File::open(&path)?;
+                let mmap = unsafe { memmap2::MmapOptions::new().map(&file)? };
+                Ok(WholesymFileContents::Mmap(mmap))
             }
             _ => {
                 panic!("FileReadOnlyHelper should only be used for local files");
@@ -238,19 +264,26 @@ impl FileAndPathHelper for FileReadOnlyHelper {
         &self,
         arch: Option<&str>,
     ) -> FileAndPathHelperResult<Vec<WholesymFileLocation>> {
-        Ok(get_dyld_shared_cache_paths(arch))
+        Ok(get_dyld_shared_cache_paths(&[], arch))
     }
 }
 
 pub struct Helper {
     downloader: Arc<Downloader>,
     symsrv_downloader: Option<SymsrvDownloader>,
+    /// Windows symbol servers which need extra headers, with the cache directory
+    /// for each. These are queried by us, because `symsrv` can't send headers.
+    symsrv_servers_with_headers: Vec<(String, PathBuf)>,
     breakpad_downloader: BreakpadSymbolDownloader,
     debuginfod_downloader: Option<DebuginfodDownloader>,
     known_libs: Mutex<KnownLibs>,
     config: SymbolManagerConfig,
     precog_symbol_data: Mutex<HashMap<DebugId, Arc<dyn SymbolMapTrait + Send + Sync>>>,
     observer: Arc<HelperDownloaderObserver>,
+    /// Limits the number of concurrent loads of local files, if configured.
+    local_file_load_limit: Option<Semaphore>,
+    /// Limits the number of concurrent loads which may download, if configured.
+    download_limit: Option<Semaphore>,
 }
 
 #[derive(Debug, Clone, Default)]
@@ -264,8 +297,13 @@ struct KnownLibs {
 impl Helper {
     pub fn with_config(config: SymbolManagerConfig) -> Self {
         let observer = Arc::new(HelperDownloaderObserver::new());
-        let downloader = Arc::new(Downloader::new());
-        let symsrv_downloader = match config.effective_nt_symbol_path() {
+        let downloader = Arc::new(Downloader::with_server_headers(&config.server_headers));
+        let nt_symbol_path = config.effective_nt_symbol_path();
+        let symsrv_servers_with_headers = nt_symbol_path
+            .as_deref()
+            .map(|nt_symbol_path| symsrv_servers_with_headers(nt_symbol_path, &downloader))
+            .unwrap_or_default();
+        let symsrv_downloader = match nt_symbol_path {
             Some(nt_symbol_path) => {
                 let mut downloader = SymsrvDownloader::new(nt_symbol_path);
                 downloader.set_default_downstream_store(symsrv::get_home_sym_dir());
@@ -292,15 +330,20 @@ impl Helper {
             Some(downloader.clone()),
         );
         breakpad_downloader.set_observer(Some(observer.clone()));
+        let local_file_load_limit = config.max_concurrent_local_file_loads.map(Semaphore::new);
+        let download_limit = config.max_concurrent_downloads.map(Semaphore::new);
         Self {
             downloader,
             symsrv_downloader,
+            symsrv_servers_with_headers,
             breakpad_downloader,
             debuginfod_downloader,
             known_libs: Mutex::new(Default::default()),
             config,
             precog_symbol_data: Mutex::new(Default::default()),
             observer,
+            local_file_load_limit,
+            download_limit,
         }
     }
 
@@ -355,10 +398,67 @@ impl Helper {
         file_exists
     }
 
+    /// The paths at which we look for the `.dwp` file of a binary: next to the
+    /// binary first, then in the configured dwp directories.
+    fn dwp_candidate_paths(&self, binary_path: &Path) -> Vec<PathBuf> {
+        let mut paths = vec![sibling_dwp_path(binary_path)];
+        if let Some(binary_name) = binary_path.file_name() {
+            let mut dwp_name = binary_name.to_owned();
+            dwp_name.push(".dwp");
+            paths.extend(
+                self.config
+                    .dwp_directories
+                    .iter()
+                    .map(|dir| dir.join(&dwp_name)),
+            );
+        }
+        paths
+    }
+
     async fn load_file_impl(
         &self,
         location: WholesymFileLocation,
     ) -> FileAndPathHelperResult<WholesymFileContents> {
+        let recording_key = (self.config.file_request_recorder.is_some()
+            || self.config.file_request_replay.is_some())
+        .then(|| location.to_string());
+        let result = match (&self.config.file_request_replay, &recording_key) {
+            (Some(replay), Some(key)) => replay.load(key),
+            _ => self.load_file_impl_limited(location).await,
+        };
+        if let (Some(recorder), Some(key)) = (&self.config.file_request_recorder, recording_key) {
+            recorder.record(key, &result);
+        }
+        result
+    }
+
+    async fn load_file_impl_limited(
+        &self,
+        location: WholesymFileLocation,
+    ) -> FileAndPathHelperResult<WholesymFileContents> {
+        let limit = if location.may_need_download() {
+            &self.download_limit
+        } else {
+            &self.local_file_load_limit
+        };
+        let _permit = match limit {
+            Some(semaphore) => Some(semaphore.acquire().await?),
+            None => None,
+        };
+        self.load_file_impl_unlimited(location).await
+    }
+
+    async fn load_file_impl_unlimited(
+        &self,
+        location: WholesymFileLocation,
+    ) -> FileAndPathHelperResult<WholesymFileContents> {
+        let expected_sha256 = self.expected_sha256(&location);
+        // Files in a symbol server's cache which don't match the pinned hash
+        // are removed, so that they're downloaded again next time.
+        let evict_on_sha256_mismatch = matches!(
+            location,
+            WholesymFileLocation::LocalSymsrvFile(..) | WholesymFileLocation::SymsrvFile(..)
+        );
         let file_path = match location {
             WholesymFileLocation::LocalFile(path) => {
                 let path = self.config.redirect_paths.get(&path).unwrap_or(&path);
@@ -388,15 +488,23 @@ impl Helper {
                 return Ok(WholesymFileContents::Bytes(bytes.into()));
             }
             WholesymFileLocation::SymsrvFile(filename, hash) => {
-                self.symsrv_downloader
-                    .as_ref()
-                    .unwrap()
-                    .get_file(&filename, &hash)
-                    .await?
+                match self
+                    .get_symsrv_file_with_server_headers(&filename, &hash)
+                    .await
+                {
+                    Some(path) => path,
+                    None => {
+                        self.symsrv_downloader
+                            .as_ref()
+                            .unwrap()
+                            .get_file(&filename, &hash)
+                            .await?
+                    }
+                }
             }
             WholesymFileLocation::BreakpadSymbolServerFile(path) => self
                 .breakpad_downloader
-                .get_file(&path)
+                .get_file(&path, expected_sha256.as_ref())
                 .await
                 .ok_or("Not found on breakpad symbol server")?,
             WholesymFileLocation::BreakpadSymindexFile(rel_path) => {
@@ -423,6 +531,21 @@ impl Helper {
                 .get_file(&build_id.to_string(), "executable")
                 .await
                 .ok_or("Debuginfod could not find executable")?,
+            WholesymFileLocation::DwpFileForBinary(binary_path) => {
+                let mut found_path = None;
+                for path in self.dwp_candidate_paths(&binary_path) {
+                    if self.check_file_exists(&path).await {
+                        found_path = Some(path);
+                        break;
+                    }
+                }
+                found_path.ok_or_else(|| format!("No .dwp file found for {binary_path:?}"))?
+            }
+            WholesymFileLocation::InMemoryBinary(name) => {
+                return Err(
+                    format!("{name} has no file, its contents were passed in memory").into(),
+                );
+            }
             WholesymFileLocation::VdsoLoadedIntoThisProcess => {
                 let vdso = get_vdso_data().ok_or("No vdso in this process")?;
                 // Pretend that the VDSO data came from a file.
@@ -436,9 +559,125 @@ impl Helper {
         };
 
         self.observer.on_file_accessed(&file_path);
-        Ok(WholesymFileContents::Mmap(unsafe {
-            memmap2::MmapOptions::new().map(&File::open(file_path)?)?
-        }))
+        let mmap = unsafe { memmap2::MmapOptions::new().map(&File::open(&file_path)?)? };
+        if let Some(expected_sha256) = expected_sha256 {
+            if ring::digest::digest(&ring::digest::SHA256, &mmap).as_ref() != expected_sha256 {
+                drop(mmap);
+                if evict_on_sha256_mismatch {
+                    let _ = std::fs::remove_file(&file_path);
+                }
+                return Err(
+                    format!("{file_path:?} does not match the expected SHA-256 hash").into(),
+                );
+            }
+        }
+        file_contents_from_mmap(&file_path, mmap)
+    }
+
+    /// Downloads a file from one of the Windows symbol servers which need extra
+    /// headers, into the server's cache directory at the path where `symsrv`
+    /// looks for it. Only uncompressed files are supported.
+    async fn get_symsrv_file_with_server_headers(
+        &self,
+        filename: &str,
+        hash: &str,
+    ) -> Option<PathBuf> {
+        for (server_url, cache_dir) in &self.symsrv_servers_with_headers {
+            let dest_path = cache_dir.join(filename).join(hash).join(filename);
+            if self.check_file_exists(&dest_path).await {
+                return Some(dest_path);
+            }
+            let server_url = server_url.trim_end_matches('/');
+            let url = format!("{server_url}/{filename}/{hash}/{filename}");
+            let Ok(download) = self
+                .downloader
+                .initiate_download(&url, Some(self.observer.clone()))
+                .await
+            else {
+                continue;
+            };
+            if download
+                .download_to_file(&dest_path, None, None)
+                .await
+                .is_ok()
+            {
+                return Some(dest_path);
+            }
+        }
+        None
+    }
+
+    /// Returns the pinned SHA-256 hash for a symbol file from a Breakpad or
+    /// Windows symbol server, or from the local cache of one. See
+    /// [`SymbolManagerConfig::symbol_file_sha256`].
+    fn expected_sha256(&self, location: &WholesymFileLocation) -> Option<[u8; 32]> {
+        let (debug_name, debug_id) = match location {
+            // The relative path is "<debug name>/<debug ID>/<file name>.sym".
+            WholesymFileLocation::LocalBreakpadFile(rel_path)
+            | WholesymFileLocation::BreakpadSymbolServerFile(rel_path) => {
+                let mut components = rel_path.split('/');
+                (components.next()?, components.next()?)
+            }
+            WholesymFileLocation::LocalSymsrvFile(name, hash)
+            | WholesymFileLocation::SymsrvFile(name, hash) => (name.as_str(), hash.as_str()),
+            _ => return None,
+        };
+        let debug_id = DebugId::from_breakpad(debug_id).ok()?;
+        self.config
+            .symbol_file_sha256s
+            .get(&(debug_name.to_lowercase(), debug_id))
+            .copied()
+    }
+
+    /// Returns the paths at which a separate debug file for this build ID might
+    /// be found, in `.build-id` directory trees: first in the configured
+    /// build-id debug roots, then in `/usr/lib/debug`.
+    fn build_id_debug_file_paths(&self, build_id: &ElfBuildId) -> Vec<PathBuf> {
+        let build_id = build_id.to_string();
+        if build_id.len() <= 2 {
+            return Vec::new();
+        }
+        let (two_chars, rest) = build_id.split_at(2);
+        let rel_path = Path::new(".build-id")
+            .join(two_chars)
+            .join(format!("{rest}.debug"));
+        self.config
+            .build_id_debug_roots
+            .iter()
+            .map(PathBuf::as_path)
+            .chain(std::iter::once(Path::new("/usr/lib/debug")))
+            .map(|root| root.join(&rel_path))
+            .collect()
+    }
+
+    /// Returns the paths of `file_name` in the `perf buildid-cache` directories
+    /// for this build ID, e.g. `~/.debug/.build-id/ab/cdef.../elf`.
+    fn perf_buildid_cache_paths(&self, build_id: &ElfBuildId, file_name: &str) -> Vec<PathBuf> {
+        let build_id = build_id.to_string();
+        if build_id.len() <= 2 {
+            return Vec::new();
+        }
+        let (two_chars, rest) = build_id.split_at(2);
+        self.config
+            .perf_buildid_cache_dirs
+            .iter()
+            .map(|dir| {
+                dir.join(".build-id")
+                    .join(two_chars)
+                    .join(rest)
+                    .join(file_name)
+            })
+            .collect()
+    }
+
+    /// Applies the configured case to a path on a symbol server, or to a file
+    /// name or identifier in such a path.
+    fn symbol_server_path_component(&self, component: &str) -> String {
+        if self.config.lowercase_symbol_server_paths {
+            component.to_lowercase()
+        } else {
+            component.to_owned()
+        }
     }
 
     fn fill_in_library_info_details(&self, info: &mut LibraryInfo) {
@@ -451,7 +690,10 @@ impl Helper {
             }
         }
 
-        // If all we have is the ELF build ID, maybe we have some paths in the known libs.
+        // If all we have is the code ID, maybe we have some paths in the known libs.
+        // Different versions of a library can share a code ID, e.g. if the code ID
+        // is absent from the binary and was derived from its contents, so we only
+        // use a match whose debug ID doesn't contradict ours.
         let known_info = match (info.code_id.as_ref(), info.name.as_deref()) {
             (Some(CodeId::PeCodeId(pe_code_id)), Some(name)) => {
                 let pe_key = (name.to_string(), pe_code_id.clone());
@@ -464,7 +706,13 @@ impl Helper {
             _ => None,
         };
         if let Some(known_info) = known_info {
-            info.absorb(known_info);
+            let debug_ids_agree = match (info.debug_id, known_info.debug_id) {
+                (Some(debug_id), Some(known_debug_id)) => debug_id == known_debug_id,
+                _ => true,
+            };
+            if debug_ids_agree {
+                info.absorb(known_info);
+            }
         }
     }
 }
@@ -563,36 +811,37 @@ impl FileAndPathHelper for Helper {
         // Find debuginfo in /usr/lib/debug/.build-id/ etc.
         // <https://sourceware.org/gdb/onlinedocs/gdb/Separate-Debug-Files.html>
         if let Some(CodeId::ElfBuildId(build_id)) = &info.code_id {
-            let build_id = build_id.to_string();
-            if build_id.len() > 2 {
-                let (two_chars, rest) = build_id.split_at(2);
-                let path = format!("/usr/lib/debug/.build-id/{two_chars}/{rest}.debug");
+            for path in self.build_id_debug_file_paths(build_id) {
                 paths.push(CandidatePathInfo::SingleFile(
-                    WholesymFileLocation::LocalFile(PathBuf::from(path)),
+                    WholesymFileLocation::LocalFile(path),
                 ));
             }
+
+            // Check the perf buildid-cache, first for a debug file and then
+            // for the binary that perf cached during recording.
+            for file_name in ["debug", "elf"] {
+                for path in self.perf_buildid_cache_paths(build_id, file_name) {
+                    paths.push(CandidatePathInfo::SingleFile(
+                        WholesymFileLocation::LocalFile(path),
+                    ));
+                }
+            }
         }
 
         if let (Some(debug_name), Some(debug_id)) = (&info.debug_name, info.debug_id) {
-            let rel_path = format!(
-                "{}/{}/{}.sym",
-                debug_name,
-                debug_id.breakpad(),
-                debug_name.trim_end_matches(".pdb")
-            );
+            let rel_path = breakpad_sym_rel_path(debug_name, debug_id);
 
             // Search breakpad symbol directories.
             paths.push(CandidatePathInfo::SingleFile(
                 WholesymFileLocation::LocalBreakpadFile(rel_path.clone()),
             ));
 
+            let symsrv_name = self.symbol_server_path_component(debug_name);
+            let symsrv_hash = self.symbol_server_path_component(&debug_id.breakpad().to_string());
             if debug_name.ends_with(".pdb") && self.symsrv_downloader.is_some() {
                 // We might find this pdb file with the help of a symbol server.
                 paths.push(CandidatePathInfo::SingleFile(
-                    WholesymFileLocation::LocalSymsrvFile(
-                        debug_name.clone(),
-                        debug_id.breakpad().to_string(),
-                    ),
+                    WholesymFileLocation::LocalSymsrvFile(symsrv_name.clone(), symsrv_hash.clone()),
                 ));
             }
 
@@ -600,17 +849,16 @@ impl FileAndPathHelper for Helper {
                 if !self.config.breakpad_servers.is_empty() {
                     // We might find a .sym file on a symbol server.
                     paths.push(CandidatePathInfo::SingleFile(
-                        WholesymFileLocation::BreakpadSymbolServerFile(rel_path),
+                        WholesymFileLocation::BreakpadSymbolServerFile(
+                            self.symbol_server_path_component(&rel_path),
+                        ),
                     ));
                 }
 
                 if debug_name.ends_with(".pdb") && self.symsrv_downloader.is_some() {
                     // We might find this pdb file with the help of a symbol server.
                     paths.push(CandidatePathInfo::SingleFile(
-                        WholesymFileLocation::SymsrvFile(
-                            debug_name.clone(),
-                            debug_id.breakpad().to_string(),
-                        ),
+                        WholesymFileLocation::SymsrvFile(symsrv_name, symsrv_hash),
                     ));
                 }
             }
@@ -663,7 +911,10 @@ impl FileAndPathHelper for Helper {
 
             // For macOS system libraries, also consult the dyld shared cache.
             if path.starts_with("/usr/") || path.starts_with("/System/") {
-                for dyld_cache_path in get_dyld_shared_cache_paths(info.arch.as_deref()) {
+                for dyld_cache_path in get_dyld_shared_cache_paths(
+                    &self.config.dyld_shared_cache_dirs,
+                    info.arch.as_deref(),
+                ) {
                     paths.push(CandidatePathInfo::InDyldCache {
                         dyld_cache_path,
                         dylib_path: path.clone(),
@@ -766,10 +1017,22 @@ impl FileAndPathHelper for Helper {
             }
         }
 
+        // Check the perf buildid-cache.
+        if let Some(CodeId::ElfBuildId(build_id)) = &info.code_id {
+            for path in self.perf_buildid_cache_paths(build_id, "elf") {
+                paths.push(CandidatePathInfo::SingleFile(
+                    WholesymFileLocation::LocalFile(path),
+                ));
+            }
+        }
+
         if let Some(path) = &info.path {
             // For macOS system libraries, also consult the dyld shared cache.
             if path.starts_with("/usr/") || path.starts_with("/System/") {
-                for dyld_cache_path in get_dyld_shared_cache_paths(info.arch.as_deref()) {
+                for dyld_cache_path in get_dyld_shared_cache_paths(
+                    &self.config.dyld_shared_cache_dirs,
+                    info.arch.as_deref(),
+                ) {
                     paths.push(CandidatePathInfo::InDyldCache {
                         dyld_cache_path,
                         dylib_path: path.clone(),
@@ -790,7 +1053,10 @@ impl FileAndPathHelper for Helper {
             {
                 // We might find this exe / dll file with the help of a symbol server.
                 paths.push(CandidatePathInfo::SingleFile(
-                    WholesymFileLocation::SymsrvFile(name.clone(), code_id.to_string()),
+                    WholesymFileLocation::SymsrvFile(
+                        self.symbol_server_path_component(name),
+                        self.symbol_server_path_component(&code_id.to_string()),
+                    ),
                 ));
             }
 
@@ -813,7 +1079,10 @@ impl FileAndPathHelper for Helper {
         &self,
         arch: Option<&str>,
     ) -> FileAndPathHelperResult<Vec<WholesymFileLocation>> {
-        Ok(get_dyld_shared_cache_paths(arch))
+        Ok(get_dyld_shared_cache_paths(
+            &self.config.dyld_shared_cache_dirs,
+            arch,
+        ))
     }
 
     fn load_file(
@@ -848,11 +1117,11 @@ impl FileAndPathHelper for Helper {
             // machine, not on this machine.
         }
 
-        let build_id = sup_file_build_id.to_string();
-        if build_id.len() > 2 {
-            let (two_chars, rest) = build_id.split_at(2);
-            let path = format!("/usr/lib/debug/.build-id/{two_chars}/{rest}.debug");
-            paths.push(WholesymFileLocation::LocalFile(PathBuf::from(path)));
+        let build_id_debug_file_paths = self.build_id_debug_file_paths(sup_file_build_id);
+        if !build_id_debug_file_paths.is_empty() {
+            for path in build_id_debug_file_paths {
+                paths.push(WholesymFileLocation::LocalFile(path));
+            }
 
             if self.debuginfod_downloader.is_some() {
                 paths.push(WholesymFileLocation::DebuginfodDebugFile(
@@ -886,9 +1155,23 @@ impl FileAndPathHelper for Helper {
 /// For example, the architecture might have been derived from the mach-O
 /// header of an object that was found in memory (e.g. the dyld images list
 /// of a profiled process).
-fn get_dyld_shared_cache_paths(arch: Option<&str>) -> Vec<WholesymFileLocation> {
+///
+/// The caches in `extracted_cache_dirs` are checked before the caches of
+/// the running system.
+fn get_dyld_shared_cache_paths(
+    extracted_cache_dirs: &[PathBuf],
+    arch: Option<&str>,
+) -> Vec<WholesymFileLocation> {
     let mut vec = Vec::new();
 
+    for dir in extracted_cache_dirs {
+        vec.extend(
+            main_dyld_cache_files_in_dir(dir, arch)
+                .into_iter()
+                .map(WholesymFileLocation::LocalFile),
+        );
+    }
+
     let mut add_entries_in_dir = |dir: &str| {
         let mut add_entry_for_arch = |arch: &str| {
             let path = format!("{dir}/dyld_shared_cache_{arch}");
@@ -921,6 +1204,107 @@ fn get_dyld_shared_cache_paths(arch: Option<&str>) -> Vec<WholesymFileLocation>
     vec
 }
 
+/// Return the paths of the main cache files in a directory with an extracted
+/// dyld shared cache, i.e. the `dyld_shared_cache_<arch>` files without the
+/// subcache files like `dyld_shared_cache_<arch>.01`. Unlike for the system
+/// directories, we don't know in advance which architectures are present, so
+/// we list the directory.
+fn main_dyld_cache_files_in_dir(dir: &Path, arch: Option<&str>) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            let cache_arch = path
+                .file_name()?
+                .to_str()?
+                .strip_prefix("dyld_shared_cache_")?;
+            if cache_arch.contains('.') {
+                // This is a subcache or a .map file.
+                return None;
+            }
+            let arch_matches = match arch {
+                None => true,
+                // x86_64 binaries can be either in the x86_64 or in the x86_64h cache.
+                Some("x86_64") => cache_arch == "x86_64" || cache_arch == "x86_64h",
+                Some(arch) => cache_arch == arch,
+            };
+            arch_matches.then_some(path)
+        })
+        .collect();
+    paths.sort();
+    paths
+}
+
+/// Returns the contents of the mapped file at `path`. Compressed kernel modules
+/// are decompressed into memory.
+fn file_contents_from_mmap(
+    path: &Path,
+    mmap: memmap2::Mmap,
+) -> FileAndPathHelperResult<WholesymFileContents> {
+    if is_compressed_kernel_module_path(path) {
+        let data = decompress_kernel_module(&mmap)
+            .ok_or_else(|| format!("Could not decompress kernel module {path:?}"))?;
+        return Ok(WholesymFileContents::Bytes(data.into()));
+    }
+    Ok(WholesymFileContents::Mmap(mmap))
+}
+
+/// "<binarypath>.dwp", the conventional location of a binary's DWARF package file.
+fn sibling_dwp_path(binary_path: &Path) -> PathBuf {
+    let mut dwp_path = binary_path.as_os_str().to_owned();
+    dwp_path.push(".dwp");
+    dwp_path.into()
+}
+
+/// Returns the Windows symbol server URLs in `nt_symbol_path` for which
+/// `downloader` sends extra headers, together with the cache directory which
+/// `symsrv` uses for each. Servers without a cache directory are skipped.
+fn symsrv_servers_with_headers(
+    nt_symbol_path: &[NtSymbolPathEntry],
+    downloader: &Downloader,
+) -> Vec<(String, PathBuf)> {
+    let default_downstream_store = symsrv::get_home_sym_dir();
+    let mut cache_dir = None;
+    let mut servers = Vec::new();
+    for entry in nt_symbol_path {
+        match entry {
+            NtSymbolPathEntry::Cache(dir) => cache_dir = Some(dir.clone()),
+            NtSymbolPathEntry::Chain {
+                cache_paths, urls, ..
+            } => {
+                let chain_cache_dir = match cache_paths.first() {
+                    Some(CachePath::Path(dir)) => Some(dir.clone()),
+                    Some(CachePath::DefaultDownstreamStore) => default_downstream_store.clone(),
+                    None => cache_dir.clone(),
+                };
+                let Some(chain_cache_dir) = chain_cache_dir else {
+                    continue;
+                };
+                for url in urls {
+                    if downloader.has_server_headers(url) {
+                        servers.push((url.clone(), chain_cache_dir.clone()));
+                    }
+                }
+            }
+            NtSymbolPathEntry::LocalOrShare(_) => {}
+        }
+    }
+    servers
+}
+
+/// The path of a library's Breakpad .sym file, relative to a symbol directory
+/// or symbol server.
+fn breakpad_sym_rel_path(debug_name: &str, debug_id: DebugId) -> String {
+    format!(
+        "{}/{}/{}.sym",
+        debug_name,
+        debug_id.breakpad(),
+        debug_name.trim_end_matches(".pdb")
+    )
+}
+
 /// Used to filter out files like `jitted-12345-12.so`, to avoid hammering debuginfod servers.
 fn might_be_fake_jit_file(info: &LibraryInfo) -> bool {
     matches!(&info.name, Some(name) if (name.starts_with("jitted-") && name.ends_with(".so")) || name.contains("jit_app_cache:"))
@@ -1233,3 +1617,56 @@ impl DownloaderObserver for HelperDownloaderObserver {
 // I think it's ok if the logging here doesn't answer all those questions. Instead, the
 // questions can be answered by information in the response JSON... or I guess by something
 // that's stored on the SymbolMap.
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn extracted_dyld_cache_dirs() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in [
+            "dyld_shared_cache_arm64e",
+            "dyld_shared_cache_arm64e.01",
+            "dyld_shared_cache_arm64e.symbols",
+            "dyld_shared_cache_arm64e.map",
+            "dyld_shared_cache_x86_64h",
+            "dyld_shared_cache_x86_64",
+            "unrelated_file",
+        ] {
+            std::fs::write(dir.path().join(name), b"").unwrap();
+        }
+        let file_names = |arch| -> Vec<String> {
+            main_dyld_cache_files_in_dir(dir.path(), arch)
+                .iter()
+                .map(|p| p.file_name().unwrap().to_string_lossy().into_owned())
+                .collect()
+        };
+
+        assert_eq!(
+            file_names(None),
+            [
+                "dyld_shared_cache_arm64e",
+                "dyld_shared_cache_x86_64",
+                "dyld_shared_cache_x86_64h"
+            ]
+        );
+        assert_eq!(file_names(Some("arm64e")), ["dyld_shared_cache_arm64e"]);
+        assert_eq!(
+            file_names(Some("x86_64")),
+            ["dyld_shared_cache_x86_64", "dyld_shared_cache_x86_64h"]
+        );
+        assert!(file_names(Some("arm64")).is_empty());
+
+        // The extracted caches come before the caches of the running system.
+        let paths = get_dyld_shared_cache_paths(&[dir.path().to_owned()], Some("arm64e"));
+        assert!(matches!(
+            &paths[0],
+            WholesymFileLocation::LocalFile(path) if *path == dir.path().join("dyld_shared_cache_arm64e")
+        ));
+        assert!(paths[1..].iter().all(|p| matches!(
+            p,
+            WholesymFileLocation::LocalFile(path) if path.starts_with("/System")
+        )));
+    }
+}
@@ -0,0 +1,48 @@
+//! Kernel modules are often shipped compressed, e.g. as `ext4.ko.zst`, and are
+//! decompressed by the kernel's module loader. The compressed file has to be
+//! decompressed before the module's build ID and symbols can be read.
+
+use std::io::{Cursor, Read};
+use std::path::Path;
+
+const XZ_MAGIC: &[u8] = b"\xfd7zXZ\0";
+const ZSTD_MAGIC: &[u8] = b"\x28\xb5\x2f\xfd";
+const GZIP_MAGIC: &[u8] = b"\x1f\x8b";
+
+/// The file name extensions of compressed kernel modules.
+pub const COMPRESSED_KERNEL_MODULE_EXTENSIONS: &[&str] = &["ko.xz", "ko.zst", "ko.gz"];
+
+/// Whether the file at `path` is a compressed kernel module, based on its extension.
+pub fn is_compressed_kernel_module_path(path: &Path) -> bool {
+    let Some(file_name) = path.file_name().and_then(|name| name.to_str()) else {
+        return false;
+    };
+    COMPRESSED_KERNEL_MODULE_EXTENSIONS
+        .iter()
+        .any(|ext| file_name.len() > ext.len() && file_name.ends_with(&format!(".{ext}")))
+}
+
+/// Decompresses the contents of a compressed kernel module. The compression
+/// format is detected from the magic bytes at the start of `data`; xz, zstd
+/// and gzip are supported. Returns `None` if the format isn't recognized or
+/// if the data is corrupt.
+pub fn decompress_kernel_module(data: &[u8]) -> Option<Vec<u8>> {
+    let mut decompressed = Vec::new();
+    if data.starts_with(XZ_MAGIC) {
+        lzma_rs::xz_decompress(&mut Cursor::new(data), &mut decompressed).ok()?;
+    } else if data.starts_with(ZSTD_MAGIC) {
+        // Multiple zstd frames are decompressed one after the other.
+        let mut remaining = data;
+        while !remaining.is_empty() {
+            let mut decoder = ruzstd::StreamingDecoder::new(&mut remaining).ok()?;
+            decoder.read_to_end(&mut decompressed).ok()?;
+        }
+    } else if data.starts_with(GZIP_MAGIC) {
+        flate2::read::GzDecoder::new(data)
+            .read_to_end(&mut decompressed)
+            .ok()?;
+    } else {
+        return None;
+    }
+    Some(decompressed)
+}
@@ -136,7 +136,9 @@ mod download;
 mod download_error;
 mod downloader;
 mod file_creation;
+mod file_request_recording;
 mod helper;
+mod kernel_module;
 mod moria_mac;
 #[cfg(target_os = "macos")]
 mod moria_mac_spotlight;
@@ -147,13 +149,19 @@ mod verbose_symbol_manager_observer;
 
 pub use config::SymbolManagerConfig;
 pub use download_error::DownloadError;
+pub use file_request_recording::{
+    FileRequest, FileRequestOutcome, FileRequestRecorder, FileRequestReplay,
+};
+pub use kernel_module::{
+    decompress_kernel_module, is_compressed_kernel_module_path, COMPRESSED_KERNEL_MODULE_EXTENSIONS,
+};
 pub use samply_symbols;
 pub use samply_symbols::{
     AddressInfo, CodeId, ElfBuildId, Error, ExternalFileAddressInFileRef, ExternalFileAddressRef,
     ExternalFileRef, ExternalFileSymbolMap, FrameDebugInfo, FramesLookupResult, LibraryInfo,
     LookupAddress, MappedPath, MultiArchDisambiguator, PeCodeId, SourceFilePath, SymbolInfo,
-    SyncAddressInfo,
+    SymbolNameOverrideKey, SyncAddressInfo,
 };
-pub use symbol_manager::{SymbolFileOrigin, SymbolManager, SymbolMap};
+pub use symbol_manager::{ArchiveMemberSelector, SymbolFileOrigin, SymbolManager, SymbolMap};
 pub use symbol_manager_observer::SymbolManagerObserver;
 pub use verbose_symbol_manager_observer::VerboseSymbolManagerObserver;
@@ -1,17 +1,33 @@
 use std::borrow::Cow;
+use std::collections::HashMap;
+use std::ops::Range;
 use std::path::Path;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
 use debugid::DebugId;
+use futures_util::stream::{self, Stream, StreamExt};
+use samply_symbols::object::read::archive::ArchiveFile;
 use samply_symbols::{
-    self, AddressInfo, Error, ExternalFileAddressInFileRef, ExternalFileAddressRef, FrameDebugInfo,
-    LibraryInfo, LookupAddress, MultiArchDisambiguator, SymbolMapTrait, SyncAddressInfo,
+    self, debug_id_for_object, object, AddressInfo, Error, ExternalFileAddressInFileRef,
+    ExternalFileAddressRef, FrameDebugInfo, LibraryInfo, LookupAddress, MultiArchDisambiguator,
+    SymbolInfo, SymbolMapTrait, SymbolNameOverrideKey, SymbolTableSymbolMap, SyncAddressInfo,
 };
 
 use crate::config::SymbolManagerConfig;
 use crate::helper::{FileReadOnlyHelper, Helper, WholesymFileContents, WholesymFileLocation};
 use crate::SymbolManagerObserver;
 
+/// Selects the member of a static library, see
+/// [`SymbolManager::load_symbol_map_for_archive_member`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ArchiveMemberSelector {
+    /// The member with this file name, e.g. `"file2.o"`.
+    Name(String),
+    /// The first member with this debug ID. For ELF object files, the debug ID
+    /// is derived from the build ID, if the object file has one.
+    DebugId(DebugId),
+}
+
 /// Used in [`SymbolManager::load_external_file`] and returned by [`SymbolMap::symbol_file_origin`].
 #[derive(Debug, Clone)]
 pub struct SymbolFileOrigin(WholesymFileLocation);
@@ -57,6 +73,14 @@ impl SymbolMap {
         self.0.lookup(address).await
     }
 
+    /// Make [`SymbolMap::lookup`] return the nearest preceding symbol, with the
+    /// offset appended to its name (e.g. `memcpy+0x123`), for addresses which
+    /// aren't covered by any symbol. Off by default. See
+    /// [`AddressInfo::nearest_symbol_offset`].
+    pub fn set_nearest_symbol_fallback(&mut self, enabled: bool) {
+        self.0.set_nearest_symbol_fallback(enabled);
+    }
+
     /// Look up symbol information, using only files that have already been loaded.
     ///
     /// If additional files are needed to fully resolve the frame information, this
@@ -125,6 +149,25 @@ impl SymbolMap {
     pub fn iter_symbols(&self) -> Box<dyn Iterator<Item = (u32, Cow<'_, str>)> + '_> {
         self.0.iter_symbols()
     }
+
+    /// Iterate over all functions in this `SymbolMap`, in address order.
+    ///
+    /// This iterator yields the relative address range and the name of each
+    /// function. Names from the debug info are preferred over symbol names.
+    pub fn iter_functions(&self) -> impl Iterator<Item = (Range<u32>, String)> + '_ {
+        self.0.iter_functions()
+    }
+
+    /// Find all symbols with the given base name, ignoring template arguments and
+    /// parameter lists. This returns all instantiations of a template function.
+    ///
+    /// For example, `std::vector::push_back` finds both
+    /// `std::vector<int>::push_back(int const&)` and
+    /// `std::vector<float>::push_back(float&&)`. See
+    /// [`samply_symbols::SymbolMap::find_symbols_by_base_name`] for details.
+    pub fn find_symbols_by_base_name(&self, base_name: &str) -> Vec<SymbolInfo> {
+        self.0.find_symbols_by_base_name(base_name)
+    }
 }
 
 pub struct ExternalFileSymbolMap(samply_symbols::ExternalFileSymbolMap<WholesymFileContents>);
@@ -148,6 +191,8 @@ impl ExternalFileSymbolMap {
 /// Allows obtaining [`SymbolMap`]s.
 pub struct SymbolManager {
     symbol_manager: samply_symbols::SymbolManager<Helper>,
+    /// The symbol maps which were loaded by [`SymbolManager::lookup_single`].
+    single_lookup_symbol_maps: Mutex<HashMap<LibraryInfo, Arc<SymbolMap>>>,
 }
 
 impl SymbolManager {
@@ -155,7 +200,10 @@ impl SymbolManager {
     pub fn with_config(config: SymbolManagerConfig) -> Self {
         let helper = Helper::with_config(config);
         let symbol_manager = samply_symbols::SymbolManager::with_helper(helper);
-        Self { symbol_manager }
+        Self {
+            symbol_manager,
+            single_lookup_symbol_maps: Mutex::new(HashMap::new()),
+        }
     }
 
     /// Find symbols for the given binary.
@@ -173,9 +221,111 @@ impl SymbolManager {
     ) -> Result<SymbolMap, Error> {
         let library_info = Self::library_info_for_binary_at_path(path, disambiguator).await?;
 
-        Ok(SymbolMap(
-            self.symbol_manager.load_symbol_map(&library_info).await?,
-        ))
+        let symbol_map = self.symbol_manager.load_symbol_map(&library_info).await?;
+        Ok(SymbolMap(symbol_map))
+    }
+
+    /// Find symbols for a system library in a dyld shared cache, for example
+    /// `/usr/lib/libobjc.A.dylib`.
+    ///
+    /// The caches in the directories from [`SymbolManagerConfig::dyld_shared_cache_dir`]
+    /// are checked first, followed by the caches of the running system (macOS only).
+    /// The `disambiguator` can pick the cache architecture or the expected debug ID.
+    pub async fn load_symbol_map_for_dyld_cache_image(
+        &self,
+        dylib_path: &str,
+        disambiguator: Option<MultiArchDisambiguator>,
+    ) -> Result<SymbolMap, Error> {
+        let symbol_map = self
+            .symbol_manager
+            .load_symbol_map_for_dyld_cache_image(dylib_path, disambiguator)
+            .await?;
+        Ok(SymbolMap(symbol_map))
+    }
+
+    /// Find symbols for a binary whose contents are already in memory, without
+    /// reading the binary from the file system.
+    ///
+    /// The `library_info` describes the binary. If it has a `debug_id`, the
+    /// symbols must match it. Its `path` (or `name`) is only used to identify the
+    /// binary; the file system is never accessed, so auxiliary debug files such
+    /// as `.dwo` or `.dwp` files aren't used.
+    pub async fn load_symbol_map_for_binary_bytes(
+        &self,
+        library_info: &LibraryInfo,
+        bytes: Vec<u8>,
+    ) -> Result<SymbolMap, Error> {
+        let path = library_info
+            .path
+            .as_deref()
+            .or(library_info.name.as_deref())
+            .unwrap_or("<memory>");
+        let symbol_map = self
+            .symbol_manager
+            .load_symbol_map_for_library_from_contents(
+                library_info,
+                WholesymFileLocation::InMemoryBinary(path.to_owned()),
+                WholesymFileContents::Bytes(bytes.into()),
+            )
+            .await?;
+        Ok(SymbolMap(symbol_map))
+    }
+
+    /// Find symbols for a member of a static library (`.a` archive), e.g. if
+    /// the final binary is stripped but the archives of its components still
+    /// have their debug info.
+    ///
+    /// The member is picked by `member`. Object files aren't linked yet, so
+    /// their addresses are the addresses in the object file's sections, not the
+    /// addresses in the final binary.
+    pub async fn load_symbol_map_for_archive_member(
+        &self,
+        archive_path: &Path,
+        member: &ArchiveMemberSelector,
+    ) -> Result<SymbolMap, Error> {
+        let archive_error = |e: Box<dyn std::error::Error + Send + Sync>| {
+            Error::ArchiveParseError(archive_path.into(), e)
+        };
+        let file = std::fs::File::open(archive_path).map_err(|e| {
+            Error::HelperErrorDuringOpenFile(archive_path.to_string_lossy().into(), e.into())
+        })?;
+        let archive_data = unsafe { memmap2::MmapOptions::new().map(&file) }.map_err(|e| {
+            Error::HelperErrorDuringOpenFile(archive_path.to_string_lossy().into(), e.into())
+        })?;
+        let archive = ArchiveFile::parse(&*archive_data).map_err(|e| archive_error(e.into()))?;
+        for archive_member in archive.members() {
+            let archive_member = archive_member.map_err(|e| archive_error(e.into()))?;
+            let name = String::from_utf8_lossy(archive_member.name());
+            let data = archive_member
+                .data(&*archive_data)
+                .map_err(|e| archive_error(e.into()))?;
+            let debug_id = match member {
+                ArchiveMemberSelector::Name(member_name) if *member_name == name => None,
+                ArchiveMemberSelector::DebugId(expected_debug_id)
+                    if object::File::parse(data)
+                        .ok()
+                        .and_then(|object| debug_id_for_object(&object))
+                        == Some(*expected_debug_id) =>
+                {
+                    Some(*expected_debug_id)
+                }
+                _ => continue,
+            };
+            let library_info = LibraryInfo {
+                name: Some(name.into_owned()),
+                path: Some(archive_path.to_string_lossy().into()),
+                debug_id,
+                ..Default::default()
+            };
+            return self
+                .load_symbol_map_for_binary_bytes(&library_info, data.to_vec())
+                .await;
+        }
+        let member_description = match member {
+            ArchiveMemberSelector::Name(name) => name.clone(),
+            ArchiveMemberSelector::DebugId(debug_id) => format!("with debug ID {debug_id}"),
+        };
+        Err(Error::FileNotInArchive(member_description))
     }
 
     /// Computes the [`LibraryInfo`] for the given binary. This [`LibraryInfo`]
@@ -240,6 +390,32 @@ impl SymbolManager {
         self.symbol_manager.helper().add_known_lib(lib_info);
     }
 
+    /// Replace the names of specific symbols in all symbol maps which are loaded
+    /// from now on, e.g. for a symbol which demangles incorrectly, or to give
+    /// a friendlier name to a known-ugly function.
+    ///
+    /// Each override is keyed by the library's debug ID and the symbol, which is
+    /// identified either by its relative start address or by its mangled name.
+    /// Overrides take precedence over the demangled names and the function
+    /// names from the debug info. See [`SymbolMap::lookup`]. They also apply to
+    /// the results of [`query_json_api`](SymbolManager::query_json_api).
+    pub fn set_symbol_name_overrides(
+        &mut self,
+        overrides: HashMap<(DebugId, SymbolNameOverrideKey), String>,
+    ) {
+        let mut overrides_by_debug_id: HashMap<DebugId, HashMap<_, _>> = HashMap::new();
+        for ((debug_id, key), display_name) in overrides {
+            overrides_by_debug_id
+                .entry(debug_id)
+                .or_default()
+                .insert(key, display_name);
+        }
+        self.symbol_manager
+            .set_symbol_name_overrides(overrides_by_debug_id);
+        // Don't reuse symbol maps which have the old overrides.
+        self.single_lookup_symbol_maps.get_mut().unwrap().clear();
+    }
+
     /// Tell the `SymbolManager` about a library's symbol table. The library
     /// must contain a DebugId. This is useful when a library's symbols are
     /// available in some way other than normal symbol lookup, or if a custom
@@ -254,6 +430,25 @@ impl SymbolManager {
             .add_precog_symbol_map(lib_info, symbol_map);
     }
 
+    /// Tell the `SymbolManager` about a library's symbol table, given as a
+    /// list of symbols with relative addresses. The library must contain a
+    /// DebugId.
+    ///
+    /// Symbol maps for this library will be backed by nothing but this list,
+    /// so no binary or debug file is needed. Lookups return the symbol name
+    /// but no file / line information.
+    pub fn add_known_library_symbol_table(
+        &mut self,
+        lib_info: LibraryInfo,
+        symbols: Vec<SymbolInfo>,
+    ) {
+        let debug_id = lib_info
+            .debug_id
+            .expect("LibraryInfo must have a debug_id to add a symbol table");
+        let symbol_map = SymbolTableSymbolMap::new(debug_id, symbols);
+        self.add_known_library_symbols(lib_info, Arc::new(symbol_map));
+    }
+
     /// Obtain a symbol map for the given `debug_name` and `debug_id`.
     pub async fn load_symbol_map(
         &self,
@@ -265,7 +460,125 @@ impl SymbolManager {
             debug_id: Some(debug_id),
             ..Default::default()
         };
-        Ok(SymbolMap(self.symbol_manager.load_symbol_map(&info).await?))
+        let symbol_map = self.symbol_manager.load_symbol_map(&info).await?;
+        Ok(SymbolMap(symbol_map))
+    }
+
+    /// Look up a single address, e.g. for debugging or in tests.
+    ///
+    /// `lib` needs to have either the `path` of the binary, or a `debug_name`
+    /// and `debug_id`. The symbol map for `lib` is loaded on the first call and
+    /// kept for later calls with the same `lib`. Like [`SymbolMap::lookup`],
+    /// this also resolves frames whose debug info is in an external file.
+    ///
+    /// Returns `Ok(None)` if the symbols were found but no symbol contains the
+    /// address.
+    pub async fn lookup_single(
+        &self,
+        lib: LibraryInfo,
+        relative_address: u32,
+    ) -> Result<Option<AddressInfo>, Error> {
+        let symbol_map = self.load_symbol_map_for_single_lookup(lib).await?;
+        Ok(symbol_map
+            .lookup(LookupAddress::Relative(relative_address))
+            .await)
+    }
+
+    /// Look up many addresses, possibly in different libraries, and receive
+    /// each result as soon as it is available, e.g. to update a UI progressively.
+    ///
+    /// Each item of `addresses` is a library, as for [`lookup_single`](Self::lookup_single),
+    /// and a relative address in it. The returned stream yields the index of the
+    /// address in `addresses` together with its [`AddressInfo`]. The symbols of
+    /// the different libraries are loaded concurrently, within the limits from
+    /// [`SymbolManagerConfig::max_concurrent_downloads`] and
+    /// [`SymbolManagerConfig::max_concurrent_local_file_loads`], so the results
+    /// don't come in the order of `addresses`.
+    ///
+    /// Addresses whose library has no symbols, or which aren't covered by any
+    /// symbol, are skipped.
+    pub fn lookup_stream<'a>(
+        &'a self,
+        addresses: impl IntoIterator<Item = (LibraryInfo, u32)>,
+    ) -> impl Stream<Item = (usize, AddressInfo)> + 'a {
+        let mut addresses_by_lib: HashMap<LibraryInfo, Vec<(usize, u32)>> = HashMap::new();
+        for (index, (lib, relative_address)) in addresses.into_iter().enumerate() {
+            addresses_by_lib
+                .entry(lib)
+                .or_default()
+                .push((index, relative_address));
+        }
+        stream::iter(addresses_by_lib)
+            .map(move |(lib, addresses)| {
+                let lookups = async move {
+                    let symbol_map = self.load_symbol_map_for_single_lookup(lib).await.ok();
+                    stream::iter(addresses).filter_map(move |(index, relative_address)| {
+                        let symbol_map = symbol_map.clone();
+                        async move {
+                            let address_info = symbol_map?
+                                .lookup(LookupAddress::Relative(relative_address))
+                                .await?;
+                            Some((index, address_info))
+                        }
+                    })
+                };
+                // flatten_unordered needs the inner streams to be Unpin.
+                Box::pin(stream::once(lookups).flatten())
+            })
+            .flatten_unordered(None)
+    }
+
+    async fn load_symbol_map_for_single_lookup(
+        &self,
+        lib: LibraryInfo,
+    ) -> Result<Arc<SymbolMap>, Error> {
+        if let Some(symbol_map) = self.single_lookup_symbol_maps.lock().unwrap().get(&lib) {
+            return Ok(symbol_map.clone());
+        }
+
+        let mut info = lib.clone();
+        if let (None, Some(path)) = (info.debug_id, &lib.path) {
+            // We need the debug ID to find the symbols, so get it from the binary.
+            let binary_info = Self::library_info_for_binary_at_path(Path::new(path), None).await?;
+            info.absorb(&binary_info);
+        }
+        let symbol_map = self.symbol_manager.load_symbol_map(&info).await?;
+        let symbol_map = Arc::new(SymbolMap(symbol_map));
+        self.single_lookup_symbol_maps
+            .lock()
+            .unwrap()
+            .insert(lib, symbol_map.clone());
+        Ok(symbol_map)
+    }
+
+    /// Load the symbols for all the given libraries concurrently, so that
+    /// later lookups for these libraries don't have to wait for downloads.
+    ///
+    /// This resolves the candidate paths for each library and downloads any
+    /// missing symbol files into the configured caches. The limits from
+    /// [`SymbolManagerConfig::max_concurrent_downloads`] and
+    /// [`SymbolManagerConfig::max_concurrent_local_file_loads`] still apply.
+    /// The loaded symbol maps are kept, so that [`lookup_single`](Self::lookup_single)
+    /// and [`lookup_stream`](Self::lookup_stream) can use them for the same
+    /// libraries without loading them again.
+    ///
+    /// Returns the libraries for which no symbols could be loaded, along with
+    /// the error for each.
+    pub async fn prewarm(
+        &self,
+        libs: impl Iterator<Item = LibraryInfo>,
+    ) -> Vec<(LibraryInfo, Error)> {
+        let loads = libs.map(|lib| async move {
+            match self.load_symbol_map_for_single_lookup(lib.clone()).await {
+                Ok(_symbol_map) => None,
+                Err(e) => Some((lib, e)),
+            }
+        });
+        futures_util::future::join_all(loads)
+            .await
+            .into_iter()
+            .flatten()
+            .collect()
     }
 
     /// Manually load and return an external file with additional debug info.
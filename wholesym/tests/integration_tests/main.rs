@@ -161,6 +161,400 @@ async fn dwz_symbolication() {
     assert_eq!(frames[1].function.as_ref().unwrap(), "gobble_file");
 }
 
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn prewarm_reports_failed_libs() {
+    let ls_dir = fixtures_dir().join("other").join("ls-linux");
+    let ls_bin_path = ls_dir.join("ls");
+    let recorder = std::sync::Arc::new(wholesym::FileRequestRecorder::new());
+    let config = wholesym::SymbolManagerConfig::default()
+        .record_file_requests(recorder.clone())
+        .redirect_path_for_testing(
+            "/usr/lib/debug/.build-id/63/260a3e6e46db57abf718f6a3562c6eedccf269.debug",
+            ls_dir.join("260a3e6e46db57abf718f6a3562c6eedccf269.debug"),
+        )
+        .redirect_path_for_testing(
+            "/usr/lib/debug/.dwz/aarch64-linux-gnu/coreutils.debug",
+            ls_dir.join("coreutils.debug"),
+        );
+    let symbol_manager = wholesym::SymbolManager::with_config(config);
+    let ls_info = wholesym::SymbolManager::library_info_for_binary_at_path(&ls_bin_path, None)
+        .await
+        .unwrap();
+    let missing_info = wholesym::LibraryInfo {
+        debug_name: Some("missing.so".to_string()),
+        debug_id: Some(DebugId::nil()),
+        ..Default::default()
+    };
+
+    let failed = symbol_manager
+        .prewarm([ls_info.clone(), missing_info].into_iter())
+        .await;
+    assert_eq!(failed.len(), 1);
+    assert_eq!(failed[0].0.debug_name.as_deref(), Some("missing.so"));
+
+    // Looking up an address in a prewarmed library doesn't load any files.
+    let request_count = recorder.requests().len();
+    let address_info = symbol_manager
+        .lookup_single(ls_info, 0xd6f4)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(address_info.symbol.name, "gobble_file.constprop.0");
+    assert_eq!(recorder.requests().len(), request_count);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn huge_concurrency_limits_are_clamped() {
+    let ls_bin_path = fixtures_dir().join("other").join("ls-linux").join("ls");
+    let config = wholesym::SymbolManagerConfig::default()
+        .max_concurrent_local_file_loads(usize::MAX)
+        .max_concurrent_downloads(usize::MAX);
+    let symbol_manager = wholesym::SymbolManager::with_config(config);
+    let symbol_map = symbol_manager
+        .load_symbol_map_for_binary_at_path(&ls_bin_path, None)
+        .await
+        .unwrap();
+    assert_eq!(
+        symbol_map.debug_id(),
+        DebugId::from_breakpad("3E0A2663466E57DBABF718F6A3562C6E0").unwrap()
+    );
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn known_libs_with_same_debug_name() {
+    let ls_dir = fixtures_dir().join("other").join("ls-linux");
+    let ls_bin_path = ls_dir.join("ls");
+    let recorder = std::sync::Arc::new(wholesym::FileRequestRecorder::new());
+    let config = wholesym::SymbolManagerConfig::default().record_file_requests(recorder.clone());
+    let mut symbol_manager = wholesym::SymbolManager::with_config(config);
+
+    // Two versions of "ls" which share a code ID, but only the first one has a path.
+    let ls_info = wholesym::SymbolManager::library_info_for_binary_at_path(&ls_bin_path, None)
+        .await
+        .unwrap();
+    let other_debug_id = DebugId::from_breakpad("0123456789ABCDEF0123456789ABCDEF0").unwrap();
+    let other_ls_info = wholesym::LibraryInfo {
+        debug_name: ls_info.debug_name.clone(),
+        debug_id: Some(other_debug_id),
+        code_id: ls_info.code_id.clone(),
+        ..Default::default()
+    };
+    symbol_manager.add_known_library(other_ls_info);
+    symbol_manager.add_known_library(ls_info.clone());
+
+    // Looking up the other version must not pick up the path of the first version.
+    let res = symbol_manager.load_symbol_map("ls", other_debug_id).await;
+    assert!(res.is_err());
+    let ls_bin_path = ls_bin_path.to_string_lossy();
+    assert!(!recorder
+        .requests()
+        .iter()
+        .any(|request| request.location.contains(&*ls_bin_path)));
+
+    // The first version is still found by its own debug ID.
+    let symbol_map = symbol_manager
+        .load_symbol_map("ls", ls_info.debug_id.unwrap())
+        .await
+        .unwrap();
+    assert_eq!(symbol_map.debug_id(), ls_info.debug_id.unwrap());
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn perf_buildid_cache() {
+    let ls_bin_path = fixtures_dir().join("other").join("ls-linux").join("ls");
+    let ls_info = wholesym::SymbolManager::library_info_for_binary_at_path(&ls_bin_path, None)
+        .await
+        .unwrap();
+    let build_id = ls_info.code_id.as_ref().unwrap().to_string();
+    let (two_chars, rest) = build_id.split_at(2);
+    let cached_path = format!("/home/user/.debug/.build-id/{two_chars}/{rest}/elf");
+    let config = wholesym::SymbolManagerConfig::default()
+        .perf_buildid_cache_dir("/home/user/.debug")
+        .redirect_path_for_testing(&cached_path, &ls_bin_path);
+    let mut symbol_manager = wholesym::SymbolManager::with_config(config);
+
+    // The library is only known by its code ID, not by its path.
+    symbol_manager.add_known_library(wholesym::LibraryInfo {
+        name: ls_info.name.clone(),
+        debug_name: ls_info.debug_name.clone(),
+        debug_id: ls_info.debug_id,
+        code_id: ls_info.code_id.clone(),
+        ..Default::default()
+    });
+    let symbol_map = symbol_manager
+        .load_symbol_map("ls", ls_info.debug_id.unwrap())
+        .await
+        .unwrap();
+    assert_eq!(symbol_map.debug_id(), ls_info.debug_id.unwrap());
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn lowercase_symbol_server_paths() {
+    // A symbol server cache which stores its files under lowercase paths.
+    let debug_id = DebugId::from_breakpad("63C609072D3499F64C4C44205044422E1").unwrap();
+    let cache_dir =
+        std::env::temp_dir().join(format!("wholesym-lowercase-test-{}", std::process::id()));
+    let pdb_dir = cache_dir
+        .join("mozglue.pdb")
+        .join(debug_id.breakpad().to_string().to_lowercase());
+    std::fs::create_dir_all(&pdb_dir).unwrap();
+    std::fs::copy(
+        fixtures_dir().join("win64-ci").join("mozglue.pdb"),
+        pdb_dir.join("mozglue.pdb"),
+    )
+    .unwrap();
+
+    let config = wholesym::SymbolManagerConfig::default()
+        .windows_symbols_server("http://127.0.0.1:1/", &cache_dir)
+        .lowercase_symbol_server_paths(true);
+    let symbol_manager = wholesym::SymbolManager::with_config(config);
+    let symbol_map = symbol_manager
+        .load_symbol_map("mozglue.pdb", debug_id)
+        .await
+        .unwrap();
+    assert_eq!(symbol_map.debug_id(), debug_id);
+
+    // By default, the identifier is requested in upper case.
+    let config = wholesym::SymbolManagerConfig::default()
+        .windows_symbols_server("http://127.0.0.1:1/", &cache_dir);
+    let symbol_manager = wholesym::SymbolManager::with_config(config);
+    assert!(symbol_manager
+        .load_symbol_map("mozglue.pdb", debug_id)
+        .await
+        .is_err());
+
+    std::fs::remove_dir_all(&cache_dir).unwrap();
+}
+
+/// Starts an HTTP server on a local port which serves `body` at `path`, but only
+/// to requests with the header line `required_header`. Returns the port.
+fn serve_file_with_required_header(
+    path: String,
+    body: Vec<u8>,
+    required_header: &'static str,
+) -> u16 {
+    use std::io::{BufRead, Write};
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else { continue };
+            let mut reader = std::io::BufReader::new(stream.try_clone().unwrap());
+            let mut request_line = String::new();
+            let _ = reader.read_line(&mut request_line);
+            let mut has_required_header = false;
+            loop {
+                let mut line = String::new();
+                if reader.read_line(&mut line).unwrap_or(0) == 0 || line == "\r\n" {
+                    break;
+                }
+                has_required_header |= line.trim_end().eq_ignore_ascii_case(required_header);
+            }
+            let response_body: &[u8] =
+                if request_line.starts_with(&format!("GET {path} ")) && has_required_header {
+                    let _ = write!(stream, "HTTP/1.1 200 OK\r\n");
+                    &body
+                } else {
+                    let _ = write!(stream, "HTTP/1.1 404 Not Found\r\n");
+                    b""
+                };
+            let _ = write!(
+                stream,
+                "Content-Length: {}\r\nConnection: close\r\n\r\n",
+                response_body.len()
+            );
+            let _ = stream.write_all(response_body);
+        }
+    });
+    port
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn windows_symbol_server_with_auth_header() {
+    let debug_id = DebugId::from_breakpad("63C609072D3499F64C4C44205044422E1").unwrap();
+    let pdb_bytes = std::fs::read(fixtures_dir().join("win64-ci").join("mozglue.pdb")).unwrap();
+    let port = serve_file_with_required_header(
+        format!("/mozglue.pdb/{}/mozglue.pdb", debug_id.breakpad()),
+        pdb_bytes,
+        "authorization: Bearer secret",
+    );
+    let server_url = format!("http://127.0.0.1:{port}/");
+
+    // A URL prefix which is only a string prefix of the server URL doesn't get
+    // the header.
+    let cache_dir = tempfile::tempdir().unwrap();
+    let config = wholesym::SymbolManagerConfig::default()
+        .windows_symbols_server(&server_url, cache_dir.path())
+        .server_bearer_token(format!("http://127.0.0.1:{}", port / 10), "secret");
+    let symbol_manager = wholesym::SymbolManager::with_config(config);
+    assert!(symbol_manager
+        .load_symbol_map("mozglue.pdb", debug_id)
+        .await
+        .is_err());
+
+    let config = wholesym::SymbolManagerConfig::default()
+        .windows_symbols_server(&server_url, cache_dir.path())
+        .server_bearer_token(&server_url, "secret");
+    let symbol_manager = wholesym::SymbolManager::with_config(config);
+    let symbol_map = symbol_manager
+        .load_symbol_map("mozglue.pdb", debug_id)
+        .await
+        .unwrap();
+    assert_eq!(symbol_map.debug_id(), debug_id);
+    assert!(cache_dir
+        .path()
+        .join("mozglue.pdb")
+        .join(debug_id.breakpad().to_string())
+        .join("mozglue.pdb")
+        .exists());
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn pinned_symbol_file_sha256() {
+    let debug_id = DebugId::from_breakpad("63C609072D3499F64C4C44205044422E1").unwrap();
+    let cache_dir =
+        std::env::temp_dir().join(format!("wholesym-sha256-test-{}", std::process::id()));
+    let pdb_dir = cache_dir
+        .join("mozglue.pdb")
+        .join(debug_id.breakpad().to_string());
+    std::fs::create_dir_all(&pdb_dir).unwrap();
+    let pdb_bytes = std::fs::read(fixtures_dir().join("win64-ci").join("mozglue.pdb")).unwrap();
+    std::fs::write(pdb_dir.join("mozglue.pdb"), &pdb_bytes).unwrap();
+    let sha256: [u8; 32] = ring::digest::digest(&ring::digest::SHA256, &pdb_bytes)
+        .as_ref()
+        .try_into()
+        .unwrap();
+
+    let config = wholesym::SymbolManagerConfig::default()
+        .windows_symbols_server("http://127.0.0.1:1/", &cache_dir)
+        .symbol_file_sha256("mozglue.pdb", debug_id, sha256);
+    let symbol_manager = wholesym::SymbolManager::with_config(config);
+    let symbol_map = symbol_manager
+        .load_symbol_map("mozglue.pdb", debug_id)
+        .await
+        .unwrap();
+    assert_eq!(symbol_map.debug_id(), debug_id);
+
+    let mut wrong_sha256 = sha256;
+    wrong_sha256[0] ^= 0xff;
+    let config = wholesym::SymbolManagerConfig::default()
+        .windows_symbols_server("http://127.0.0.1:1/", &cache_dir)
+        .symbol_file_sha256("mozglue.pdb", debug_id, wrong_sha256);
+    let symbol_manager = wholesym::SymbolManager::with_config(config);
+    assert!(symbol_manager
+        .load_symbol_map("mozglue.pdb", debug_id)
+        .await
+        .is_err());
+    // The mismatching file is evicted from the cache.
+    assert!(!pdb_dir.join("mozglue.pdb").exists());
+
+    std::fs::remove_dir_all(&cache_dir).unwrap();
+}
+
+/// Wraps `data` in a zstd frame of uncompressed blocks.
+fn zstd_frame_with_raw_blocks(data: &[u8]) -> Vec<u8> {
+    const MAX_BLOCK_SIZE: usize = 128 * 1024;
+    // Magic number, frame header descriptor, and a window descriptor for a
+    // 128KiB window.
+    let mut frame = vec![0x28, 0xb5, 0x2f, 0xfd, 0x00, 0x38];
+    let chunk_count = data.chunks(MAX_BLOCK_SIZE).count();
+    for (i, chunk) in data.chunks(MAX_BLOCK_SIZE).enumerate() {
+        let is_last_block = (i + 1 == chunk_count) as u32;
+        let block_header = is_last_block | ((chunk.len() as u32) << 3);
+        frame.extend_from_slice(&block_header.to_le_bytes()[..3]);
+        frame.extend_from_slice(chunk);
+    }
+    frame
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn compressed_kernel_modules() {
+    let ls_bin_path = fixtures_dir().join("other").join("ls-linux").join("ls");
+    let data = std::fs::read(&ls_bin_path).unwrap();
+    let expected_info =
+        wholesym::SymbolManager::library_info_for_binary_at_path(&ls_bin_path, None)
+            .await
+            .unwrap();
+
+    let mut xz = Vec::new();
+    lzma_rs::xz_compress(&mut &data[..], &mut xz).unwrap();
+    let mut gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+    std::io::Write::write_all(&mut gz, &data).unwrap();
+    let gz = gz.finish().unwrap();
+    let zst = zstd_frame_with_raw_blocks(&data);
+
+    let dir = std::env::temp_dir().join(format!("wholesym-ko-test-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    for (ext, compressed) in [("ko.xz", xz), ("ko.gz", gz), ("ko.zst", zst)] {
+        let module_path = dir.join(format!("ls.{ext}"));
+        std::fs::write(&module_path, compressed).unwrap();
+        let info = wholesym::SymbolManager::library_info_for_binary_at_path(&module_path, None)
+            .await
+            .unwrap();
+        assert_eq!(info.debug_id, expected_info.debug_id, "{ext}");
+        assert_eq!(info.code_id, expected_info.code_id, "{ext}");
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn record_and_replay_file_requests() {
+    let ls_dir = fixtures_dir().join("other").join("ls-linux");
+    let ls_bin_path = ls_dir.join("ls");
+    let recorder = std::sync::Arc::new(wholesym::FileRequestRecorder::with_contents());
+    let config = wholesym::SymbolManagerConfig::default()
+        .redirect_path_for_testing(
+            "/usr/lib/debug/.build-id/63/260a3e6e46db57abf718f6a3562c6eedccf269.debug",
+            ls_dir.join("260a3e6e46db57abf718f6a3562c6eedccf269.debug"),
+        )
+        .redirect_path_for_testing(
+            "/usr/lib/debug/.dwz/aarch64-linux-gnu/coreutils.debug",
+            ls_dir.join("coreutils.debug"),
+        )
+        .record_file_requests(recorder.clone());
+    let symbol_manager = wholesym::SymbolManager::with_config(config);
+    let symbol_map = symbol_manager
+        .load_symbol_map_for_binary_at_path(&ls_bin_path, None)
+        .await
+        .unwrap();
+    let recorded_function = symbol_map
+        .lookup_sync(LookupAddress::Relative(0xd6f4))
+        .unwrap()
+        .symbol
+        .name;
+
+    let requests = recorder.requests();
+    assert!(requests.iter().any(|request| {
+        request.location.contains("coreutils.debug")
+            && matches!(
+                request.outcome,
+                wholesym::FileRequestOutcome::Found {
+                    contents: Some(_),
+                    ..
+                }
+            )
+    }));
+
+    // Without the redirects, the debug files can only come from the recording.
+    let config =
+        wholesym::SymbolManagerConfig::default().replay_file_requests(recorder.to_replay());
+    let symbol_manager = wholesym::SymbolManager::with_config(config);
+    let symbol_map = symbol_manager
+        .load_symbol_map_for_binary_at_path(&ls_bin_path, None)
+        .await
+        .unwrap();
+    let sym = symbol_map
+        .lookup_sync(LookupAddress::Relative(0xd6f4))
+        .unwrap();
+    assert_eq!(sym.symbol.name, recorded_function);
+    let frames = match &sym.frames {
+        Some(FramesLookupResult::Available(frames)) => frames,
+        _ => panic!("failed to obtain debug info from the replay"),
+    };
+    assert_eq!(frames[0].function.as_ref().unwrap(), "do_lstat");
+}
+
 mod simple_example {
     use std::pin::Pin;
 
@@ -228,6 +622,12 @@ mod simple_example {
     }
 
     async fn linux_simple_example_test_fn(symbol_map: &wholesym::SymbolMap) {
+        let functions: Vec<_> = symbol_map.iter_functions().collect();
+        assert!(functions
+            .windows(2)
+            .all(|pair| pair[0].0.start < pair[1].0.start));
+        assert!(functions.contains(&(0xafc..0xb28, "file1_func2(int)".to_string())));
+
         test_address(symbol_map, 0xb14, |t| {
             assert_eq!(
                 t,
@@ -291,6 +691,84 @@ mod simple_example {
         .await;
     }
 
+    #[tokio::test]
+    async fn run_test_chained_debuglink() {
+        // Layered debug packaging: main links to main.mid, which is stripped
+        // like main and links to main.dbg, which has the DWARF data.
+        let regular_debuglink_dir =
+            fixtures_dir().join("other/simple-example/out/regular-debuglink");
+        let dir = std::env::temp_dir().join(format!(
+            "wholesym-chained-debuglink-test-{}",
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::copy(regular_debuglink_dir.join("main.dbg"), dir.join("main.dbg")).unwrap();
+        let stripped = std::fs::read(regular_debuglink_dir.join("main")).unwrap();
+        std::fs::write(dir.join("main.mid"), &stripped).unwrap();
+
+        let mut crc = flate2::Crc::new();
+        crc.update(&stripped);
+        let mut main = stripped.clone();
+        let link_pos = main
+            .windows(9)
+            .position(|window| window == b"main.dbg\0")
+            .unwrap();
+        main[link_pos..link_pos + 8].copy_from_slice(b"main.mid");
+        main[link_pos + 12..link_pos + 16].copy_from_slice(&crc.sum().to_le_bytes());
+        std::fs::write(dir.join("main"), &main).unwrap();
+
+        run_single_test(
+            &dir.join("main"),
+            &[],
+            DebugId::from_breakpad("0C3E1D589F360C231BC06257AD3D38270").unwrap(),
+            |sm| Box::pin(linux_simple_example_test_fn(sm)),
+        )
+        .await;
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn run_test_chained_debuglink_with_corrupt_first_candidate() {
+        // main links to main.mid, which exists both next to main and in
+        // .debug/. The chain through the first copy ends in a corrupt
+        // main.dbg, so the chain through the second copy has to be used.
+        let regular_debuglink_dir =
+            fixtures_dir().join("other/simple-example/out/regular-debuglink");
+        let dir = std::env::temp_dir().join(format!(
+            "wholesym-corrupt-debuglink-test-{}",
+            std::process::id()
+        ));
+        std::fs::create_dir_all(dir.join(".debug/.debug")).unwrap();
+        let debug_file = std::fs::read(regular_debuglink_dir.join("main.dbg")).unwrap();
+        std::fs::write(dir.join("main.dbg"), &debug_file[..debug_file.len() / 2]).unwrap();
+        std::fs::write(dir.join(".debug/.debug/main.dbg"), &debug_file).unwrap();
+        let stripped = std::fs::read(regular_debuglink_dir.join("main")).unwrap();
+        std::fs::write(dir.join("main.mid"), &stripped).unwrap();
+        std::fs::write(dir.join(".debug/main.mid"), &stripped).unwrap();
+
+        let mut crc = flate2::Crc::new();
+        crc.update(&stripped);
+        let mut main = stripped.clone();
+        let link_pos = main
+            .windows(9)
+            .position(|window| window == b"main.dbg\0")
+            .unwrap();
+        main[link_pos..link_pos + 8].copy_from_slice(b"main.mid");
+        main[link_pos + 12..link_pos + 16].copy_from_slice(&crc.sum().to_le_bytes());
+        std::fs::write(dir.join("main"), &main).unwrap();
+
+        run_single_test(
+            &dir.join("main"),
+            &[],
+            DebugId::from_breakpad("0C3E1D589F360C231BC06257AD3D38270").unwrap(),
+            |sm| Box::pin(linux_simple_example_test_fn(sm)),
+        )
+        .await;
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
     #[tokio::test]
     async fn run_test_with_dwo() {
         let dwo_obj_dir = fixtures_dir().join("other/simple-example/out/with-dwo");
@@ -336,6 +814,29 @@ mod simple_example {
         .await;
     }
 
+    #[tokio::test]
+    async fn run_test_with_dwp_in_configured_dir() {
+        let dwp_obj_dir = fixtures_dir().join("other/simple-example/out/with-dwp");
+        let bin_dir =
+            std::env::temp_dir().join(format!("wholesym-dwp-test-{}", std::process::id()));
+        std::fs::create_dir_all(&bin_dir).unwrap();
+        std::fs::copy(dwp_obj_dir.join("main"), bin_dir.join("main")).unwrap();
+
+        let config = wholesym::SymbolManagerConfig::default().dwp_dir(&dwp_obj_dir);
+        let symbol_manager = wholesym::SymbolManager::with_config(config);
+        let symbol_map = symbol_manager
+            .load_symbol_map_for_binary_at_path(&bin_dir.join("main"), None)
+            .await
+            .unwrap();
+        assert_eq!(
+            symbol_map.debug_id(),
+            DebugId::from_breakpad("AA203F622728BC24591A89512845E0900").unwrap()
+        );
+        linux_simple_example_test_fn(&symbol_map).await;
+
+        std::fs::remove_dir_all(&bin_dir).unwrap();
+    }
+
     async fn mac_simple_example_test_fn(symbol_map: &wholesym::SymbolMap) {
         test_address(symbol_map, 0x3ac0, |t| {
             assert_eq!(
@@ -420,3 +921,418 @@ mod simple_example {
         .await;
     }
 }
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn symbolicate_from_bytes() {
+    let bytes = std::fs::read(
+        fixtures_dir()
+            .join("other")
+            .join("example-linux-minidebuginfo"),
+    )
+    .unwrap();
+    let symbol_manager =
+        wholesym::SymbolManager::with_config(wholesym::SymbolManagerConfig::default());
+    let library_info = wholesym::LibraryInfo {
+        name: Some("example-linux-minidebuginfo".into()),
+        ..Default::default()
+    };
+    let symbol_map = symbol_manager
+        .load_symbol_map_for_binary_bytes(&library_info, bytes.clone())
+        .await
+        .unwrap();
+    let sym = symbol_map
+        .lookup_sync(LookupAddress::Relative(0x1100))
+        .unwrap();
+    assert_eq!(sym.symbol.name, "exported_function");
+
+    // The path is only a label, the file at that path isn't read.
+    let library_info_with_path = wholesym::LibraryInfo {
+        path: Some("/nonexistent/example-linux-minidebuginfo".into()),
+        ..library_info.clone()
+    };
+    let symbol_map = symbol_manager
+        .load_symbol_map_for_binary_bytes(&library_info_with_path, bytes.clone())
+        .await
+        .unwrap();
+    assert_eq!(
+        symbol_map
+            .lookup_sync(LookupAddress::Relative(0x1100))
+            .unwrap()
+            .symbol
+            .name,
+        "exported_function"
+    );
+
+    // A debug ID which doesn't match the bytes is rejected.
+    let mismatched_library_info = wholesym::LibraryInfo {
+        debug_id: Some(DebugId::from_breakpad("63C609072D3499F64C4C44205044422E1").unwrap()),
+        ..library_info
+    };
+    assert!(matches!(
+        symbol_manager
+            .load_symbol_map_for_binary_bytes(&mismatched_library_info, bytes)
+            .await,
+        Err(wholesym::Error::UnmatchedDebugId(_, _))
+    ));
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn lookup_single() {
+    let bin_path = fixtures_dir().join("other/simple-example/out/regular-debuglink/main");
+    let symbol_manager =
+        wholesym::SymbolManager::with_config(wholesym::SymbolManagerConfig::default());
+    let lib = wholesym::LibraryInfo {
+        path: Some(bin_path.to_string_lossy().into_owned()),
+        ..Default::default()
+    };
+
+    let address_info = symbol_manager
+        .lookup_single(lib.clone(), 0xb14)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(address_info.symbol.name, "file1_func2(int)");
+    let frames = address_info.frames.unwrap();
+    let functions: Vec<_> = frames
+        .iter()
+        .map(|frame| (frame.function.as_deref().unwrap(), frame.line_number))
+        .collect();
+    assert_eq!(
+        functions,
+        [
+            ("file1_func3(int, int)", Some(5)),
+            ("file1_func2(int)", Some(13))
+        ]
+    );
+
+    // The second lookup uses the same symbol map.
+    let address_info = symbol_manager
+        .lookup_single(lib, 0xb98)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(address_info.symbol.name, "file2_func1(int)");
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn lookup_stream() {
+    use futures::StreamExt;
+
+    let bin_path = fixtures_dir().join("other/simple-example/out/regular-debuglink/main");
+    let symbol_manager =
+        wholesym::SymbolManager::with_config(wholesym::SymbolManagerConfig::default());
+    let lib = wholesym::LibraryInfo {
+        path: Some(bin_path.to_string_lossy().into_owned()),
+        ..Default::default()
+    };
+    let missing_lib = wholesym::LibraryInfo {
+        path: Some(
+            fixtures_dir()
+                .join("nonexistent")
+                .to_string_lossy()
+                .into_owned(),
+        ),
+        ..Default::default()
+    };
+
+    let addresses = [(lib.clone(), 0xb14), (missing_lib, 0xb14), (lib, 0xb98)];
+    let mut results: Vec<_> = symbol_manager
+        .lookup_stream(addresses)
+        .map(|(index, address_info)| (index, address_info.symbol.name))
+        .collect()
+        .await;
+    results.sort();
+    assert_eq!(
+        results,
+        [
+            (0, "file1_func2(int)".to_string()),
+            (2, "file2_func1(int)".to_string())
+        ]
+    );
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn symbol_name_overrides() {
+    use std::collections::HashMap;
+
+    use wholesym::SymbolNameOverrideKey;
+
+    let bin_path = fixtures_dir().join("other/simple-example/out/regular-debuglink/main");
+    let mut symbol_manager =
+        wholesym::SymbolManager::with_config(wholesym::SymbolManagerConfig::default());
+    let symbol_map = symbol_manager
+        .load_symbol_map_for_binary_at_path(&bin_path, None)
+        .await
+        .unwrap();
+    let debug_id = symbol_map.debug_id();
+    let file2_func1 = symbol_map
+        .lookup(LookupAddress::Relative(0xb98))
+        .await
+        .unwrap()
+        .symbol;
+    assert_eq!(file2_func1.name, "file2_func1(int)");
+
+    let overrides = HashMap::from([
+        (
+            (
+                debug_id,
+                SymbolNameOverrideKey::MangledName("_Z11file1_func2i".to_string()),
+            ),
+            "func2".to_string(),
+        ),
+        (
+            (
+                debug_id,
+                SymbolNameOverrideKey::Address(file2_func1.address),
+            ),
+            "other_func".to_string(),
+        ),
+    ]);
+    symbol_manager.set_symbol_name_overrides(overrides);
+    let symbol_map = symbol_manager
+        .load_symbol_map_for_binary_at_path(&bin_path, None)
+        .await
+        .unwrap();
+
+    let address_info = symbol_map
+        .lookup(LookupAddress::Relative(0xb14))
+        .await
+        .unwrap();
+    assert_eq!(address_info.symbol.name, "func2");
+    // Only the outer function is renamed, not the inlined function.
+    let functions: Vec<_> = address_info
+        .frames
+        .unwrap()
+        .into_iter()
+        .map(|frame| frame.function.unwrap())
+        .collect();
+    assert_eq!(functions, ["file1_func3(int, int)", "func2"]);
+
+    let address_info = symbol_map
+        .lookup_sync(LookupAddress::Relative(0xb98))
+        .unwrap();
+    assert_eq!(address_info.symbol.name, "other_func");
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn archive_member() {
+    let archive_path = fixtures_dir().join("other/simple-example/out/with-dwo/libfile23.a");
+    let symbol_manager =
+        wholesym::SymbolManager::with_config(wholesym::SymbolManagerConfig::default());
+    let symbol_map = symbol_manager
+        .load_symbol_map_for_archive_member(
+            &archive_path,
+            &wholesym::ArchiveMemberSelector::Name("file2.o".to_string()),
+        )
+        .await
+        .unwrap();
+    let address_info = symbol_map.lookup(LookupAddress::Svma(0x38)).await.unwrap();
+    assert_eq!(address_info.symbol.name, "file2_func2(int)");
+
+    // Select the member by its debug ID.
+    let debug_id = symbol_map.debug_id();
+    let symbol_map = symbol_manager
+        .load_symbol_map_for_archive_member(
+            &archive_path,
+            &wholesym::ArchiveMemberSelector::DebugId(debug_id),
+        )
+        .await
+        .unwrap();
+    assert_eq!(symbol_map.debug_id(), debug_id);
+
+    let err = symbol_manager
+        .load_symbol_map_for_archive_member(
+            &archive_path,
+            &wholesym::ArchiveMemberSelector::Name("file1.o".to_string()),
+        )
+        .await
+        .err()
+        .unwrap();
+    assert!(matches!(err, wholesym::Error::FileNotInArchive(_)));
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn nearest_symbol_fallback() {
+    let debug_id = DebugId::from_breakpad("9F1D3B6E2C4A8E0B4C4C44205044422E1").unwrap();
+    let mut symbol_manager =
+        wholesym::SymbolManager::with_config(wholesym::SymbolManagerConfig::default());
+    symbol_manager.add_known_library_symbol_table(
+        wholesym::LibraryInfo {
+            debug_name: Some("libasm.so".into()),
+            debug_id: Some(debug_id),
+            ..Default::default()
+        },
+        vec![
+            wholesym::SymbolInfo {
+                address: 0x1000,
+                size: Some(0x20),
+                name: "memcpy_avx".to_string(),
+            },
+            wholesym::SymbolInfo {
+                address: 0x2000,
+                size: Some(0x20),
+                name: "memset_avx".to_string(),
+            },
+        ],
+    );
+    let mut symbol_map = symbol_manager
+        .load_symbol_map("libasm.so", debug_id)
+        .await
+        .unwrap();
+
+    assert_eq!(
+        symbol_map.lookup(LookupAddress::Relative(0x1123)).await,
+        None
+    );
+
+    symbol_map.set_nearest_symbol_fallback(true);
+    let info = symbol_map
+        .lookup(LookupAddress::Relative(0x1123))
+        .await
+        .unwrap();
+    assert_eq!(info.symbol.address, 0x1000);
+    assert_eq!(info.symbol.name, "memcpy_avx+0x123");
+    assert_eq!(info.nearest_symbol_offset, Some(0x123));
+
+    // Exact matches and addresses before the first symbol are unaffected.
+    let info = symbol_map
+        .lookup(LookupAddress::Relative(0x2010))
+        .await
+        .unwrap();
+    assert_eq!(info.symbol.name, "memset_avx");
+    assert_eq!(info.nearest_symbol_offset, None);
+    assert_eq!(
+        symbol_map.lookup(LookupAddress::Relative(0x800)).await,
+        None
+    );
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn find_template_instantiations() {
+    let debug_id = DebugId::from_breakpad("63C609072D3499F64C4C44205044422E1").unwrap();
+    let mut symbol_manager =
+        wholesym::SymbolManager::with_config(wholesym::SymbolManagerConfig::default());
+    let symbol = |address, name: &str| wholesym::SymbolInfo {
+        address,
+        size: Some(0x10),
+        name: name.to_string(),
+    };
+    symbol_manager.add_known_library_symbol_table(
+        wholesym::LibraryInfo {
+            debug_name: Some("libtemplates.so".into()),
+            debug_id: Some(debug_id),
+            ..Default::default()
+        },
+        vec![
+            symbol(
+                0x1000,
+                "std::vector<int, std::allocator<int>>::push_back(int const&)",
+            ),
+            symbol(
+                0x2000,
+                "std::vector<float, std::allocator<float>>::push_back(float&&)",
+            ),
+            symbol(0x3000, "std::vector<int, std::allocator<int>>::pop_back()"),
+            symbol(0x4000, "my_vector<int>::push_back(int)"),
+        ],
+    );
+    let symbol_map = symbol_manager
+        .load_symbol_map("libtemplates.so", debug_id)
+        .await
+        .unwrap();
+
+    let addresses = |base_name| {
+        symbol_map
+            .find_symbols_by_base_name(base_name)
+            .iter()
+            .map(|symbol| (symbol.address, symbol.size))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        addresses("std::vector::push_back"),
+        vec![(0x1000, Some(0x10)), (0x2000, Some(0x10))]
+    );
+    assert_eq!(
+        addresses("vector<int>::push_back"),
+        vec![(0x1000, Some(0x10)), (0x2000, Some(0x10))]
+    );
+    assert_eq!(addresses("push_back").len(), 3);
+    assert!(addresses("vector::push").is_empty());
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn symbolicate_from_symbol_table() {
+    let debug_id = DebugId::from_breakpad("63C609072D3499F64C4C44205044422E1").unwrap();
+    let mut symbol_manager =
+        wholesym::SymbolManager::with_config(wholesym::SymbolManagerConfig::default());
+    symbol_manager.add_known_library_symbol_table(
+        wholesym::LibraryInfo {
+            debug_name: Some("runtime.so".into()),
+            debug_id: Some(debug_id),
+            ..Default::default()
+        },
+        vec![
+            wholesym::SymbolInfo {
+                address: 0x2000,
+                size: Some(0x10),
+                name: "second".into(),
+            },
+            wholesym::SymbolInfo {
+                address: 0x1000,
+                size: None,
+                name: "first".into(),
+            },
+        ],
+    );
+    let symbol_map = symbol_manager
+        .load_symbol_map("runtime.so", debug_id)
+        .await
+        .unwrap();
+    assert_eq!(symbol_map.debug_id(), debug_id);
+    assert_eq!(symbol_map.symbol_count(), 2);
+
+    let lookup = |address| {
+        symbol_map
+            .lookup_sync(LookupAddress::Relative(address))
+            .map(|info| (info.symbol.name, info.symbol.size))
+    };
+    assert_eq!(lookup(0xfff), None);
+    assert_eq!(lookup(0x1800), Some(("first".into(), Some(0x1000))));
+    assert_eq!(lookup(0x200f), Some(("second".into(), Some(0x10))));
+    assert_eq!(lookup(0x2010), None);
+}
+
+#[cfg(feature = "api")]
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn symbol_name_overrides_in_json_api() {
+    use std::collections::HashMap;
+
+    use wholesym::SymbolNameOverrideKey;
+
+    let bin_path = fixtures_dir().join("other/simple-example/out/regular-debuglink/main");
+    let mut symbol_manager =
+        wholesym::SymbolManager::with_config(wholesym::SymbolManagerConfig::default());
+    let lib_info = wholesym::SymbolManager::library_info_for_binary_at_path(&bin_path, None)
+        .await
+        .unwrap();
+    let debug_id = lib_info.debug_id.unwrap();
+    symbol_manager.add_known_library(lib_info);
+    symbol_manager.set_symbol_name_overrides(HashMap::from([(
+        (
+            debug_id,
+            SymbolNameOverrideKey::MangledName("_Z11file1_func2i".to_string()),
+        ),
+        "func2".to_string(),
+    )]));
+
+    let request = format!(
+        r#"{{"memoryMap": [["main", "{}"]], "stacks": [[[0, {}]]]}}"#,
+        debug_id.breakpad(),
+        0xb14
+    );
+    let response = symbol_manager
+        .query_json_api("/symbolicate/v5", &request)
+        .await;
+    assert!(response.contains(r#""function":"func2""#), "{response}");
+    assert!(!response.contains("file1_func2"), "{response}");
+}